@@ -1,15 +1,24 @@
 use crate::config::Config;
-use crate::file_manager::FileSystemManager;
+use crate::file_manager::{FileSystemManager, conflict_error, is_conflict, store_local_version};
+use crate::services::generations::{Generation, GenerationStore};
+use crate::services::git::get_head_commit_id;
+use crate::services::hooks::{
+    HookPoint, check_pre_hooks, display_hook_results, display_planned_hooks, plan_hooks, run_hooks,
+};
+use crate::services::journal::journal_path;
+use crate::services::sqlite::{SIDE_FILES, owner_running, side_file};
+use crate::services::template::{TemplateContext, render_to, rendered_differs};
 use crate::services::{FileOperation, Transaction};
-use crate::types::TrackedFile;
+use crate::types::{ConflictPolicy, SymlinkResolution, TrackedFile};
 use crate::utils::dry_run::DryRun;
 use crate::utils::error::{DotfilesError, Result};
-use crate::utils::path_utils::{files_differ, symlink_points_to_correct_target};
+use crate::utils::path_utils::{paths_differ, stale_links, symlink_points_to_correct_target};
 use crate::utils::prompt::prompt_yes_no;
 use colored::Colorize;
+use serde::Serialize;
+use std::collections::BTreeSet;
 use std::fs;
-use std::path::Path;
-use tempfile::TempDir;
+use std::path::{Path, PathBuf};
 
 /// Options for applying configuration
 #[derive(Debug, Clone)]
@@ -26,46 +35,138 @@ pub struct ApplyOptions<'a> {
     pub description: Option<&'a str>,
     /// Force sync: replace all files that aren't correct symlinks (no backups)
     pub force: bool,
+    /// Resolve conflicts with this policy (`None`: confirming the apply replaces them)
+    pub conflict_policy: Option<ConflictPolicy>,
 }
 
 /// Difference between declared and actual system state.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct StateDiff {
     /// Files that need to be synced to match configuration
     pub files_to_sync: Vec<TrackedFile>,
+    /// Directory symlinks to replace with real directories for `link = "contents"`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dirs_to_unfold: Vec<PathBuf>,
+    /// Links into `link = "contents"` directories whose repo file was removed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stale_links: Vec<PathBuf>,
+    /// Tools owning `stale_links`
+    #[serde(skip)]
+    pub stale_link_tools: BTreeSet<String>,
 }
 
 impl StateDiff {
     pub fn is_empty(&self) -> bool {
         self.files_to_sync.is_empty()
+            && self.dirs_to_unfold.is_empty()
+            && self.stale_links.is_empty()
     }
 
     pub fn total_changes(&self) -> usize {
-        self.files_to_sync.len()
+        self.files_to_sync.len() + self.dirs_to_unfold.len() + self.stale_links.len()
+    }
+
+    /// Every change as one record, in the order the preview lists them.
+    pub fn records(&self) -> Vec<DriftRecord<'_>> {
+        let unfold = self
+            .dirs_to_unfold
+            .iter()
+            .map(|path| DriftRecord::Unfold { path });
+        let stale = self
+            .stale_links
+            .iter()
+            .map(|path| DriftRecord::StaleLink { path });
+        let sync = self
+            .files_to_sync
+            .iter()
+            .map(|file| DriftRecord::Sync { file });
+        unfold.chain(stale).chain(sync).collect()
+    }
+
+    /// Tools whose destinations this diff changes, for running their hooks.
+    pub fn changed_tools(&self) -> BTreeSet<String> {
+        self.files_to_sync
+            .iter()
+            .map(|file| file.tool.clone())
+            .chain(self.stale_link_tools.iter().cloned())
+            .collect()
     }
 }
 
+/// One change in a [`StateDiff`], as printed by `flux apply --check --format ndjson`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DriftRecord<'a> {
+    /// Directory symlink to replace with a real directory
+    Unfold { path: &'a Path },
+    /// Link into a `link = "contents"` directory whose repo file was removed
+    StaleLink { path: &'a Path },
+    /// File to sync, with the fields of a file record
+    Sync {
+        #[serde(flatten)]
+        file: &'a TrackedFile,
+    },
+}
+
 /// Compare declared state (from config) with actual system state
 pub fn compare_states(config: &Config, profile: Option<&str>, force: bool) -> Result<StateDiff> {
     let mut diff = StateDiff::default();
 
     // Compare files
     let tracked_files = config.get_tracked_files(profile)?;
+    let templates = TemplateContext::new(config, profile);
+    let mut link_roots = Vec::new();
     for file in tracked_files {
-        if needs_sync(&file, force)? {
+        if let Some(root) = &file.link_root
+            && !link_roots.iter().any(|(known, _)| known == root)
+        {
+            link_roots.push((root.clone(), file.tool.clone()));
+        }
+
+        // Files under a folded directory resolve into the repo, so they always need
+        // linking once the directory is unfolded
+        if let Some(folded) = file.folded_dir() {
+            if !diff.dirs_to_unfold.contains(&folded) {
+                diff.dirs_to_unfold.push(folded);
+            }
+            diff.files_to_sync.push(file);
+            continue;
+        }
+
+        // With `follow`, the file goes at the end of the destination's symlink chain
+        let (file, _) = file.followed()?;
+        if needs_sync(&file, force, &templates)? {
             diff.files_to_sync.push(file);
         }
     }
 
+    for (root, tool) in &link_roots {
+        let stale = stale_links(&root.dest, &root.repo)?;
+        if !stale.is_empty() {
+            diff.stale_link_tools.insert(tool.clone());
+            diff.stale_links.extend(stale);
+        }
+    }
+
     Ok(diff)
 }
 
 /// Check if a file needs to be synced
-fn needs_sync(file: &TrackedFile, force: bool) -> Result<bool> {
+fn needs_sync(file: &TrackedFile, force: bool, templates: &TemplateContext) -> Result<bool> {
     if !file.repo_path.exists() {
         return Ok(false); // Skip if repo file doesn't exist
     }
 
+    // Templates and secrets are copies of the rendered output, never symlinks
+    if file.is_rendered() {
+        return rendered_differs(file, templates);
+    }
+
+    // Copies are compared by content hash; a symlink has to be replaced by a copy
+    if file.is_copy() {
+        return file.copy_differs();
+    }
+
     if !file.dest_path.exists() && !file.dest_path.is_symlink() {
         return Ok(true); // Destination doesn't exist, needs sync
     }
@@ -92,7 +193,7 @@ fn needs_sync(file: &TrackedFile, force: bool) -> Result<bool> {
     }
 
     // Check if files differ
-    if files_differ(&file.repo_path, &file.dest_path)? {
+    if paths_differ(&file.repo_path, &file.dest_path, &file.dir_filter()?)? {
         return Ok(true);
     }
 
@@ -100,12 +201,11 @@ fn needs_sync(file: &TrackedFile, force: bool) -> Result<bool> {
     Ok(true)
 }
 
-
 /// Display a preview of changes that would be applied
 pub fn display_preview(diff: &StateDiff) {
     println!("\n{} Preview of changes:", "→".cyan().bold());
 
-    if diff.files_to_sync.is_empty() {
+    if diff.is_empty() {
         println!(
             "  {} System is already in sync with configuration",
             "✓".green()
@@ -113,6 +213,28 @@ pub fn display_preview(diff: &StateDiff) {
         return;
     }
 
+    if !diff.dirs_to_unfold.is_empty() {
+        println!(
+            "\n  {} Directories to unfold ({}):",
+            "📂".cyan(),
+            diff.dirs_to_unfold.len()
+        );
+        for dir in &diff.dirs_to_unfold {
+            println!("    • {}", dir.display());
+        }
+    }
+
+    if !diff.stale_links.is_empty() {
+        println!(
+            "\n  {} Stale links to remove ({}):",
+            "🗑".cyan(),
+            diff.stale_links.len()
+        );
+        for link in &diff.stale_links {
+            println!("    • {}", link.display());
+        }
+    }
+
     if !diff.files_to_sync.is_empty() {
         println!(
             "\n  {} Files to sync ({}):",
@@ -139,7 +261,24 @@ pub fn apply_config(options: ApplyOptions<'_>) -> Result<()> {
     }
 
     // Compare states
-    let diff = compare_states(options.config, options.profile, options.force)?;
+    let mut diff = compare_states(options.config, options.profile, options.force)?;
+    skip_running_databases(&mut diff);
+
+    // Rendered destinations that are up to date are remembered, so later edits conflict
+    let templates = TemplateContext::new(options.config, options.profile);
+    if !options.dry_run {
+        for file in options.config.get_tracked_files(options.profile)? {
+            if file.is_rendered()
+                && file.repo_path.exists()
+                && !diff
+                    .files_to_sync
+                    .iter()
+                    .any(|f| f.dest_path == file.dest_path)
+            {
+                templates.record_render(&file)?;
+            }
+        }
+    }
 
     if diff.is_empty() {
         println!(
@@ -149,9 +288,24 @@ pub fn apply_config(options: ApplyOptions<'_>) -> Result<()> {
         return Ok(());
     }
 
+    let adopted = match options.conflict_policy {
+        Some(policy) if !options.force => resolve_conflicts(&mut diff, policy, &templates)?,
+        _ => Vec::new(),
+    };
+    if diff.is_empty() && adopted.is_empty() {
+        println!("{} Nothing to apply after skipping conflicts", "⊘".yellow());
+        return Ok(());
+    }
+
     // Display preview
     display_preview(&diff);
 
+    // Hooks run only for the tools this apply changes
+    let changed_tools = diff.changed_tools();
+    let pre_hooks = plan_hooks(options.config, HookPoint::PreApply, &changed_tools);
+    let post_hooks = plan_hooks(options.config, HookPoint::PostApply, &changed_tools);
+    display_planned_hooks(&[pre_hooks.as_slice(), post_hooks.as_slice()].concat());
+
     // Confirm if not auto-yes
     if !options.dry_run && !options.yes && !prompt_yes_no("Apply these changes?")? {
         println!("{} Apply cancelled", "⊘".yellow());
@@ -166,11 +320,13 @@ pub fn apply_config(options: ApplyOptions<'_>) -> Result<()> {
         return Ok(());
     }
 
-    // Create transaction
-    let temp_dir = TempDir::new()?.path().to_path_buf();
-    let mut transaction = Transaction::begin(temp_dir.clone())?;
+    let mut hook_results = run_hooks(options.config, options.profile, &pre_hooks, true)?;
+    if let Err(e) = check_pre_hooks(&hook_results) {
+        display_hook_results(&hook_results);
+        return Err(e);
+    }
 
-    // Add metadata
+    let mut transaction = begin_transaction(options.config)?;
     if let Some(desc) = options.description {
         transaction
             .metadata
@@ -181,12 +337,8 @@ pub fn apply_config(options: ApplyOptions<'_>) -> Result<()> {
             .metadata
             .insert("profile".to_string(), prof.to_string());
     }
-    transaction
-        .metadata
-        .insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339());
 
     // Add file operations
-    let symlink_resolution = options.config.general.symlink_resolution;
     let home = dirs::home_dir()
         .ok_or_else(|| DotfilesError::Config("Could not find home directory".to_string()))?;
 
@@ -195,39 +347,284 @@ pub fn apply_config(options: ApplyOptions<'_>) -> Result<()> {
     let transaction_backup_dir =
         backup_dir.join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string());
 
-    for file in &diff.files_to_sync {
-        add_file_operation_to_transaction(
+    // Kept local versions replace the repo files before the destinations are linked
+    if let Some(policy) = options.conflict_policy {
+        add_adoptions_to_transaction(
+            options.config,
             &mut transaction,
-            file,
-            options.force,
-            &symlink_resolution,
+            &adopted,
+            policy,
             &home,
             &transaction_backup_dir,
-        );
+        )?;
+    }
+
+    // Unfold directory symlinks and prune stale links before linking files into them
+    for path in diff.dirs_to_unfold.iter().chain(&diff.stale_links) {
+        transaction.add_operation(FileOperation::RemoveSymlink {
+            target: path.clone(),
+            original: None,
+        });
+    }
+
+    for (index, file) in diff.files_to_sync.iter().enumerate() {
+        if file.is_rendered() {
+            // Render into the transaction temp dir and place it with the Replace copy path
+            let rendered = transaction
+                .temp_dir
+                .join("rendered")
+                .join(index.to_string());
+            render_to(file, &templates, &rendered)?;
+            let rendered_file = TrackedFile {
+                repo_path: rendered,
+                resolution: SymlinkResolution::Replace,
+                ..file.clone()
+            };
+            add_file_operation_to_transaction(
+                &mut transaction,
+                &rendered_file,
+                options.force,
+                &home,
+                &transaction_backup_dir,
+            );
+        } else {
+            if file.is_sqlite() {
+                // A WAL left from the replaced database would be replayed into the snapshot
+                for side_file in SIDE_FILES
+                    .iter()
+                    .map(|suffix| side_file(&file.dest_path, suffix))
+                    .filter(|path| path.exists())
+                {
+                    transaction.add_operation(FileOperation::RemoveSymlink {
+                        target: side_file,
+                        original: None,
+                    });
+                }
+            }
+            add_file_operation_to_transaction(
+                &mut transaction,
+                file,
+                options.force,
+                &home,
+                &transaction_backup_dir,
+            );
+        }
     }
 
     // Execute transaction
     let mut dry_run_tracker = DryRun::default();
     let mut fs_manager = FileSystemManager::new(&mut dry_run_tracker, false);
+    let profile = options
+        .profile
+        .unwrap_or(&options.config.general.current_profile);
+    let generation = run_transaction(options.config, &mut transaction, profile, &mut fs_manager)?;
+    for file in diff.files_to_sync.iter().chain(&adopted) {
+        if file.is_rendered() {
+            templates.record_render(file)?;
+        }
+    }
 
-    // Validate
-    transaction.validate(options.config)?;
+    println!("\n{} Configuration applied successfully", "✓".green());
+    println!("  Transaction ID: {}", transaction.id);
+    println!("  Generation: {}", generation.number);
 
-    // Prepare
-    transaction.prepare(options.config)?;
+    hook_results.extend(run_hooks(
+        options.config,
+        options.profile,
+        &post_hooks,
+        false,
+    )?);
+    display_hook_results(&hook_results);
 
-    // Commit
-    transaction.commit(options.config, &mut fs_manager)?;
+    Ok(())
+}
 
-    // Verify
-    transaction.verify()?;
+/// Start a transaction, staging under the state dir so removed files survive a crash or
+/// reboot.
+pub fn begin_transaction(config: &Config) -> Result<Transaction> {
+    let temp_dir = config
+        .get_state_dir()?
+        .join("tmp")
+        .join(uuid::Uuid::new_v4().to_string());
+    let mut transaction = Transaction::begin(temp_dir)?;
+    transaction
+        .metadata
+        .insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339());
+    Ok(transaction)
+}
+
+/// Validate, journal and commit a transaction, and record it as a generation of `profile`.
+pub fn run_transaction(
+    config: &Config,
+    transaction: &mut Transaction,
+    profile: &str,
+    fs_manager: &mut FileSystemManager,
+) -> Result<Generation> {
+    transaction.validate(config)?;
+    transaction.prepare(config)?;
+
+    // Capture prior state of every target so the transaction can be rolled back later
+    let generations = GenerationStore::open(config)?;
+    let pending = generations.stage(transaction)?;
+
+    // Journal every step so an interrupted transaction can be recovered
+    if let Err(e) = transaction.enable_journal(&journal_path(config)?) {
+        pending.discard()?;
+        return Err(e);
+    }
+
+    if let Err(e) = transaction.commit(config, fs_manager) {
+        pending.discard()?;
+        transaction.cleanup()?;
+        return Err(e);
+    }
 
-    // Cleanup
+    let generation = pending.commit(
+        &generations,
+        transaction,
+        profile,
+        get_head_commit_id(&config.get_repo_path()?),
+    )?;
+    transaction.verify()?;
     transaction.cleanup()?;
+    Ok(generation)
+}
 
-    println!("\n{} Configuration applied successfully", "✓".green());
-    println!("  Transaction ID: {}", transaction.id);
+/// Leave out databases whose owning application is running, since it would keep using
+/// (and later overwrite) the database it has open.
+fn skip_running_databases(diff: &mut StateDiff) {
+    diff.files_to_sync.retain(|file| {
+        let Some(reason) = file.is_sqlite().then(|| owner_running(file)).flatten() else {
+            return true;
+        };
+        println!(
+            "  {} Not restoring {} ({})",
+            "⚠".yellow(),
+            file.dest_path.display(),
+            reason
+        );
+        false
+    });
+}
 
+/// Apply a conflict policy to files whose destination differs from the repo version.
+///
+/// Skipped files are removed from `diff`; returns the files whose local version is kept.
+fn resolve_conflicts(
+    diff: &mut StateDiff,
+    policy: ConflictPolicy,
+    templates: &TemplateContext,
+) -> Result<Vec<TrackedFile>> {
+    let mut conflicts = Vec::new();
+    for file in &diff.files_to_sync {
+        if is_conflict(file, templates)? {
+            conflicts.push(file.clone());
+        }
+    }
+
+    // Rendered output cannot be turned back into a template, so the local file stays.
+    // Secrets are encrypted into the repo, their destination already being up to date.
+    if matches!(policy, ConflictPolicy::KeepLocal | ConflictPolicy::Adopt) {
+        let kept: Vec<TrackedFile> = conflicts.extract_if(.., |file| file.template).collect();
+        diff.files_to_sync.retain(|file| {
+            !kept
+                .iter()
+                .chain(conflicts.iter().filter(|conflict| conflict.encrypted))
+                .any(|kept| kept.dest_path == file.dest_path)
+        });
+        for file in &kept {
+            println!(
+                "  {} Keeping local version of {} (the repo file is not updated from it)",
+                "↻".yellow(),
+                file.dest_path.display()
+            );
+        }
+    }
+    if conflicts.is_empty() {
+        return Ok(conflicts);
+    }
+
+    match policy {
+        ConflictPolicy::Fail => Err(conflict_error(&conflicts)),
+        ConflictPolicy::KeepRepo => Ok(Vec::new()),
+        ConflictPolicy::Skip => {
+            diff.files_to_sync.retain(|file| {
+                !conflicts
+                    .iter()
+                    .any(|conflict| conflict.dest_path == file.dest_path)
+            });
+            for file in &conflicts {
+                println!(
+                    "  {} Skipping conflicting file {}",
+                    "⊘".yellow(),
+                    file.dest_path.display()
+                );
+            }
+            Ok(Vec::new())
+        }
+        ConflictPolicy::KeepLocal => {
+            for file in &conflicts {
+                println!(
+                    "  {} Keeping local version of {} (repo version backed up)",
+                    "↻".yellow(),
+                    file.dest_path.display()
+                );
+            }
+            Ok(conflicts)
+        }
+        ConflictPolicy::Adopt => {
+            for file in &conflicts {
+                println!(
+                    "  {} Adopting local version of {} into the repo",
+                    "↻".yellow(),
+                    file.dest_path.display()
+                );
+            }
+            Ok(conflicts)
+        }
+    }
+}
+
+/// Stage the local versions of `adopted` files in the transaction temp dir (secrets
+/// encrypted) and add operations that copy them over the repo files. `keep-local` backs
+/// the repo files up first, `adopt` leaves their old versions to git history (as
+/// `adopt_local_file` does).
+///
+/// The repo is only changed when the transaction commits, and a failed commit restores it.
+pub fn add_adoptions_to_transaction(
+    config: &Config,
+    transaction: &mut Transaction,
+    adopted: &[TrackedFile],
+    policy: ConflictPolicy,
+    home: &Path,
+    backup_dir: &Path,
+) -> Result<()> {
+    let mut dry_run_tracker = DryRun::default();
+    let mut fs_manager = FileSystemManager::new(&mut dry_run_tracker, false);
+    for (index, file) in adopted.iter().enumerate() {
+        let staged = transaction.temp_dir.join("adopted").join(index.to_string());
+        fs_manager.create_dir_all(&transaction.temp_dir.join("adopted"))?;
+        store_local_version(file, config, &staged, &mut fs_manager)?;
+        if policy == ConflictPolicy::KeepLocal {
+            transaction.add_operation(FileOperation::BackupAndReplace {
+                source: staged,
+                target: file.repo_path.clone(),
+                backup_path: backup_dir
+                    .join(file.repo_path.strip_prefix(home).unwrap_or(&file.repo_path)),
+                resolution: SymlinkResolution::Replace,
+            });
+        } else {
+            transaction.add_operation(FileOperation::RemoveSymlink {
+                target: file.repo_path.clone(),
+                original: None,
+            });
+            transaction.add_operation(FileOperation::CreateSymlink {
+                source: staged,
+                target: file.repo_path.clone(),
+                resolution: SymlinkResolution::Replace,
+            });
+        }
+    }
     Ok(())
 }
 
@@ -236,25 +633,31 @@ fn add_file_operation_to_transaction(
     transaction: &mut Transaction,
     file: &TrackedFile,
     force: bool,
-    symlink_resolution: &crate::types::SymlinkResolution,
     home: &Path,
     backup_dir: &Path,
 ) {
-    if force {
+    // Under a folded directory the destination is the repo file itself, and the
+    // directory symlink is removed before this operation runs
+    let dest_exists = file.dest_path.exists() && file.folded_dir().is_none();
+    // A `link = "contents"` subdirectory folded into one link holds nothing but links,
+    // so it is removed (and kept for rollback) by the transaction instead of backed up
+    let refold = dest_exists && file.link_root.is_some() && file.dir.is_some();
+    if force || refold {
         // Force mode: no backups, just remove and create symlink
-        if file.dest_path.exists() {
+        if dest_exists {
             transaction.add_operation(FileOperation::RemoveSymlink {
                 target: file.dest_path.clone(),
+                original: None,
             });
         }
         transaction.add_operation(FileOperation::CreateSymlink {
             source: file.repo_path.clone(),
             target: file.dest_path.clone(),
-            resolution: *symlink_resolution,
+            resolution: file.resolution,
         });
     } else {
         // Normal mode: backup existing files
-        if file.dest_path.exists() {
+        if dest_exists {
             let backup_path = backup_dir.join(
                 file.dest_path
                     .strip_prefix(home)
@@ -265,13 +668,13 @@ fn add_file_operation_to_transaction(
                 source: file.repo_path.clone(),
                 target: file.dest_path.clone(),
                 backup_path: backup_path.clone(),
-                resolution: *symlink_resolution,
+                resolution: file.resolution,
             });
         } else {
             transaction.add_operation(FileOperation::CreateSymlink {
                 source: file.repo_path.clone(),
                 target: file.dest_path.clone(),
-                resolution: *symlink_resolution,
+                resolution: file.resolution,
             });
         }
     }
@@ -0,0 +1,90 @@
+use crate::config::Config;
+use crate::file_manager::{FileSystemManager, add_file};
+use crate::services::browser::{ARTIFACTS, Browser, find_profile, read_profiles};
+use crate::utils::error::{DotfilesError, Result};
+use colored::Colorize;
+
+/// Track the settings of a browser profile under a tool named after the browser.
+///
+/// Each artifact found in the profile is added like `flux add` would, with a symbolic
+/// destination (`{firefox.default}/prefs.js`) so it follows the profile directory on
+/// every machine. Returns the number of entries added.
+pub fn add_browser(
+    config: &mut Config,
+    browser: Browser,
+    browser_profile: Option<&str>,
+    profile: Option<&str>,
+    fs_manager: &mut FileSystemManager,
+) -> Result<usize> {
+    let home = dirs::home_dir().ok_or_else(crate::utils::error_utils::home_dir_not_found)?;
+    let root = browser.find_root(&home).ok_or_else(|| {
+        DotfilesError::Config(format!(
+            "What: No {} profiles found (no profiles.ini)\n  \
+             💡 Solution: Start {} once so it creates a profile, then run this again",
+            browser, browser
+        ))
+    })?;
+    let profiles = read_profiles(&root)?;
+    let selected = find_profile(&profiles, browser_profile).ok_or_else(|| {
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        DotfilesError::Config(format!(
+            "What: {} has no profile '{}' in {}\n  \
+             💡 Solution: Pick one of: {}",
+            browser,
+            browser_profile.unwrap_or("default"),
+            root.display(),
+            names.join(", ")
+        ))
+    })?;
+    println!(
+        "{} Found {} profile '{}' at {}",
+        "✓".green(),
+        browser,
+        selected.name,
+        selected.path.display()
+    );
+
+    let tool = browser.name();
+    let key = format!("{}.{}", tool, browser_profile.unwrap_or("default"));
+    let mut added = 0;
+    for artifact in ARTIFACTS {
+        let source = selected.path.join(artifact.path);
+        let dest = format!("{{{}}}/{}", key, artifact.path);
+        if !source.exists() {
+            println!("  {} {} (not present)", "⊘".yellow(), artifact.path);
+            continue;
+        }
+        let tracked = config
+            .tools
+            .get(tool)
+            .is_some_and(|tool_config| tool_config.files.iter().any(|file| file.dest == dest));
+        if tracked {
+            println!("  {} {} (already tracked)", "⊘".yellow(), dest);
+            continue;
+        }
+
+        let relative = source.strip_prefix(&home).unwrap_or(&source).to_path_buf();
+        add_file(config, tool, &source, &relative, profile, false, fs_manager)?;
+        if let Some(entry) = config
+            .tools
+            .get_mut(tool)
+            .and_then(|tool_config| tool_config.files.last_mut())
+        {
+            entry.dest = dest;
+            entry.resolution = artifact.resolution;
+        }
+        added += 1;
+    }
+
+    if added > 0 && !fs_manager.is_dry_run {
+        config.save(false)?;
+        println!(
+            "{} Registered {} {} entries under {{{}}}; run 'flux commit' to link them",
+            "✓".green(),
+            added,
+            tool,
+            key
+        );
+    }
+    Ok(added)
+}
@@ -0,0 +1,231 @@
+use crate::config::Config;
+use crate::config::profile::list_profiles;
+use crate::services::git::clone_repo;
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::error_utils;
+use crate::utils::host;
+use colored::Colorize;
+use dialoguer::{Select, theme::ColorfulTheme};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options for `flux clone`
+#[derive(Debug, Clone)]
+pub struct CloneOptions {
+    /// URL (or local path) of the dotfiles repository
+    pub url: String,
+    /// Directory to clone into; must not exist or be empty
+    pub dest: PathBuf,
+    /// Branch to check out (default: the remote's default branch)
+    pub branch: Option<String>,
+    /// Profile to use (default: the profile named after this host, or the config's
+    /// current profile)
+    pub profile: Option<String>,
+    /// Timeout for the clone in seconds
+    pub timeout: u64,
+    /// XDG config path; only written when it does not exist yet
+    pub xdg_config: PathBuf,
+    /// Whether a profile may be chosen from a list
+    pub interactive: bool,
+}
+
+/// Where later commands find the configuration of a cloned repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigLocation {
+    /// The repository's config.toml, used as it is
+    Repo(PathBuf),
+    /// A new XDG config with this machine's repo_path and profile
+    Written(PathBuf),
+    /// An existing XDG config, left untouched, that takes precedence over the repository's
+    Shadowed(PathBuf),
+}
+
+/// A cloned repository and the configuration to apply from it.
+#[derive(Debug, Clone)]
+pub struct ClonedRepo {
+    /// The repository's config, with this machine's repo_path and profile
+    pub config: Config,
+    pub profile: String,
+    pub location: ConfigLocation,
+}
+
+/// Clone a dotfiles repository and load its `config.toml` for this machine.
+///
+/// The default config is never created along the way, and an existing XDG config is
+/// never overwritten.
+pub fn clone_dotfiles(options: &CloneOptions) -> Result<ClonedRepo> {
+    check_dest(&options.dest)?;
+    clone_repo(
+        &options.url,
+        &options.dest,
+        options.branch.as_deref(),
+        options.timeout,
+    )
+    .map_err(|e| {
+        DotfilesError::Config(format!(
+            "What: Cannot clone {}: {}\n  \
+             💡 Solution: Check the URL and your credentials (SSH agent, or GIT_USERNAME and \
+             GIT_PASSWORD for HTTPS)",
+            options.url, e
+        ))
+    })?;
+    println!(
+        "{} Cloned {} into {}",
+        "✓".green(),
+        options.url,
+        options.dest.display()
+    );
+
+    let repo_config = options.dest.join("config.toml");
+    if !repo_config.exists() {
+        return Err(DotfilesError::Config(format!(
+            "What: The cloned repository has no config.toml: {}\n  \
+             💡 Solution: Commit your flux config as config.toml at the repository root, or \
+             register files with 'flux add' (the clone is kept)",
+            repo_config.display()
+        )));
+    }
+    let mut config = Config::load_file(&repo_config)?;
+    let file_config = config.clone();
+
+    config.general.repo_path = options.dest.display().to_string();
+    let profile = select_profile(&config, options.profile.as_deref(), options.interactive)?;
+    config.general.current_profile = profile.clone();
+    println!("{} Using profile '{}'", "✓".green(), profile);
+
+    let location = save_machine_config(&file_config, &config, &repo_config, &options.xdg_config)?;
+    match &location {
+        ConfigLocation::Repo(path) => {
+            println!("{} Using {}", "✓".green(), path.display());
+        }
+        ConfigLocation::Written(path) => {
+            println!(
+                "{} Wrote {} (repo_path and profile of this machine)",
+                "✓".green(),
+                path.display()
+            );
+        }
+        ConfigLocation::Shadowed(path) => {
+            println!(
+                "{} {} already exists and takes precedence over the cloned config.toml; \
+                 move it aside to use the repository's config",
+                "⚠".yellow(),
+                path.display()
+            );
+        }
+    }
+
+    Ok(ClonedRepo {
+        config,
+        profile,
+        location,
+    })
+}
+
+/// The profile to use: `explicit`, else the profile named after this host, else one
+/// chosen from a list, else the config's current profile.
+pub fn select_profile(
+    config: &Config,
+    explicit: Option<&str>,
+    interactive: bool,
+) -> Result<String> {
+    let profiles = list_profiles(config)?;
+    if let Some(name) = explicit {
+        if !profiles.iter().any(|profile| profile == name) {
+            return Err(error_utils::profile_not_found(name, &profiles));
+        }
+        return Ok(name.to_string());
+    }
+
+    let hostname = host::hostname();
+    let short_hostname = hostname.split('.').next().unwrap_or_default();
+    if let Some(profile) = profiles.iter().find(|profile| *profile == short_hostname) {
+        return Ok(profile.clone());
+    }
+
+    if interactive && profiles.len() > 1 {
+        let current = profiles
+            .iter()
+            .position(|profile| *profile == config.general.current_profile)
+            .unwrap_or(0);
+        let selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Which profile should this machine use?")
+            .items(&profiles)
+            .default(current)
+            .interact()
+            .map_err(|e| DotfilesError::Io(std::io::Error::other(e)))?;
+        return Ok(profiles[selection].clone());
+    }
+
+    Ok(config.general.current_profile.clone())
+}
+
+/// A destination that already holds files would mix them into the repository.
+fn check_dest(dest: &Path) -> Result<()> {
+    let occupied = if dest.is_dir() {
+        fs::read_dir(dest)?.next().is_some()
+    } else {
+        dest.exists() || dest.is_symlink()
+    };
+    if occupied {
+        return Err(DotfilesError::Config(format!(
+            "What: {} already exists and is not empty\n  \
+             💡 Solution: Clone into another directory with --path, or move it aside",
+            dest.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Record where the repository lives and which profile this machine uses.
+///
+/// Without an XDG config, flux reads `~/.dotfiles/config.toml`, so the repository's
+/// config is used as it is when it is there and its settings match. Otherwise the
+/// settings go into a new XDG config based on the repository's.
+fn save_machine_config(
+    file_config: &Config,
+    config: &Config,
+    repo_config: &Path,
+    xdg_config: &Path,
+) -> Result<ConfigLocation> {
+    if xdg_config.exists() {
+        return Ok(ConfigLocation::Shadowed(xdg_config.to_path_buf()));
+    }
+
+    let repo_path = config.get_repo_path()?;
+    if repo_config == Config::get_repo_config_path()?
+        && file_config.get_repo_path()? == repo_path
+        && file_config.general.current_profile == config.general.current_profile
+    {
+        return Ok(ConfigLocation::Repo(repo_config.to_path_buf()));
+    }
+
+    // Edit the repository's config text, so its comments are kept
+    let mut doc = fs::read_to_string(repo_config)?
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|e| DotfilesError::Config(format!("Failed to parse config: {}", e)))?;
+    let general = doc["general"].or_insert(toml_edit::table());
+    general["repo_path"] = toml_edit::value(config.general.repo_path.as_str());
+    general["current_profile"] = toml_edit::value(config.general.current_profile.as_str());
+
+    // Includes are relative to the file that lists them, which is now a different one
+    if let Some(includes) = general
+        .get_mut("include")
+        .and_then(|item| item.as_array_mut())
+    {
+        for include in includes.iter_mut() {
+            if let Some(path) = include.as_str()
+                && !path.starts_with('~')
+                && Path::new(path).is_relative()
+            {
+                *include = repo_path.join(path).display().to_string().into();
+            }
+        }
+    }
+
+    if let Some(parent) = xdg_config.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(xdg_config, doc.to_string())?;
+    Ok(ConfigLocation::Written(xdg_config.to_path_buf()))
+}
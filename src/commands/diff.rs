@@ -0,0 +1,256 @@
+use crate::commands::restore::{list_backups, select_backup};
+use crate::config::Config;
+use crate::services::git::worktree_changes;
+use crate::services::template::TemplateContext;
+use crate::types::TrackedFile;
+use crate::utils::diff::{FileDiff, diff_contents, print_diffs, print_stat};
+use crate::utils::error::{DotfilesError, Result};
+use colored::Colorize;
+use git2::Repository;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What `flux diff` compares the repository against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    /// Destinations that are copies or not linked to the repository
+    Dest,
+    /// The last commit, for uncommitted changes in the dotfiles repository
+    Head,
+    /// A backup: `latest` or an index from `flux backup restore list`
+    Backup(String),
+}
+
+/// Collect the diffs of every tracked file matching `target` (a tool name or a path).
+pub fn collect_diffs(
+    config: &Config,
+    profile: Option<&str>,
+    target: Option<&str>,
+    source: &DiffSource,
+) -> Result<Vec<FileDiff>> {
+    let target = target.map(DiffTarget::new);
+    match source {
+        DiffSource::Dest => diff_destinations(config, profile, target.as_ref()),
+        DiffSource::Head => diff_head(config, target.as_ref()),
+        DiffSource::Backup(selector) => diff_backup(config, profile, target.as_ref(), selector),
+    }
+}
+
+/// Print the diffs, or a `--stat` summary of them.
+pub fn display_diffs(diffs: &[FileDiff], stat: bool) {
+    if diffs.is_empty() {
+        println!("{} No differences", "✓".green());
+    } else if stat {
+        print_stat(diffs);
+    } else {
+        print_diffs(diffs);
+    }
+}
+
+/// A tool name or a path given on the command line.
+struct DiffTarget {
+    name: String,
+    path: PathBuf,
+}
+
+impl DiffTarget {
+    fn new(target: &str) -> Self {
+        let path = PathBuf::from(shellexpand::tilde(target).as_ref());
+        let path = if path.is_absolute() {
+            path
+        } else {
+            std::env::current_dir()
+                .map(|cwd| cwd.join(&path))
+                .unwrap_or(path)
+        };
+        Self {
+            name: target.to_string(),
+            path,
+        }
+    }
+
+    /// Whether the target names `tool` or contains (or is inside) one of `paths`.
+    fn matches(&self, tool: &str, paths: &[&Path]) -> bool {
+        self.name == tool
+            || paths
+                .iter()
+                .any(|path| path.starts_with(&self.path) || self.path.starts_with(path))
+    }
+}
+
+/// Repo files against their destinations. Correctly linked files are identical by
+/// definition and are skipped; templates and secrets are compared as rendered.
+fn diff_destinations(
+    config: &Config,
+    profile: Option<&str>,
+    target: Option<&DiffTarget>,
+) -> Result<Vec<FileDiff>> {
+    let repo_root = config.get_repo_path()?;
+    let templates = TemplateContext::new(config, profile);
+
+    let mut diffs = Vec::new();
+    for file in config.get_tracked_files(profile)? {
+        let (file, _) = file.followed()?;
+        if !file.repo_path.exists() || file.is_linked() {
+            continue;
+        }
+
+        for (repo_file, dest_file) in file_pairs(&file, &file.dest_path)? {
+            if target.is_some_and(|t| !t.matches(&file.tool, &[&repo_file, &dest_file])) {
+                continue;
+            }
+            let old = if file.is_rendered() {
+                Some(templates.render_file(&file)?)
+            } else {
+                fs::read(&repo_file).ok()
+            };
+            let new = if dest_file.is_file() {
+                Some(fs::read(&dest_file)?)
+            } else {
+                None
+            };
+            diffs.extend(diff_contents(
+                &display_home(&dest_file),
+                &repo_label(&repo_root, &repo_file),
+                &display_home(&dest_file),
+                old.as_deref(),
+                new.as_deref(),
+            ));
+        }
+    }
+    Ok(diffs)
+}
+
+/// Uncommitted changes in the dotfiles repository.
+fn diff_head(config: &Config, target: Option<&DiffTarget>) -> Result<Vec<FileDiff>> {
+    let repo_root = config.get_repo_path()?;
+    let repo = Repository::open(&repo_root).map_err(|e| {
+        DotfilesError::Config(format!(
+            "What: Cannot open the dotfiles repository at {}: {}\n  \
+             💡 Solution: Run 'flux init' to create it",
+            repo_root.display(),
+            e
+        ))
+    })?;
+
+    let mut diffs = Vec::new();
+    for change in worktree_changes(&repo)? {
+        let tool = change
+            .path
+            .components()
+            .next()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let full_path = repo_root.join(&change.path);
+        if target.is_some_and(|t| !t.matches(&tool, &[&full_path])) {
+            continue;
+        }
+
+        let path = change.path.display().to_string();
+        diffs.extend(diff_contents(
+            &path,
+            &format!("a/{}", path),
+            &format!("b/{}", path),
+            change.head.as_deref(),
+            change.worktree.as_deref(),
+        ));
+    }
+    Ok(diffs)
+}
+
+/// Repo files against the copies saved in a backup.
+fn diff_backup(
+    config: &Config,
+    profile: Option<&str>,
+    target: Option<&DiffTarget>,
+    selector: &str,
+) -> Result<Vec<FileDiff>> {
+    let backups = list_backups(config)?;
+    let backup = select_backup(&backups, selector)?;
+    let home = dirs::home_dir()
+        .ok_or_else(|| DotfilesError::Config("Could not find home directory".to_string()))?;
+    let repo_root = config.get_repo_path()?;
+    let templates = TemplateContext::new(config, profile);
+    let backup_name = backup
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut diffs = Vec::new();
+    for file in config.get_tracked_files(profile)? {
+        let Ok(relative) = file.dest_path.strip_prefix(&home) else {
+            continue;
+        };
+        let backup_path = backup.path.join(relative);
+        if !backup_path.exists() {
+            continue;
+        }
+
+        for (repo_file, backup_file) in file_pairs(&file, &backup_path)? {
+            let dest_file = home.join(
+                backup_file
+                    .strip_prefix(&backup.path)
+                    .unwrap_or(&backup_file),
+            );
+            if target.is_some_and(|t| !t.matches(&file.tool, &[&repo_file, &dest_file])) {
+                continue;
+            }
+            let old = if file.is_rendered() && repo_file.exists() {
+                Some(templates.render_file(&file)?)
+            } else {
+                fs::read(&repo_file).ok()
+            };
+            let new = fs::read(&backup_file).ok();
+            let backup_relative = backup_file
+                .strip_prefix(&backup.path)
+                .unwrap_or(&backup_file);
+            diffs.extend(diff_contents(
+                &display_home(&dest_file),
+                &repo_label(&repo_root, &repo_file),
+                &format!("backup/{}/{}", backup_name, backup_relative.display()),
+                old.as_deref(),
+                new.as_deref(),
+            ));
+        }
+    }
+    Ok(diffs)
+}
+
+/// Pairs of repo file and `other` file to compare. Directories are paired file by file
+/// (including files present on one side only), using the entry's globs.
+fn file_pairs(file: &TrackedFile, other: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    if !file.repo_path.is_dir() {
+        return Ok(vec![(file.repo_path.clone(), other.to_path_buf())]);
+    }
+
+    let filter = file.dir_filter()?;
+    let mut relatives: BTreeSet<PathBuf> = filter.entries(&file.repo_path)?.into_keys().collect();
+    if !other.is_symlink() {
+        relatives.extend(filter.entries(other)?.into_keys());
+    }
+    Ok(relatives
+        .into_iter()
+        .map(|relative| (file.repo_path.join(&relative), other.join(relative)))
+        .collect())
+}
+
+/// Repo file path as shown on the old side of a diff.
+fn repo_label(repo_root: &Path, repo_file: &Path) -> String {
+    format!(
+        "repo/{}",
+        repo_file
+            .strip_prefix(repo_root)
+            .unwrap_or(repo_file)
+            .display()
+    )
+}
+
+/// Path with the home directory shortened to `~`.
+fn display_home(path: &Path) -> String {
+    match dirs::home_dir().and_then(|home| path.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => path.display().to_string(),
+    }
+}
@@ -0,0 +1,166 @@
+use crate::config::Config;
+use crate::file_manager::{FileSystemManager, add_file};
+use crate::services::browser;
+use crate::services::catalog::Catalog;
+use crate::utils::error::{DotfilesError, Result};
+use colored::Colorize;
+use dialoguer::{MultiSelect, theme::ColorfulTheme};
+use std::path::{Path, PathBuf};
+
+/// Options for `flux discover`
+#[derive(Debug, Clone)]
+pub struct DiscoverOptions {
+    /// Register every app found without asking
+    pub all: bool,
+    /// Profile name (optional)
+    pub profile: Option<String>,
+    /// Whether apps may be chosen from a list
+    pub interactive: bool,
+}
+
+/// A catalog app with configuration on this machine that flux does not track yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredApp {
+    /// Tool name the app is registered under
+    pub name: String,
+    pub description: String,
+    /// Existing config paths that no entry covers
+    pub paths: Vec<PathBuf>,
+    /// Globs for the directories among `paths`
+    pub ignore: Vec<String>,
+}
+
+/// Catalog apps with config paths under `home` that no entry tracks, neither directly
+/// nor through a tracked directory.
+pub fn find_untracked_apps(config: &Config, catalog: &Catalog, home: &Path) -> Vec<DiscoveredApp> {
+    let tracked: Vec<PathBuf> = config
+        .tools
+        .values()
+        .flat_map(|tool_config| &tool_config.files)
+        .filter_map(|entry| browser::resolve_dest(home, &entry.dest).ok())
+        .collect();
+
+    catalog
+        .apps
+        .iter()
+        .filter_map(|(name, app)| {
+            let paths: Vec<PathBuf> = app
+                .existing_paths(home)
+                .into_iter()
+                .filter(|path| {
+                    !tracked
+                        .iter()
+                        .any(|dest| path.starts_with(dest) || dest.starts_with(path))
+                })
+                .collect();
+            (!paths.is_empty()).then(|| DiscoveredApp {
+                name: name.clone(),
+                description: app.description.clone(),
+                paths,
+                ignore: app.ignore.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Find well-known apps whose configuration is not tracked yet, and register the ones
+/// the user selects (or all of them with `--all`) like `flux add` would.
+///
+/// Returns the number of entries added.
+pub fn discover(
+    config: &mut Config,
+    options: &DiscoverOptions,
+    fs_manager: &mut FileSystemManager,
+) -> Result<usize> {
+    let home = dirs::home_dir().ok_or_else(crate::utils::error_utils::home_dir_not_found)?;
+    let catalog = Catalog::load()?;
+    let found = find_untracked_apps(config, &catalog, &home);
+    if found.is_empty() {
+        println!(
+            "{} No untracked configuration of known apps found",
+            "✓".green()
+        );
+        return Ok(0);
+    }
+
+    println!(
+        "{} Found untracked configuration of {} apps:",
+        "→".cyan(),
+        found.len()
+    );
+    for app in &found {
+        println!("  {} {}", app.name.bold(), app.description.dimmed());
+        for path in &app.paths {
+            println!("    {} {}", "↳".cyan(), display_relative(path, &home));
+        }
+    }
+
+    let selected: Vec<&DiscoveredApp> = if options.all {
+        found.iter().collect()
+    } else if options.interactive {
+        let items: Vec<String> = found
+            .iter()
+            .map(|app| format!("{} - {}", app.name, app.description))
+            .collect();
+        let selection = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt("Select apps to track (space to toggle, enter to confirm)")
+            .items(&items)
+            .interact()
+            .map_err(|e| DotfilesError::Io(std::io::Error::other(e)))?;
+        selection.into_iter().map(|index| &found[index]).collect()
+    } else {
+        println!(
+            "{} Not registering anything without a terminal; run 'flux discover --all' to track them all",
+            "⊘".yellow()
+        );
+        return Ok(0);
+    };
+    if selected.is_empty() {
+        println!("{} No apps selected", "⊘".yellow());
+        return Ok(0);
+    }
+
+    let mut added = 0;
+    for app in &selected {
+        for path in &app.paths {
+            let relative = path.strip_prefix(&home).unwrap_or(path).to_path_buf();
+            add_file(
+                config,
+                &app.name,
+                path,
+                &relative,
+                options.profile.as_deref(),
+                false,
+                fs_manager,
+            )?;
+            if path.is_dir()
+                && let Some(entry) = config
+                    .tools
+                    .get_mut(&app.name)
+                    .and_then(|tool_config| tool_config.files.last_mut())
+            {
+                entry.ignore = app.ignore.clone();
+            }
+            added += 1;
+        }
+    }
+
+    if !fs_manager.is_dry_run {
+        config.save(false)?;
+        println!(
+            "{} Registered {} entries for {} apps; run 'flux commit' to link them",
+            "✓".green(),
+            added,
+            selected.len()
+        );
+    }
+    Ok(added)
+}
+
+/// `path` relative to the home directory, written with `~/`.
+fn display_relative(path: &Path, home: &Path) -> String {
+    match path.strip_prefix(home) {
+        Ok(relative) => format!("~/{}", relative.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
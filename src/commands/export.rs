@@ -0,0 +1,428 @@
+use crate::config::Config;
+use crate::services::template::TemplateContext;
+use crate::types::{SymlinkResolution, TrackedFile};
+use crate::utils::error::{DotfilesError, Result};
+use colored::Colorize;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Directory under the home directory that exported files are installed into.
+pub const INSTALL_DIR: &str = ".dotfiles";
+
+/// Top-level directory inside an export tarball.
+const BUNDLE_ROOT: &str = "flux-export";
+
+/// Line of a self-extracting installer after which the tarball starts.
+const ARCHIVE_MARKER: &str = "__ARCHIVE_BELOW__";
+
+/// Output format of `flux export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// Gzipped tarball with the files and a manifest
+    Tar,
+    /// Self-extracting POSIX shell installer
+    Sh,
+}
+
+/// Options for `flux export`
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Profile name (default: current profile)
+    pub profile: Option<String>,
+    pub format: ExportFormat,
+    /// Output file (default: flux-<profile>.tar.gz or flux-<profile>.sh)
+    pub output: Option<PathBuf>,
+}
+
+/// How an exported file is placed at its destination, following its
+/// [`SymlinkResolution`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    /// Copied (`replace`, templates and databases)
+    Copy,
+    /// Relative symlink into the install directory (`auto`, `relative` and `follow`)
+    Relative,
+    /// Absolute symlink into the install directory (`absolute`)
+    Absolute,
+}
+
+/// One file or directory of an export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub tool: String,
+    /// Path under `files/` in the bundle, and under the install directory once installed
+    pub path: String,
+    /// Destination relative to the home directory
+    pub dest: String,
+    pub placement: Placement,
+    /// Symlink target of a relative placement
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Whether the entry is a directory
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dir: bool,
+}
+
+/// Description of an export, stored as `manifest.toml` in the bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub profile: String,
+    /// Time of the export (RFC 3339)
+    pub created: String,
+    /// Install directory, relative to the home directory
+    pub install_dir: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Content of an exported entry.
+#[derive(Debug, Clone)]
+pub enum ExportContent {
+    /// A repository file, copied as it is
+    File(PathBuf),
+    /// Files of a repository directory that the entry tracks, by relative path
+    Dir(BTreeMap<PathBuf, PathBuf>),
+    /// A rendered template
+    Rendered(Vec<u8>),
+}
+
+/// The files of a profile, ready to be written as a bundle.
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub manifest: Manifest,
+    /// Content of each manifest entry, in the same order
+    pub contents: Vec<ExportContent>,
+    /// Tracked files left out, and why
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+/// Collect the tracked files of `profile` with the placement their resolution asks for.
+///
+/// Templates are rendered for the profile on this machine. Encrypted files are left out
+/// so the bundle never holds secrets in plaintext.
+pub fn collect_export(config: &Config, profile: Option<&str>) -> Result<Export> {
+    let home = dirs::home_dir().ok_or_else(crate::utils::error_utils::home_dir_not_found)?;
+    let repo_root = config.get_repo_path()?;
+    let templates = TemplateContext::new(config, profile);
+
+    let mut export = Export {
+        manifest: Manifest {
+            profile: profile
+                .unwrap_or(&config.general.current_profile)
+                .to_string(),
+            created: chrono::Utc::now().to_rfc3339(),
+            install_dir: INSTALL_DIR.to_string(),
+            files: Vec::new(),
+        },
+        contents: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for file in config.get_tracked_files(profile)? {
+        let Ok(dest) = file.dest_path.strip_prefix(&home) else {
+            export.skipped.push((
+                file.dest_path.clone(),
+                "outside the home directory".to_string(),
+            ));
+            continue;
+        };
+        if file.encrypted {
+            export.skipped.push((
+                file.dest_path.clone(),
+                "encrypted; the bundle would hold it in plaintext".to_string(),
+            ));
+            continue;
+        }
+        if !file.repo_path.exists() {
+            export.skipped.push((
+                file.dest_path.clone(),
+                "missing from the repository".to_string(),
+            ));
+            continue;
+        }
+
+        let path = file
+            .repo_path
+            .strip_prefix(&repo_root)
+            .unwrap_or(&file.repo_path)
+            .to_path_buf();
+        let placement = placement(&file);
+        let content = if file.template {
+            ExportContent::Rendered(templates.render_file(&file)?)
+        } else if file.repo_path.is_dir() {
+            ExportContent::Dir(file.dir_filter()?.entries(&file.repo_path)?)
+        } else {
+            ExportContent::File(file.repo_path.clone())
+        };
+
+        export.manifest.files.push(ManifestEntry {
+            tool: file.tool.clone(),
+            path: path.display().to_string(),
+            dest: dest.display().to_string(),
+            placement,
+            link: (placement == Placement::Relative).then(|| relative_link(&path, dest)),
+            dir: matches!(content, ExportContent::Dir(_)),
+        });
+        export.contents.push(content);
+    }
+    Ok(export)
+}
+
+fn placement(file: &TrackedFile) -> Placement {
+    if file.is_rendered() || file.is_copy() {
+        return Placement::Copy;
+    }
+    match file.resolution {
+        SymlinkResolution::Absolute => Placement::Absolute,
+        // There is no symlink chain to follow on a fresh machine
+        SymlinkResolution::Auto | SymlinkResolution::Relative | SymlinkResolution::Follow => {
+            Placement::Relative
+        }
+        SymlinkResolution::Replace => Placement::Copy,
+    }
+}
+
+/// Symlink target from `dest` to `path` in the install directory, both relative to the
+/// home directory.
+fn relative_link(path: &Path, dest: &Path) -> String {
+    let home = Path::new("/home");
+    let dest_dir = dest
+        .parent()
+        .map_or(home.to_path_buf(), |dir| home.join(dir));
+    let target = home.join(INSTALL_DIR).join(path);
+    pathdiff::diff_paths(&target, &dest_dir)
+        .unwrap_or(target)
+        .display()
+        .to_string()
+}
+
+/// Write the export as a gzipped tarball: `flux-export/manifest.toml` and the files
+/// under `flux-export/files/`.
+pub fn write_tarball<W: Write>(export: &Export, writer: W) -> Result<W> {
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    let manifest = toml::to_string_pretty(&export.manifest)?;
+    append_bytes(
+        &mut builder,
+        &format!("{}/manifest.toml", BUNDLE_ROOT),
+        manifest.as_bytes(),
+    )?;
+
+    for (entry, content) in export.manifest.files.iter().zip(&export.contents) {
+        let name = Path::new(BUNDLE_ROOT).join("files").join(&entry.path);
+        match content {
+            ExportContent::File(source) => builder.append_path_with_name(source, &name)?,
+            ExportContent::Dir(files) => {
+                for (relative, source) in files {
+                    builder.append_path_with_name(source, name.join(relative))?;
+                }
+            }
+            ExportContent::Rendered(bytes) => {
+                append_bytes(&mut builder, &name.display().to_string(), bytes)?
+            }
+        }
+    }
+    Ok(builder.into_inner()?.finish()?)
+}
+
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, name: &str, bytes: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
+
+/// Self-extracting POSIX shell installer for `export`, with the tarball appended.
+///
+/// The installer unpacks the files into `~/.dotfiles`, moves anything in the way to
+/// `~/.dotfiles-backup/<timestamp>`, and symlinks or copies each entry. It needs only
+/// `sh`, `tar` and `gzip`; `--dry-run` shows what it would do.
+pub fn render_installer(export: &Export) -> Result<Vec<u8>> {
+    let manifest = &export.manifest;
+    let mut installs = String::new();
+    for entry in &manifest.files {
+        let line = match entry.placement {
+            Placement::Copy => format!(
+                "install_copy {} {}",
+                sh_quote(&entry.path),
+                sh_quote(&entry.dest)
+            ),
+            Placement::Relative => format!(
+                "install_link {} {}",
+                sh_quote(entry.link.as_deref().unwrap_or(&entry.path)),
+                sh_quote(&entry.dest)
+            ),
+            Placement::Absolute => format!(
+                "install_link \"$INSTALL_DIR\"/{} {}",
+                sh_quote(&entry.path),
+                sh_quote(&entry.dest)
+            ),
+        };
+        installs.push_str(&line);
+        installs.push('\n');
+    }
+
+    let script = format!(
+        r#"#!/bin/sh
+# Installer written by `flux export` for profile {profile} ({created}).
+#
+# Unpacks {count} entries into ~/{install_dir}, then symlinks or copies each one to its
+# destination. Anything in the way is moved to ~/.dotfiles-backup/<timestamp> first.
+#
+# Usage: sh <this file> [--dry-run]
+set -eu
+
+INSTALL_DIR="$HOME/"{install_dir_quoted}
+BACKUP_DIR="$HOME/.dotfiles-backup/$(date +%Y%m%d_%H%M%S)"
+DRY_RUN=0
+case "${{1:-}}" in
+    --dry-run) DRY_RUN=1 ;;
+    "") ;;
+    *) echo "Usage: sh $0 [--dry-run]" >&2; exit 2 ;;
+esac
+# Rendered templates would overwrite their sources in a dotfiles repository
+if [ -e "$INSTALL_DIR/.git" ]; then
+    echo "$INSTALL_DIR is a git repository; move it aside before installing" >&2
+    exit 1
+fi
+
+ARCHIVE_LINE=@ARCHIVE_LINE@
+TMP_DIR=$(mktemp -d)
+trap 'rm -rf "$TMP_DIR"' EXIT
+tail -n +"$ARCHIVE_LINE" "$0" | gzip -dc | tar -xf - -C "$TMP_DIR"
+FILES="$TMP_DIR/{bundle_root}/files"
+
+run() {{
+    if [ "$DRY_RUN" = 1 ]; then
+        echo "    would run: $*"
+    else
+        "$@"
+    fi
+}}
+
+# backup DEST: move ~/DEST aside, keeping its path under the backup directory
+backup() {{
+    echo "  backing up ~/$1 to $BACKUP_DIR"
+    run mkdir -p "$(dirname "$BACKUP_DIR/$1")"
+    run mv "$HOME/$1" "$BACKUP_DIR/$1"
+}}
+
+# install_copy PATH DEST: copy PATH from the install directory to ~/DEST
+install_copy() {{
+    if [ -e "$HOME/$2" ] || [ -L "$HOME/$2" ]; then
+        if [ ! -L "$HOME/$2" ] && diff -r "$FILES/$1" "$HOME/$2" >/dev/null 2>&1; then
+            echo "  ok ~/$2"
+            return 0
+        fi
+        backup "$2"
+    fi
+    echo "  copying ~/$2"
+    run mkdir -p "$(dirname "$HOME/$2")"
+    run cp -R "$INSTALL_DIR/$1" "$HOME/$2"
+}}
+
+# install_link TARGET DEST: symlink ~/DEST to TARGET
+install_link() {{
+    if [ -L "$HOME/$2" ] && [ "$(readlink "$HOME/$2")" = "$1" ]; then
+        echo "  ok ~/$2"
+        return 0
+    fi
+    if [ -e "$HOME/$2" ] || [ -L "$HOME/$2" ]; then
+        backup "$2"
+    fi
+    echo "  linking ~/$2 -> $1"
+    run mkdir -p "$(dirname "$HOME/$2")"
+    run ln -s "$1" "$HOME/$2"
+}}
+
+echo "Installing {count} entries of profile {profile} into $INSTALL_DIR"
+run mkdir -p "$INSTALL_DIR"
+run cp -R "$FILES/." "$INSTALL_DIR/"
+{installs}echo "Done"
+exit 0
+{marker}
+"#,
+        profile = sh_quote(&manifest.profile),
+        created = manifest.created,
+        count = manifest.files.len(),
+        install_dir = manifest.install_dir,
+        install_dir_quoted = sh_quote(&manifest.install_dir),
+        bundle_root = BUNDLE_ROOT,
+        installs = installs,
+        marker = ARCHIVE_MARKER,
+    );
+    // The tarball starts on the line after the marker
+    let archive_line = script.lines().count() + 1;
+    let script = script.replace("@ARCHIVE_LINE@", &archive_line.to_string());
+
+    write_tarball(export, script.into_bytes())
+}
+
+/// Quote `value` for a POSIX shell.
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Write the tracked files of a profile as a tarball or a self-extracting installer.
+///
+/// Returns the path written.
+pub fn export_profile(config: &Config, options: &ExportOptions) -> Result<PathBuf> {
+    let export = collect_export(config, options.profile.as_deref())?;
+    if export.manifest.files.is_empty() {
+        return Err(DotfilesError::Config(format!(
+            "What: Profile '{}' has no files to export\n  \
+             💡 Solution: Check the profile with 'flux ls-files --profile {}'",
+            export.manifest.profile, export.manifest.profile
+        )));
+    }
+    for (dest, reason) in &export.skipped {
+        println!(
+            "  {} Not exporting {} ({})",
+            "⊘".yellow(),
+            dest.display(),
+            reason
+        );
+    }
+
+    let output = options.output.clone().unwrap_or_else(|| {
+        PathBuf::from(match options.format {
+            ExportFormat::Tar => format!("flux-{}.tar.gz", export.manifest.profile),
+            ExportFormat::Sh => format!("flux-{}.sh", export.manifest.profile),
+        })
+    });
+    match options.format {
+        ExportFormat::Tar => {
+            write_tarball(&export, fs::File::create(&output)?)?;
+        }
+        ExportFormat::Sh => {
+            fs::write(&output, render_installer(&export)?)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&output, fs::Permissions::from_mode(0o755))?;
+            }
+        }
+    }
+
+    println!(
+        "{} Exported {} entries of profile '{}' to {}",
+        "✓".green(),
+        export.manifest.files.len(),
+        export.manifest.profile,
+        output.display()
+    );
+    if options.format == ExportFormat::Sh {
+        println!(
+            "  Install with: sh {} (add --dry-run to preview)",
+            output.display()
+        );
+    }
+    Ok(output)
+}
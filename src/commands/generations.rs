@@ -0,0 +1,350 @@
+use crate::commands::apply::{begin_transaction, run_transaction};
+use crate::config::Config;
+use crate::file_manager::FileSystemManager;
+use crate::services::generations::{
+    DEFAULT_KEEP_GENERATIONS, Generation, GenerationStore, PriorState, drifted_targets,
+};
+use crate::services::transactions::FileOperation;
+use crate::utils::dry_run::DryRun;
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::prompt::prompt_yes_no;
+use colored::Colorize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+pub fn display_generations(generations: &[Generation]) {
+    if generations.is_empty() {
+        println!("{}", "No generations recorded yet.".yellow());
+        return;
+    }
+
+    println!("\n{}", "Generations:".bold().cyan());
+    println!("{}", "=".repeat(60).cyan());
+
+    let latest = generations.last().map(|g| g.number);
+    for generation in generations {
+        let marker = if Some(generation.number) == latest {
+            "→"
+        } else {
+            " "
+        };
+        println!(
+            "{} {:>3}. {} [{}] - {} operation(s){}",
+            marker.green(),
+            generation.number,
+            format_timestamp(generation).green(),
+            generation.profile.cyan(),
+            generation.operations.len(),
+            generation
+                .description()
+                .map(|d| format!(" - {}", d))
+                .unwrap_or_default()
+        );
+    }
+
+    println!("{}", "=".repeat(60).cyan());
+}
+
+pub fn display_generation(generation: &Generation) {
+    println!(
+        "\n{} {}",
+        "Generation".bold().cyan(),
+        generation.number.to_string().bold().cyan()
+    );
+    println!("{}", "=".repeat(60).cyan());
+    println!("  Transaction ID: {}", generation.transaction_id);
+    println!("  Applied:        {}", format_timestamp(generation));
+    println!("  Profile:        {}", generation.profile);
+    println!(
+        "  Git HEAD:       {}",
+        generation.git_head.as_deref().unwrap_or("(none)")
+    );
+    if let Some(description) = generation.description() {
+        println!("  Description:    {}", description);
+    }
+
+    println!(
+        "\n  {} Operations ({}):",
+        "→".cyan(),
+        generation.operations.len()
+    );
+    for recorded in &generation.operations {
+        println!(
+            "    • {} (was {})",
+            describe_operation(&recorded.operation),
+            describe_prior_state(&recorded.prior)
+        );
+    }
+
+    println!("{}", "=".repeat(60).cyan());
+}
+
+/// Show what changed between two generations.
+///
+/// Lists every target touched by the generations after `from` up to and including `to`.
+pub fn display_generation_diff(store: &GenerationStore, from: u64, to: u64) -> Result<()> {
+    let (low, high) = if from <= to { (from, to) } else { (to, from) };
+    let newer = store.load(high)?;
+    let older = if low == 0 {
+        None
+    } else {
+        Some(store.load(low)?)
+    };
+
+    println!("\n{} Generation {} → {}", "→".cyan().bold(), from, to);
+    println!("{}", "=".repeat(60).cyan());
+
+    let old_profile = older
+        .as_ref()
+        .map(|g| g.profile.as_str())
+        .unwrap_or("(none)");
+    if old_profile != newer.profile {
+        println!("  Profile:  {} → {}", old_profile, newer.profile);
+    }
+    let old_head = older
+        .as_ref()
+        .and_then(|g| g.git_head.as_deref())
+        .unwrap_or("(none)");
+    let new_head = newer.git_head.as_deref().unwrap_or("(none)");
+    if old_head != new_head {
+        println!("  Git HEAD: {} → {}", old_head, new_head);
+    }
+
+    let mut targets = BTreeSet::new();
+    for generation in store.list()? {
+        if generation.number > low && generation.number <= high {
+            targets.extend(generation.targets().into_iter().map(PathBuf::from));
+        }
+    }
+
+    if targets.is_empty() {
+        println!("  {} No file changes", "✓".green());
+    } else {
+        println!("\n  {} Changed targets ({}):", "→".cyan(), targets.len());
+        for target in &targets {
+            println!("    • {}", target.display());
+        }
+    }
+
+    println!("{}", "=".repeat(60).cyan());
+    Ok(())
+}
+
+/// Roll back to a previous generation by undoing every newer generation.
+///
+/// Each target is restored to the state it had before the oldest undone generation
+/// touched it, in one transaction recorded as a new generation, so a rollback can be
+/// rolled back too. Rolling back to generation 0 restores the state before the first
+/// apply. Targets changed since they were applied are only overwritten with `force`,
+/// after being backed up.
+pub fn rollback_to_generation(
+    config: &Config,
+    target: u64,
+    yes: bool,
+    force: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let store = GenerationStore::open(config)?;
+    let latest = store.latest_number()?;
+
+    if target > latest {
+        return Err(DotfilesError::Config(format!(
+            "Generation {} does not exist (latest is {})",
+            target, latest
+        )));
+    }
+    if target == latest {
+        println!("{} Already at generation {}", "✓".green(), target);
+        return Ok(());
+    }
+    if let Some(oldest) = store.list()?.first().map(|g| g.number)
+        && target + 1 < oldest
+    {
+        return Err(DotfilesError::Config(format!(
+            "What: Generation {} was pruned (the oldest generation kept is {})\n  \
+             💡 Solution: Roll back to generation {} or later, and raise `keep_generations` to keep more history",
+            target,
+            oldest,
+            oldest - 1
+        )));
+    }
+
+    let to_undo: Vec<Generation> = store
+        .list()?
+        .into_iter()
+        .filter(|g| g.number > target)
+        .rev()
+        .collect();
+
+    println!(
+        "{} Rolling back to generation {} (undoing {} generation(s))",
+        "→".cyan().bold(),
+        target,
+        to_undo.len()
+    );
+    for generation in &to_undo {
+        println!(
+            "  • Generation {} - {} target(s)",
+            generation.number,
+            generation.targets().len()
+        );
+    }
+
+    let drifted = drifted_targets(&to_undo)?;
+    if !drifted.is_empty() {
+        println!(
+            "\n{} Changed since they were applied ({}):",
+            "⚠".yellow(),
+            drifted.len()
+        );
+        for path in &drifted {
+            println!("  • {}", path.display());
+        }
+        if !force && !dry_run {
+            return Err(DotfilesError::Config(format!(
+                "What: {} target(s) changed since they were applied and would be overwritten\n  \
+                 💡 Solution: Re-run with --force to back them up and roll back anyway",
+                drifted.len()
+            )));
+        }
+    }
+
+    if dry_run {
+        println!(
+            "\n{} DRY RUN MODE - No changes will be applied",
+            "⚠".yellow().bold()
+        );
+        return Ok(());
+    }
+
+    if !yes && !prompt_yes_no("Roll back these generations?")? {
+        println!("{} Rollback cancelled", "⊘".yellow());
+        return Ok(());
+    }
+
+    let mut dry_run_tracker = DryRun::default();
+    let mut fs_manager = FileSystemManager::new(&mut dry_run_tracker, false);
+
+    let backup_dir = config
+        .get_backup_dir()?
+        .join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string());
+    let home = dirs::home_dir()
+        .ok_or_else(|| DotfilesError::Config("Could not find home directory".to_string()))?;
+    for path in drifted.iter().filter(|path| path.exists()) {
+        let relative = path
+            .strip_prefix(&home)
+            .or_else(|_| path.strip_prefix("/"))
+            .unwrap_or(path);
+        let backup_path = backup_dir.join(relative);
+        if let Some(parent) = backup_path.parent() {
+            fs_manager.create_dir_all(parent)?;
+        }
+        if path.is_dir() {
+            fs_manager.copy_dir_all(path, &backup_path)?;
+        } else {
+            fs_manager.copy(path, &backup_path)?;
+        }
+        println!(
+            "  {} Backed up {} -> {}",
+            "✓".yellow(),
+            path.display(),
+            backup_path.display()
+        );
+    }
+
+    let mut transaction = begin_transaction(config)?;
+    transaction.metadata.insert(
+        "description".to_string(),
+        format!("Rollback to generation {}", target),
+    );
+    store.add_undo_to_transaction(&to_undo, &mut transaction);
+    let profile = match target {
+        0 => config.general.current_profile.clone(),
+        _ => store.load(target)?.profile,
+    };
+    let generation = run_transaction(config, &mut transaction, &profile, &mut fs_manager)?;
+
+    println!(
+        "\n{} Rolled back to generation {} (recorded as generation {})",
+        "✓".green(),
+        target,
+        generation.number
+    );
+
+    Ok(())
+}
+
+/// Delete all but the newest `keep` generations (default: `general.keep_generations`).
+pub fn prune_generations(config: &Config, keep: Option<usize>, dry_run: bool) -> Result<()> {
+    let store = GenerationStore::open(config)?;
+    let keep = keep
+        .or(config.general.keep_generations)
+        .unwrap_or(DEFAULT_KEEP_GENERATIONS);
+    let pruned = if dry_run {
+        store.prunable(keep)?
+    } else {
+        store.prune(keep)?
+    };
+
+    match (pruned.first(), pruned.last()) {
+        (Some(first), Some(last)) if dry_run => println!(
+            "[DRY RUN] Would delete {} generation(s) ({}-{}), keeping the newest {}",
+            pruned.len(),
+            first,
+            last,
+            keep.max(1)
+        ),
+        (Some(first), Some(last)) => println!(
+            "{} Deleted {} generation(s) ({}-{}), kept the newest {}",
+            "✓".green(),
+            pruned.len(),
+            first,
+            last,
+            keep.max(1)
+        ),
+        _ => println!("{} Nothing to prune", "✓".green()),
+    }
+    Ok(())
+}
+
+fn format_timestamp(generation: &Generation) -> String {
+    generation
+        .timestamp()
+        .and_then(|ts| chrono::DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| {
+            ts.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown time".to_string())
+}
+
+fn describe_operation(operation: &FileOperation) -> String {
+    match operation {
+        FileOperation::CreateSymlink { source, target, .. } => {
+            format!("link {} -> {}", target.display(), source.display())
+        }
+        FileOperation::RemoveSymlink { target, .. } => format!("remove {}", target.display()),
+        FileOperation::BackupAndReplace {
+            source,
+            target,
+            backup_path,
+            ..
+        } => format!(
+            "replace {} -> {} (backup: {})",
+            target.display(),
+            source.display(),
+            backup_path.display()
+        ),
+    }
+}
+
+fn describe_prior_state(prior: &PriorState) -> String {
+    match prior {
+        PriorState::Missing => "missing".to_string(),
+        PriorState::Symlink { link_target } => format!("symlink to {}", link_target.display()),
+        PriorState::File { .. } => "regular file".to_string(),
+        PriorState::Directory { .. } => "directory".to_string(),
+        PriorState::Links { links } => format!("directory of {} symlink(s)", links.len()),
+    }
+}
@@ -0,0 +1,421 @@
+use crate::commands::apply::{begin_transaction, run_transaction};
+use crate::config::Config;
+use crate::file_manager::FileSystemManager;
+use crate::services::FileOperation;
+use crate::services::importers::{ImportItem, ImportScan, ImportSource, SkippedItem, scan};
+use crate::types::{EntryType, TrackedFile};
+use crate::utils::dry_run::DryRun;
+use crate::utils::error::Result;
+use crate::utils::path_utils::{DirFilter, copy_differs};
+use crate::utils::prompt::prompt_yes_no;
+use colored::Colorize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Options for `flux import`
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Kind of setup to import from
+    pub source: ImportSource,
+    /// Stow directory, chezmoi source directory or git directory of the setup
+    pub path: PathBuf,
+    /// Profile for the imported entries (yadm classes set their own)
+    pub profile: Option<String>,
+    /// Skip the confirmation prompt
+    pub yes: bool,
+    /// Only show the report
+    pub dry_run: bool,
+}
+
+/// An imported file and the entry registered for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedEntry {
+    pub item: ImportItem,
+    /// Path in the repository relative to its root, `<tool>/<file name>` as for `flux add`
+    pub repo: String,
+}
+
+/// What happens to a destination once its file is imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relink {
+    /// A symlink (into the old setup) is replaced by one into the repository
+    Repoint,
+    /// A copy with the imported content is backed up and replaced by a symlink
+    ReplaceCopy,
+    /// Nothing is there yet, so a symlink is created
+    Create,
+    /// The destination differs from the imported file and is left alone
+    Keep,
+}
+
+/// Files to import, and what happens to their destinations on this machine.
+#[derive(Debug, Clone)]
+pub struct ImportPlan {
+    pub entries: Vec<PlannedEntry>,
+    pub skipped: Vec<SkippedItem>,
+    /// Destinations this machine uses, with their imported entry
+    pub links: Vec<(TrackedFile, Relink)>,
+    /// The configuration with the imported entries registered
+    pub config: Config,
+}
+
+/// Work out repository paths and entries for everything found in a setup, without
+/// changing anything. Destinations that are already tracked are skipped.
+pub fn plan_import(
+    config: &Config,
+    found: ImportScan,
+    profile: Option<&str>,
+) -> Result<ImportPlan> {
+    let repo_root = config.get_repo_path()?;
+    let mut skipped = found.skipped;
+    let mut tracked: HashMap<String, &str> = HashMap::new();
+    let mut taken: HashSet<String> = HashSet::new();
+    for (tool, tool_config) in &config.tools {
+        for entry in &tool_config.files {
+            tracked.insert(entry.dest.clone(), tool);
+            taken.insert(
+                config
+                    .repo_file_path(tool, entry)?
+                    .strip_prefix(&repo_root)
+                    .unwrap_or(Path::new(&entry.repo))
+                    .display()
+                    .to_string(),
+            );
+        }
+    }
+
+    // Group by tool, keeping the order of alternates of the same destination
+    let mut items = found.items;
+    items.sort_by(|a, b| a.tool.cmp(&b.tool));
+
+    let mut entries = Vec::new();
+    for item in items {
+        if let Some(tool) = tracked.get(item.dest.to_string_lossy().as_ref()) {
+            skipped.push(SkippedItem {
+                path: item.source.clone(),
+                reason: format!("~/{} is already tracked by {}", item.dest.display(), tool),
+            });
+            continue;
+        }
+        let repo = repo_name(&item, &repo_root, &mut taken);
+        entries.push(PlannedEntry { item, repo });
+    }
+
+    let mut imported = config.clone();
+    for planned in &entries {
+        let item = &planned.item;
+        let entry = imported.add_file_to_tool(
+            &item.tool,
+            &planned.repo,
+            &item.dest,
+            item.profile.as_deref().or(profile),
+        )?;
+        entry.hosts = item.hosts.clone();
+        entry.os = item.os.clone();
+        if item.source.is_dir() {
+            entry.entry_type = EntryType::Dir;
+        }
+    }
+
+    // Only destinations selected for this machine are linked now
+    let sources: HashMap<PathBuf, &Path> = entries
+        .iter()
+        .map(|planned| (repo_root.join(&planned.repo), planned.item.source.as_path()))
+        .collect();
+    let mut links = Vec::new();
+    for file in imported.get_tracked_files(None)? {
+        let Some(source) = sources.get(&file.repo_path) else {
+            continue;
+        };
+        if file.dest_path.is_symlink() {
+            if !file.is_linked() {
+                links.push((file, Relink::Repoint));
+            }
+        } else if !file.dest_path.exists() {
+            links.push((file, Relink::Create));
+        } else if copy_differs(source, &file.dest_path, &DirFilter::default())? {
+            links.push((file, Relink::Keep));
+        } else {
+            links.push((file, Relink::ReplaceCopy));
+        }
+    }
+
+    Ok(ImportPlan {
+        entries,
+        skipped,
+        links,
+        config: imported,
+    })
+}
+
+/// Repository path for an imported item: `<tool>/<file name>` like `flux add`, with the
+/// variant appended for alternates. Falls back to the destination's path when the name
+/// is taken by another file of the tool.
+fn repo_name(item: &ImportItem, repo_root: &Path, taken: &mut HashSet<String>) -> String {
+    let with_variant = |name: String| match &item.variant {
+        Some(variant) => format!("{}.{}", name, variant),
+        None => name,
+    };
+    let file_name = item
+        .dest
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let preferred = [
+        format!("{}/{}", item.tool, with_variant(file_name)),
+        format!(
+            "{}/{}",
+            item.tool,
+            with_variant(item.dest.display().to_string())
+        ),
+    ];
+
+    let is_free = |name: &String, taken: &HashSet<String>| {
+        !taken.contains(name) && !repo_root.join(name).exists()
+    };
+    let name = preferred
+        .iter()
+        .find(|name| is_free(name, taken))
+        .cloned()
+        .unwrap_or_else(|| {
+            (2..)
+                .map(|n| format!("{}.{}", preferred[1], n))
+                .find(|name| is_free(name, taken))
+                .expect("an unused name exists")
+        });
+    taken.insert(name.clone());
+    name
+}
+
+/// Print what an import will do.
+pub fn display_import_plan(plan: &ImportPlan, options: &ImportOptions, home: &Path) {
+    println!(
+        "{} Importing from {} ({})",
+        "→".cyan().bold(),
+        options.source,
+        options.path.display()
+    );
+
+    let repo_root = plan.config.get_repo_path().ok();
+    let relinks: HashMap<&Path, Relink> = plan
+        .links
+        .iter()
+        .map(|(file, relink)| (file.repo_path.as_path(), *relink))
+        .collect();
+    let mut tool = "";
+    for planned in &plan.entries {
+        let item = &planned.item;
+        if item.tool != tool {
+            tool = &item.tool;
+            println!("\n  {}", tool.bold());
+        }
+        let mut selectors = Vec::new();
+        if let Some(profile) = item.profile.as_deref().or(options.profile.as_deref()) {
+            selectors.push(format!("profile {}", profile));
+        }
+        if let Some(os) = &item.os {
+            selectors.push(format!("os {}", os));
+        }
+        if let Some(hosts) = &item.hosts {
+            selectors.push(format!("hosts {}", hosts.join(",")));
+        }
+        let relink = repo_root
+            .as_ref()
+            .and_then(|root| relinks.get(root.join(&planned.repo).as_path()));
+        let action = match relink {
+            Some(Relink::Repoint) => "re-point symlink".green(),
+            Some(Relink::ReplaceCopy) => "replace copy with symlink (backed up)".green(),
+            Some(Relink::Create) => "create symlink".green(),
+            Some(Relink::Keep) => "left in place: differs from the imported file".yellow(),
+            None => "not used on this machine".dimmed(),
+        };
+        println!(
+            "    {} ← {}",
+            planned.repo,
+            display_relative(&item.source, home)
+        );
+        println!(
+            "      {} ~/{}{} ({})",
+            "↳".cyan(),
+            item.dest.display(),
+            if selectors.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", selectors.join(", "))
+            },
+            action
+        );
+    }
+
+    if !plan.skipped.is_empty() {
+        println!("\n  {}", "Skipped".bold());
+        for skipped in &plan.skipped {
+            println!(
+                "    {} {}: {}",
+                "⊘".yellow(),
+                display_relative(&skipped.path, home),
+                skipped.reason
+            );
+        }
+    }
+    println!();
+}
+
+/// Import the files of another dotfiles setup: copy them into the repository, register
+/// their entries, then re-point their destinations in one transaction.
+///
+/// The plan is shown first; with `dry_run` nothing else happens. Returns the number of
+/// entries imported.
+pub fn import_dotfiles(config: &mut Config, options: &ImportOptions) -> Result<usize> {
+    let home = dirs::home_dir().ok_or_else(crate::utils::error_utils::home_dir_not_found)?;
+    let found = scan(options.source, &options.path, &home)?;
+    let plan = plan_import(config, found, options.profile.as_deref())?;
+    display_import_plan(&plan, options, &home);
+
+    if plan.entries.is_empty() {
+        println!("{} Nothing to import", "✓".green());
+        return Ok(0);
+    }
+    if options.dry_run {
+        println!(
+            "[DRY RUN] Would import {} files; run again without --dry-run to import them",
+            plan.entries.len()
+        );
+        return Ok(0);
+    }
+    if !options.yes && !prompt_yes_no("Import these files?")? {
+        println!("{} Import cancelled", "⊘".yellow());
+        return Ok(0);
+    }
+
+    // Copy everything before any destination changes, since a destination may be the
+    // only copy (yadm and bare repositories check files out into the home directory)
+    let repo_root = config.get_repo_path()?;
+    let mut dry_run_tracker = DryRun::default();
+    let mut fs_manager = FileSystemManager::new(&mut dry_run_tracker, false);
+    copy_into_repo(&plan.entries, &repo_root, &mut fs_manager)?;
+    *config = plan.config;
+    config.save(false)?;
+    println!(
+        "{} Imported {} files into {}",
+        "✓".green(),
+        plan.entries.len(),
+        repo_root.display()
+    );
+
+    relink_destinations(config, options, &plan.links, &home, &mut fs_manager)?;
+    for (file, _) in plan
+        .links
+        .iter()
+        .filter(|(_, relink)| *relink == Relink::Keep)
+    {
+        println!(
+            "  {} Left {} in place: it differs from the imported file (see 'flux diff')",
+            "⚠".yellow(),
+            file.dest_path.display()
+        );
+    }
+    println!("  Run 'flux commit' to commit the imported files");
+    Ok(plan.entries.len())
+}
+
+/// Copy the imported files to their repository paths, with the modes the setup records.
+pub fn copy_into_repo(
+    entries: &[PlannedEntry],
+    repo_root: &Path,
+    fs_manager: &mut FileSystemManager,
+) -> Result<()> {
+    for planned in entries {
+        let target = repo_root.join(&planned.repo);
+        if let Some(parent) = target.parent() {
+            fs_manager.create_dir_all(parent)?;
+        }
+        if planned.item.source.is_dir() {
+            fs_manager.copy_dir_all(&planned.item.source, &target)?;
+        } else {
+            fs_manager.copy(&planned.item.source, &target)?;
+        }
+        if let Some(mode) = planned.item.mode {
+            fs::set_permissions(&target, fs::Permissions::from_mode(mode))?;
+        }
+    }
+    Ok(())
+}
+
+/// Point destinations at the repository in one journaled transaction, recorded as a
+/// generation so `flux generations rollback` restores the previous setup's links.
+fn relink_destinations(
+    config: &Config,
+    options: &ImportOptions,
+    links: &[(TrackedFile, Relink)],
+    home: &Path,
+    fs_manager: &mut FileSystemManager,
+) -> Result<()> {
+    let mut transaction = begin_transaction(config)?;
+    transaction.metadata.insert(
+        "description".to_string(),
+        format!("flux import --from {}", options.source),
+    );
+
+    let backup_dir = config
+        .get_backup_dir()?
+        .join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string());
+    for (file, relink) in links {
+        let create = FileOperation::CreateSymlink {
+            source: file.repo_path.clone(),
+            target: file.dest_path.clone(),
+            resolution: file.resolution,
+        };
+        match relink {
+            Relink::Repoint => {
+                transaction.add_operation(FileOperation::RemoveSymlink {
+                    target: file.dest_path.clone(),
+                    original: None,
+                });
+                transaction.add_operation(create);
+            }
+            Relink::ReplaceCopy => {
+                transaction.add_operation(FileOperation::BackupAndReplace {
+                    source: file.repo_path.clone(),
+                    target: file.dest_path.clone(),
+                    backup_path: backup_dir
+                        .join(file.dest_path.strip_prefix(home).unwrap_or(&file.dest_path)),
+                    resolution: file.resolution,
+                });
+            }
+            Relink::Create => transaction.add_operation(create),
+            Relink::Keep => {}
+        }
+    }
+    if transaction.operations.is_empty() {
+        return Ok(());
+    }
+
+    let generation = run_transaction(
+        config,
+        &mut transaction,
+        &config.general.current_profile,
+        fs_manager,
+    )?;
+
+    println!(
+        "{} Linked {} destinations into the repository (generation {})",
+        "✓".green(),
+        links
+            .iter()
+            .filter(|(_, relink)| *relink != Relink::Keep)
+            .count(),
+        generation.number
+    );
+    Ok(())
+}
+
+/// `path` relative to the home directory, written with `~/`.
+fn display_relative(path: &Path, home: &Path) -> String {
+    match path.strip_prefix(home) {
+        Ok(relative) => format!("~/{}", relative.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::config::{Config, HostFacts, SelectedFile};
+use crate::utils::error::Result;
+use colored::Colorize;
+use serde::Serialize;
+
+/// Document printed by `flux ls-files --format json`.
+#[derive(Serialize)]
+pub struct FileListOutput<'a> {
+    pub files: &'a [SelectedFile],
+}
+
+/// Evaluate every file entry for `ls-files`, sorted by tool and destination.
+pub fn list_files(config: &Config, profile: Option<&str>) -> Result<Vec<SelectedFile>> {
+    let mut files = config.select_files(profile, &HostFacts::current())?;
+    files.sort_by(|a, b| {
+        a.file
+            .tool
+            .cmp(&b.file.tool)
+            .then_with(|| a.file.dest_path.cmp(&b.file.dest_path))
+    });
+    Ok(files)
+}
+
+/// Display every entry, with the reason it was included or excluded.
+pub fn display_file_list(files: &[SelectedFile]) {
+    println!("\n{}", "Tracked files:".bold().cyan());
+
+    let mut current_tool: Option<&str> = None;
+    for selected in files {
+        let file = &selected.file;
+        if current_tool != Some(file.tool.as_str()) {
+            println!("\n{} {}", "Tool:".bold(), file.tool.cyan());
+            current_tool = Some(&file.tool);
+        }
+
+        if selected.selection.included {
+            println!(
+                "  {} {} -> {}",
+                "✓".green(),
+                file.repo_path.display(),
+                file.dest_path.display()
+            );
+        } else {
+            println!(
+                "  {} {} -> {} {}",
+                "⊘".yellow(),
+                file.repo_path.display(),
+                file.dest_path.display(),
+                "(excluded)".yellow()
+            );
+        }
+        println!(
+            "      {}",
+            selected.selection.reasons.join("; ").bright_black()
+        );
+    }
+
+    let included = files.iter().filter(|f| f.selection.included).count();
+    println!(
+        "\n{} {} included, {} excluded",
+        "Summary:".bold(),
+        included.to_string().green(),
+        (files.len() - included).to_string().yellow()
+    );
+}
@@ -52,7 +52,6 @@ pub fn migrate_files(
         discrepancies.len()
     );
 
-    let symlink_resolution = config.general.symlink_resolution;
     let mut migrated_count = 0;
     let mut skipped_count = 0;
 
@@ -68,7 +67,6 @@ pub fn migrate_files(
         match migrate_file(
             &discrepancy.file,
             &discrepancy.issue,
-            &symlink_resolution,
             config,
             &mut fs_manager,
             no_backup,
@@ -101,11 +99,35 @@ enum MigrationResult {
 fn migrate_file(
     file: &TrackedFile,
     issue: &IssueType,
-    resolution: &SymlinkResolution,
     config: &Config,
     fs_manager: &mut FileSystemManager,
     no_backup: bool,
 ) -> Result<MigrationResult> {
+    // Rendered files are generated from the repo: copying them back would overwrite
+    // the template or commit a secret in plaintext
+    if file.encrypted {
+        return Ok(MigrationResult::Skipped(
+            "Encrypted file: edit it with `flux secret edit` and run `flux apply`".to_string(),
+        ));
+    }
+    if file.template {
+        return Ok(MigrationResult::Skipped(
+            "Template file: run `flux apply` to render it".to_string(),
+        ));
+    }
+    if file.is_sqlite() {
+        return Ok(MigrationResult::Skipped(
+            "SQLite database: run `flux commit` to snapshot it or `flux apply` to restore it"
+                .to_string(),
+        ));
+    }
+    if file.is_copy() {
+        return Ok(MigrationResult::Skipped(
+            "Copied file: run `flux apply` to update the copy".to_string(),
+        ));
+    }
+    let resolution = &file.resolution;
+
     match issue {
         IssueType::Missing => {
             // File doesn't exist - just create symlink (repo should exist)
@@ -177,7 +199,11 @@ fn migrate_file(
                 }
 
                 if file.dest_path.is_dir() {
-                    fs_manager.copy_dir_all(&file.dest_path, &file.repo_path)?;
+                    fs_manager.copy_dir_filtered(
+                        &file.dest_path,
+                        &file.repo_path,
+                        &file.dir_filter()?,
+                    )?;
                 } else {
                     fs_manager.copy(&file.dest_path, &file.repo_path)?;
                 }
@@ -330,7 +356,11 @@ fn migrate_file(
                         }
 
                         if source.is_dir() {
-                            fs_manager.copy_dir_all(source, &file.repo_path)?;
+                            fs_manager.copy_dir_filtered(
+                                source,
+                                &file.repo_path,
+                                &file.dir_filter()?,
+                            )?;
                         } else {
                             fs_manager.copy(source, &file.repo_path)?;
                         }
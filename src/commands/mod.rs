@@ -1,15 +1,45 @@
 pub mod apply;
+pub mod browser;
+pub mod clone;
+pub mod diff;
+pub mod discover;
+pub mod export;
+pub mod generations;
+pub mod import;
+pub mod list;
 pub mod migrate;
+pub mod recover;
 pub mod restore;
+pub mod secret;
+pub mod service;
 pub mod status;
 pub mod untracked;
 pub mod validate;
+pub mod watch;
 
 pub use apply::{ApplyOptions, apply_config, compare_states, display_preview};
+pub use browser::add_browser;
+pub use clone::{CloneOptions, ConfigLocation, clone_dotfiles};
+pub use diff::{DiffSource, collect_diffs, display_diffs};
+pub use discover::{DiscoverOptions, discover};
+pub use export::{ExportFormat, ExportOptions, export_profile};
+pub use generations::{
+    display_generation, display_generation_diff, display_generations, prune_generations,
+    rollback_to_generation,
+};
+pub use import::{ImportOptions, import_dotfiles};
+pub use list::{FileListOutput, display_file_list, list_files};
 pub use migrate::migrate_files;
+pub use recover::{find_unfinished_transaction, recover_transaction};
 pub use restore::{
     add_backup_to_repo, cleanup_backups, display_backups, list_backups, restore_backup,
+    select_backup,
+};
+pub use secret::{edit_secret, rekey_secrets};
+pub use service::{
+    ServiceOptions, display_service_status, install_service, service_status, uninstall_service,
 };
-pub use status::{check_status, display_status};
-pub use untracked::{display_discrepancies, find_discrepancies};
+pub use status::{StatusOutput, check_status, display_status};
+pub use untracked::{DiscrepancyOutput, display_discrepancies, find_discrepancies};
 pub use validate::{display_validation, validate_config};
+pub use watch::{WatchOptions, install_watch_unit, watch};
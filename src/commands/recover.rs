@@ -0,0 +1,146 @@
+use crate::config::Config;
+use crate::file_manager::FileSystemManager;
+use crate::services::generations::GenerationStore;
+use crate::services::git::get_head_commit_id;
+use crate::services::journal::{Journal, JournalState, journal_path, journal_path_in};
+use crate::services::transactions::{FileOperation, OperationResult, Transaction};
+use crate::utils::dry_run::DryRun;
+use crate::utils::error::Result;
+use crate::utils::prompt::{RecoveryChoice, prompt_recovery};
+use colored::Colorize;
+use std::path::Path;
+
+/// Load the journal of a transaction that did not finish in `state_dir`, if there is one.
+pub fn find_unfinished_transaction(state_dir: &Path) -> Result<Option<JournalState>> {
+    Journal::load(&journal_path_in(state_dir))
+}
+
+pub fn display_unfinished_transaction(state: &JournalState) {
+    println!(
+        "\n{} {}",
+        "⚠".yellow().bold(),
+        "Interrupted transaction found".bold().yellow()
+    );
+    println!("{}", "=".repeat(60).cyan());
+    println!("  Transaction ID: {}", state.transaction_id);
+    if let Some(description) = state.metadata.get("description") {
+        println!("  Description:    {}", description);
+    }
+    if let Some(timestamp) = state.metadata.get("timestamp") {
+        println!("  Started:        {}", timestamp);
+    }
+    println!(
+        "  Progress:       {}/{} operation(s) completed",
+        state.completed,
+        state.operations.len()
+    );
+
+    for (index, operation) in state.operations.iter().enumerate() {
+        let marker = if index < state.completed {
+            "✓".green()
+        } else if state.in_flight == Some(index) {
+            "↻".yellow()
+        } else {
+            "•".normal()
+        };
+        println!("    {} {}", marker, operation.target().display());
+    }
+
+    println!("{}", "=".repeat(60).cyan());
+}
+
+/// Finish or undo a transaction that was interrupted before it completed.
+///
+/// Finishing resumes the commit at the first operation that did not complete and
+/// records the apply generation. Undoing rolls back every completed operation
+/// (and the interrupted one, if it got far enough to change anything) using the
+/// backups recorded in the journal.
+pub fn recover_transaction(config: &Config, choice: Option<RecoveryChoice>) -> Result<()> {
+    let path = journal_path(config)?;
+    let Some(state) = Journal::load(&path)? else {
+        println!("{} No interrupted transaction to recover", "✓".green());
+        return Ok(());
+    };
+
+    display_unfinished_transaction(&state);
+
+    let choice = match choice {
+        Some(choice) => choice,
+        None => prompt_recovery()?,
+    };
+
+    let generations = GenerationStore::open(config)?;
+    let pending = generations.pending(&state.transaction_id)?;
+    let mut transaction = Transaction::from_journal(&state, &path)?;
+    let mut dry_run_tracker = DryRun::default();
+    let mut fs_manager = FileSystemManager::new(&mut dry_run_tracker, false);
+
+    match choice {
+        RecoveryChoice::Finish => {
+            println!("{} Finishing transaction...", "→".cyan().bold());
+
+            if let Err(e) = transaction.commit(config, &mut fs_manager) {
+                if let Some(pending) = pending {
+                    pending.discard()?;
+                }
+                return Err(e);
+            }
+
+            if let Some(pending) = pending {
+                let profile = state
+                    .metadata
+                    .get("profile")
+                    .unwrap_or(&config.general.current_profile);
+                let generation = pending.commit(
+                    &generations,
+                    &transaction,
+                    profile,
+                    get_head_commit_id(&config.get_repo_path()?),
+                )?;
+                println!("  Generation: {}", generation.number);
+            }
+
+            transaction.verify()?;
+            transaction.cleanup()?;
+            println!("{} Transaction finished", "✓".green());
+        }
+        RecoveryChoice::Undo => {
+            println!("{} Undoing transaction...", "→".cyan().bold());
+
+            if let Some(index) = state.in_flight
+                && in_flight_changed_target(&state, &state.operations[index])
+            {
+                transaction.results.push(OperationResult {
+                    operation: state.operations[index].clone(),
+                    success: true,
+                    error: None,
+                });
+            }
+
+            transaction.rollback(config, &mut fs_manager)?;
+            transaction.cleanup()?;
+            if let Some(pending) = pending {
+                pending.discard()?;
+            }
+            println!("{} Transaction undone", "✓".green());
+        }
+        RecoveryChoice::Cancel => {
+            println!("{} Recovery cancelled", "⊘".yellow());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether an interrupted operation may have modified its target.
+///
+/// A backup-and-replace only touches the target after its backup was recorded, so
+/// restoring from a backup that was never completed would lose data.
+fn in_flight_changed_target(state: &JournalState, operation: &FileOperation) -> bool {
+    match operation {
+        FileOperation::BackupAndReplace { backup_path, .. } => {
+            state.backups.iter().any(|b| b == backup_path)
+        }
+        FileOperation::CreateSymlink { .. } | FileOperation::RemoveSymlink { .. } => true,
+    }
+}
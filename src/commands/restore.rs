@@ -58,11 +58,32 @@ pub fn list_backups(config: &Config) -> Result<Vec<BackupInfo>> {
     }
 
     // Sort by timestamp, newest first
-    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    backups.sort_by_key(|b| std::cmp::Reverse(b.timestamp));
 
     Ok(backups)
 }
 
+/// Pick a backup by `latest` or by its 1-based index in [`list_backups`] order.
+pub fn select_backup<'a>(backups: &'a [BackupInfo], selector: &str) -> Result<&'a BackupInfo> {
+    if backups.is_empty() {
+        return Err(DotfilesError::Path("No backups available".to_string()));
+    }
+    if selector == "latest" {
+        return Ok(&backups[0]);
+    }
+
+    let index: usize = selector.parse().map_err(|_| {
+        DotfilesError::Path("Invalid backup index. Use 'latest', 'list', or a number".to_string())
+    })?;
+    if index == 0 || index > backups.len() {
+        return Err(DotfilesError::Path(format!(
+            "Backup index out of range (1-{})",
+            backups.len()
+        )));
+    }
+    Ok(&backups[index - 1])
+}
+
 fn collect_backup_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
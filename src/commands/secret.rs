@@ -0,0 +1,255 @@
+use crate::config::Config;
+use crate::services::secrets::{self, SecretKeys};
+use crate::utils::error::{DotfilesError, Result};
+use age::x25519;
+use colored::Colorize;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// An encrypted file entry, across all profiles.
+struct EncryptedFile {
+    tool: String,
+    repo_path: PathBuf,
+    dest: String,
+    profile: Option<String>,
+}
+
+fn encrypted_files(config: &Config) -> Result<Vec<EncryptedFile>> {
+    let mut files = Vec::new();
+    for (tool, tool_config) in &config.tools {
+        for entry in tool_config.files.iter().filter(|entry| entry.encrypted) {
+            files.push(EncryptedFile {
+                tool: tool.clone(),
+                repo_path: config.repo_file_path(tool, entry)?,
+                dest: entry.dest.clone(),
+                profile: entry.profile.clone(),
+            });
+        }
+    }
+    files.sort_by(|a, b| a.tool.cmp(&b.tool).then_with(|| a.dest.cmp(&b.dest)));
+    Ok(files)
+}
+
+/// Find the encrypted file matching a destination or repository path.
+fn find_encrypted_file(config: &Config, file: &str) -> Result<EncryptedFile> {
+    let home = dirs::home_dir().ok_or_else(crate::utils::error_utils::home_dir_not_found)?;
+    let expanded = PathBuf::from(shellexpand::tilde(file).into_owned());
+    let repo_path = config.get_repo_path()?;
+
+    let (mut matches, others): (Vec<_>, Vec<_>) =
+        encrypted_files(config)?.into_iter().partition(|candidate| {
+            candidate.dest == file
+                || home.join(&candidate.dest) == expanded
+                || candidate.repo_path == expanded
+                || candidate.repo_path == repo_path.join(file)
+        });
+
+    match matches.len() {
+        1 => Ok(matches.remove(0)),
+        0 => {
+            let known: Vec<_> = others.iter().map(|f| format!("~/{}", f.dest)).collect();
+            Err(DotfilesError::Config(format!(
+                "No encrypted file matches '{}'. Encrypted files: {}",
+                file,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )))
+        }
+        _ => {
+            let candidates: Vec<_> = matches
+                .iter()
+                .map(|f| {
+                    format!(
+                        "{} [profile: {}]",
+                        f.repo_path.display(),
+                        f.profile.as_deref().unwrap_or("all")
+                    )
+                })
+                .collect();
+            Err(DotfilesError::Config(format!(
+                "'{}' matches several encrypted files; pass the repository path instead: {}",
+                file,
+                candidates.join(", ")
+            )))
+        }
+    }
+}
+
+/// Decrypt a secret into a private temporary file, open it in `$VISUAL`/`$EDITOR`
+/// and re-encrypt it into the repository if it changed.
+pub fn edit_secret(config: &Config, file: &str) -> Result<()> {
+    let secret = find_encrypted_file(config, file)?;
+    let keys = SecretKeys::load(config)?;
+    let plaintext = keys.decrypt(&fs::read(&secret.repo_path)?)?;
+
+    // Keep the extension so editors pick the right syntax highlighting
+    let suffix = secret
+        .repo_path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    // tempfile creates the file with mode 0600
+    let mut temp = tempfile::Builder::new()
+        .prefix("flux-secret-")
+        .suffix(&suffix)
+        .tempfile()?;
+    temp.write_all(&plaintext)?;
+    temp.flush()?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Run through the shell so editors with arguments (e.g. "code --wait") work
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(temp.path())
+        .status()?;
+    if !status.success() {
+        return Err(DotfilesError::Config(format!(
+            "Editor '{}' exited with {}; {} was left unchanged",
+            editor,
+            status,
+            secret.repo_path.display()
+        )));
+    }
+
+    let edited = fs::read(temp.path())?;
+    if edited == plaintext {
+        println!(
+            "{} No changes to {}",
+            "⊘".yellow(),
+            secret.repo_path.display()
+        );
+        return Ok(());
+    }
+
+    write_atomic(&secret.repo_path, &keys.encrypt(&edited)?)?;
+    println!(
+        "{} Re-encrypted {}",
+        "✓".green(),
+        secret.repo_path.display()
+    );
+    println!(
+        "  {} Run `flux apply` to update ~/{}",
+        "→".cyan(),
+        secret.dest
+    );
+    Ok(())
+}
+
+/// Re-encrypt every encrypted file to the current recipients, optionally rotating the
+/// local identity first.
+pub fn rekey_secrets(config: &Config, new_identity: bool, dry_run: bool) -> Result<()> {
+    let files = encrypted_files(config)?;
+    let keys = SecretKeys::load(config)?;
+
+    // Decrypt everything up front so a bad file aborts before anything is rewritten
+    let mut decrypted = Vec::with_capacity(files.len());
+    for file in &files {
+        let plaintext = keys.decrypt(&fs::read(&file.repo_path)?).map_err(|e| {
+            e.with_context(&format!("Failed to decrypt {}", file.repo_path.display()))
+        })?;
+        decrypted.push((file, plaintext));
+    }
+
+    if dry_run {
+        if new_identity {
+            println!(
+                "  [DRY RUN] Would generate a new identity at {}",
+                keys.identity_path.display()
+            );
+        }
+        for file in &files {
+            println!("  [DRY RUN] Would re-encrypt {}", file.repo_path.display());
+        }
+        return Ok(());
+    }
+
+    let identity_path = keys.identity_path.clone();
+    let new_keys = if new_identity {
+        let staged = with_suffix(&identity_path, ".new");
+        secrets::write_identity(&staged, &x25519::Identity::generate())?;
+        Some((
+            SecretKeys::from_file(
+                &staged,
+                config.general.recipients.as_deref().unwrap_or_default(),
+            )?,
+            staged,
+        ))
+    } else {
+        None
+    };
+    let target_keys = new_keys.as_ref().map(|(keys, _)| keys).unwrap_or(&keys);
+
+    for (file, plaintext) in &decrypted {
+        write_atomic(&file.repo_path, &target_keys.encrypt(plaintext)?)?;
+        println!("{} Re-encrypted {}", "✓".green(), file.repo_path.display());
+    }
+
+    if let Some((new_keys, staged)) = &new_keys {
+        // Keep the old identity so older commits can still be decrypted
+        let old = retired_identity_path(&identity_path);
+        fs::rename(&identity_path, &old)?;
+        fs::rename(staged, &identity_path)?;
+        println!(
+            "{} New identity {} (public key: {})",
+            "✓".green(),
+            identity_path.display(),
+            new_keys.public_key()
+        );
+        println!(
+            "  {} Previous identity kept at {}",
+            "→".cyan(),
+            old.display()
+        );
+    }
+
+    println!(
+        "{} Re-encrypted {} file(s) to {} recipient(s)",
+        "→".cyan(),
+        files.len(),
+        target_keys.recipient_count()
+    );
+    if !files.is_empty() {
+        println!(
+            "\n{}",
+            "Tip: Commit the re-encrypted files with `flux commit`"
+                .yellow()
+                .italic()
+        );
+    }
+    Ok(())
+}
+
+/// Unused path to keep a replaced identity at, `identity.txt.<timestamp>.old`. Earlier
+/// identities are never overwritten, even by several rotations within a second.
+fn retired_identity_path(identity_path: &Path) -> PathBuf {
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let mut path = with_suffix(identity_path, &format!(".{}.old", timestamp));
+    let mut number = 1;
+    while path.exists() || path.is_symlink() {
+        path = with_suffix(identity_path, &format!(".{}-{}.old", timestamp, number));
+        number += 1;
+    }
+    path
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension("flux-temp-write");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path).map_err(Into::into)
+}
@@ -0,0 +1,267 @@
+use crate::commands::watch::WATCH_UNIT_NAME;
+use crate::config::Config;
+use crate::config::cli::env_keys;
+use crate::services::systemd::{UnitFile, exec_line, quote_arg, systemctl, write_units};
+use crate::types::ConflictPolicy;
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::prompt::select_conflict_policy;
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Service that pulls and applies once.
+pub const SYNC_SERVICE_NAME: &str = "flux-sync.service";
+/// Timer that starts [`SYNC_SERVICE_NAME`] on a schedule.
+pub const SYNC_TIMER_NAME: &str = "flux-sync.timer";
+
+/// Options for `flux service install`
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+    /// Profile to apply (optional)
+    pub profile: Option<String>,
+    /// Remote to pull from (default: config default_remote or origin)
+    pub remote: Option<String>,
+    /// Branch to pull (default: config default_branch, or the current branch when the
+    /// unit runs)
+    pub branch: Option<String>,
+    /// systemd `OnCalendar=` expression
+    pub schedule: String,
+    /// Policy for conflicts during apply (default: config on_conflict or keep-repo)
+    pub conflict_policy: Option<ConflictPolicy>,
+    /// Config file passed to the units through `DOTFILES_CONFIG` (optional)
+    pub config_file: Option<PathBuf>,
+}
+
+/// Installed state of one flux unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitState {
+    pub name: String,
+    /// Path of the unit file when it is installed
+    pub path: Option<PathBuf>,
+    /// Output of `systemctl --user is-enabled` (`None` without systemd)
+    pub enabled: Option<String>,
+    /// Output of `systemctl --user is-active` (`None` without systemd)
+    pub active: Option<String>,
+}
+
+/// Render the service and timer that run `flux pull` followed by `flux apply --yes`.
+pub fn render_sync_units(
+    executable: &Path,
+    config: &Config,
+    options: &ServiceOptions,
+) -> Vec<UnitFile> {
+    let flux = executable.display().to_string();
+
+    let remote = options
+        .remote
+        .clone()
+        .or_else(|| config.general.default_remote.clone())
+        .unwrap_or_else(|| "origin".to_string());
+    let timeout = config.general.push_timeout.unwrap_or(60).to_string();
+    let mut pull = vec![
+        flux.clone(),
+        "pull".to_string(),
+        "--remote".to_string(),
+        remote,
+    ];
+    if let Some(branch) = options
+        .branch
+        .clone()
+        .or_else(|| config.general.default_branch.clone())
+    {
+        pull.extend(["--branch".to_string(), branch]);
+    }
+    pull.extend(["--timeout".to_string(), timeout]);
+
+    // Units never have a terminal, so the policy is always resolved up front
+    let policy = select_conflict_policy(options.conflict_policy, config.general.on_conflict, false)
+        .unwrap_or(ConflictPolicy::KeepRepo);
+    let mut apply = vec![
+        flux,
+        "apply".to_string(),
+        "--yes".to_string(),
+        "--on-conflict".to_string(),
+        policy.to_string(),
+    ];
+    if let Some(profile) = &options.profile {
+        apply.extend(["--profile".to_string(), profile.clone()]);
+    }
+
+    let environment = options
+        .config_file
+        .as_ref()
+        .map(|path| {
+            format!(
+                "Environment={}\n",
+                quote_arg(&format!("{}={}", env_keys::CONFIG_FILE, path.display()))
+            )
+        })
+        .unwrap_or_default();
+
+    let service = format!(
+        "[Unit]\n\
+         Description=Pull and apply dotfiles with flux\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         {}\
+         ExecStart={}\n\
+         ExecStart={}\n",
+        environment,
+        exec_line(&pull),
+        exec_line(&apply)
+    );
+    let timer = format!(
+        "[Unit]\n\
+         Description=Pull and apply dotfiles with flux on a schedule\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={}\n\
+         Persistent=true\n\
+         Unit={}\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        options.schedule, SYNC_SERVICE_NAME
+    );
+
+    vec![
+        UnitFile {
+            name: SYNC_SERVICE_NAME.to_string(),
+            contents: service,
+        },
+        UnitFile {
+            name: SYNC_TIMER_NAME.to_string(),
+            contents: timer,
+        },
+    ]
+}
+
+/// Write the sync units into `unit_dir` and, if `enable` is set, start the timer.
+pub fn install_service(
+    config: &Config,
+    options: &ServiceOptions,
+    unit_dir: &Path,
+    enable: bool,
+) -> Result<Vec<PathBuf>> {
+    let units = render_sync_units(&std::env::current_exe()?, config, options);
+    let paths = write_units(unit_dir, &units)?;
+    for path in &paths {
+        println!("{} Wrote {}", "✓".green(), path.display());
+    }
+
+    if enable {
+        run_systemctl(&["daemon-reload"])?;
+        run_systemctl(&["enable", "--now", SYNC_TIMER_NAME])?;
+        println!(
+            "{} Enabled {} ({})",
+            "✓".green(),
+            SYNC_TIMER_NAME,
+            options.schedule
+        );
+    } else {
+        println!(
+            "  Enable it with: systemctl --user daemon-reload && systemctl --user enable --now {}",
+            SYNC_TIMER_NAME
+        );
+    }
+    Ok(paths)
+}
+
+/// Stop the timer (if `disable` is set) and remove the sync units from `unit_dir`.
+pub fn uninstall_service(unit_dir: &Path, disable: bool) -> Result<Vec<PathBuf>> {
+    if disable {
+        // Not being enabled, or systemd not running, is fine when removing
+        if let Err(e) = run_systemctl(&["disable", "--now", SYNC_TIMER_NAME]) {
+            log::debug!("Could not disable {}: {}", SYNC_TIMER_NAME, e);
+        }
+    }
+
+    let mut removed = Vec::new();
+    for name in [SYNC_TIMER_NAME, SYNC_SERVICE_NAME] {
+        let path = unit_dir.join(name);
+        if path.exists() {
+            fs::remove_file(&path)?;
+            println!("{} Removed {}", "✓".green(), path.display());
+            removed.push(path);
+        }
+    }
+    if removed.is_empty() {
+        println!("{} No flux sync units installed", "⊘".yellow());
+    } else if disable && let Err(e) = run_systemctl(&["daemon-reload"]) {
+        log::debug!("Could not reload systemd: {}", e);
+    }
+    Ok(removed)
+}
+
+/// State of the sync units and the `flux watch` unit.
+pub fn service_status(unit_dir: &Path, query_systemd: bool) -> Vec<UnitState> {
+    [SYNC_TIMER_NAME, SYNC_SERVICE_NAME, WATCH_UNIT_NAME]
+        .iter()
+        .map(|name| {
+            let path = unit_dir.join(name);
+            let query = |command: &str| {
+                if !query_systemd {
+                    return None;
+                }
+                systemctl(&[command, name])
+                    .ok()
+                    .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                    .filter(|state| !state.is_empty())
+            };
+            UnitState {
+                name: name.to_string(),
+                path: path.exists().then_some(path),
+                enabled: query("is-enabled"),
+                active: query("is-active"),
+            }
+        })
+        .collect()
+}
+
+pub fn display_service_status(states: &[UnitState]) {
+    println!("\n{}", "flux systemd units:".bold().cyan());
+    println!("{}", "=".repeat(60).cyan());
+
+    for state in states {
+        let Some(path) = &state.path else {
+            println!("  {} {} (not installed)", "⊘".yellow(), state.name);
+            continue;
+        };
+        // The sync service is inactive between timer runs, so only a failure stands out
+        let icon = if state.active.as_deref() == Some("failed") {
+            "✗".red()
+        } else {
+            "✓".green()
+        };
+        println!(
+            "  {} {} [{}, {}]",
+            icon,
+            state.name.cyan(),
+            state.enabled.as_deref().unwrap_or("unknown"),
+            state.active.as_deref().unwrap_or("unknown")
+        );
+        println!("      {}", path.display().to_string().bright_black());
+        for line in fs::read_to_string(path).unwrap_or_default().lines() {
+            if let Some(command) = line.strip_prefix("ExecStart=") {
+                println!("      {} {}", "↳".bright_black(), command);
+            } else if let Some(schedule) = line.strip_prefix("OnCalendar=") {
+                println!("      {} {}", "Schedule:".bright_black(), schedule);
+            }
+        }
+    }
+}
+
+/// Run `systemctl --user`, turning a non-zero exit into an error with its output.
+fn run_systemctl(args: &[&str]) -> Result<()> {
+    let output = systemctl(args)?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(DotfilesError::Config(format!(
+        "What: 'systemctl --user {}' failed: {}\n  \
+         💡 Solution: Check that a systemd user session is running ('systemctl --user status')",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )))
+}
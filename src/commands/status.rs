@@ -1,12 +1,18 @@
 use crate::config::Config;
-use crate::types::TrackedFile;
+use crate::services::template::{TemplateContext, rendered_differs};
+use crate::types::{Severity, TrackedFile};
 use crate::utils::error::Result;
-use crate::utils::path_utils::{files_differ, resolve_symlink_target, symlink_points_to_correct_target};
+use crate::utils::path_utils::{
+    normalize_path, paths_differ, resolve_symlink_target, symlink_points_to_correct_target,
+};
 use colored::Colorize;
+use serde::Serialize;
 use std::fs;
+use std::path::PathBuf;
 
 /// Status of a tracked file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FileStatus {
     /// File is correctly synced
     Synced,
@@ -20,7 +26,18 @@ pub enum FileStatus {
     MissingRepo,
 }
 
+impl FileStatus {
+    pub fn severity(&self) -> Severity {
+        match self {
+            FileStatus::Synced => Severity::Ok,
+            FileStatus::MissingSymlink | FileStatus::OutOfSync => Severity::Warning,
+            FileStatus::BrokenSymlink | FileStatus::MissingRepo => Severity::Error,
+        }
+    }
+}
+
 /// Status report for a tracked file.
+#[derive(Serialize)]
 pub struct StatusReport {
     /// The tracked file
     pub file: TrackedFile,
@@ -28,26 +45,74 @@ pub struct StatusReport {
     pub status: FileStatus,
     /// Human-readable status message
     pub message: String,
+    /// Symlinks followed from the destination to where the file is placed
+    /// (`follow` resolution only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub chain: Vec<PathBuf>,
+}
+
+/// Counts shown in the status summary.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StatusSummary {
+    /// Files that are correctly synced
+    pub synced: usize,
+    /// Files with any other status
+    pub needs_attention: usize,
+}
+
+impl StatusSummary {
+    pub fn from_reports(reports: &[StatusReport]) -> Self {
+        let synced = reports
+            .iter()
+            .filter(|r| matches!(r.status, FileStatus::Synced))
+            .count();
+        Self {
+            synced,
+            needs_attention: reports.len() - synced,
+        }
+    }
+}
+
+/// Document printed by `flux status --format json`.
+#[derive(Serialize)]
+pub struct StatusOutput<'a> {
+    pub files: &'a [StatusReport],
+    pub summary: StatusSummary,
+}
+
+impl<'a> StatusOutput<'a> {
+    pub fn new(files: &'a [StatusReport]) -> Self {
+        Self {
+            files,
+            summary: StatusSummary::from_reports(files),
+        }
+    }
 }
 
 pub fn check_status(config: &Config, profile: Option<&str>) -> Result<Vec<StatusReport>> {
     let tracked_files = config.get_tracked_files(profile)?;
+    let templates = TemplateContext::new(config, profile);
     let mut reports = Vec::new();
 
     for file in tracked_files {
-        let status = check_file_status(&file)?;
+        // A symlink cycle cannot be followed, so it is reported as a broken symlink
+        let (status, chain) = match file.followed() {
+            Ok((followed, chain)) => (check_file_status(&followed, &templates)?, chain),
+            Err(_) => (FileStatus::BrokenSymlink, Vec::new()),
+        };
         let message = status_message(&file, &status);
         reports.push(StatusReport {
             file,
             status,
             message,
+            chain,
         });
     }
 
     Ok(reports)
 }
 
-fn check_file_status(file: &TrackedFile) -> Result<FileStatus> {
+fn check_file_status(file: &TrackedFile, templates: &TemplateContext) -> Result<FileStatus> {
     // Check if repo file exists
     if !file.repo_path.exists() {
         return Ok(FileStatus::MissingRepo);
@@ -58,6 +123,24 @@ fn check_file_status(file: &TrackedFile) -> Result<FileStatus> {
         return Ok(FileStatus::MissingSymlink);
     }
 
+    // Templates and secrets are compared against their rendered output
+    if file.is_rendered() {
+        return Ok(if rendered_differs(file, templates)? {
+            FileStatus::OutOfSync
+        } else {
+            FileStatus::Synced
+        });
+    }
+
+    // Copies are compared by content hash rather than by symlink target
+    if file.is_copy() {
+        return Ok(if file.copy_differs()? {
+            FileStatus::OutOfSync
+        } else {
+            FileStatus::Synced
+        });
+    }
+
     // Check if it's a symlink
     if let Ok(link_target) = fs::read_link(&file.dest_path) {
         let resolved_target = resolve_symlink_target(&file.dest_path, &link_target);
@@ -73,7 +156,7 @@ fn check_file_status(file: &TrackedFile) -> Result<FileStatus> {
         }
 
         // Check if files differ (for non-symlink cases or if symlink resolution is "replace")
-        if files_differ(&file.repo_path, &file.dest_path)? {
+        if paths_differ(&file.repo_path, &file.dest_path, &file.dir_filter()?)? {
             return Ok(FileStatus::OutOfSync);
         }
 
@@ -84,28 +167,42 @@ fn check_file_status(file: &TrackedFile) -> Result<FileStatus> {
     }
 }
 
-
 fn status_message(file: &TrackedFile, status: &FileStatus) -> String {
     match status {
-        FileStatus::Synced => format!("✓ {}", file.dest_path.display()),
-        FileStatus::MissingSymlink => format!("⊘ Missing: {}", file.dest_path.display()),
-        FileStatus::BrokenSymlink => format!("⚠ Broken symlink: {}", file.dest_path.display()),
-        FileStatus::OutOfSync => format!("↻ Out of sync: {}", file.dest_path.display()),
-        FileStatus::MissingRepo => format!("✗ Missing repo file: {}", file.repo_path.display()),
+        FileStatus::Synced => file.dest_path.display().to_string(),
+        FileStatus::MissingSymlink => format!("Missing: {}", file.dest_path.display()),
+        FileStatus::BrokenSymlink => format!("Broken symlink: {}", file.dest_path.display()),
+        FileStatus::OutOfSync => format!("Out of sync: {}", file.dest_path.display()),
+        FileStatus::MissingRepo => format!("Missing repo file: {}", file.repo_path.display()),
     }
 }
 
+/// The followed symlink chain, ending at the link's target when the last hop is a link.
+fn chain_message(report: &StatusReport) -> String {
+    let mut hops: Vec<_> = report
+        .chain
+        .iter()
+        .map(|hop| hop.display().to_string())
+        .collect();
+    if let Some(last) = report.chain.last()
+        && let Ok(target) = fs::read_link(last)
+    {
+        hops.push(
+            normalize_path(&resolve_symlink_target(last, &target))
+                .display()
+                .to_string(),
+        );
+    }
+    hops.join(" → ")
+}
+
 pub fn display_status(reports: &[StatusReport]) {
     if reports.is_empty() {
         println!("{}", "No tracked files found.".yellow());
         return;
     }
 
-    let synced_count = reports
-        .iter()
-        .filter(|r| matches!(r.status, FileStatus::Synced))
-        .count();
-    let issues_count = reports.len() - synced_count;
+    let summary = StatusSummary::from_reports(reports);
 
     // Group by tool
     use std::collections::HashMap;
@@ -148,6 +245,9 @@ pub fn display_status(reports: &[StatusReport]) {
             };
 
             println!("  {} {}{}", icon, report.message, profile_info);
+            if !report.chain.is_empty() {
+                println!("      {} {}", "↳".bright_black(), chain_message(report));
+            }
         }
     }
 
@@ -155,8 +255,8 @@ pub fn display_status(reports: &[StatusReport]) {
     println!(
         "{} {} synced, {} need attention",
         "Summary:".bold(),
-        synced_count.to_string().green(),
-        issues_count.to_string().yellow()
+        summary.synced.to_string().green(),
+        summary.needs_attention.to_string().yellow()
     );
 }
 
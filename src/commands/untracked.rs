@@ -1,11 +1,17 @@
 use crate::config::Config;
-use crate::types::TrackedFile;
+use crate::services::template::{TemplateContext, rendered_differs};
+use crate::types::{Severity, TrackedFile};
 use crate::utils::error::Result;
-use crate::utils::path_utils::{files_differ, resolve_symlink_target, symlink_points_to_correct_target};
+use crate::utils::path_utils::{
+    dir_differences, is_unfolded_dir, paths_differ, resolve_symlink_target,
+    symlink_points_to_correct_target,
+};
 use colored::Colorize;
+use serde::Serialize;
 use std::fs;
 
 /// A discrepancy found in a tracked file.
+#[derive(Serialize)]
 pub struct Discrepancy {
     /// The tracked file with the issue
     pub file: TrackedFile,
@@ -15,7 +21,8 @@ pub struct Discrepancy {
     pub message: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum IssueType {
     /// File doesn't exist at expected location
     Missing,
@@ -31,12 +38,32 @@ pub enum IssueType {
     BrokenSymlink,
 }
 
+impl IssueType {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueType::Missing
+            | IssueType::NotSymlink
+            | IssueType::WrongTarget
+            | IssueType::ContentDiffers => Severity::Warning,
+            IssueType::MissingRepo | IssueType::BrokenSymlink => Severity::Error,
+        }
+    }
+}
+
+/// Document printed by `flux maintain check --format json`.
+#[derive(Serialize)]
+pub struct DiscrepancyOutput<'a> {
+    pub discrepancies: &'a [Discrepancy],
+}
+
 pub fn find_discrepancies(config: &Config, profile: Option<&str>) -> Result<Vec<Discrepancy>> {
     let tracked_files = config.get_tracked_files(profile)?;
+    let templates = TemplateContext::new(config, profile);
     let mut discrepancies = Vec::new();
 
     for file in tracked_files {
-        if let Some(discrepancy) = check_file_discrepancy(&file)? {
+        let (file, _) = file.followed()?;
+        if let Some(discrepancy) = check_file_discrepancy(&file, &templates)? {
             discrepancies.push(discrepancy);
         }
     }
@@ -52,7 +79,10 @@ pub fn find_discrepancies(config: &Config, profile: Option<&str>) -> Result<Vec<
     Ok(discrepancies)
 }
 
-fn check_file_discrepancy(file: &TrackedFile) -> Result<Option<Discrepancy>> {
+fn check_file_discrepancy(
+    file: &TrackedFile,
+    templates: &TemplateContext,
+) -> Result<Option<Discrepancy>> {
     // First check: repo file exists
     if !file.repo_path.exists() {
         return Ok(Some(Discrepancy {
@@ -71,6 +101,56 @@ fn check_file_discrepancy(file: &TrackedFile) -> Result<Option<Discrepancy>> {
         }));
     }
 
+    // Templates and secrets should be a regular file matching the rendered output
+    if file.is_rendered() {
+        if !rendered_differs(file, templates)? {
+            return Ok(None);
+        }
+        let kind = if file.encrypted {
+            "decrypted secret"
+        } else {
+            "rendered template"
+        };
+        let message = if file.dest_path.is_symlink() {
+            format!(
+                "Expected a {} file, found symlink: {}",
+                kind,
+                file.dest_path.display()
+            )
+        } else {
+            format!(
+                "File content differs from {}: {}",
+                kind,
+                file.dest_path.display()
+            )
+        };
+        return Ok(Some(Discrepancy {
+            file: file.clone(),
+            issue: IssueType::ContentDiffers,
+            message,
+        }));
+    }
+
+    // Copies should be a regular file matching the repo file
+    if file.is_copy() {
+        if !file.copy_differs()? {
+            return Ok(None);
+        }
+        let message = if file.dest_path.is_symlink() {
+            format!(
+                "Expected a copy, found symlink: {}",
+                file.dest_path.display()
+            )
+        } else {
+            format!("Copy differs from repo: {}", file.dest_path.display())
+        };
+        return Ok(Some(Discrepancy {
+            file: file.clone(),
+            issue: IssueType::ContentDiffers,
+            message,
+        }));
+    }
+
     // Third check: is it a symlink?
     let is_symlink = file.dest_path.is_symlink();
 
@@ -120,8 +200,45 @@ fn check_file_discrepancy(file: &TrackedFile) -> Result<Option<Discrepancy>> {
             }
         }
     } else {
+        if let Some(folded) = file.folded_dir() {
+            return Ok(Some(Discrepancy {
+                file: file.clone(),
+                issue: IssueType::NotSymlink,
+                message: format!(
+                    "Directory is linked as a whole, expected a link per file (link = \"contents\"): {}",
+                    folded.display()
+                ),
+            }));
+        }
+        if is_unfolded_dir(&file.dest_path, &file.repo_path)? {
+            return Ok(Some(Discrepancy {
+                file: file.clone(),
+                issue: IssueType::NotSymlink,
+                message: format!(
+                    "Directory holds a link per file, expected a symlink to {}: {}",
+                    file.repo_path.display(),
+                    file.dest_path.display()
+                ),
+            }));
+        }
+
         // Not a symlink - check if content differs
-        if files_differ(&file.repo_path, &file.dest_path)? {
+        if file.dest_path.is_dir() && file.repo_path.is_dir() {
+            let differences =
+                dir_differences(&file.repo_path, &file.dest_path, &file.dir_filter()?)?;
+            if !differences.is_empty() {
+                return Ok(Some(Discrepancy {
+                    file: file.clone(),
+                    issue: IssueType::ContentDiffers,
+                    message: format!(
+                        "Directory differs from repo in {} file(s): {}",
+                        differences.len(),
+                        file.dest_path.display()
+                    ),
+                }));
+            }
+        }
+        if paths_differ(&file.repo_path, &file.dest_path, &file.dir_filter()?)? {
             Ok(Some(Discrepancy {
                 file: file.clone(),
                 issue: IssueType::ContentDiffers,
@@ -132,8 +249,7 @@ fn check_file_discrepancy(file: &TrackedFile) -> Result<Option<Discrepancy>> {
             }))
         } else {
             // File exists, content matches, but it's not a symlink
-            // This might be intentional (e.g., if symlink_resolution is "replace")
-            // But typically we expect symlinks, so we'll flag it
+            // Copies are handled above, so a symlink is expected here
             Ok(Some(Discrepancy {
                 file: file.clone(),
                 issue: IssueType::NotSymlink,
@@ -147,7 +263,6 @@ fn check_file_discrepancy(file: &TrackedFile) -> Result<Option<Discrepancy>> {
     }
 }
 
-
 pub fn display_discrepancies(discrepancies: &[Discrepancy]) {
     if discrepancies.is_empty() {
         println!(
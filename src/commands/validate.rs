@@ -1,8 +1,12 @@
 use crate::config::Config;
-use crate::types::TrackedFile;
+use crate::config::profile::{list_profiles, resolve_layers};
+use crate::services::secrets::is_encrypted;
+use crate::types::{EntryType, LinkMode, Severity, TrackedFile};
 use crate::utils::error::Result;
-use crate::utils::path_utils::symlink_points_to_correct_target;
+use crate::utils::path_utils::{DirFilter, symlink_points_to_correct_target};
 use colored::Colorize;
+use serde::Serialize;
+use serde::ser::{SerializeMap, Serializer};
 use std::fs;
 use std::path::Path;
 
@@ -13,6 +17,8 @@ pub enum ValidationIssue {
     MissingRepoFile(TrackedFile),
     /// Symlink is invalid or broken
     InvalidSymlink(TrackedFile),
+    /// File marked `encrypted = true` is stored in plaintext in the repo
+    UnencryptedSecret(TrackedFile),
     /// File exists in repo but not tracked in config
     OrphanedEntry(String, String), // tool, file
     /// Profile directory is missing
@@ -21,7 +27,79 @@ pub enum ValidationIssue {
     InvalidConfig(String),
 }
 
+impl ValidationIssue {
+    /// Stable identifier of the issue kind, used in machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ValidationIssue::MissingRepoFile(_) => "missing_repo_file",
+            ValidationIssue::InvalidSymlink(_) => "invalid_symlink",
+            ValidationIssue::UnencryptedSecret(_) => "unencrypted_secret",
+            ValidationIssue::OrphanedEntry(_, _) => "orphaned_entry",
+            ValidationIssue::MissingProfileDir(_) => "missing_profile_dir",
+            ValidationIssue::InvalidConfig(_) => "invalid_config",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            ValidationIssue::MissingRepoFile(_)
+            | ValidationIssue::UnencryptedSecret(_)
+            | ValidationIssue::InvalidConfig(_) => Severity::Error,
+            ValidationIssue::InvalidSymlink(_)
+            | ValidationIssue::OrphanedEntry(_, _)
+            | ValidationIssue::MissingProfileDir(_) => Severity::Warning,
+        }
+    }
+
+    /// Human-readable description of the issue.
+    pub fn message(&self) -> String {
+        match self {
+            ValidationIssue::MissingRepoFile(file) => {
+                format!("Missing repo file: {}", file.repo_path.display())
+            }
+            ValidationIssue::InvalidSymlink(file) => {
+                format!("Invalid symlink: {}", file.dest_path.display())
+            }
+            ValidationIssue::UnencryptedSecret(file) => format!(
+                "Encrypted file is stored in plaintext: {}",
+                file.repo_path.display()
+            ),
+            ValidationIssue::OrphanedEntry(tool, file) => {
+                format!("Orphaned file in {}: {}", tool, file)
+            }
+            ValidationIssue::MissingProfileDir(profile) => {
+                format!("Missing profile directory: {}", profile)
+            }
+            ValidationIssue::InvalidConfig(msg) => msg.clone(),
+        }
+    }
+}
+
+/// Serialized as `{"kind": ..., "message": ...}` plus the fields of the variant.
+impl Serialize for ValidationIssue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        map.serialize_entry("message", &self.message())?;
+        match self {
+            ValidationIssue::MissingRepoFile(file)
+            | ValidationIssue::InvalidSymlink(file)
+            | ValidationIssue::UnencryptedSecret(file) => map.serialize_entry("file", file)?,
+            ValidationIssue::OrphanedEntry(tool, file) => {
+                map.serialize_entry("tool", tool)?;
+                map.serialize_entry("path", file)?;
+            }
+            ValidationIssue::MissingProfileDir(profile) => {
+                map.serialize_entry("profile", profile)?
+            }
+            ValidationIssue::InvalidConfig(_) => {}
+        }
+        map.end()
+    }
+}
+
 /// Report from configuration validation.
+#[derive(Serialize)]
 pub struct ValidationReport {
     /// All issues found during validation
     pub issues: Vec<ValidationIssue>,
@@ -41,6 +119,67 @@ pub fn validate_config(config: &Config) -> Result<ValidationReport> {
         )));
     }
 
+    // Validate profile inheritance before resolving files through it
+    let mut known_profiles = list_profiles(config)?;
+    for tool_config in config.tools.values() {
+        known_profiles.extend(tool_config.files.iter().filter_map(|f| f.profile.clone()));
+    }
+    let mut inheritance_valid = true;
+    let mut declared: Vec<_> = config.profiles.iter().collect();
+    declared.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, profile_config) in declared {
+        for parent in &profile_config.extends {
+            if !known_profiles.contains(parent) {
+                issues.push(ValidationIssue::InvalidConfig(format!(
+                    "Profile '{}' extends unknown profile '{}'",
+                    name, parent
+                )));
+            }
+        }
+        if let Err(e) = resolve_layers(config, name) {
+            inheritance_valid = false;
+            issues.push(ValidationIssue::InvalidConfig(e.to_string()));
+        }
+    }
+    if !inheritance_valid {
+        return Ok(ValidationReport {
+            is_valid: false,
+            issues,
+        });
+    }
+
+    // Directory globs and link modes must compile and only apply to directory entries
+    let mut tools: Vec<_> = config.tools.iter().collect();
+    tools.sort_by(|a, b| a.0.cmp(b.0));
+    for (tool, tool_config) in tools {
+        for entry in &tool_config.files {
+            let has_globs = !entry.include.is_empty() || !entry.ignore.is_empty();
+            if has_globs && entry.entry_type != EntryType::Dir {
+                issues.push(ValidationIssue::InvalidConfig(format!(
+                    "{}: {} has include/ignore globs but is not type = \"dir\"",
+                    tool, entry.repo
+                )));
+            } else if entry.link == LinkMode::Contents && entry.entry_type != EntryType::Dir {
+                issues.push(ValidationIssue::InvalidConfig(format!(
+                    "{}: {} has link = \"contents\" but is not type = \"dir\"",
+                    tool, entry.repo
+                )));
+            } else if let Err(e) = DirFilter::new(&entry.include, &entry.ignore) {
+                issues.push(ValidationIssue::InvalidConfig(format!(
+                    "{}: {}: {}",
+                    tool, entry.repo, e
+                )));
+            } else if entry.entry_type == EntryType::Dir
+                && config.repo_file_path(tool, entry)?.is_file()
+            {
+                issues.push(ValidationIssue::InvalidConfig(format!(
+                    "{}: {} is type = \"dir\" but is a file in the repository",
+                    tool, entry.repo
+                )));
+            }
+        }
+    }
+
     // Validate all tracked files
     let tracked_files = config.get_tracked_files(None)?;
 
@@ -51,6 +190,12 @@ pub fn validate_config(config: &Config) -> Result<ValidationReport> {
             continue;
         }
 
+        // Check that secrets are not committed in plaintext
+        if file.encrypted && file.repo_path.is_file() && !is_encrypted(&fs::read(&file.repo_path)?)
+        {
+            issues.push(ValidationIssue::UnencryptedSecret(file.clone()));
+        }
+
         // Check if destination is a symlink
         if file.dest_path.exists() && file.dest_path.is_symlink() {
             if let Ok(link_target) = fs::read_link(&file.dest_path) {
@@ -131,9 +276,12 @@ fn check_orphaned_entries(
             tracked_files.insert(normalized_repo);
         }
 
-        // Find orphaned files
+        // Find orphaned files (files inside a tracked directory are tracked with it)
         for repo_file in repo_files {
-            if !tracked_files.contains(&repo_file) {
+            let in_tracked_dir = tracked_files
+                .iter()
+                .any(|tracked| repo_file.starts_with(&format!("{}/", tracked)));
+            if !tracked_files.contains(&repo_file) && !in_tracked_dir {
                 issues.push(ValidationIssue::OrphanedEntry(tool.clone(), repo_file));
             }
         }
@@ -173,31 +321,12 @@ pub fn display_validation(report: &ValidationReport) {
     println!("{}", "=".repeat(60).red());
 
     for issue in &report.issues {
-        match issue {
-            ValidationIssue::MissingRepoFile(file) => {
-                println!(
-                    "{} Missing repo file: {}",
-                    "✗".red(),
-                    file.repo_path.display()
-                );
-            }
-            ValidationIssue::InvalidSymlink(file) => {
-                println!(
-                    "{} Invalid symlink: {}",
-                    "⚠".yellow(),
-                    file.dest_path.display()
-                );
-            }
-            ValidationIssue::OrphanedEntry(tool, file) => {
-                println!("{} Orphaned file in {}: {}", "⊘".yellow(), tool, file);
-            }
-            ValidationIssue::MissingProfileDir(profile) => {
-                println!("{} Missing profile directory: {}", "⚠".yellow(), profile);
-            }
-            ValidationIssue::InvalidConfig(msg) => {
-                println!("{} {}", "✗".red(), msg);
-            }
-        }
+        let icon = match issue {
+            ValidationIssue::OrphanedEntry(_, _) => "⊘".yellow(),
+            _ if issue.severity() == Severity::Error => "✗".red(),
+            _ => "⚠".yellow(),
+        };
+        println!("{} {}", icon, issue.message());
     }
 
     println!("{}", "=".repeat(60).red());
@@ -0,0 +1,335 @@
+use crate::commands::untracked::{Discrepancy, IssueType};
+use crate::config::Config;
+use crate::file_manager::{exclude_ignored_changes, sync_selected_files};
+use crate::services::git::{detect_changes, init_repo};
+use crate::services::hooks::commit_with_hooks;
+use crate::services::systemd::{UnitFile, exec_line, user_unit_dir, write_units};
+use crate::types::{ConflictPolicy, FileChange, TrackedFile};
+use crate::utils::dry_run::DryRun;
+use crate::utils::error::Result;
+use colored::Colorize;
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, poll};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the systemd user unit written by `flux watch --systemd`.
+pub const WATCH_UNIT_NAME: &str = "flux-watch.service";
+
+/// Options for `flux watch`
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// Profile name (optional)
+    pub profile: Option<String>,
+    /// Quiet period after the last change before syncing
+    pub debounce: Duration,
+    /// Policy for destinations an application replaced with a regular file
+    /// (`None`: config on_conflict, or keep-repo)
+    pub conflict_policy: Option<ConflictPolicy>,
+}
+
+/// Tracked paths of one watch round.
+struct WatchSet {
+    files: Vec<TrackedFile>,
+    /// Directories to add inotify watches on
+    dirs: BTreeSet<PathBuf>,
+    /// Destinations that were correct symlinks when the round started
+    linked: HashSet<PathBuf>,
+    config_path: Option<PathBuf>,
+}
+
+impl WatchSet {
+    fn scan(config: &Config, profile: Option<&str>) -> Result<Self> {
+        let mut files = Vec::new();
+        for file in config.get_tracked_files(profile)? {
+            files.push(file.followed()?.0);
+        }
+
+        let mut dirs = BTreeSet::new();
+        let config_path = Config::get_config_path().ok();
+        if let Some(parent) = config_path.as_deref().and_then(Path::parent) {
+            dirs.insert(parent.to_path_buf());
+        }
+        for file in &files {
+            for path in [&file.repo_path, &file.dest_path] {
+                // Watch the parent to see the file being replaced, not just written
+                if let Some(dir) = path.parent().and_then(existing_ancestor) {
+                    dirs.insert(dir);
+                }
+                if path.is_dir() && !path.is_symlink() {
+                    dirs.extend(
+                        walkdir::WalkDir::new(path)
+                            .into_iter()
+                            .filter_map(|entry| entry.ok())
+                            .filter(|entry| entry.file_type().is_dir())
+                            .map(|entry| entry.into_path()),
+                    );
+                }
+            }
+        }
+
+        let linked = files
+            .iter()
+            .filter(|file| file.is_linked())
+            .map(|file| file.dest_path.clone())
+            .collect();
+        Ok(Self {
+            files,
+            dirs,
+            linked,
+            config_path,
+        })
+    }
+
+    /// Whether a change at `path` can affect a tracked file or the configuration.
+    fn is_relevant(&self, path: &Path) -> bool {
+        self.config_path.as_deref() == Some(path)
+            || self.files.iter().any(|file| {
+                [&file.repo_path, &file.dest_path]
+                    .iter()
+                    .any(|tracked| path.starts_with(tracked) || tracked.starts_with(path))
+            })
+    }
+}
+
+/// Watch tracked files in the foreground, committing repository changes and re-linking
+/// destinations that an application replaced with a regular file.
+pub fn watch(options: &WatchOptions) -> Result<()> {
+    let mut config = Config::load()?;
+    let repo_path = config.get_repo_path()?;
+    println!(
+        "{} Watching tracked files in {} (Ctrl-C to stop)",
+        "→".cyan(),
+        repo_path.display()
+    );
+
+    loop {
+        let watch_set = WatchSet::scan(&config, options.profile.as_deref())?;
+        let touched = wait_for_changes(&watch_set, options.debounce)?;
+
+        match Config::load() {
+            Ok(reloaded) => config = reloaded,
+            Err(e) => eprintln!("{} Keeping the previous configuration: {}", "⚠".yellow(), e),
+        }
+        if let Err(e) = sync_changes(&config, options, &watch_set, &touched) {
+            eprintln!("{} {}", "✗".red(), e);
+        }
+    }
+}
+
+/// Block until a relevant change happens, then until no further change arrives for
+/// `debounce`. Returns the changed paths, or every tracked path if events were lost.
+fn wait_for_changes(watch_set: &WatchSet, debounce: Duration) -> Result<BTreeSet<PathBuf>> {
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC).map_err(std::io::Error::from)?;
+    let mask = AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_MODIFY
+        | AddWatchFlags::IN_ATTRIB
+        | AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVED_FROM
+        | AddWatchFlags::IN_MOVED_TO
+        | AddWatchFlags::IN_DELETE_SELF
+        | AddWatchFlags::IN_ONLYDIR;
+    let mut watches: HashMap<WatchDescriptor, PathBuf> = HashMap::new();
+    for dir in &watch_set.dirs {
+        match inotify.add_watch(dir.as_path(), mask) {
+            Ok(wd) => {
+                watches.insert(wd, dir.clone());
+            }
+            Err(e) => log::warn!("Cannot watch {}: {}", dir.display(), e),
+        }
+    }
+
+    let debounce_ms = debounce.as_millis().min(i32::MAX as u128) as i32;
+    let mut touched = BTreeSet::new();
+    loop {
+        let timeout = if touched.is_empty() { -1 } else { debounce_ms };
+        let mut fds = [PollFd::new(&inotify, PollFlags::POLLIN)];
+        match poll(&mut fds, timeout) {
+            Ok(0) => return Ok(touched),
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(std::io::Error::from(e).into()),
+        }
+
+        for event in inotify.read_events().map_err(std::io::Error::from)? {
+            if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+                for file in &watch_set.files {
+                    touched.insert(file.repo_path.clone());
+                    touched.insert(file.dest_path.clone());
+                }
+                continue;
+            }
+            let Some(dir) = watches.get(&event.wd) else {
+                continue;
+            };
+            let path = match &event.name {
+                Some(name) => dir.join(name),
+                None => dir.clone(),
+            };
+            if watch_set.is_relevant(&path) {
+                touched.insert(path);
+            }
+        }
+    }
+}
+
+/// Re-link replaced symlinks, then commit whatever changed in the repository.
+fn sync_changes(
+    config: &Config,
+    options: &WatchOptions,
+    watch_set: &WatchSet,
+    touched: &BTreeSet<PathBuf>,
+) -> Result<()> {
+    let replaced = replaced_symlinks(&watch_set.files, &watch_set.linked, touched);
+    if !replaced.is_empty() {
+        for discrepancy in &replaced {
+            println!("{} {}", "⚠".yellow(), discrepancy.message);
+        }
+        let files: Vec<TrackedFile> = replaced.into_iter().map(|d| d.file).collect();
+        sync_selected_files(
+            config,
+            options.profile.as_deref(),
+            &files,
+            &mut DryRun::default(),
+            replaced_policy(config, options),
+        )?;
+    }
+
+    let repo_path = config.get_repo_path()?;
+    let repo = init_repo(&repo_path)?;
+    let changes = exclude_ignored_changes(config, detect_changes(&repo)?)?;
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let message = watch_commit_message(&changes, &repo_path);
+    commit_with_hooks(
+        config,
+        options.profile.as_deref(),
+        &repo,
+        &changes,
+        &message,
+        &mut DryRun::default(),
+    )
+}
+
+/// Policy for destinations an app replaced: the option, then the config, then keep-repo
+/// (which backs up the app's version). Adopting would commit the app's version, so it is
+/// never the default.
+pub fn replaced_policy(config: &Config, options: &WatchOptions) -> ConflictPolicy {
+    options
+        .conflict_policy
+        .or(config.general.on_conflict)
+        .unwrap_or(ConflictPolicy::KeepRepo)
+}
+
+/// Tracked files whose destination was a correct symlink and has been replaced by a
+/// regular file or directory since, as editors and apps do when they save atomically.
+pub fn replaced_symlinks(
+    files: &[TrackedFile],
+    linked: &HashSet<PathBuf>,
+    touched: &BTreeSet<PathBuf>,
+) -> Vec<Discrepancy> {
+    files
+        .iter()
+        .filter(|file| linked.contains(&file.dest_path))
+        .filter(|file| touched.iter().any(|path| file.dest_path.starts_with(path)))
+        .filter(|file| file.dest_path.exists() && !file.dest_path.is_symlink())
+        .map(|file| Discrepancy {
+            file: file.clone(),
+            issue: IssueType::NotSymlink,
+            message: format!(
+                "Symlink was replaced by a regular file (expected symlink to {}): {}",
+                file.repo_path.display(),
+                file.dest_path.display()
+            ),
+        })
+        .collect()
+}
+
+/// Commit message for changes picked up by `flux watch`, naming the file when there is
+/// only one and the tools otherwise.
+pub fn watch_commit_message(changes: &[FileChange], repo_root: &Path) -> String {
+    let relative =
+        |path: &Path| -> PathBuf { path.strip_prefix(repo_root).unwrap_or(path).to_path_buf() };
+
+    if let [change] = changes {
+        let (verb, path) = match change {
+            FileChange::Added(path) => ("add", path),
+            FileChange::Modified(path) => ("update", path),
+            FileChange::Deleted(path) => ("remove", path),
+        };
+        return format!("flux watch: {} {}", verb, relative(path).display());
+    }
+
+    let tools: BTreeSet<String> = changes
+        .iter()
+        .filter_map(|change| {
+            relative(change.path())
+                .components()
+                .next()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        })
+        .collect();
+    format!(
+        "flux watch: update {} files in {}",
+        changes.len(),
+        tools.into_iter().collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// systemd user unit that runs `flux watch` with the same options.
+pub fn render_watch_unit(executable: &Path, options: &WatchOptions) -> String {
+    let mut command = vec![
+        executable.display().to_string(),
+        "watch".to_string(),
+        "--debounce".to_string(),
+        options.debounce.as_secs().to_string(),
+    ];
+    if let Some(profile) = &options.profile {
+        command.extend(["--profile".to_string(), profile.clone()]);
+    }
+    if let Some(policy) = options.conflict_policy {
+        command.extend(["--on-conflict".to_string(), policy.to_string()]);
+    }
+
+    format!(
+        "[Unit]\n\
+         Description=flux dotfiles watcher\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exec_line(&command)
+    )
+}
+
+/// Write the `flux watch` unit to the systemd user unit directory.
+pub fn install_watch_unit(options: &WatchOptions) -> Result<PathBuf> {
+    let unit = UnitFile {
+        name: WATCH_UNIT_NAME.to_string(),
+        contents: render_watch_unit(&std::env::current_exe()?, options),
+    };
+    let unit_path = write_units(&user_unit_dir()?, &[unit])?.remove(0);
+
+    println!("{} Wrote {}", "✓".green(), unit_path.display());
+    println!(
+        "  Enable it with: systemctl --user daemon-reload && systemctl --user enable --now {}",
+        WATCH_UNIT_NAME
+    );
+    Ok(unit_path)
+}
+
+/// The path itself, or its closest ancestor that exists.
+fn existing_ancestor(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|ancestor| ancestor.is_dir())
+        .map(Path::to_path_buf)
+}
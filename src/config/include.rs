@@ -0,0 +1,354 @@
+//! Composition of the config from `general.include` files.
+//!
+//! A file is merged before the files it includes, and each include is resolved
+//! recursively right after it, depth first, so later files override earlier ones.
+//! Merging is per key: `general` settings, `profiles` and `[environment]` variables
+//! individually, and `tools` per tool, so an included `[tools.nvim]` replaces the whole
+//! tool. Include paths support `~` and are relative to the including file. A file
+//! included more than once is merged only the first time, and includes that do not
+//! exist are skipped, so a machine-local overlay can be absent on other machines.
+//!
+//! When the config is saved, every part is written back to the file it came from.
+
+use super::{Config, ToolConfig};
+use crate::utils::error::{DotfilesError, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Where each part of a composed config came from.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    /// The config file that was loaded
+    pub root: PathBuf,
+    /// Every file that was merged, in merge order (the root first)
+    pub files: Vec<PathBuf>,
+    /// Included files that do not exist
+    pub missing: Vec<PathBuf>,
+    /// Included file that last set each key (e.g. `["tools", "nvim"]`); keys set only
+    /// by the root file are absent
+    origins: BTreeMap<Vec<String>, PathBuf>,
+    /// The merged document as loaded, to find what changed when saving
+    loaded: Table,
+}
+
+/// A key to set in an included file, or to remove when the value is `None`.
+type KeyChange<'a> = (&'a [String], Option<&'a Value>);
+
+/// Load `root` and every file it includes, merged into a single document.
+pub fn load_composed(root: &Path) -> Result<(Table, ConfigSources)> {
+    let mut sources = ConfigSources {
+        root: root.to_path_buf(),
+        ..ConfigSources::default()
+    };
+    let mut merged = Table::new();
+    merge_file(root, &mut Vec::new(), &mut merged, &mut sources)?;
+    sources.loaded = merged.clone();
+    Ok((merged, sources))
+}
+
+fn merge_file(
+    path: &Path,
+    visited: &mut Vec<PathBuf>,
+    merged: &mut Table,
+    sources: &mut ConfigSources,
+) -> Result<()> {
+    if let Some(start) = visited.iter().position(|seen| seen == path) {
+        let chain: Vec<_> = visited[start..]
+            .iter()
+            .chain(std::iter::once(&path.to_path_buf()))
+            .map(|p| p.display().to_string())
+            .collect();
+        return Err(DotfilesError::Config(format!(
+            "Config include cycle: {}",
+            chain.join(" → ")
+        )));
+    }
+
+    // A file included more than once is merged only the first time
+    if sources.files.iter().any(|merged| merged == path) {
+        return Ok(());
+    }
+
+    let table = read_table(path)?;
+    let is_root = visited.is_empty();
+    let includes = include_paths(&table, path)?;
+    merge_table(merged, table, path, is_root, sources);
+    sources.files.push(path.to_path_buf());
+
+    visited.push(path.to_path_buf());
+    for include in includes {
+        match include.canonicalize() {
+            Ok(include) => merge_file(&include, visited, merged, sources)?,
+            Err(_) => sources.missing.push(include),
+        }
+    }
+    visited.pop();
+    Ok(())
+}
+
+fn read_table(path: &Path) -> Result<Table> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        DotfilesError::Config(format!("Failed to read config {}: {}", path.display(), e))
+    })?;
+    toml::from_str(&content).map_err(|e| {
+        DotfilesError::Config(format!("Failed to parse config {}: {}", path.display(), e))
+    })
+}
+
+/// Resolve the `general.include` entries of a file against its directory.
+fn include_paths(table: &Table, path: &Path) -> Result<Vec<PathBuf>> {
+    let Some(include) = table
+        .get("general")
+        .and_then(|general| general.get("include"))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let entries = include.as_array().ok_or_else(|| {
+        DotfilesError::Config(format!(
+            "general.include in {} must be an array of paths",
+            path.display()
+        ))
+    })?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.as_str().ok_or_else(|| {
+                DotfilesError::Config(format!(
+                    "general.include in {} must be an array of paths",
+                    path.display()
+                ))
+            })?;
+            Ok(base.join(shellexpand::tilde(entry).as_ref()))
+        })
+        .collect()
+}
+
+fn merge_table(
+    merged: &mut Table,
+    table: Table,
+    path: &Path,
+    is_root: bool,
+    sources: &mut ConfigSources,
+) {
+    let mut record = |key: Vec<String>| {
+        if !is_root {
+            sources.origins.insert(key, path.to_path_buf());
+        }
+    };
+
+    for (section, value) in table {
+        match (section.as_str(), value) {
+            ("general" | "tools" | "profiles", Value::Table(entries)) => {
+                let target = section_table(merged, &section);
+                for (key, value) in entries {
+                    // Only the root file's includes are kept; the others are already resolved
+                    if section == "general" && key == "include" && !is_root {
+                        continue;
+                    }
+                    record(vec![section.clone(), key.clone()]);
+                    target.insert(key, value);
+                }
+            }
+            ("environment", Value::Table(entries)) => {
+                let target = section_table(merged, &section);
+                for (key, value) in entries {
+                    match value {
+                        Value::Table(variables) if key == "variables" => {
+                            let target = section_table(target, &key);
+                            for (name, value) in variables {
+                                record(vec![section.clone(), key.clone(), name.clone()]);
+                                target.insert(name, value);
+                            }
+                        }
+                        value => {
+                            record(vec![section.clone(), key.clone()]);
+                            target.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (_, value) => {
+                record(vec![section.clone()]);
+                merged.insert(section, value);
+            }
+        }
+    }
+}
+
+fn section_table<'a>(table: &'a mut Table, key: &str) -> &'a mut Table {
+    let entry = table
+        .entry(key)
+        .or_insert_with(|| Value::Table(Table::new()));
+    if !entry.is_table() {
+        *entry = Value::Table(Table::new());
+    }
+    entry.as_table_mut().expect("entry was just made a table")
+}
+
+fn lookup<'a>(table: &'a Table, key: &[String]) -> Option<&'a Value> {
+    let (last, parents) = key.split_last()?;
+    let mut table = table;
+    for parent in parents {
+        table = table.get(parent)?.as_table()?;
+    }
+    table.get(last)
+}
+
+impl ConfigSources {
+    /// Included file that a key such as `["tools", "nvim"]` came from, if not the root.
+    pub fn origin(&self, key: &[&str]) -> Option<&Path> {
+        let key: Vec<String> = key.iter().map(|part| part.to_string()).collect();
+        self.origins.get(&key).map(PathBuf::as_path)
+    }
+
+    /// Write changes to parts that came from included files back to those files.
+    ///
+    /// Returns the keys owned by included files, which must not be written to the root.
+    pub fn save_included(&self, current: &Table) -> Result<Vec<Vec<String>>> {
+        let mut changes: BTreeMap<&Path, Vec<KeyChange>> = BTreeMap::new();
+        for (key, path) in &self.origins {
+            let value = lookup(current, key);
+            if value != lookup(&self.loaded, key) {
+                changes.entry(path).or_default().push((key, value));
+            }
+        }
+
+        for (path, changes) in changes {
+            update_file(path, &changes)?;
+        }
+        Ok(self.origins.keys().cloned().collect())
+    }
+}
+
+/// Set or remove keys in an included file, preserving its comments and formatting.
+fn update_file(path: &Path, changes: &[KeyChange]) -> Result<()> {
+    let content = fs::read_to_string(path)?;
+    let mut doc = content.parse::<toml_edit::DocumentMut>().map_err(|e| {
+        DotfilesError::Config(format!("Failed to parse config {}: {}", path.display(), e))
+    })?;
+
+    for (key, value) in changes {
+        let Some((last, parents)) = key.split_last() else {
+            continue;
+        };
+        let mut table = doc.as_table_mut();
+        for parent in parents {
+            let item = table
+                .entry(parent)
+                .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()));
+            table = item.as_table_mut().ok_or_else(|| {
+                DotfilesError::Config(format!(
+                    "Cannot update {} in {}: '{}' is not a table",
+                    key.join("."),
+                    path.display(),
+                    parent
+                ))
+            })?;
+        }
+
+        match value {
+            Some(value) => {
+                let mut item = to_item(key, value)?;
+                // Keep comments attached to a table that is rewritten
+                if let (Some(new), Some(old)) = (
+                    item.as_table_mut(),
+                    table.get(last).and_then(|item| item.as_table()),
+                ) {
+                    *new.decor_mut() = old.decor().clone();
+                }
+                table.insert(last, item);
+            }
+            None => {
+                table.remove(last);
+            }
+        }
+    }
+
+    fs::write(path, doc.to_string())?;
+    Ok(())
+}
+
+fn to_item(key: &[String], value: &Value) -> Result<toml_edit::Item> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        DotfilesError::Config(format!("Failed to serialize {}: {}", key.join("."), e))
+    };
+
+    if key.len() == 2 && key[0] == "tools" {
+        let tool: ToolConfig = value.clone().try_into().map_err(|e| invalid(&e))?;
+        return Ok(toml_edit::Item::Table(Config::tool_table(&tool)));
+    }
+    let value = value
+        .serialize(toml_edit::ser::ValueSerializer::new())
+        .map_err(|e| invalid(&e))?;
+    Ok(toml_edit::Item::Value(value))
+}
+
+/// Display the merged config, annotated with the file each tool and override came from.
+pub fn display_resolved_config(config: &Config) -> Result<()> {
+    let sources = &config.sources;
+    println!("\n{}", "Config files (in merge order):".bold().cyan());
+    for (index, file) in sources.files.iter().enumerate() {
+        println!("  {}. {}", index + 1, file.display());
+    }
+    for file in &sources.missing {
+        println!(
+            "  {} {} {}",
+            "⊘".yellow(),
+            file.display(),
+            "(missing, skipped)".bright_black()
+        );
+    }
+    println!("{}", "=".repeat(60).cyan());
+
+    let mut doc = config.to_document()?;
+    let root = sources.root.display().to_string();
+
+    if let Some(general) = doc.get_mut("general").and_then(|item| item.as_table_mut()) {
+        for (key, item) in general.iter_mut() {
+            if let (Some(origin), Some(value)) =
+                (sources.origin(&["general", key.get()]), item.as_value_mut())
+            {
+                value
+                    .decor_mut()
+                    .set_suffix(format!("  # from {}", origin.display()));
+            }
+        }
+    }
+
+    if let Some(tools) = doc.get_mut("tools").and_then(|item| item.as_table_mut()) {
+        tools.sort_values();
+        for (key, item) in tools.iter_mut() {
+            let origin = sources
+                .origin(&["tools", key.get()])
+                .map(|path| path.display().to_string())
+                .unwrap_or_else(|| root.clone());
+            if let Some(table) = item.as_table_mut() {
+                table
+                    .decor_mut()
+                    .set_prefix(format!("\n# from {}\n", origin));
+            }
+        }
+    }
+
+    if let Some(profiles) = doc.get_mut("profiles").and_then(|item| item.as_table_mut()) {
+        for (key, item) in profiles.iter_mut() {
+            if let (Some(origin), Some(table)) = (
+                sources.origin(&["profiles", key.get()]),
+                item.as_table_mut(),
+            ) {
+                table
+                    .decor_mut()
+                    .set_prefix(format!("\n# from {}\n", origin.display()));
+            }
+        }
+    }
+
+    print!("{}", doc);
+    Ok(())
+}
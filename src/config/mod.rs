@@ -1,7 +1,10 @@
 pub mod cli;
+pub mod include;
 pub mod profile;
+pub mod selection;
 
 pub use cli::EnvironmentConfig;
+pub use selection::{HostFacts, SelectedFile};
 
 // The config module itself is in this file
 use colored::Colorize;
@@ -9,9 +12,15 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use toml::{Table, Value};
 
-use crate::types::{EnvironmentSpec, FileEntry, SymlinkResolution};
+use crate::services::browser;
+use crate::types::{
+    ConflictPolicy, DirSpec, EntryType, EnvironmentSpec, FileEntry, FileKind, Hooks, LinkMode,
+    SymlinkResolution,
+};
 use crate::utils::error::{DotfilesError, Result};
+use selection::{apply_overrides, select_entry};
 
 /// General configuration settings.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,9 +43,27 @@ pub struct GeneralConfig {
     /// Push timeout in seconds (default: 60)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub push_timeout: Option<u64>,
-    /// Config files to include and merge (later files override earlier ones)
+    /// Config files to include and merge (later files override earlier ones, see
+    /// [`include`] for the merge order)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub include: Option<Vec<String>>,
+    /// State directory for apply generations (supports ~ expansion, default: XDG state dir)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<String>,
+    /// Number of apply generations kept in the state directory; older ones are pruned
+    /// when a new one is recorded (default: 50)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_generations: Option<usize>,
+    /// Identity file used to decrypt encrypted files (supports ~ expansion,
+    /// default: ~/.config/flux/identity.txt)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity_file: Option<String>,
+    /// Additional public keys that encrypted files are encrypted to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipients: Option<Vec<String>>,
+    /// Conflict policy used instead of prompting when not interactive (default: keep-repo)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_conflict: Option<ConflictPolicy>,
 }
 
 fn default_symlink_resolution() -> SymlinkResolution {
@@ -54,15 +81,67 @@ impl Default for GeneralConfig {
             default_branch: None,
             push_timeout: None,
             include: None,
+            state_dir: None,
+            keep_generations: None,
+            identity_file: None,
+            recipients: None,
+            on_conflict: None,
         }
     }
 }
 
+/// Name of a unit enum variant as serde writes it (e.g. `SymlinkResolution::Replace` is
+/// "replace").
+fn variant_name<T: Serialize>(value: &T) -> String {
+    Value::try_from(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Remove a nested key such as `["general", "repo_path"]` from a document table.
+fn remove_key(table: &mut toml_edit::Table, key: &[String]) {
+    let Some((last, parents)) = key.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for parent in parents {
+        match table.get_mut(parent).and_then(|item| item.as_table_mut()) {
+            Some(child) => table = child,
+            None => return,
+        }
+    }
+    table.remove(last);
+}
+
+/// `configured` with `~` expanded, or `$XDG_STATE_HOME/flux` if it is not set.
+fn state_dir(configured: Option<&str>) -> Result<PathBuf> {
+    if let Some(state_dir) = configured {
+        return Ok(PathBuf::from(shellexpand::tilde(state_dir).into_owned()));
+    }
+
+    let state_dir = dirs::state_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+        .ok_or_else(|| DotfilesError::Config("Could not find state directory".to_string()))?;
+    Ok(state_dir.join("flux"))
+}
+
 /// Configuration for a single tool.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolConfig {
     /// Files tracked for this tool
     pub files: Vec<FileEntry>,
+    /// Commands run when this tool's files change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
+}
+
+/// Configuration for a single profile.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProfileConfig {
+    /// Profiles this profile inherits files from, lowest priority first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -70,11 +149,18 @@ pub struct Config {
     pub general: GeneralConfig,
     #[serde(default)]
     pub tools: HashMap<String, ToolConfig>,
+    /// Profile inheritance (e.g., [profiles.work] extends = ["base"])
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, ProfileConfig>,
 
     // ==================== New Declarative System Layer ====================
     /// Environment configuration (e.g., [environment])
     #[serde(skip_serializing_if = "Option::is_none")]
     pub environment: Option<EnvironmentSpec>,
+
+    /// Files this config was composed from
+    #[serde(skip)]
+    pub sources: include::ConfigSources,
 }
 
 impl Config {
@@ -91,7 +177,7 @@ impl Config {
             let xdg_config = Self::get_xdg_config_path()?;
             if xdg_config.exists() {
                 // XDG config exists - use it and overwrite repo version
-                let config = Self::load_from_path(&xdg_config, &mut Vec::new())?;
+                let config = Self::load_from_path(&xdg_config)?;
                 // Overwrite repo version with XDG contents
                 let repo_config = Self::get_repo_config_path()?;
                 // Create parent directory if needed
@@ -114,13 +200,23 @@ impl Config {
             Self::get_config_path()?
         };
 
-        Self::load_from_path(&config_path, &mut Vec::new())
+        Self::load_from_path(&config_path)
     }
 
-    /// Load configuration from a specific path.
-    ///
-    /// No merging - configs are loaded as-is with precedence order.
-    fn load_from_path(config_path: &Path, _visited: &mut Vec<PathBuf>) -> Result<Self> {
+    /// Load a config file (and its includes) as it is, without creating a default config
+    /// or syncing it with the XDG config.
+    pub fn load_file(config_path: &Path) -> Result<Self> {
+        if !config_path.exists() {
+            return Err(DotfilesError::Config(format!(
+                "What: Config file not found: {}",
+                config_path.display()
+            )));
+        }
+        Self::load_from_path(config_path)
+    }
+
+    /// Load configuration from a specific path, merging in its `general.include` files.
+    fn load_from_path(config_path: &Path) -> Result<Self> {
         let config_path = match config_path.canonicalize() {
             Ok(path) => path,
             Err(_) => config_path.to_path_buf(),
@@ -130,24 +226,20 @@ impl Config {
             // Create default config if it doesn't exist
             let config = Config::default();
             config.save(false)?;
-            return Ok(config);
+            if !config_path.exists() {
+                return Ok(config);
+            }
         }
 
-        let content = fs::read_to_string(&config_path).map_err(|e| {
-            DotfilesError::Config(format!(
-                "Failed to read config {}: {}",
-                config_path.display(),
-                e
-            ))
-        })?;
-
-        let config: Config = toml::from_str(&content).map_err(|e| {
+        let (merged, sources) = include::load_composed(&config_path)?;
+        let mut config: Config = Value::Table(merged).try_into().map_err(|e| {
             DotfilesError::Config(format!(
                 "Failed to parse config {}: {}",
                 config_path.display(),
                 e
             ))
         })?;
+        config.sources = sources;
 
         // Validate the config
         config.validate()?;
@@ -190,16 +282,15 @@ impl Config {
             toml_edit::DocumentMut::new()
         };
 
-        // Serialize current config to TOML and merge into existing document
-        let new_toml = toml::to_string_pretty(self)
+        // Parts that came from included files are written back there, not to this file
+        let current = Table::try_from(self)
             .map_err(|e| DotfilesError::Config(format!("Failed to serialize config: {}", e)))?;
-        let mut new_doc = new_toml.parse::<toml_edit::DocumentMut>().map_err(|e| {
-            DotfilesError::Config(format!("Failed to parse serialized config: {}", e))
-        })?;
+        let included_keys = self.sources.save_included(&current)?;
 
-        // Manually format tools section to use per-tool format [tools.X] files = [...]
-        // instead of array-of-tables [[tools.X.files]]
-        Self::format_tools_section(&mut new_doc, &self.tools);
+        let mut new_doc = self.to_document()?;
+        for key in &included_keys {
+            remove_key(new_doc.as_table_mut(), key);
+        }
 
         // Remove old array-of-tables format from existing document if present
         Self::remove_old_tools_format(&mut doc);
@@ -230,10 +321,24 @@ impl Config {
         Ok(())
     }
 
+    /// Serialize the config to a TOML document in the format it is saved in.
+    pub(crate) fn to_document(&self) -> Result<toml_edit::DocumentMut> {
+        let new_toml = toml::to_string_pretty(self)
+            .map_err(|e| DotfilesError::Config(format!("Failed to serialize config: {}", e)))?;
+        let mut new_doc = new_toml.parse::<toml_edit::DocumentMut>().map_err(|e| {
+            DotfilesError::Config(format!("Failed to parse serialized config: {}", e))
+        })?;
+
+        // Manually format tools section to use per-tool format [tools.X] files = [...]
+        // instead of array-of-tables [[tools.X.files]]
+        Self::format_tools_section(&mut new_doc, &self.tools);
+        Ok(new_doc)
+    }
+
     /// Format tools section to use per-tool format `[tools.X] files = [...]`
     /// instead of array-of-tables `[[tools.X.files]]`.
     fn format_tools_section(doc: &mut toml_edit::DocumentMut, tools: &HashMap<String, ToolConfig>) {
-        use toml_edit::{Array, Item, Table, Value};
+        use toml_edit::{Item, Table};
 
         // Remove existing tools section if present
         doc.remove("tools");
@@ -243,46 +348,109 @@ impl Config {
         tools_table.set_implicit(true);
 
         for (tool_name, tool_config) in tools {
-            // Create table for this tool
-            let mut tool_table = Table::new();
-            tool_table.set_implicit(true);
+            tools_table.insert(tool_name, Item::Table(Self::tool_table(tool_config)));
+        }
 
-            // Create array of file entries
-            let mut files_array = Array::new();
-            files_array.set_trailing_comma(true);
-            files_array.set_trailing("\n");
+        doc.insert("tools", Item::Table(tools_table));
+    }
 
-            for file_entry in &tool_config.files {
-                let mut file_table = toml_edit::InlineTable::new();
+    /// Format one tool as `[tools.X] files = [...]` with an inline table per file.
+    pub(crate) fn tool_table(tool_config: &ToolConfig) -> toml_edit::Table {
+        use toml_edit::{Array, Item, Table, Value};
 
-                // Add repo field
+        // Create table for this tool
+        let mut tool_table = Table::new();
+        tool_table.set_implicit(true);
+
+        // Create array of file entries
+        let mut files_array = Array::new();
+        files_array.set_trailing_comma(true);
+        files_array.set_trailing("\n");
+
+        for file_entry in &tool_config.files {
+            let mut file_table = toml_edit::InlineTable::new();
+
+            // Add repo field
+            file_table.insert(
+                "repo",
+                Value::String(toml_edit::Formatted::new(file_entry.repo.clone())),
+            );
+
+            // Add dest field
+            file_table.insert(
+                "dest",
+                Value::String(toml_edit::Formatted::new(file_entry.dest.clone())),
+            );
+
+            // Add profile field if present
+            if let Some(profile) = &file_entry.profile {
                 file_table.insert(
-                    "repo",
-                    Value::String(toml_edit::Formatted::new(file_entry.repo.clone())),
+                    "profile",
+                    Value::String(toml_edit::Formatted::new(profile.clone())),
                 );
+            }
 
-                // Add dest field
+            // Add host patterns if present
+            if let Some(hosts) = &file_entry.hosts {
                 file_table.insert(
-                    "dest",
-                    Value::String(toml_edit::Formatted::new(file_entry.dest.clone())),
+                    "hosts",
+                    Value::Array(hosts.iter().map(|host| host.as_str()).collect()),
                 );
+            }
 
-                // Add profile field if present
-                if let Some(profile) = &file_entry.profile {
-                    file_table.insert(
-                        "profile",
-                        Value::String(toml_edit::Formatted::new(profile.clone())),
-                    );
-                }
+            // Add os matcher if present
+            if let Some(os) = &file_entry.os {
+                file_table.insert("os", Value::String(toml_edit::Formatted::new(os.clone())));
+            }
+
+            // Add template flag if set
+            if file_entry.template {
+                file_table.insert("template", Value::Boolean(toml_edit::Formatted::new(true)));
+            }
+
+            // Add encrypted flag if set
+            if file_entry.encrypted {
+                file_table.insert("encrypted", Value::Boolean(toml_edit::Formatted::new(true)));
+            }
 
-                files_array.push_formatted(Value::InlineTable(file_table));
+            // Add resolution override if present
+            if let Some(resolution) = &file_entry.resolution {
+                file_table.insert("resolution", Value::from(variant_name(resolution)));
             }
 
-            tool_table.insert("files", Item::Value(Value::Array(files_array)));
-            tools_table.insert(tool_name, Item::Table(tool_table));
+            // Add directory settings if this is a directory entry
+            if !file_entry.entry_type.is_file() {
+                file_table.insert("type", Value::from(variant_name(&file_entry.entry_type)));
+            }
+            if !file_entry.ignore.is_empty() {
+                file_table.insert(
+                    "ignore",
+                    Value::Array(file_entry.ignore.iter().map(String::as_str).collect()),
+                );
+            }
+            if !file_entry.include.is_empty() {
+                file_table.insert(
+                    "include",
+                    Value::Array(file_entry.include.iter().map(String::as_str).collect()),
+                );
+            }
+            if !file_entry.link.is_dir() {
+                file_table.insert("link", Value::from(variant_name(&file_entry.link)));
+            }
+
+            // Add database settings if this is a snapshot entry
+            if !file_entry.kind.is_plain() {
+                file_table.insert("kind", Value::from(variant_name(&file_entry.kind)));
+            }
+            if let Some(app) = &file_entry.app {
+                file_table.insert("app", Value::from(app.as_str()));
+            }
+
+            files_array.push_formatted(Value::InlineTable(file_table));
         }
 
-        doc.insert("tools", Item::Table(tools_table));
+        tool_table.insert("files", Item::Value(Value::Array(files_array)));
+        tool_table
     }
 
     /// Remove old array-of-tables format `[[tools.X.files]]` from document.
@@ -392,7 +560,7 @@ impl Config {
     }
 
     /// Get the repo config path
-    fn get_repo_config_path() -> Result<PathBuf> {
+    pub(crate) fn get_repo_config_path() -> Result<PathBuf> {
         let home = dirs::home_dir()
             .ok_or_else(|| DotfilesError::Config("Could not find home directory".to_string()))?;
         Ok(home.join(".dotfiles").join("config.toml"))
@@ -408,6 +576,31 @@ impl Config {
         Ok(PathBuf::from(expanded))
     }
 
+    /// Get the state directory (`general.state_dir`, or `$XDG_STATE_HOME/flux`).
+    pub fn get_state_dir(&self) -> Result<PathBuf> {
+        state_dir(self.general.state_dir.as_deref())
+    }
+
+    /// Get the state directory of the config [`Config::load`] would read, without
+    /// loading it: no default config is created, nothing is synced and only
+    /// `general.state_dir` is looked at.
+    pub fn locate_state_dir() -> Result<PathBuf> {
+        let config_path = match std::env::var(cli::env_keys::CONFIG_FILE) {
+            Ok(config_path_str) => PathBuf::from(config_path_str),
+            Err(_) => Self::get_config_path()?,
+        };
+        if !config_path.exists() {
+            return state_dir(None);
+        }
+        let (merged, _) = include::load_composed(&config_path)?;
+        state_dir(
+            merged
+                .get("general")
+                .and_then(|general| general.get("state_dir"))
+                .and_then(Value::as_str),
+        )
+    }
+
     pub fn validate(&self) -> Result<()> {
         if self.general.repo_path.is_empty() {
             return Err(DotfilesError::Config(
@@ -447,65 +640,132 @@ impl Config {
         repo_file: &str,
         dest_path: &Path,
         profile: Option<&str>,
-    ) -> Result<()> {
+    ) -> Result<&mut FileEntry> {
         let dest_str = dest_path.to_string_lossy().to_string();
 
         let entry = FileEntry {
             repo: repo_file.to_string(),
             dest: dest_str,
             profile: profile.map(|p| p.to_string()),
+            hosts: None,
+            os: None,
+            template: false,
+            encrypted: false,
+            resolution: None,
+            entry_type: EntryType::File,
+            ignore: Vec::new(),
+            include: Vec::new(),
+            link: LinkMode::Dir,
+            kind: FileKind::Plain,
+            app: None,
         };
 
-        self.tools
+        let files = &mut self
+            .tools
             .entry(tool.to_string())
-            .or_insert(ToolConfig { files: Vec::new() })
-            .files
-            .push(entry);
+            .or_insert(ToolConfig {
+                files: Vec::new(),
+                hooks: None,
+            })
+            .files;
+        files.push(entry);
 
-        Ok(())
+        Ok(files.last_mut().expect("entry was just pushed"))
+    }
+
+    /// Full path of a file entry in the repository.
+    pub fn repo_file_path(&self, tool: &str, file: &FileEntry) -> Result<PathBuf> {
+        let repo_path = self.get_repo_path()?;
+        // Handle both cases: file.repo may or may not include the tool name prefix
+        if file.repo.starts_with(&format!("{}/", tool)) {
+            // file.repo already includes tool name (e.g., "cursor/settings.json")
+            Ok(repo_path.join(&file.repo))
+        } else {
+            // file.repo doesn't include tool name (e.g., "config")
+            Ok(repo_path.join(tool).join(&file.repo))
+        }
     }
 
     pub fn get_tracked_files(
         &self,
         profile: Option<&str>,
     ) -> Result<Vec<crate::types::TrackedFile>> {
-        let repo_path = self.get_repo_path()?;
+        let mut files = Vec::new();
+        for selected in self.select_files(profile, &HostFacts::current())? {
+            if selected.selection.included {
+                // `link = "contents"` directories are placed file by file
+                files.extend(selected.file.expand_contents()?);
+            }
+        }
+        Ok(files)
+    }
+
+    /// Evaluate every file entry against the profile and host, keeping excluded entries
+    /// along with the reason they were excluded.
+    ///
+    /// Entries are returned in a deterministic order (by tool, then position). When
+    /// several included entries share a destination, the one from the highest profile
+    /// layer wins (later position breaks ties) and the others are marked as overridden.
+    pub fn select_files(
+        &self,
+        profile: Option<&str>,
+        facts: &HostFacts,
+    ) -> Result<Vec<SelectedFile>> {
         let current_profile = profile.unwrap_or(&self.general.current_profile);
+        let layers = profile::resolve_layers(self, current_profile)?;
+        let home = dirs::home_dir()
+            .ok_or_else(|| DotfilesError::Config("Could not find home directory".to_string()))?;
 
-        let mut tracked_files = Vec::new();
+        let mut tools: Vec<_> = self.tools.iter().collect();
+        tools.sort_by(|a, b| a.0.cmp(b.0));
 
-        for (tool, tool_config) in &self.tools {
+        let mut selected_files = Vec::new();
+
+        for (tool, tool_config) in tools {
             for file in &tool_config.files {
-                // Include if no profile specified, or if profile matches current_profile or is None
-                let include =
-                    file.profile.is_none() || file.profile.as_deref() == Some(current_profile);
-
-                if include {
-                    // Handle both cases: file.repo may or may not include the tool name prefix
-                    let repo_file_path = if file.repo.starts_with(&format!("{}/", tool)) {
-                        // file.repo already includes tool name (e.g., "cursor/settings.json")
-                        repo_path.join(&file.repo)
-                    } else {
-                        // file.repo doesn't include tool name (e.g., "config")
-                        repo_path.join(tool).join(&file.repo)
-                    };
-                    let dest_path = dirs::home_dir()
-                        .ok_or_else(|| {
-                            DotfilesError::Config("Could not find home directory".to_string())
-                        })?
-                        .join(&file.dest);
-
-                    tracked_files.push(crate::types::TrackedFile {
+                let mut selection = select_entry(file, &layers, facts)?;
+                // A browser profile that does not exist on this machine excludes the entry
+                let dest_path = match browser::resolve_dest(&home, &file.dest) {
+                    Ok(path) => path,
+                    Err(reason) => {
+                        selection.included = false;
+                        selection.reasons.push(reason);
+                        home.join(&file.dest)
+                    }
+                };
+
+                selected_files.push(SelectedFile {
+                    file: crate::types::TrackedFile {
                         tool: tool.clone(),
-                        repo_path: repo_file_path,
+                        repo_path: self.repo_file_path(tool, file)?,
                         dest_path,
                         profile: file.profile.clone(),
-                    });
-                }
+                        template: file.template,
+                        encrypted: file.encrypted,
+                        // A database snapshot is always copied: SQLite follows symlinks
+                        // and would keep its WAL and journal in the repository
+                        resolution: if file.kind == FileKind::Sqlite {
+                            SymlinkResolution::Replace
+                        } else {
+                            file.resolution.unwrap_or(self.general.symlink_resolution)
+                        },
+                        dir: (file.entry_type == EntryType::Dir).then(|| DirSpec {
+                            include: file.include.clone(),
+                            ignore: file.ignore.clone(),
+                            link: file.link,
+                        }),
+                        link_root: None,
+                        sqlite_owner: (file.kind == FileKind::Sqlite)
+                            .then(|| file.app.clone().unwrap_or_else(|| tool.clone())),
+                    },
+                    selection,
+                });
             }
         }
 
-        Ok(tracked_files)
+        apply_overrides(&mut selected_files, &layers);
+
+        Ok(selected_files)
     }
 
     /// Sync XDG config to repo (overwrite repo config with XDG config).
@@ -1,26 +1,39 @@
-use crate::config::Config;
-use crate::utils::error::Result;
+use crate::config::selection::layer_name;
+use crate::config::{Config, HostFacts, ProfileConfig};
+use crate::utils::error::{DotfilesError, Result};
 use crate::utils::error_utils;
 use colored::Colorize;
-use std::path::PathBuf;
+use std::collections::BTreeSet;
 
-pub fn create_profile(config: &mut Config, name: &str) -> Result<()> {
+pub fn create_profile(config: &mut Config, name: &str, extends: &[String]) -> Result<()> {
     let repo_path = config.get_repo_path()?;
     let profile_dir = repo_path.join("profiles").join(name);
 
     std::fs::create_dir_all(&profile_dir)?;
 
+    if !extends.is_empty() {
+        config.profiles.insert(
+            name.to_string(),
+            ProfileConfig {
+                extends: extends.to_vec(),
+            },
+        );
+        // Reject cycles before they are written to the config
+        resolve_layers(config, name)?;
+        config.save(false)?;
+    }
+
     println!("{} Created profile: {}", "✓".green(), name);
+    if !extends.is_empty() {
+        println!("  Extends: {}", extends.join(", "));
+    }
     Ok(())
 }
 
 pub fn switch_profile(config: &mut Config, name: &str) -> Result<()> {
     // Verify profile exists
-    let repo_path = config.get_repo_path()?;
-    let profile_dir = repo_path.join("profiles").join(name);
-
-    if !profile_dir.exists() {
-        let available_profiles = list_profiles(config)?;
+    let available_profiles = list_profiles(config)?;
+    if !available_profiles.iter().any(|profile| profile == name) {
         return Err(error_utils::profile_not_found(name, &available_profiles));
     }
 
@@ -31,56 +44,126 @@ pub fn switch_profile(config: &mut Config, name: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn list_profiles(config: &Config) -> Result<Vec<String>> {
-    let repo_path = config.get_repo_path()?;
-    let profiles_dir = repo_path.join("profiles");
-
-    if !profiles_dir.exists() {
-        return Ok(vec!["default".to_string()]);
-    }
-
-    let mut profiles = vec!["default".to_string()];
+/// Resolve the layers of a profile, lowest priority first and `profile` itself last.
+///
+/// `extends` is walked depth-first in the listed order and each profile appears once,
+/// at its first position: with `work.extends = ["base", "laptop"]` and
+/// `laptop.extends = ["base"]` the layers are `base`, `laptop`, `work`.
+/// Entries without a profile form an implicit `common` layer below all of them.
+pub fn resolve_layers(config: &Config, profile: &str) -> Result<Vec<String>> {
+    fn visit(
+        config: &Config,
+        profile: &str,
+        stack: &mut Vec<String>,
+        layers: &mut Vec<String>,
+    ) -> Result<()> {
+        if let Some(start) = stack.iter().position(|p| p == profile) {
+            let mut cycle = stack[start..].to_vec();
+            cycle.push(profile.to_string());
+            return Err(DotfilesError::Config(format!(
+                "Profile inheritance cycle: {}",
+                cycle.join(" → ")
+            )));
+        }
+        if layers.iter().any(|p| p == profile) {
+            return Ok(());
+        }
 
-    for entry in std::fs::read_dir(&profiles_dir)? {
-        let entry = entry?;
-        if entry.path().is_dir()
-            && let Some(name) = entry.file_name().to_str()
-        {
-            profiles.push(name.to_string());
+        stack.push(profile.to_string());
+        if let Some(profile_config) = config.profiles.get(profile) {
+            for parent in &profile_config.extends {
+                visit(config, parent, stack, layers)?;
+            }
         }
+        stack.pop();
+
+        layers.push(profile.to_string());
+        Ok(())
     }
 
-    Ok(profiles)
+    let mut layers = Vec::new();
+    visit(config, profile, &mut Vec::new(), &mut layers)?;
+    Ok(layers)
 }
 
-pub fn get_profile_files(config: &Config, profile: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+pub fn list_profiles(config: &Config) -> Result<Vec<String>> {
     let repo_path = config.get_repo_path()?;
-    let profile_dir = repo_path.join("profiles").join(profile);
+    let profiles_dir = repo_path.join("profiles");
+
+    // Profiles declared under [profiles] exist even without a directory
+    let mut named: BTreeSet<String> = config.profiles.keys().cloned().collect();
 
-    if !profile_dir.exists() {
-        return Ok(Vec::new());
+    if profiles_dir.exists() {
+        for entry in std::fs::read_dir(&profiles_dir)? {
+            let entry = entry?;
+            if entry.path().is_dir()
+                && let Some(name) = entry.file_name().to_str()
+            {
+                named.insert(name.to_string());
+            }
+        }
     }
 
-    let mut profile_files = Vec::new();
+    let mut profiles = vec!["default".to_string()];
+    profiles.extend(named.into_iter().filter(|name| name != "default"));
 
-    // Walk through profile directory structure
-    if profile_dir.is_dir() {
-        for tool_entry in std::fs::read_dir(&profile_dir)? {
-            let tool_entry = tool_entry?;
-            let tool_path = tool_entry.path();
+    Ok(profiles)
+}
 
-            if tool_path.is_dir() {
-                for file_entry in std::fs::read_dir(&tool_path)? {
-                    let file_entry = file_entry?;
-                    let file_path = file_entry.path();
+/// Display the effective file set of a profile and the layer each file comes from.
+pub fn display_profile(config: &Config, name: &str) -> Result<()> {
+    let available_profiles = list_profiles(config)?;
+    if !available_profiles.iter().any(|profile| profile == name) {
+        return Err(error_utils::profile_not_found(name, &available_profiles));
+    }
 
-                    if file_path.is_file() {
-                        profile_files.push((file_path.clone(), file_path));
-                    }
-                }
+    let layers = resolve_layers(config, name)?;
+    let mut files = config.select_files(Some(name), &HostFacts::current())?;
+    files.sort_by(|a, b| a.file.dest_path.cmp(&b.file.dest_path));
+
+    println!("\n{} {}", "Profile:".bold().cyan(), name.cyan());
+    let chain: Vec<&str> = std::iter::once("common")
+        .chain(layers.iter().map(String::as_str))
+        .collect();
+    println!("  Layers: {}", chain.join(" → "));
+    println!("{}", "=".repeat(60).cyan());
+
+    let mut effective = 0;
+    let mut overridden = 0;
+    for selected in &files {
+        let file = &selected.file;
+        let layer = format!("[{}]", layer_name(file));
+        if selected.selection.included {
+            effective += 1;
+            println!(
+                "  {} {} <- {} {}",
+                "✓".green(),
+                file.dest_path.display(),
+                file.repo_path.display(),
+                layer.yellow()
+            );
+        } else if selected.selection.overridden {
+            overridden += 1;
+            println!(
+                "  {} {} <- {} {}",
+                "↻".bright_black(),
+                file.dest_path.display().to_string().bright_black(),
+                file.repo_path.display().to_string().bright_black(),
+                layer.bright_black()
+            );
+            if let Some(reason) = selected.selection.reasons.last() {
+                println!("      {}", reason.bright_black());
             }
         }
     }
 
-    Ok(profile_files)
+    println!("{}", "=".repeat(60).cyan());
+    println!(
+        "{} {} file(s) from {} layer(s), {} overridden",
+        "Summary:".bold(),
+        effective.to_string().green(),
+        chain.len(),
+        overridden.to_string().yellow()
+    );
+    Ok(())
 }
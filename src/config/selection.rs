@@ -0,0 +1,191 @@
+//! Selection of file entries by profile, hostname and operating system.
+//!
+//! An entry is included when every matcher it declares matches: `profile` against the
+//! layers of the active profile (see [`super::profile::resolve_layers`]), `hosts` (glob patterns such as `desktop-*`) against the hostname and
+//! `os` against the platform (e.g. "linux", "macos"). Entries without matchers are
+//! always included. Among included entries with the same destination, the one from the
+//! highest layer overrides the others.
+
+use crate::types::{FileEntry, TrackedFile};
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::host;
+use globset::GlobBuilder;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The machine that file entries are matched against.
+#[derive(Debug, Clone)]
+pub struct HostFacts {
+    /// Hostname of the machine
+    pub hostname: String,
+    /// Operating system name (e.g. "linux", "macos")
+    pub os: String,
+}
+
+impl HostFacts {
+    /// Facts about the machine flux is running on.
+    pub fn current() -> Self {
+        Self {
+            hostname: host::hostname(),
+            os: host::os().to_string(),
+        }
+    }
+}
+
+/// Whether an entry was selected, and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct EntrySelection {
+    /// Whether the entry is included
+    pub included: bool,
+    /// One explanation per matcher, in the order profile, hosts, os, then override
+    pub reasons: Vec<String>,
+    /// Whether the entry matched but was overridden by a higher layer
+    pub overridden: bool,
+}
+
+/// A file entry resolved to paths, with its selection result.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelectedFile {
+    /// The entry as it would be tracked
+    pub file: TrackedFile,
+    /// Whether it is included, and why
+    #[serde(flatten)]
+    pub selection: EntrySelection,
+}
+
+/// Evaluate every matcher of `entry` against the profile layers and host.
+///
+/// `layers` is ordered lowest priority first and ends with the active profile.
+pub fn select_entry(
+    entry: &FileEntry,
+    layers: &[String],
+    facts: &HostFacts,
+) -> Result<EntrySelection> {
+    let active = layers.last().map(String::as_str).unwrap_or_default();
+    let mut included = true;
+    let mut reasons = Vec::new();
+
+    match entry.profile.as_deref() {
+        None => reasons.push("no profile restriction".to_string()),
+        Some(p) if p == active => reasons.push(format!("profile '{}' is active", p)),
+        Some(p) if layers.iter().any(|layer| layer == p) => {
+            reasons.push(format!("profile '{}' is inherited by '{}'", p, active))
+        }
+        Some(p) => {
+            included = false;
+            reasons.push(format!(
+                "profile '{}' is not active (current: '{}')",
+                p, active
+            ));
+        }
+    }
+
+    if let Some(hosts) = &entry.hosts {
+        match matching_host_pattern(hosts, &facts.hostname)? {
+            Some(pattern) => {
+                reasons.push(format!("host '{}' matches '{}'", facts.hostname, pattern))
+            }
+            None => {
+                included = false;
+                reasons.push(format!(
+                    "host '{}' matches none of: {}",
+                    facts.hostname,
+                    hosts.join(", ")
+                ));
+            }
+        }
+    }
+
+    if let Some(os) = &entry.os {
+        if os.eq_ignore_ascii_case(&facts.os) {
+            reasons.push(format!("os '{}' matches", facts.os));
+        } else {
+            included = false;
+            reasons.push(format!("os '{}' does not match '{}'", facts.os, os));
+        }
+    }
+
+    Ok(EntrySelection {
+        included,
+        reasons,
+        overridden: false,
+    })
+}
+
+/// Name of the layer an entry belongs to (`common` for entries without a profile).
+pub fn layer_name(file: &TrackedFile) -> &str {
+    file.profile.as_deref().unwrap_or("common")
+}
+
+/// Exclude included entries whose destination is provided by a higher layer.
+///
+/// `files` must be in a deterministic order; among entries of the same layer the
+/// later one wins.
+pub fn apply_overrides(files: &mut [SelectedFile], layers: &[String]) {
+    let rank = |file: &TrackedFile| match &file.profile {
+        None => 0,
+        Some(profile) => layers
+            .iter()
+            .position(|layer| layer == profile)
+            .map_or(0, |index| index + 1),
+    };
+
+    // Winning entry per destination
+    let mut winners: HashMap<PathBuf, usize> = HashMap::new();
+    for (index, selected) in files.iter().enumerate() {
+        if !selected.selection.included {
+            continue;
+        }
+        match winners.get(&selected.file.dest_path) {
+            Some(&winner) if rank(&files[winner].file) > rank(&selected.file) => {}
+            _ => {
+                winners.insert(selected.file.dest_path.clone(), index);
+            }
+        }
+    }
+
+    let overridden: Vec<(usize, String)> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, selected)| selected.selection.included)
+        .filter_map(|(index, selected)| {
+            let winner = winners[&selected.file.dest_path];
+            (winner != index).then(|| {
+                let file = &files[winner].file;
+                (
+                    index,
+                    format!(
+                        "overridden by {} from layer '{}'",
+                        file.repo_path.display(),
+                        layer_name(file)
+                    ),
+                )
+            })
+        })
+        .collect();
+
+    for (index, reason) in overridden {
+        let selection = &mut files[index].selection;
+        selection.included = false;
+        selection.overridden = true;
+        selection.reasons.push(reason);
+    }
+}
+
+/// First pattern in `hosts` matching `hostname` (case-insensitive).
+fn matching_host_pattern<'a>(hosts: &'a [String], hostname: &str) -> Result<Option<&'a str>> {
+    for pattern in hosts {
+        let matcher = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| {
+                DotfilesError::Config(format!("Invalid host pattern '{}': {}", pattern, e))
+            })?
+            .compile_matcher();
+        if matcher.is_match(hostname) {
+            return Ok(Some(pattern));
+        }
+    }
+    Ok(None)
+}
@@ -1,8 +1,18 @@
 use crate::config::Config;
-use crate::types::{SymlinkResolution, TrackedFile};
+use crate::services::browser;
+use crate::services::secrets::SecretKeys;
+use crate::services::sqlite;
+use crate::services::template::{self, TemplateContext, rendered_differs};
+use crate::types::{
+    ConflictPolicy, EntryType, FileChange, FileKind, SymlinkResolution, TrackedFile,
+};
+use crate::utils::diff::{diff_contents, print_diffs};
 use crate::utils::dry_run::{DryRun, Operation};
 use crate::utils::error::{DotfilesError, Result};
-use crate::utils::path_utils::{files_differ, normalize_path, resolve_symlink_target, symlink_points_to_correct_target};
+use crate::utils::path_utils::{
+    DirFilter, is_unfolded_dir, normalize_path, paths_differ, resolve_symlink_target, stale_links,
+    symlink_points_to_correct_target,
+};
 use crate::utils::prompt::{ConflictResolution, prompt_conflict};
 use crate::utils::security;
 use chrono::Local;
@@ -22,15 +32,25 @@ pub fn add_file(
     source_path: &Path,
     dest_path: &Path,
     profile: Option<&str>,
+    encrypted: bool,
     fs_manager: &mut FileSystemManager,
 ) -> Result<()> {
+    if encrypted && source_path.is_dir() {
+        return Err(DotfilesError::Path(format!(
+            "Cannot encrypt a directory: {}",
+            source_path.display()
+        )));
+    }
+
     // Create backup of destination file if it exists before making any changes
     let home = dirs::home_dir().ok_or_else(crate::utils::error_utils::home_dir_not_found)?;
 
-    // Validate destination path is within home directory
-    crate::utils::security::validate_dest_path(dest_path, &home)?;
+    // Validate destination path is within home directory (where a browser profile
+    // destination like `{firefox.default}/places.sqlite` resolves to)
+    let full_dest_path =
+        browser::resolve_dest(&home, &dest_path.to_string_lossy()).map_err(DotfilesError::Path)?;
+    crate::utils::security::validate_dest_path(&full_dest_path, &home)?;
 
-    let full_dest_path = home.join(dest_path);
     if let Some(path_to_backup) = get_path_to_backup(&full_dest_path) {
         println!("  Creating backup of existing destination...");
         fs_manager.backup_file(&path_to_backup, config, None)?;
@@ -51,7 +71,13 @@ pub fn add_file(
     // Copy source file or directory to repository
     fs_manager.create_dir_all(&tool_dir)?;
 
-    if source_path.is_dir() {
+    // Databases an application has open cannot be copied byte for byte
+    let database = !encrypted && sqlite::is_database(source_path);
+    if encrypted {
+        write_encrypted(config, source_path, &repo_file, fs_manager)?;
+    } else if database {
+        fs_manager.snapshot_sqlite(source_path, &repo_file)?;
+    } else if source_path.is_dir() {
         fs_manager.copy_dir_all(source_path, &repo_file)?;
     } else {
         fs_manager.copy(source_path, &repo_file)?;
@@ -69,7 +95,19 @@ pub fn add_file(
         })?
         .to_string_lossy()
         .to_string();
-    config.add_file_to_tool(tool, &repo_relative, dest_path, profile)?;
+    let entry = config.add_file_to_tool(tool, &repo_relative, dest_path, profile)?;
+    entry.encrypted = encrypted;
+    if source_path.is_dir() {
+        entry.entry_type = EntryType::Dir;
+    }
+    if database {
+        entry.kind = FileKind::Sqlite;
+        println!(
+            "  {} {} is a SQLite database; the repository keeps snapshots of it",
+            "↳".cyan(),
+            source_path.display()
+        );
+    }
 
     // Only save config if not in dry run mode
     if !fs_manager.is_dry_run {
@@ -91,16 +129,115 @@ pub fn add_file(
     Ok(())
 }
 
+/// Drop repository changes that directory entries leave out with their `include` and
+/// `ignore` globs, so files an app writes into a tracked directory are not committed.
+///
+/// Changes to whole untracked directories are expanded into their files first.
+pub fn exclude_ignored_changes(
+    config: &Config,
+    changes: Vec<FileChange>,
+) -> Result<Vec<FileChange>> {
+    let mut dirs = Vec::new();
+    for (tool, tool_config) in &config.tools {
+        for entry in &tool_config.files {
+            if entry.entry_type == EntryType::Dir
+                && (!entry.include.is_empty() || !entry.ignore.is_empty())
+            {
+                dirs.push((
+                    config.repo_file_path(tool, entry)?,
+                    DirFilter::new(&entry.include, &entry.ignore)?,
+                ));
+            }
+        }
+    }
+    if dirs.is_empty() {
+        return Ok(changes);
+    }
+
+    let keep = |path: &Path| match dirs.iter().find(|(root, _)| path.starts_with(root)) {
+        Some((root, filter)) => {
+            let relative = path.strip_prefix(root).unwrap_or(path);
+            relative.as_os_str().is_empty() || filter.matches(relative)
+        }
+        None => true,
+    };
+
+    let mut kept = Vec::new();
+    for change in changes {
+        let (FileChange::Added(path) | FileChange::Modified(path) | FileChange::Deleted(path)) =
+            &change;
+        let overlaps = dirs
+            .iter()
+            .any(|(root, _)| path.starts_with(root) || root.starts_with(path));
+
+        if path.is_dir() && overlaps {
+            for file in DirFilter::default().entries(path)?.into_values() {
+                if keep(&file) {
+                    kept.push(match &change {
+                        FileChange::Added(_) => FileChange::Added(file),
+                        FileChange::Modified(_) => FileChange::Modified(file),
+                        FileChange::Deleted(_) => FileChange::Deleted(file),
+                    });
+                }
+            }
+        } else if keep(path) {
+            kept.push(change);
+        }
+    }
+    Ok(kept)
+}
+
+/// Encrypt `source_path` into the repository, generating an identity on first use.
+fn write_encrypted(
+    config: &Config,
+    source_path: &Path,
+    repo_file: &Path,
+    fs_manager: &mut FileSystemManager,
+) -> Result<()> {
+    if fs_manager.is_dry_run {
+        println!(
+            "  [DRY RUN] Would encrypt: {} -> {}",
+            source_path.display(),
+            repo_file.display()
+        );
+        fs_manager.dry_run.log_operation(Operation::WriteFile {
+            path: repo_file.to_path_buf(),
+        });
+        return Ok(());
+    }
+
+    let (keys, generated) = SecretKeys::load_or_generate(config)?;
+    if generated {
+        println!(
+            "{} Generated identity {} (public key: {})",
+            "✓".green(),
+            keys.identity_path.display(),
+            keys.public_key()
+        );
+        println!(
+            "  {} Back up this file: encrypted files cannot be decrypted without it",
+            "⚠".yellow()
+        );
+    }
+
+    let ciphertext = keys.encrypt(&fs::read(source_path)?)?;
+    fs::write(repo_file, ciphertext)?;
+    Ok(())
+}
+
 /// Sync all tracked files, creating symlinks from repo to destination.
+///
+/// Conflicts are resolved with `conflict_policy`, or prompted for when it is `None`.
 pub fn sync_files(
     config: &Config,
     profile: Option<&str>,
     dry_run_tracker: &mut DryRun,
     is_dry_run_mode: bool,
     verbose: bool,
+    conflict_policy: Option<ConflictPolicy>,
 ) -> Result<()> {
     let tracked_files = config.get_tracked_files(profile)?;
-    let symlink_resolution = config.general.symlink_resolution;
+    let templates = TemplateContext::new(config, profile);
 
     // Create FileSystemManager to handle all file operations with dry-run support
     let mut fs_manager = FileSystemManager::new(dry_run_tracker, is_dry_run_mode);
@@ -111,40 +248,123 @@ pub fn sync_files(
         .get_backup_dir()?
         .join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string());
 
+    let stats = sync_each(
+        &tracked_files,
+        &templates,
+        config,
+        &mut fs_manager,
+        &backup_dir,
+        verbose,
+        conflict_policy,
+    )?;
+
+    // Remove links to files that were deleted from `link = "contents"` directories
+    let mut link_roots = Vec::new();
+    for root in tracked_files
+        .iter()
+        .filter_map(|file| file.link_root.as_ref())
+    {
+        if !link_roots.contains(&root) {
+            link_roots.push(root);
+        }
+    }
+    for root in link_roots {
+        for link in stale_links(&root.dest, &root.repo)? {
+            if verbose {
+                println!("  Removing stale link: {}", link.display());
+            }
+            fs_manager.remove_file(&link)?;
+        }
+    }
+
+    // Display sync operation summary
     if verbose {
-        println!("{} Syncing {} file(s)...", "→".cyan(), tracked_files.len());
+        println!("\n{} Sync complete", "✓".green());
+    } else {
+        stats.print_summary();
     }
+    Ok(())
+}
 
-    let mut stats = SyncStats::default();
+/// Sync only `files`, resolving conflicts with `conflict_policy`.
+///
+/// Unlike [`sync_files`], other tracked files and stale links are left alone.
+pub fn sync_selected_files(
+    config: &Config,
+    profile: Option<&str>,
+    files: &[TrackedFile],
+    dry_run_tracker: &mut DryRun,
+    conflict_policy: ConflictPolicy,
+) -> Result<()> {
+    let templates = TemplateContext::new(config, profile);
+    let mut fs_manager = FileSystemManager::new(dry_run_tracker, false);
+    let backup_dir = config
+        .get_backup_dir()?
+        .join(chrono::Local::now().format("%Y%m%d_%H%M%S").to_string());
 
-    for (idx, file) in tracked_files.iter().enumerate() {
+    sync_each(
+        files,
+        &templates,
+        config,
+        &mut fs_manager,
+        &backup_dir,
+        false,
+        Some(conflict_policy),
+    )?
+    .print_summary();
+    Ok(())
+}
+
+/// Sync each of `files`, backing up into `backup_dir`.
+fn sync_each(
+    files: &[TrackedFile],
+    templates: &TemplateContext,
+    config: &Config,
+    fs_manager: &mut FileSystemManager,
+    backup_dir: &Path,
+    verbose: bool,
+    conflict_policy: Option<ConflictPolicy>,
+) -> Result<SyncStats> {
+    // Fail before touching anything rather than halfway through
+    if conflict_policy == Some(ConflictPolicy::Fail) {
+        let mut conflicts = Vec::new();
+        for file in files {
+            if is_conflict(file, templates)? {
+                conflicts.push(file.clone());
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(conflict_error(&conflicts));
+        }
+    }
+
+    if verbose {
+        println!("{} Syncing {} file(s)...", "→".cyan(), files.len());
+    }
+
+    let mut stats = SyncStats::default();
+    for (idx, file) in files.iter().enumerate() {
         if verbose {
             println!(
                 "\n{} [{}/{}] Processing: {}",
                 "→".cyan(),
                 idx + 1,
-                tracked_files.len(),
+                files.len(),
                 file.dest_path.display()
             );
         }
         let result = sync_file(
             file,
-            &symlink_resolution,
+            templates,
             config,
-            &mut fs_manager,
-            Some(&backup_dir),
+            fs_manager,
+            Some(backup_dir),
             verbose,
+            conflict_policy,
         )?;
         stats.update(result);
     }
-
-    // Display sync operation summary
-    if verbose {
-        println!("\n{} Sync complete", "✓".green());
-    } else {
-        stats.print_summary();
-    }
-    Ok(())
+    Ok(stats)
 }
 
 #[derive(Default)]
@@ -326,9 +546,9 @@ pub fn remove_file(
         .find(|e| e.repo == file)
         .ok_or_else(|| DotfilesError::Path(format!("File {} not found in tool {}", file, tool)))?;
 
-    let dest_path = dirs::home_dir()
-        .ok_or_else(|| DotfilesError::Path("Could not find home directory".to_string()))?
-        .join(&file_entry.dest);
+    let home = dirs::home_dir()
+        .ok_or_else(|| DotfilesError::Path("Could not find home directory".to_string()))?;
+    let dest_path = browser::resolve_dest(&home, &file_entry.dest).map_err(DotfilesError::Path)?;
 
     // Create backup before removing file from filesystem
     if let Some(path_to_backup) = get_path_to_backup(&dest_path) {
@@ -427,6 +647,39 @@ impl<'a> FileSystemManager<'a> {
         }
     }
 
+    /// Write the rendered contents of `file` to its destination, replacing it atomically.
+    pub fn write_rendered(&mut self, file: &TrackedFile, contents: &[u8]) -> Result<()> {
+        if self.is_dry_run {
+            println!("  [DRY RUN] Would write file: {}", file.dest_path.display());
+            self.dry_run.log_operation(Operation::WriteFile {
+                path: file.dest_path.clone(),
+            });
+            Ok(())
+        } else {
+            let temp_path = file.dest_path.with_extension("flux-temp-write");
+            template::write_rendered(file, &temp_path, contents)?;
+            fs::rename(&temp_path, &file.dest_path).map_err(Into::into)
+        }
+    }
+
+    /// Write a consistent snapshot of the SQLite database at `from` to `to`.
+    pub fn snapshot_sqlite(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if self.is_dry_run {
+            println!(
+                "  [DRY RUN] Would snapshot database: {} -> {}",
+                from.display(),
+                to.display()
+            );
+            self.dry_run.log_operation(Operation::CopyFile {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            });
+            Ok(())
+        } else {
+            sqlite::snapshot(from, to)
+        }
+    }
+
     pub fn copy_dir_all(&mut self, src: &Path, dst: &Path) -> Result<()> {
         // Safety check: don't copy a directory to itself
         if src == dst {
@@ -452,6 +705,57 @@ impl<'a> FileSystemManager<'a> {
         }
     }
 
+    /// Copy a directory entry over `dst` in place.
+    ///
+    /// Files in `dst` that the repository doesn't have (caches, logs matched by
+    /// `ignore`) are left alone. A symlink at `dst` is replaced by a real directory.
+    pub fn copy_dir_over(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        if dst.is_symlink() || dst.is_file() {
+            self.remove_file(dst)?;
+        }
+        self.copy_dir_all(src, dst)
+    }
+
+    /// Copy the files of a directory entry that `filter` selects.
+    pub fn copy_dir_filtered(&mut self, src: &Path, dst: &Path, filter: &DirFilter) -> Result<()> {
+        if src == dst {
+            return Err(DotfilesError::Path(format!(
+                "Cannot copy directory to itself: {}",
+                src.display()
+            )));
+        }
+
+        if self.is_dry_run {
+            println!(
+                "  [DRY RUN] Would copy directory: {} -> {}",
+                src.display(),
+                dst.display()
+            );
+            self.dry_run.log_operation(Operation::CopyFile {
+                from: src.to_path_buf(),
+                to: dst.to_path_buf(),
+            });
+            return Ok(());
+        }
+
+        fs::create_dir_all(dst)?;
+        for (relative, path) in filter.entries(src)? {
+            let target = dst.join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if path.is_symlink() {
+                if target.is_symlink() || target.exists() {
+                    fs::remove_file(&target)?;
+                }
+                std::os::unix::fs::symlink(fs::read_link(&path)?, &target)?;
+            } else {
+                fs::copy(&path, &target)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn remove_file(&mut self, path: &Path) -> Result<()> {
         if self.is_dry_run {
             println!("  [DRY RUN] Would remove file: {}", path.display());
@@ -469,6 +773,20 @@ impl<'a> FileSystemManager<'a> {
         }
     }
 
+    pub fn remove_dir_all(&mut self, path: &Path) -> Result<()> {
+        if self.is_dry_run {
+            println!("  [DRY RUN] Would remove directory: {}", path.display());
+            self.dry_run.log_operation(Operation::RemoveFile {
+                path: path.to_path_buf(),
+            });
+            Ok(())
+        } else if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path).map_err(Into::into)
+        } else {
+            self.remove_file(path)
+        }
+    }
+
     pub fn rename(&mut self, from: &Path, to: &Path) -> Result<()> {
         if self.is_dry_run {
             println!(
@@ -606,17 +924,30 @@ enum SyncAction {
 /// Orchestrates the sync for a single file.
 fn sync_file(
     file: &TrackedFile,
-    resolution: &SymlinkResolution,
+    templates: &TemplateContext,
     config: &Config,
     fs_manager: &mut FileSystemManager,
     backup_dir: Option<&Path>,
     verbose: bool,
+    conflict_policy: Option<ConflictPolicy>,
 ) -> Result<SyncResult> {
     if verbose {
         println!("  Repo: {}", file.repo_path.display());
         println!("  Dest: {}", file.dest_path.display());
     }
 
+    // With `follow`, the file goes at the end of the destination's symlink chain
+    let (file, chain) = file.followed()?;
+    let file = &file;
+    if verbose && !chain.is_empty() {
+        println!("  Following symlinks to: {}", file.dest_path.display());
+    }
+
+    // Databases are snapshotted into the repo rather than linked
+    if file.is_sqlite() {
+        return sync_sqlite(file, config, fs_manager, backup_dir, verbose);
+    }
+
     // Step 1: Precondition checks
     if !file.repo_path.exists() {
         if verbose {
@@ -636,6 +967,36 @@ fn sync_file(
         return Ok(skip_reason);
     }
 
+    // A `link = "contents"` directory still linked as a whole becomes a real directory
+    if let Some(folded) = file.folded_dir() {
+        if verbose {
+            println!("  Unfolding {}", folded.display());
+        }
+        fs_manager.remove_file(&folded)?;
+        fs_manager.create_dir_all(&folded)?;
+    }
+
+    // Templates and secrets are rendered and copied rather than symlinked
+    if file.is_rendered() {
+        return sync_rendered(
+            file,
+            templates,
+            config,
+            fs_manager,
+            backup_dir,
+            verbose,
+            conflict_policy,
+        );
+    }
+
+    // Copies that still match the repo need no backup or replacement
+    if file.is_copy() && !file.copy_differs()? {
+        if verbose {
+            println!("  {} Copy is up to date", "✓".green());
+        }
+        return Ok(SyncResult::Skipped);
+    }
+
     // Step 2: Create backup before any modifications
     // Backup is created before determining action to simplify downstream logic,
     // since any action (except DoNothing) might modify the destination.
@@ -658,7 +1019,7 @@ fn sync_file(
             if verbose {
                 println!("  Destination needs to be symlinked.");
             }
-            create_symlink_managed(file, resolution, fs_manager, verbose)?;
+            create_symlink_managed(file, fs_manager, verbose)?;
             Ok(SyncResult::Synced)
         }
         SyncAction::UpdateRepoFromDest => {
@@ -679,7 +1040,11 @@ fn sync_file(
                 fs_manager.create_dir_all(parent)?;
             }
             if file.dest_path.is_dir() {
-                fs_manager.copy_dir_all(&file.dest_path, &file.repo_path)?;
+                fs_manager.copy_dir_filtered(
+                    &file.dest_path,
+                    &file.repo_path,
+                    &file.dir_filter()?,
+                )?;
             } else {
                 fs_manager.copy(&file.dest_path, &file.repo_path)?;
             }
@@ -688,18 +1053,183 @@ fn sync_file(
                 println!("{} Updated repo file from destination", "✓".green());
             }
             // Now that repo is updated, create the symlink
-            create_symlink_managed(file, resolution, fs_manager, verbose)?;
+            create_symlink_managed(file, fs_manager, verbose)?;
             Ok(SyncResult::Synced)
         }
-        SyncAction::ResolveConflict => {
-            handle_file_conflict(file, resolution, fs_manager, verbose)?;
-            Ok(SyncResult::Synced)
+        SyncAction::ResolveConflict => resolve_conflict(
+            file,
+            templates,
+            config,
+            fs_manager,
+            backup_dir,
+            verbose,
+            conflict_policy,
+        ),
+    }
+}
+
+/// Renders a template or secret and writes it to the destination if the output changed.
+///
+/// A destination edited since it was last rendered is a conflict.
+fn sync_rendered(
+    file: &TrackedFile,
+    templates: &TemplateContext,
+    config: &Config,
+    fs_manager: &mut FileSystemManager,
+    backup_dir: Option<&Path>,
+    verbose: bool,
+    conflict_policy: Option<ConflictPolicy>,
+) -> Result<SyncResult> {
+    let rendered = templates.render_file(file)?;
+
+    if !file.dest_path.is_symlink()
+        && file.dest_path.is_file()
+        && fs::read(&file.dest_path)? == rendered
+    {
+        if verbose {
+            println!("  {} Rendered file is up to date", "✓".green());
+        }
+        if !fs_manager.is_dry_run {
+            templates.record_render(file)?;
+        }
+        return Ok(SyncResult::Skipped);
+    }
+
+    if let Some(path_to_backup) = get_path_to_backup(&file.dest_path) {
+        if verbose {
+            println!("  Creating backup before rendering...");
+        }
+        fs_manager.backup_file(&path_to_backup, config, backup_dir)?;
+    }
+
+    if templates.edited_since_render(file)? {
+        if verbose {
+            println!("  Destination was edited since it was last rendered.");
+        }
+        return resolve_conflict(
+            file,
+            templates,
+            config,
+            fs_manager,
+            backup_dir,
+            verbose,
+            conflict_policy,
+        );
+    }
+
+    write_rendered_managed(file, &rendered, templates, fs_manager, verbose)?;
+    Ok(SyncResult::Synced)
+}
+
+/// Writes rendered output to the destination and records it as the last render.
+/// Assumes backups have *already been created* by the caller.
+fn write_rendered_managed(
+    file: &TrackedFile,
+    rendered: &[u8],
+    templates: &TemplateContext,
+    fs_manager: &mut FileSystemManager,
+    verbose: bool,
+) -> Result<()> {
+    if let Some(parent) = file.dest_path.parent() {
+        fs_manager.create_dir_all(parent)?;
+    }
+    fs_manager.write_rendered(file, rendered)?;
+
+    if !fs_manager.is_dry_run {
+        templates.record_render(file)?;
+        if verbose {
+            println!(
+                "    {} Rendered {} -> {}",
+                "✓".green(),
+                file.repo_path.display(),
+                file.dest_path.display()
+            );
         }
     }
+    Ok(())
+}
+
+/// Snapshots a SQLite database into the repo. A destination without a database gets the
+/// snapshot restored, unless the application owning it is running.
+fn sync_sqlite(
+    file: &TrackedFile,
+    config: &Config,
+    fs_manager: &mut FileSystemManager,
+    backup_dir: Option<&Path>,
+    verbose: bool,
+) -> Result<SyncResult> {
+    if file.dest_path.is_file() && !file.dest_path.is_symlink() {
+        if file.repo_path.exists() && !file.copy_differs()? {
+            if verbose {
+                println!("  {} Snapshot is up to date", "✓".green());
+            }
+            return Ok(SyncResult::Skipped);
+        }
+        if let Some(parent) = file.repo_path.parent() {
+            fs_manager.create_dir_all(parent)?;
+        }
+        fs_manager.snapshot_sqlite(&file.dest_path, &file.repo_path)?;
+        if verbose && !fs_manager.is_dry_run {
+            println!(
+                "    {} Snapshot {} -> {}",
+                "✓".green(),
+                file.dest_path.display(),
+                file.repo_path.display()
+            );
+        }
+        return Ok(SyncResult::Synced);
+    }
+
+    if !file.repo_path.exists() {
+        eprintln!(
+            "  {} Skipping {} (repo file does not exist)",
+            "⊘".yellow(),
+            file.dest_path.display()
+        );
+        return Ok(SyncResult::Skipped);
+    }
+    if let Some(reason) = sqlite::owner_running(file) {
+        eprintln!(
+            "  {} Not restoring {} ({})",
+            "⚠".yellow(),
+            file.dest_path.display(),
+            reason
+        );
+        return Ok(SyncResult::Skipped);
+    }
+
+    if let Some(path_to_backup) = get_path_to_backup(&file.dest_path) {
+        fs_manager.backup_file(&path_to_backup, config, backup_dir)?;
+    }
+    // A WAL left from another database would be replayed into the restored one
+    fs_manager.remove_file(&file.dest_path)?;
+    for suffix in sqlite::SIDE_FILES {
+        fs_manager.remove_file(&sqlite::side_file(&file.dest_path, suffix))?;
+    }
+    if let Some(parent) = file.dest_path.parent() {
+        fs_manager.create_dir_all(parent)?;
+    }
+    fs_manager.copy(&file.repo_path, &file.dest_path)?;
+    if verbose && !fs_manager.is_dry_run {
+        println!(
+            "    {} Restored {} -> {}",
+            "✓".green(),
+            file.repo_path.display(),
+            file.dest_path.display()
+        );
+    }
+    Ok(SyncResult::Synced)
 }
 
 /// Determines what action to take for a file. (No side-effects)
 fn determine_sync_action(file: &TrackedFile, verbose: bool) -> Result<SyncAction> {
+    if let Some(folded) = file.folded_dir() {
+        if verbose {
+            println!("  Directory is linked as a whole: {}", folded.display());
+        }
+        return Ok(SyncAction::CreateSymlink);
+    }
+
     if !file.dest_path.exists() && !file.dest_path.is_symlink() {
         if verbose {
             println!("  Destination does not exist");
@@ -719,6 +1249,12 @@ fn determine_sync_action(file: &TrackedFile, verbose: bool) -> Result<SyncAction
             println!("  Is symlink pointing to: {}", link_target.display());
         }
         if symlink_points_to_correct_target(&file.dest_path, &link_target, &file.repo_path) {
+            if file.is_copy() {
+                if verbose {
+                    println!("  Linked to the repo, but should be a copy");
+                }
+                return Ok(SyncAction::CreateSymlink);
+            }
             if verbose {
                 println!("  {} Already correctly linked", "✓".green());
             }
@@ -757,15 +1293,33 @@ fn determine_sync_action(file: &TrackedFile, verbose: bool) -> Result<SyncAction
         return Ok(SyncAction::UpdateRepoFromDest);
     }
 
+    // Per-file links left by `link = "contents"` can be folded back into one link
+    if !file.is_copy() && is_unfolded_dir(&file.dest_path, &file.repo_path)? {
+        if verbose {
+            println!("  {} Directory holds only links to the repo", "✓".green());
+        }
+        return Ok(SyncAction::CreateSymlink);
+    }
+
     // Check if files are different
     if verbose {
         println!("  Comparing files...");
     }
-    if files_differ(&file.repo_path, &file.dest_path)? {
+    let differ = if file.is_copy() {
+        file.copy_differs()?
+    } else {
+        paths_differ(&file.repo_path, &file.dest_path, &file.dir_filter()?)?
+    };
+    if differ {
         if verbose {
             println!("  {} Files differ", "↻".yellow());
         }
         Ok(SyncAction::ResolveConflict)
+    } else if file.is_copy() {
+        if verbose {
+            println!("  {} Copy is identical", "✓".green());
+        }
+        Ok(SyncAction::DoNothing)
     } else {
         if verbose {
             println!("  {} Files are identical", "✓".green());
@@ -775,11 +1329,168 @@ fn determine_sync_action(file: &TrackedFile, verbose: bool) -> Result<SyncAction
     }
 }
 
+/// Whether syncing `file` would replace a destination that differs from the repo version.
+///
+/// Templates and secrets conflict when their destination was edited since flux last
+/// rendered it.
+pub(crate) fn is_conflict(file: &TrackedFile, templates: &TemplateContext) -> Result<bool> {
+    if !file.repo_path.exists() {
+        return Ok(false);
+    }
+    if file.is_rendered() {
+        return Ok(templates.edited_since_render(file)? && rendered_differs(file, templates)?);
+    }
+    let (file, _) = file.followed()?;
+    Ok(matches!(
+        determine_sync_action(&file, false)?,
+        SyncAction::ResolveConflict
+    ))
+}
+
+/// Error returned for conflicts under `--on-conflict=fail`.
+pub(crate) fn conflict_error(conflicts: &[TrackedFile]) -> DotfilesError {
+    let paths: Vec<_> = conflicts
+        .iter()
+        .map(|file| format!("    - {}", file.dest_path.display()))
+        .collect();
+    DotfilesError::Config(format!(
+        "What: {} file(s) differ from the repository and the conflict policy is 'fail'\n  \
+         Files:\n{}\n  \
+         💡 Solution: Run interactively, or pass --on-conflict=keep-repo|keep-local|skip|adopt",
+        conflicts.len(),
+        paths.join("\n")
+    ))
+}
+
+/// Store the local version of a conflicting file over the repo file so it wins.
+///
+/// `keep-local` backs up the repo version first; `adopt` leaves it to git history.
+pub(crate) fn adopt_local_file(
+    file: &TrackedFile,
+    policy: ConflictPolicy,
+    config: &Config,
+    fs_manager: &mut FileSystemManager,
+    backup_dir: Option<&Path>,
+) -> Result<()> {
+    if policy == ConflictPolicy::KeepLocal {
+        fs_manager.backup_file(&file.repo_path, config, backup_dir)?;
+    }
+    store_local_version(file, config, &file.repo_path, fs_manager)
+}
+
+/// Write the local version of a conflicting file to `to` the way the repo stores it.
+/// Secrets are encrypted; other files are copied from their destination or, for a
+/// symlink pointing elsewhere, the symlink's target.
+pub(crate) fn store_local_version(
+    file: &TrackedFile,
+    config: &Config,
+    to: &Path,
+    fs_manager: &mut FileSystemManager,
+) -> Result<()> {
+    if file.encrypted {
+        return write_encrypted(config, &file.dest_path, to, fs_manager);
+    }
+
+    let local = get_path_to_backup(&file.dest_path).ok_or_else(|| {
+        DotfilesError::Path(format!(
+            "Cannot keep local version of {}: it is a broken symlink",
+            file.dest_path.display()
+        ))
+    })?;
+
+    if local.is_dir() {
+        fs_manager.copy_dir_filtered(&local, to, &file.dir_filter()?)
+    } else if file.is_sqlite() {
+        fs_manager.snapshot_sqlite(&local, to)
+    } else {
+        fs_manager.copy(&local, to)
+    }
+}
+
+/// Resolves a file conflict with `conflict_policy`, or by prompting when it is `None`.
+/// Assumes the destination has already been backed up.
+fn resolve_conflict(
+    file: &TrackedFile,
+    templates: &TemplateContext,
+    config: &Config,
+    fs_manager: &mut FileSystemManager,
+    backup_dir: Option<&Path>,
+    verbose: bool,
+    conflict_policy: Option<ConflictPolicy>,
+) -> Result<SyncResult> {
+    match conflict_policy {
+        Some(policy) => resolve_conflict_with_policy(
+            file, policy, templates, config, fs_manager, backup_dir, verbose,
+        ),
+        None => {
+            handle_file_conflict(file, templates, fs_manager, verbose)?;
+            Ok(SyncResult::Synced)
+        }
+    }
+}
+
+/// Resolves a file conflict without prompting.
+/// Assumes the destination has already been backed up.
+fn resolve_conflict_with_policy(
+    file: &TrackedFile,
+    policy: ConflictPolicy,
+    templates: &TemplateContext,
+    config: &Config,
+    fs_manager: &mut FileSystemManager,
+    backup_dir: Option<&Path>,
+    verbose: bool,
+) -> Result<SyncResult> {
+    match policy {
+        ConflictPolicy::KeepRepo => {
+            if verbose {
+                println!("  Conflict policy: keep-repo");
+            }
+            place_repo_version(file, templates, fs_manager, verbose)?;
+            Ok(SyncResult::Synced)
+        }
+        // Rendered output cannot be turned back into a template, so the local file stays
+        ConflictPolicy::KeepLocal | ConflictPolicy::Adopt if file.template => {
+            println!(
+                "  {} Kept local version of {} ({}, the repo file is not updated from it)",
+                "↻".yellow(),
+                file.dest_path.display(),
+                policy
+            );
+            Ok(SyncResult::Skipped)
+        }
+        ConflictPolicy::KeepLocal | ConflictPolicy::Adopt => {
+            adopt_local_file(file, policy, config, fs_manager, backup_dir)?;
+            println!(
+                "  {} Kept local version of {} ({})",
+                "↻".yellow(),
+                file.dest_path.display(),
+                policy
+            );
+            if !file.encrypted {
+                create_symlink_managed(file, fs_manager, verbose)?;
+            } else if !fs_manager.is_dry_run {
+                // The destination already holds the plaintext of the new repo file
+                templates.record_render(file)?;
+            }
+            Ok(SyncResult::Synced)
+        }
+        ConflictPolicy::Skip => {
+            println!(
+                "  {} Skipped conflicting file {}",
+                "⊘".yellow(),
+                file.dest_path.display()
+            );
+            Ok(SyncResult::Skipped)
+        }
+        ConflictPolicy::Fail => Err(conflict_error(std::slice::from_ref(file))),
+    }
+}
+
 /// Handles the user-interactive part of resolving a file conflict.
 /// Assumes backup has already been created.
 fn handle_file_conflict(
     file: &TrackedFile,
-    resolution: &SymlinkResolution,
+    templates: &TemplateContext,
     fs_manager: &mut FileSystemManager,
     verbose: bool,
 ) -> Result<()> {
@@ -799,7 +1510,7 @@ fn handle_file_conflict(
             if verbose {
                 println!("  User chose: Backup and Replace");
             }
-            create_symlink_managed(file, resolution, fs_manager, verbose)?;
+            place_repo_version(file, templates, fs_manager, verbose)?;
         }
         ConflictResolution::Skip => {
             if verbose {
@@ -809,17 +1520,15 @@ fn handle_file_conflict(
         }
         ConflictResolution::ViewDiff => {
             if !fs_manager.is_dry_run {
-                show_diff(&file.repo_path, &file.dest_path)?;
+                show_diff(file, templates)?;
                 // Ask again after showing diff
                 let post_diff_resolution = prompt_conflict(&file.dest_path)?;
                 match post_diff_resolution {
                     ConflictResolution::BackupAndReplace => {
-                        create_symlink_managed(file, resolution, fs_manager, verbose)?;
+                        place_repo_version(file, templates, fs_manager, verbose)?;
                     }
-                    ConflictResolution::Skip => {
-                        if verbose {
-                            println!("{} Skipped {}", "⊘".yellow(), file.dest_path.display());
-                        }
+                    ConflictResolution::Skip if verbose => {
+                        println!("{} Skipped {}", "⊘".yellow(), file.dest_path.display());
                     }
                     ConflictResolution::Cancel => {
                         return Err(DotfilesError::Cancelled);
@@ -831,7 +1540,7 @@ fn handle_file_conflict(
                     println!("  [DRY RUN] Would show diff and prompt again");
                     println!("  [DRY RUN] Assuming: Backup and Replace");
                 }
-                create_symlink_managed(file, resolution, fs_manager, verbose)?;
+                place_repo_version(file, templates, fs_manager, verbose)?;
             }
         }
         ConflictResolution::Cancel => {
@@ -841,14 +1550,32 @@ fn handle_file_conflict(
     Ok(())
 }
 
+/// Replaces the destination with the repo version: the rendered output for templates
+/// and secrets, a symlink otherwise.
+/// Assumes backups have *already been created* by the caller.
+fn place_repo_version(
+    file: &TrackedFile,
+    templates: &TemplateContext,
+    fs_manager: &mut FileSystemManager,
+    verbose: bool,
+) -> Result<()> {
+    if !file.is_rendered() {
+        return create_symlink_managed(file, fs_manager, verbose);
+    }
+    let rendered = templates.render_file(file)?;
+    write_rendered_managed(file, &rendered, templates, fs_manager, verbose)
+}
+
 /// Creates a symlink, managed by the FileSystemManager.
 /// Assumes backups have *already been created* by the caller.
 fn create_symlink_managed(
     file: &TrackedFile,
-    resolution: &SymlinkResolution,
     fs_manager: &mut FileSystemManager,
     verbose: bool,
 ) -> Result<()> {
+    let resolution = &file.resolution;
+    let (file, _) = file.followed()?;
+    let file = &file;
     // SECURITY: Validate symlink target is within repo
     if let Err(e) = security::validate_symlink_target(&file.repo_path, &file.repo_path) {
         warn!(
@@ -878,6 +1605,10 @@ fn create_symlink_managed(
             println!("    Copying file instead of symlinking (Replace strategy)...");
         }
 
+        if file.repo_path.is_dir() {
+            fs_manager.copy_dir_over(&file.repo_path, &file.dest_path)?;
+            return Ok(());
+        }
         fs_manager.copy(&file.repo_path, &temp_path)?;
         fs_manager.rename(&temp_path, &file.dest_path)?; // Atomic move
 
@@ -905,9 +1636,7 @@ fn create_symlink_managed(
         }
         SymlinkResolution::Absolute => file.repo_path.clone(),
         SymlinkResolution::Follow => {
-            if verbose {
-                println!("    'Follow' resolution strategy is treated as 'Auto'.");
-            }
+            // Placed at the end of the symlink chain above, linked like Auto
             pathdiff::diff_paths(&file.repo_path, file.dest_path.parent().unwrap())
                 .unwrap_or_else(|| file.repo_path.clone())
         }
@@ -934,6 +1663,11 @@ fn create_symlink_managed(
     let _ = fs_manager.remove_file(&temp_link_path);
     fs_manager.symlink(&link_target, &temp_link_path)?;
 
+    // A link cannot be renamed over a directory; it was backed up before syncing
+    if file.dest_path.is_dir() && !file.dest_path.is_symlink() {
+        fs_manager.remove_dir_all(&file.dest_path)?;
+    }
+
     // Atomically rename temporary symlink to final destination
     if verbose {
         println!(
@@ -1030,7 +1764,7 @@ fn get_path_to_backup(path: &Path) -> Option<PathBuf> {
 }
 
 /// Recursively copy a directory.
-fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
+pub fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
     fs::create_dir_all(dst)?;
 
     for entry in fs::read_dir(src)? {
@@ -1050,23 +1784,25 @@ fn copy_dir_all(src: &Path, dst: &Path) -> Result<()> {
 }
 
 
-/// Show a diff between two files using the `diff` command.
-fn show_diff(path1: &Path, path2: &Path) -> Result<()> {
-    use std::process::Command;
-
-    let output = Command::new("diff")
-        .arg("-u")
-        .arg(path1)
-        .arg(path2)
-        .output()?;
-
-    // `diff` returns 1 if files differ, 0 if same, 2 on error.
-    // We want to print stdout for both 0 and 1.
-    if output.status.success() || output.status.code() == Some(1) {
-        print!("{}", String::from_utf8_lossy(&output.stdout));
+/// Show a unified diff between the repo file and the destination.
+fn show_diff(file: &TrackedFile, templates: &TemplateContext) -> Result<()> {
+    let label1 = file.repo_path.display().to_string();
+    let label2 = file.dest_path.display().to_string();
+    // Templates and secrets are compared through their rendered output
+    let repo = if file.is_rendered() {
+        Some(templates.render_file(file)?)
     } else {
-        // Print stderr if `diff` command failed
-        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        fs::read(&file.repo_path).ok()
+    };
+    match diff_contents(
+        &label2,
+        &label1,
+        &label2,
+        repo.as_deref(),
+        fs::read(&file.dest_path).ok().as_deref(),
+    ) {
+        Some(diff) => print_diffs(&[diff]),
+        None => println!("  {} Files are identical", "✓".green()),
     }
 
     Ok(())
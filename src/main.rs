@@ -8,22 +8,41 @@ mod utils;
 #[cfg(test)]
 mod tests;
 
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use commands::{
-    add_backup_to_repo, apply_config, check_status, cleanup_backups, compare_states,
-    display_backups, display_discrepancies, display_preview, display_status, display_validation,
-    find_discrepancies, list_backups, migrate_files, restore_backup, validate_config,
+    ApplyOptions, CloneOptions, ConfigLocation, DiffSource, DiscoverOptions, DiscrepancyOutput,
+    ExportFormat, ExportOptions, FileListOutput, ImportOptions, ServiceOptions, StatusOutput,
+    WatchOptions, add_backup_to_repo, add_browser, apply_config, check_status, cleanup_backups,
+    clone_dotfiles, collect_diffs, compare_states, discover, display_backups, display_diffs,
+    display_discrepancies, display_file_list, display_generation, display_generation_diff,
+    display_generations, display_preview, display_service_status, display_status,
+    display_validation, edit_secret, export_profile, find_discrepancies,
+    find_unfinished_transaction, import_dotfiles, install_service, install_watch_unit,
+    list_backups, list_files, migrate_files, prune_generations, recover_transaction, rekey_secrets,
+    restore_backup, rollback_to_generation, select_backup, service_status, uninstall_service,
+    validate_config, watch,
 };
-use config::profile::{create_profile, get_profile_files, list_profiles, switch_profile};
-use config::{Config, EnvironmentConfig};
-use file_manager::{add_file, backup_all_files, remove_file, sync_files};
+use config::include::display_resolved_config;
+use config::profile::{create_profile, display_profile, list_profiles, switch_profile};
+use config::{Config, EnvironmentConfig, HostFacts};
+use file_manager::{add_file, backup_all_files, exclude_ignored_changes, remove_file, sync_files};
+use services::browser::Browser;
+use services::generations::GenerationStore;
 use services::git;
+use services::hooks;
+use services::importers::ImportSource;
+use services::systemd;
 use services::{
-    add_remote, commit_changes, detect_changes, init_repo, list_remotes, pull_from_remote,
-    push_to_remote, remove_remote, set_remote_url, show_git_status, stage_changes,
+    add_remote, detect_changes, init_repo, list_remotes, pull_from_remote, push_to_remote,
+    remove_remote, set_remote_url, show_git_status,
+};
+use types::{ConflictPolicy, Severity};
+use utils::output::{self, OutputFormat};
+use utils::prompt::{
+    RecoveryChoice, is_interactive, prompt_commit_message, prompt_yes_no, select_conflict_policy,
 };
-use utils::prompt::{prompt_commit_message, prompt_yes_no};
 use utils::{DotfilesError, DryRun, Result, logging};
 
 #[derive(Parser)]
@@ -32,6 +51,19 @@ use utils::{DotfilesError, DryRun, Result, logging};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Output format for status, ls-files, apply --check, maintain check and maintain validate
+    #[arg(long, global = true, default_value = "text", value_parser = possible_values::<OutputFormat>())]
+    format: String,
+}
+
+/// Parser accepting the values of `T` as strings. `flux export --format` shares its
+/// name with the global `--format`, and clap requires both to hold the same type.
+fn possible_values<T: ValueEnum>() -> PossibleValuesParser {
+    PossibleValuesParser::new(
+        T::value_variants()
+            .iter()
+            .filter_map(ValueEnum::to_possible_value),
+    )
 }
 
 #[derive(Subcommand)]
@@ -42,6 +74,32 @@ enum Commands {
         #[arg(long)]
         repo_path: Option<String>,
     },
+    /// Clone a dotfiles repository and set up this machine from its config.toml
+    Clone {
+        /// Repository URL (or path to a local repository)
+        url: String,
+        /// Directory to clone into (default: ~/.dotfiles)
+        #[arg(long)]
+        path: Option<String>,
+        /// Branch to check out (default: the remote's default branch)
+        #[arg(long)]
+        branch: Option<String>,
+        /// Profile name (default: the profile named after this host, or the config's current profile)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Apply the configuration after cloning
+        #[arg(long)]
+        apply: bool,
+        /// Skip confirmation prompts when applying
+        #[arg(long, requires = "apply")]
+        yes: bool,
+        /// Policy for existing files when applying (default: keep-repo, which backs them up)
+        #[arg(long, value_enum, requires = "apply")]
+        on_conflict: Option<ConflictPolicy>,
+        /// Timeout in seconds (default: 60)
+        #[arg(long)]
+        timeout: Option<u64>,
+    },
     /// Add a file to tracking
     Add {
         /// Tool name (e.g., sway, waybar, cursor, firefox, zen)
@@ -60,6 +118,9 @@ enum Commands {
         /// File already exists in repo - just register it, don't copy
         #[arg(long)]
         from_repo: bool,
+        /// Store the file encrypted in the repository
+        #[arg(long, conflicts_with = "from_repo")]
+        encrypt: bool,
     },
     /// Sync tracked files (create symlinks) and commit changes to repository
     Commit {
@@ -75,6 +136,68 @@ enum Commands {
         /// Verbose output (show detailed progress for each file)
         #[arg(long, short = 'v')]
         verbose: bool,
+        /// Resolve conflicts without prompting (default when not interactive: config on_conflict or keep-repo)
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// Track a Firefox, Zen or LibreWolf profile (prefs.js, user.js, extensions, containers,
+    /// userChrome.css)
+    AddBrowser {
+        /// Browser to detect
+        #[arg(value_enum)]
+        browser: Browser,
+        /// Browser profile name from profiles.ini (default: the profile the browser starts with)
+        #[arg(long)]
+        browser_profile: Option<String>,
+        /// Profile name (optional)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Dry run mode
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Find well-known apps whose configuration is not tracked yet and register the
+    /// selected ones
+    Discover {
+        /// Register every app found without asking
+        #[arg(long)]
+        all: bool,
+        /// Profile name (optional)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Dry run mode
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Import files from a GNU Stow, chezmoi, yadm or bare-git dotfiles setup
+    Import {
+        /// Kind of setup to import from
+        #[arg(long, value_enum)]
+        from: ImportSource,
+        /// Stow directory, chezmoi source directory, or git directory of a yadm or bare repository
+        path: String,
+        /// Profile name for the imported entries (optional)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        yes: bool,
+        /// Only show what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the files of a profile as a tarball or a self-extracting shell installer
+    /// for machines without flux or git
+    Export {
+        /// Profile name (default: current profile)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Bundle format
+        #[arg(long, value_parser = possible_values::<ExportFormat>())]
+        format: String,
+        /// Output file (default: flux-<profile>.tar.gz or flux-<profile>.sh)
+        #[arg(long, short)]
+        output: Option<String>,
     },
     /// Remove a file from tracking
     Rm {
@@ -86,7 +209,7 @@ enum Commands {
         #[arg(long)]
         dry_run: bool,
     },
-    /// List all tracked files
+    /// List all file entries and why each is included or excluded
     #[command(visible_alias = "list")]
     LsFiles {
         /// Profile name (default: current profile)
@@ -110,6 +233,31 @@ enum Commands {
         /// Force sync: replace all files that aren't correct symlinks (no backups, uses repo version)
         #[arg(long)]
         force: bool,
+        /// Check for drift without applying; exits non-zero if anything would change
+        #[arg(long, conflicts_with_all = ["dry_run", "yes", "description"])]
+        check: bool,
+        /// Resolve conflicts without prompting (default when not interactive: config on_conflict or keep-repo)
+        #[arg(long, value_enum, conflicts_with = "force")]
+        on_conflict: Option<ConflictPolicy>,
+    },
+    /// Finish or undo an apply that was interrupted
+    Recover {
+        /// Finish the remaining operations without prompting
+        #[arg(long, conflicts_with = "undo")]
+        finish: bool,
+        /// Undo the completed operations without prompting
+        #[arg(long)]
+        undo: bool,
+    },
+    /// Inspect and roll back apply generations
+    Generations {
+        #[command(subcommand)]
+        command: GenerationCommands,
+    },
+    /// Manage encrypted files
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommands,
     },
     /// Profile management
     Profile {
@@ -170,6 +318,43 @@ enum Commands {
         #[arg(long)]
         profile: Option<String>,
     },
+    /// Show unified diffs between repo files and their destinations, HEAD or a backup
+    Diff {
+        /// Tool name or path to limit the diff to (default: all tracked files)
+        target: Option<String>,
+        /// Profile name (default: current profile)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Diff uncommitted changes in the repository against HEAD
+        #[arg(long, conflicts_with = "backup")]
+        head: bool,
+        /// Diff repo files against a backup ('latest' or an index from 'flux backup restore list')
+        #[arg(long)]
+        backup: Option<String>,
+        /// Show changed line counts per file instead of the diff
+        #[arg(long)]
+        stat: bool,
+    },
+    /// Watch tracked files and commit changes as they happen
+    Watch {
+        /// Profile name (default: current profile)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Seconds without further changes before syncing
+        #[arg(long, default_value_t = 2)]
+        debounce: u64,
+        /// Policy for destinations an app replaced with a regular file (default: config on_conflict or keep-repo)
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        /// Write a systemd user unit that runs this command instead of watching
+        #[arg(long)]
+        systemd: bool,
+    },
+    /// Manage systemd user units that pull and apply on a schedule
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommands,
+    },
     /// Maintenance and repair operations
     Maintain {
         #[command(subcommand)]
@@ -182,6 +367,39 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum ServiceCommands {
+    /// Write flux-sync.service and flux-sync.timer and enable the timer
+    Install {
+        /// Profile name (default: current profile)
+        #[arg(long)]
+        profile: Option<String>,
+        /// Remote name (default: origin or config default_remote)
+        #[arg(long)]
+        remote: Option<String>,
+        /// Branch name (default: config default_branch, or the current HEAD when the unit runs)
+        #[arg(long)]
+        branch: Option<String>,
+        /// systemd OnCalendar expression (e.g. hourly, daily, *:0/15)
+        #[arg(long, default_value = "hourly")]
+        schedule: String,
+        /// Conflict policy for apply (default: config on_conflict or keep-repo)
+        #[arg(long, value_enum)]
+        on_conflict: Option<ConflictPolicy>,
+        /// Only write the unit files, without calling systemctl
+        #[arg(long)]
+        no_enable: bool,
+    },
+    /// Disable the timer and remove the units
+    Uninstall {
+        /// Only remove the unit files, without calling systemctl
+        #[arg(long)]
+        no_disable: bool,
+    },
+    /// Show whether the units are installed, enabled and active
+    Status,
+}
+
 #[derive(Subcommand)]
 enum BackupCommands {
     /// Backup all currently tracked files
@@ -278,6 +496,65 @@ enum MaintainCommands {
     Gitignore,
 }
 
+#[derive(Subcommand)]
+enum GenerationCommands {
+    /// List all recorded generations
+    List,
+    /// Show the operations recorded in a generation
+    Show {
+        /// Generation number
+        generation: u64,
+    },
+    /// Show targets changed between two generations
+    Diff {
+        /// Older generation number (0 for the state before the first apply)
+        from: u64,
+        /// Newer generation number
+        to: u64,
+    },
+    /// Roll back to a generation by undoing every newer one
+    Rollback {
+        /// Generation number to roll back to (0 for the state before the first apply)
+        generation: u64,
+        /// Skip confirmation prompts (auto-confirm)
+        #[arg(long)]
+        yes: bool,
+        /// Overwrite targets changed since they were applied (they are backed up first)
+        #[arg(long)]
+        force: bool,
+        /// Dry run mode
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Delete old generations, keeping the newest ones
+    Prune {
+        /// Number of generations to keep (default: keep_generations from the config, or 50)
+        #[arg(long)]
+        keep: Option<usize>,
+        /// Dry run mode
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretCommands {
+    /// Decrypt an encrypted file, open it in $EDITOR and re-encrypt it
+    Edit {
+        /// Destination (e.g., ~/.ssh/config) or repository path of the file
+        file: String,
+    },
+    /// Re-encrypt all encrypted files to the current identity and recipients
+    Rekey {
+        /// Generate a new identity and re-encrypt to it (the old one is kept as .old)
+        #[arg(long)]
+        new_identity: bool,
+        /// Dry run mode
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 #[derive(Subcommand)]
 enum ProfileCommands {
     /// List all profiles
@@ -286,6 +563,14 @@ enum ProfileCommands {
     Create {
         /// Profile name
         name: String,
+        /// Profiles to inherit files from, lowest priority first (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        extends: Vec<String>,
+    },
+    /// Show the effective file set of a profile and the layer each file comes from
+    Show {
+        /// Profile name (default: current profile)
+        name: Option<String>,
     },
     /// Switch to a profile
     Switch {
@@ -342,6 +627,12 @@ enum ConfigCommands {
         #[arg(long)]
         dry_run: bool,
     },
+    /// Print the config file
+    Show {
+        /// Print the result of merging all included files, with the file each tool came from
+        #[arg(long)]
+        resolved: bool,
+    },
 }
 
 fn main() {
@@ -370,6 +661,21 @@ fn main() {
 
     let cli = Cli::parse();
 
+    // Warn about a transaction that was interrupted by a crash or kill. Only the state
+    // dir is looked up, since loading the config here would create or sync it
+    if !matches!(cli.command, Commands::Recover { .. })
+        && let Ok(state_dir) = Config::locate_state_dir()
+        && let Ok(Some(state)) = find_unfinished_transaction(&state_dir)
+    {
+        eprintln!(
+            "{} Transaction {} was interrupted ({}/{} operations completed). Run `flux recover` to finish or undo it",
+            "Warning:".yellow().bold(),
+            state.transaction_id,
+            state.completed,
+            state.operations.len()
+        );
+    }
+
     if let Err(e) = run(cli, env_config) {
         eprintln!("{} {}", "Error:".red().bold(), e);
         std::process::exit(1);
@@ -414,18 +720,7 @@ fn handle_backup_command(command: BackupCommands) -> Result<()> {
                 display_backups(&backups);
                 return Ok(());
             } else {
-                let index: usize = backup.parse().map_err(|_| {
-                    DotfilesError::Path(
-                        "Invalid backup index. Use 'latest', 'list', or a number".to_string(),
-                    )
-                })?;
-                if index == 0 || index > backups.len() {
-                    return Err(DotfilesError::Path(format!(
-                        "Backup index out of range (1-{})",
-                        backups.len()
-                    )));
-                }
-                &backups[index - 1]
+                select_backup(&backups, &backup)?
             };
 
             let mut dry_run_tracker = DryRun::default();
@@ -563,24 +858,102 @@ fn handle_backup_command(command: BackupCommands) -> Result<()> {
     Ok(())
 }
 
-fn handle_maintain_command(command: MaintainCommands) -> Result<()> {
+fn handle_service_command(command: ServiceCommands, env_config: &EnvironmentConfig) -> Result<()> {
+    let unit_dir = systemd::user_unit_dir()?;
+    match command {
+        ServiceCommands::Install {
+            profile,
+            remote,
+            branch,
+            schedule,
+            on_conflict,
+            no_enable,
+        } => {
+            let config = Config::load()?;
+            let options = ServiceOptions {
+                profile,
+                remote,
+                branch,
+                schedule,
+                conflict_policy: on_conflict,
+                config_file: env_config.config_file.clone(),
+            };
+            install_service(&config, &options, &unit_dir, !no_enable)?;
+        }
+        ServiceCommands::Uninstall { no_disable } => {
+            uninstall_service(&unit_dir, !no_disable)?;
+        }
+        ServiceCommands::Status => {
+            display_service_status(&service_status(&unit_dir, true));
+        }
+    }
+    Ok(())
+}
+
+fn handle_generations_command(command: GenerationCommands) -> Result<()> {
+    let config = Config::load()?;
+    let store = GenerationStore::open(&config)?;
+
+    match command {
+        GenerationCommands::List => {
+            display_generations(&store.list()?);
+        }
+        GenerationCommands::Show { generation } => {
+            display_generation(&store.load(generation)?);
+        }
+        GenerationCommands::Diff { from, to } => {
+            display_generation_diff(&store, from, to)?;
+        }
+        GenerationCommands::Rollback {
+            generation,
+            yes,
+            force,
+            dry_run,
+        } => {
+            rollback_to_generation(&config, generation, yes, force, dry_run)?;
+        }
+        GenerationCommands::Prune { keep, dry_run } => {
+            prune_generations(&config, keep, dry_run)?;
+        }
+    }
+    Ok(())
+}
+
+/// Exit with the code for `severity` unless nothing was found.
+fn exit_with_severity(severity: Severity) {
+    if severity != Severity::Ok {
+        std::process::exit(severity.exit_code());
+    }
+}
+
+fn handle_maintain_command(command: MaintainCommands, format: OutputFormat) -> Result<()> {
     match command {
         MaintainCommands::Check { profile } => {
             let config = Config::load()?;
             let discrepancies = find_discrepancies(&config, profile.as_deref())?;
-            display_discrepancies(&discrepancies);
-
-            if !discrepancies.is_empty() {
-                std::process::exit(1);
+            match format {
+                OutputFormat::Text => display_discrepancies(&discrepancies),
+                OutputFormat::Json => output::print_json(&DiscrepancyOutput {
+                    discrepancies: &discrepancies,
+                })?,
+                OutputFormat::Ndjson => output::print_ndjson(&discrepancies)?,
             }
+
+            exit_with_severity(Severity::max_of(
+                discrepancies.iter().map(|d| d.issue.severity()),
+            ));
         }
         MaintainCommands::Validate => {
             let config = Config::load()?;
             let report = validate_config(&config)?;
-            display_validation(&report);
-            if !report.is_valid {
-                std::process::exit(1);
+            match format {
+                OutputFormat::Text => display_validation(&report),
+                OutputFormat::Json => output::print_json(&report)?,
+                OutputFormat::Ndjson => output::print_ndjson(&report.issues)?,
             }
+            exit_with_severity(Severity::max_of(
+                report.issues.iter().map(|issue| issue.severity()),
+            ));
         }
         MaintainCommands::Migrate {
             profile,
@@ -657,9 +1030,10 @@ desktop.ini
     Ok(())
 }
 
-fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
+fn run(cli: Cli, env_config: EnvironmentConfig) -> Result<()> {
+    let output_format = OutputFormat::from_str(&cli.format, true).unwrap_or_default();
     // Note: env_config is validated at startup for early error detection.
-    // It's used for custom config file paths and git authentication.
+    // It's used for custom config file paths, git authentication and CI detection.
     match cli.command {
         Commands::Init { repo_path } => {
             let mut config = Config::load()?;
@@ -686,6 +1060,53 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                 }
             );
         }
+        Commands::Clone {
+            url,
+            path,
+            branch,
+            profile,
+            apply,
+            yes,
+            on_conflict,
+            timeout,
+        } => {
+            let dest = match path {
+                Some(path) => std::path::absolute(shellexpand::tilde(&path).as_ref())?,
+                None => dirs::home_dir()
+                    .ok_or_else(|| {
+                        DotfilesError::Config("Could not find home directory".to_string())
+                    })?
+                    .join(".dotfiles"),
+            };
+            let cloned = clone_dotfiles(&CloneOptions {
+                url,
+                dest,
+                branch,
+                profile,
+                timeout: timeout.unwrap_or(60),
+                xdg_config: Config::get_xdg_config_path()?,
+                interactive: is_interactive(env_config.is_ci_environment),
+            })?;
+
+            if apply {
+                apply_config(ApplyOptions {
+                    config: &cloned.config,
+                    profile: Some(&cloned.profile),
+                    dry_run: false,
+                    yes,
+                    description: Some("flux clone"),
+                    force: false,
+                    conflict_policy: Some(on_conflict.unwrap_or(ConflictPolicy::KeepRepo)),
+                })?;
+            } else if let ConfigLocation::Shadowed(path) = &cloned.location {
+                println!(
+                    "  Move {} aside, then run 'flux apply' to link your files",
+                    path.display()
+                );
+            } else {
+                println!("  Run 'flux apply' to link your files");
+            }
+        }
         Commands::Add {
             tool,
             file,
@@ -693,6 +1114,7 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
             profile,
             dry_run,
             from_repo,
+            encrypt,
         } => {
             let mut config = Config::load()?;
             let mut dry_run_tracker = DryRun::default();
@@ -771,6 +1193,7 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                     source_path,
                     &dest_path,
                     profile.as_deref(),
+                    encrypt,
                     &mut fs_manager,
                 )?;
             }
@@ -784,9 +1207,15 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
             message,
             dry_run,
             verbose,
+            on_conflict,
         } => {
             let config = Config::load()?;
             let mut dry_run_tracker = DryRun::default();
+            let conflict_policy = select_conflict_policy(
+                on_conflict,
+                config.general.on_conflict,
+                is_interactive(env_config.is_ci_environment),
+            );
 
             sync_files(
                 &config,
@@ -794,15 +1223,24 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                 &mut dry_run_tracker,
                 dry_run,
                 verbose,
+                conflict_policy,
             )?;
 
             if dry_run {
                 dry_run_tracker.display_summary();
+
+                // Hooks for the repository changes made so far
+                if let Ok(repo) = git2::Repository::open(config.get_repo_path()?) {
+                    let changes = exclude_ignored_changes(&config, detect_changes(&repo)?)?;
+                    let (pre_hooks, post_hooks) =
+                        hooks::plan_commit_hooks(&config, profile.as_deref(), &changes)?;
+                    hooks::display_planned_hooks(&[pre_hooks, post_hooks].concat());
+                }
             } else {
                 // Automatically commit any changes to the repository
                 let repo_path = config.get_repo_path()?;
                 let repo = init_repo(&repo_path)?;
-                let changes = detect_changes(&repo)?;
+                let changes = exclude_ignored_changes(&config, detect_changes(&repo)?)?;
 
                 if !changes.is_empty() {
                     let commit_message = if let Some(msg) = message {
@@ -810,11 +1248,88 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                     } else {
                         prompt_commit_message(&changes)?
                     };
-                    stage_changes(&repo, &changes, &mut dry_run_tracker, dry_run)?;
-                    commit_changes(&repo, &commit_message, &mut dry_run_tracker, dry_run)?;
+                    hooks::commit_with_hooks(
+                        &config,
+                        profile.as_deref(),
+                        &repo,
+                        &changes,
+                        &commit_message,
+                        &mut dry_run_tracker,
+                    )?;
                 }
             }
         }
+        Commands::AddBrowser {
+            browser,
+            browser_profile,
+            profile,
+            dry_run,
+        } => {
+            let mut config = Config::load()?;
+            let mut dry_run_tracker = DryRun::default();
+            let mut fs_manager =
+                file_manager::FileSystemManager::new(&mut dry_run_tracker, dry_run);
+            add_browser(
+                &mut config,
+                browser,
+                browser_profile.as_deref(),
+                profile.as_deref(),
+                &mut fs_manager,
+            )?;
+            if dry_run {
+                dry_run_tracker.display_summary();
+            }
+        }
+        Commands::Discover {
+            all,
+            profile,
+            dry_run,
+        } => {
+            let mut config = Config::load()?;
+            let mut dry_run_tracker = DryRun::default();
+            let mut fs_manager =
+                file_manager::FileSystemManager::new(&mut dry_run_tracker, dry_run);
+            let options = DiscoverOptions {
+                all,
+                profile,
+                interactive: is_interactive(env_config.is_ci_environment),
+            };
+            discover(&mut config, &options, &mut fs_manager)?;
+            if dry_run {
+                dry_run_tracker.display_summary();
+            }
+        }
+        Commands::Import {
+            from,
+            path,
+            profile,
+            yes,
+            dry_run,
+        } => {
+            let mut config = Config::load()?;
+            let options = ImportOptions {
+                source: from,
+                path: std::path::PathBuf::from(shellexpand::tilde(&path).into_owned()),
+                profile,
+                yes,
+                dry_run,
+            };
+            import_dotfiles(&mut config, &options)?;
+        }
+        Commands::Export {
+            profile,
+            format,
+            output,
+        } => {
+            let config = Config::load()?;
+            let options = ExportOptions {
+                profile,
+                format: ExportFormat::from_str(&format, true).map_err(DotfilesError::Config)?,
+                output: output
+                    .map(|path| std::path::PathBuf::from(shellexpand::tilde(&path).into_owned())),
+            };
+            export_profile(&config, &options)?;
+        }
         Commands::Rm {
             tool,
             file,
@@ -832,15 +1347,11 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
         }
         Commands::LsFiles { profile } => {
             let config = Config::load()?;
-            let files = config.get_tracked_files(profile.as_deref())?;
-
-            println!("\n{}", "Tracked files:".bold().cyan());
-            for file in files {
-                println!(
-                    "  {} -> {}",
-                    file.repo_path.display(),
-                    file.dest_path.display()
-                );
+            let files = list_files(&config, profile.as_deref())?;
+            match output_format {
+                OutputFormat::Text => display_file_list(&files),
+                OutputFormat::Json => output::print_json(&FileListOutput { files: &files })?,
+                OutputFormat::Ndjson => output::print_ndjson(&files)?,
             }
         }
         Commands::Apply {
@@ -849,15 +1360,28 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
             yes,
             description,
             force,
+            check,
+            on_conflict,
         } => {
             let config = Config::load()?;
+            let conflict_policy = select_conflict_policy(
+                on_conflict,
+                config.general.on_conflict,
+                is_interactive(env_config.is_ci_environment),
+            );
 
-            if dry_run {
-                // In dry-run mode, only show preview without applying changes
+            if check {
                 let diff = compare_states(&config, profile.as_deref(), force)?;
-                display_preview(&diff);
+                match output_format {
+                    OutputFormat::Text => display_preview(&diff),
+                    OutputFormat::Json => output::print_json(&diff)?,
+                    OutputFormat::Ndjson => output::print_ndjson(&diff.records())?,
+                }
+                if !diff.is_empty() {
+                    exit_with_severity(Severity::Warning);
+                }
             } else {
-                use crate::commands::ApplyOptions;
+                // In dry-run mode, apply_config only shows the preview
                 apply_config(ApplyOptions {
                     config: &config,
                     profile: profile.as_deref(),
@@ -865,9 +1389,34 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                     yes,
                     description: description.as_deref(),
                     force,
+                    conflict_policy,
                 })?;
             }
         }
+        Commands::Recover { finish, undo } => {
+            let config = Config::load()?;
+            let choice = if finish {
+                Some(RecoveryChoice::Finish)
+            } else if undo {
+                Some(RecoveryChoice::Undo)
+            } else {
+                None
+            };
+            recover_transaction(&config, choice)?;
+        }
+        Commands::Generations { command } => {
+            return handle_generations_command(command);
+        }
+        Commands::Secret { command } => {
+            let config = Config::load()?;
+            match command {
+                SecretCommands::Edit { file } => edit_secret(&config, &file)?,
+                SecretCommands::Rekey {
+                    new_identity,
+                    dry_run,
+                } => rekey_secrets(&config, new_identity, dry_run)?,
+            }
+        }
         Commands::Profile { command } => {
             let mut config = Config::load()?;
             match command {
@@ -880,8 +1429,11 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                         } else {
                             " "
                         };
-                        let profile_files = get_profile_files(&config, profile)?;
-                        let file_count = profile_files.len();
+                        let file_count = config
+                            .select_files(Some(profile), &HostFacts::current())?
+                            .iter()
+                            .filter(|selected| selected.selection.included)
+                            .count();
                         println!(
                             "  {} {} ({} file(s))",
                             marker.green(),
@@ -894,8 +1446,12 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
                         );
                     }
                 }
-                ProfileCommands::Create { name } => {
-                    create_profile(&mut config, &name)?;
+                ProfileCommands::Create { name, extends } => {
+                    create_profile(&mut config, &name, &extends)?;
+                }
+                ProfileCommands::Show { name } => {
+                    let name = name.unwrap_or_else(|| config.general.current_profile.clone());
+                    display_profile(&config, &name)?;
                 }
                 ProfileCommands::Switch { name } => {
                     switch_profile(&mut config, &name)?;
@@ -906,6 +1462,16 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
             ConfigCommands::Sync { dry_run } => {
                 Config::sync_xdg_to_repo(dry_run)?;
             }
+            ConfigCommands::Show { resolved } => {
+                let config = Config::load()?;
+                if resolved {
+                    display_resolved_config(&config)?;
+                } else {
+                    let path = &config.sources.root;
+                    println!("{} {}", "#".bright_black(), path.display());
+                    print!("{}", std::fs::read_to_string(path)?);
+                }
+            }
             ConfigCommands::Format { dry_run } => {
                 let xdg_config = Config::get_xdg_config_path()?;
 
@@ -1035,6 +1601,22 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
             // Resolve timeout with precedence: CLI flag > config push_timeout > 60 seconds
             let resolved_timeout = timeout.or(config.general.push_timeout).unwrap_or(60);
 
+            // Incoming changes are unknown before fetching, so pre_pull hooks always run
+            let all_tools = config.tools.keys().cloned().collect();
+            let pre_hooks = hooks::plan_hooks(&config, hooks::HookPoint::PrePull, &all_tools);
+            if dry_run {
+                hooks::display_planned_hooks(&pre_hooks);
+            }
+            let mut hook_results = Vec::new();
+            if !dry_run {
+                hook_results = hooks::run_hooks(&config, None, &pre_hooks, true)?;
+                if let Err(e) = hooks::check_pre_hooks(&hook_results) {
+                    hooks::display_hook_results(&hook_results);
+                    return Err(e);
+                }
+            }
+
+            let head_before = git::head_oid(&repo);
             pull_from_remote(
                 &repo,
                 &resolved_remote,
@@ -1046,23 +1628,75 @@ fn run(cli: Cli, _env_config: EnvironmentConfig) -> Result<()> {
 
             if dry_run {
                 dry_run_tracker.display_summary();
+            } else if let Some(head_after) = git::head_oid(&repo)
+                && Some(head_after) != head_before
+            {
+                let changed = git::changed_paths(&repo, head_before, head_after)?;
+                let tools = hooks::tools_for_paths(&config, None, &changed)?;
+                let post_hooks = hooks::plan_hooks(&config, hooks::HookPoint::PostPull, &tools);
+                hook_results.extend(hooks::run_hooks(&config, None, &post_hooks, false)?);
             }
+            hooks::display_hook_results(&hook_results);
         }
         Commands::Status { profile } => {
             let config = Config::load()?;
-            let repo_path = config.get_repo_path()?;
+            let reports = check_status(&config, profile.as_deref())?;
+            match output_format {
+                OutputFormat::Text => {
+                    // Display git repository status
+                    let repo_path = config.get_repo_path()?;
+                    if let Ok(repo) = init_repo(&repo_path) {
+                        show_git_status(&repo)?;
+                    }
 
-            // Display git repository status
-            if let Ok(repo) = init_repo(&repo_path) {
-                show_git_status(&repo)?;
+                    // Display file synchronization status
+                    display_status(&reports);
+                }
+                OutputFormat::Json => output::print_json(&StatusOutput::new(&reports))?,
+                OutputFormat::Ndjson => output::print_ndjson(&reports)?,
             }
-
-            // Display file synchronization status
-            let reports = check_status(&config, profile.as_deref())?;
-            display_status(&reports);
+            exit_with_severity(Severity::max_of(
+                reports.iter().map(|report| report.status.severity()),
+            ));
+        }
+        Commands::Diff {
+            target,
+            profile,
+            head,
+            backup,
+            stat,
+        } => {
+            let config = Config::load()?;
+            let source = match backup {
+                Some(backup) => DiffSource::Backup(backup),
+                None if head => DiffSource::Head,
+                None => DiffSource::Dest,
+            };
+            let diffs = collect_diffs(&config, profile.as_deref(), target.as_deref(), &source)?;
+            display_diffs(&diffs, stat);
+        }
+        Commands::Watch {
+            profile,
+            debounce,
+            on_conflict,
+            systemd,
+        } => {
+            let options = WatchOptions {
+                profile,
+                debounce: std::time::Duration::from_secs(debounce),
+                conflict_policy: on_conflict,
+            };
+            if systemd {
+                install_watch_unit(&options)?;
+            } else {
+                watch(&options)?;
+            }
+        }
+        Commands::Service { command } => {
+            return handle_service_command(command, &env_config);
         }
         Commands::Maintain { command } => {
-            return handle_maintain_command(command);
+            return handle_maintain_command(command, output_format);
         }
         Commands::Completion { shell } => {
             use clap_complete::{generate, shells::Zsh};
@@ -0,0 +1,221 @@
+//! Firefox-family browser profiles.
+//!
+//! Profile directories have a random prefix per machine (`xxxxxxxx.default-release`),
+//! so browser files are tracked with a symbolic destination such as
+//! `{firefox.default}/prefs.js`. The part in braces names a browser and a profile:
+//! `default` is the profile the browser starts with (from `installs.ini`, or the
+//! `Default=1` profile of `profiles.ini`), any other name matches a profile's `Name=`.
+//! It is resolved to the profile directory whenever tracked files are listed.
+
+use crate::types::SymlinkResolution;
+use crate::utils::error::{DotfilesError, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Browsers whose profiles flux can find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Browser {
+    Firefox,
+    Zen,
+    Librewolf,
+}
+
+/// A file or directory in a browser profile that is worth tracking.
+pub struct Artifact {
+    /// Path relative to the profile directory
+    pub path: &'static str,
+    /// Resolution override for files the browser rewrites by replacing them
+    pub resolution: Option<SymlinkResolution>,
+}
+
+/// Profile artifacts registered by `flux add-browser`.
+pub const ARTIFACTS: &[Artifact] = &[
+    Artifact {
+        path: "prefs.js",
+        resolution: Some(SymlinkResolution::Replace),
+    },
+    Artifact {
+        path: "user.js",
+        resolution: None,
+    },
+    Artifact {
+        path: "extensions",
+        resolution: None,
+    },
+    Artifact {
+        path: "containers.json",
+        resolution: Some(SymlinkResolution::Replace),
+    },
+    Artifact {
+        path: "chrome/userChrome.css",
+        resolution: None,
+    },
+];
+
+impl Browser {
+    /// Name used for the tool and in symbolic destinations.
+    pub fn name(self) -> &'static str {
+        match self {
+            Browser::Firefox => "firefox",
+            Browser::Zen => "zen",
+            Browser::Librewolf => "librewolf",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Browser::Firefox, Browser::Zen, Browser::Librewolf]
+            .into_iter()
+            .find(|browser| browser.name() == name)
+    }
+
+    /// Directories that may hold `profiles.ini`, relative to the home directory, in
+    /// order of preference (native, then Flatpak).
+    fn profile_roots(self) -> &'static [&'static str] {
+        if cfg!(target_os = "macos") {
+            return match self {
+                Browser::Firefox => &["Library/Application Support/Firefox"],
+                Browser::Zen => &["Library/Application Support/zen"],
+                Browser::Librewolf => &["Library/Application Support/librewolf"],
+            };
+        }
+        match self {
+            Browser::Firefox => &[
+                ".mozilla/firefox",
+                ".config/mozilla/firefox",
+                ".var/app/org.mozilla.firefox/.mozilla/firefox",
+                "snap/firefox/common/.mozilla/firefox",
+            ],
+            Browser::Zen => &[".zen", ".var/app/app.zen_browser.zen/.zen"],
+            Browser::Librewolf => &[
+                ".librewolf",
+                ".var/app/io.gitlab.librewolf-community/.librewolf",
+            ],
+        }
+    }
+
+    /// The first directory under `home` with a `profiles.ini`.
+    pub fn find_root(self, home: &Path) -> Option<PathBuf> {
+        self.profile_roots()
+            .iter()
+            .map(|root| home.join(root))
+            .find(|root| root.join("profiles.ini").is_file())
+    }
+}
+
+impl std::fmt::Display for Browser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A profile listed in `profiles.ini`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserProfile {
+    pub name: String,
+    /// Absolute path of the profile directory
+    pub path: PathBuf,
+    /// Whether the browser starts with this profile
+    pub is_default: bool,
+}
+
+/// Read the profiles of the browser installation at `root`.
+pub fn read_profiles(root: &Path) -> Result<Vec<BrowserProfile>> {
+    let profiles_ini = root.join("profiles.ini");
+    let sections = parse_ini(&fs::read_to_string(&profiles_ini).map_err(|e| {
+        DotfilesError::Config(format!("Cannot read {}: {}", profiles_ini.display(), e))
+    })?);
+    // installs.ini is optional (older browsers keep [Install...] sections in profiles.ini)
+    let installs = fs::read_to_string(root.join("installs.ini"))
+        .map(|text| parse_ini(&text))
+        .unwrap_or_default();
+
+    let resolve = |section: &HashMap<String, String>, path: &str| {
+        if section.get("IsRelative").is_some_and(|value| value == "0") {
+            PathBuf::from(path)
+        } else {
+            root.join(path)
+        }
+    };
+
+    // The installation's default wins over the legacy Default=1 flag
+    let install_default = installs
+        .iter()
+        .chain(sections.iter())
+        .filter(|(name, _)| !name.starts_with("Profile"))
+        .find_map(|(_, section)| section.get("Default"))
+        .map(|path| root.join(path));
+
+    let mut profiles: Vec<BrowserProfile> = sections
+        .iter()
+        .filter(|(name, _)| name.starts_with("Profile"))
+        .filter_map(|(_, section)| {
+            let path = resolve(section, section.get("Path")?);
+            Some(BrowserProfile {
+                name: section.get("Name").cloned().unwrap_or_default(),
+                is_default: match &install_default {
+                    Some(default) => *default == path,
+                    None => section.get("Default").is_some_and(|value| value == "1"),
+                },
+                path,
+            })
+        })
+        .collect();
+    if let [only] = profiles.as_mut_slice() {
+        only.is_default = true;
+    }
+    Ok(profiles)
+}
+
+/// The profile named `name`, or the default profile for `None` and `default`.
+pub fn find_profile<'a>(
+    profiles: &'a [BrowserProfile],
+    name: Option<&str>,
+) -> Option<&'a BrowserProfile> {
+    match name {
+        None | Some("default") => profiles.iter().find(|profile| profile.is_default),
+        Some(name) => profiles.iter().find(|profile| profile.name == name),
+    }
+}
+
+/// Resolve a destination from the config to an absolute path. Plain destinations are
+/// relative to `home`; symbolic ones (`{firefox.default}/prefs.js`) are resolved
+/// through the browser's `profiles.ini`. The error explains why a symbolic destination
+/// does not exist on this machine.
+pub fn resolve_dest(home: &Path, dest: &str) -> std::result::Result<PathBuf, String> {
+    let Some((key, rest)) = dest
+        .strip_prefix('{')
+        .and_then(|symbolic| symbolic.split_once('}'))
+    else {
+        return Ok(home.join(dest));
+    };
+    let (browser_name, profile_name) = key.split_once('.').unwrap_or((key, "default"));
+    let browser = Browser::from_name(browser_name)
+        .ok_or_else(|| format!("unknown browser '{}' in {}", browser_name, dest))?;
+
+    let root = browser
+        .find_root(home)
+        .ok_or_else(|| format!("{} is not installed (no profiles.ini)", browser))?;
+    let profiles = read_profiles(&root).map_err(|e| e.to_string())?;
+    let profile = find_profile(&profiles, Some(profile_name))
+        .ok_or_else(|| format!("{} has no profile '{}'", browser, profile_name))?;
+    Ok(profile.path.join(rest.trim_start_matches('/')))
+}
+
+/// Sections of an INI file in order, as (name, key/value pairs).
+fn parse_ini(text: &str) -> Vec<(String, HashMap<String, String>)> {
+    let mut sections: Vec<(String, HashMap<String, String>)> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.to_string(), HashMap::new()));
+        } else if let (Some((key, value)), Some((_, section))) =
+            (line.split_once('='), sections.last_mut())
+        {
+            section.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    sections
+}
@@ -0,0 +1,117 @@
+//! Catalog of well-known apps and where they keep their configuration, used by
+//! `flux discover`.
+//!
+//! The built-in catalog ships as `config/catalog.toml`. Apps listed in the user catalog
+//! (`~/.config/flux/catalog.toml`, same format) are added to it, replacing a built-in
+//! app of the same name.
+
+use crate::config::Config;
+use crate::utils::error::{DotfilesError, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BUILTIN_CATALOG: &str = include_str!("../../config/catalog.toml");
+
+/// An app whose configuration flux knows how to find.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct CatalogApp {
+    /// Shown next to the app's name when selecting apps
+    #[serde(default)]
+    pub description: String,
+    /// Candidate config files or directories; `~` and environment variables are expanded
+    pub paths: Vec<String>,
+    /// Globs relative to a directory path that stay out of the repository
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+/// Apps by the tool name they are registered under.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Catalog {
+    #[serde(default)]
+    pub apps: BTreeMap<String, CatalogApp>,
+}
+
+impl Catalog {
+    /// The catalog shipped with flux.
+    pub fn builtin() -> Result<Self> {
+        Self::parse(BUILTIN_CATALOG, Path::new("built-in catalog"))
+    }
+
+    /// The built-in catalog extended with the user catalog, if there is one.
+    pub fn load() -> Result<Self> {
+        let mut catalog = Self::builtin()?;
+        let user_path = user_catalog_path()?;
+        if user_path.exists() {
+            catalog.extend(Self::parse(&fs::read_to_string(&user_path)?, &user_path)?);
+        }
+        Ok(catalog)
+    }
+
+    /// Parse a catalog file; `source` names it in errors.
+    pub fn parse(text: &str, source: &Path) -> Result<Self> {
+        toml::from_str(text).map_err(|e| {
+            DotfilesError::Config(format!(
+                "What: Invalid app catalog {}: {}\n  \
+                 💡 Solution: Each app is an [apps.<name>] table with a `paths` array",
+                source.display(),
+                e
+            ))
+        })
+    }
+
+    /// Add the apps of `other`, replacing apps of the same name.
+    pub fn extend(&mut self, other: Catalog) {
+        self.apps.extend(other.apps);
+    }
+}
+
+impl CatalogApp {
+    /// Candidate paths that exist under `home`, in catalog order.
+    ///
+    /// A candidate with the same file name as an earlier one is skipped, since both
+    /// would be stored as the same file in the repository.
+    pub fn existing_paths(&self, home: &Path) -> Vec<PathBuf> {
+        let mut file_names = HashSet::new();
+        self.paths
+            .iter()
+            .map(|path| expand_path(path, home))
+            .filter(|path| path.starts_with(home) && (path.exists() || path.is_symlink()))
+            .filter(|path| file_names.insert(path.file_name().map(|name| name.to_os_string())))
+            .collect()
+    }
+}
+
+/// Path of the user catalog, next to the XDG config.
+pub fn user_catalog_path() -> Result<PathBuf> {
+    Ok(Config::get_xdg_config_path()?.with_file_name("catalog.toml"))
+}
+
+/// Expand `~`, `$HOME` and environment variables in a catalog path. XDG base
+/// directories that are unset default to their standard locations under `home`.
+fn expand_path(path: &str, home: &Path) -> PathBuf {
+    let home_str = home.to_string_lossy();
+    let expanded = shellexpand::full_with_context_no_errors(
+        path,
+        || Some(home_str.as_ref()),
+        |var| match var {
+            "HOME" => Some(home_str.to_string()),
+            "XDG_CONFIG_HOME" => Some(xdg_dir(var, home, ".config")),
+            "XDG_DATA_HOME" => Some(xdg_dir(var, home, ".local/share")),
+            "XDG_STATE_HOME" => Some(xdg_dir(var, home, ".local/state")),
+            _ => std::env::var(var).ok(),
+        },
+    );
+    PathBuf::from(expanded.as_ref())
+}
+
+/// An XDG base directory from the environment; relative values are ignored, as the
+/// specification requires.
+fn xdg_dir(var: &str, home: &Path, default: &str) -> String {
+    std::env::var(var)
+        .ok()
+        .filter(|dir| Path::new(dir).is_absolute())
+        .unwrap_or_else(|| home.join(default).display().to_string())
+}
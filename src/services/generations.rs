@@ -0,0 +1,502 @@
+//! Persisted apply generations.
+//!
+//! Every committed apply transaction is recorded as a numbered generation under
+//! `<state_dir>/generations/<N>/`. A generation stores the transaction id and
+//! metadata, the profile, the git HEAD of the dotfiles repository, and every
+//! [`FileOperation`] together with the state its target had before the apply.
+//! Prior file and directory contents are snapshotted next to the record so a
+//! generation can be undone without relying on the backup directory. A directory
+//! holding nothing but symlinks (an unfolded `link = "contents"` directory) is
+//! recorded as its links, so undoing recreates links rather than copies. Undoing
+//! generations is itself an apply transaction, recorded as a new generation.
+//!
+//! Only the newest `general.keep_generations` generations are kept: older ones are
+//! pruned whenever a new one is recorded.
+
+use crate::config::Config;
+use crate::file_manager::copy_dir_all;
+use crate::services::transactions::{FileOperation, Transaction};
+use crate::types::SymlinkResolution;
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::path_utils::{DirFilter, copy_differs, symlink_points_to_correct_target};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const RECORD_FILE: &str = "generation.json";
+const PRIOR_DIR: &str = "prior";
+const STAGED_FILE: &str = "operations.json";
+
+/// Generations kept when `general.keep_generations` is not set.
+pub const DEFAULT_KEEP_GENERATIONS: usize = 50;
+
+/// State of an operation target before the apply touched it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PriorState {
+    /// Nothing existed at the target
+    Missing,
+    /// Target was a symlink with this link target
+    Symlink { link_target: PathBuf },
+    /// Target was a regular file, snapshot path is relative to the generation directory
+    File { snapshot: PathBuf },
+    /// Target was a directory, snapshot path is relative to the generation directory
+    Directory { snapshot: PathBuf },
+    /// Target was a directory holding nothing but symlinks, by path relative to it
+    Links { links: BTreeMap<PathBuf, PathBuf> },
+}
+
+/// A file operation together with the prior state of its target.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedOperation {
+    /// The operation executed by the transaction
+    pub operation: FileOperation,
+    /// State of the target before the operation ran
+    pub prior: PriorState,
+}
+
+/// A committed apply transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Generation {
+    /// Sequential generation number (starting at 1)
+    pub number: u64,
+    /// Id of the transaction that produced this generation
+    pub transaction_id: String,
+    /// Profile the configuration was applied for
+    pub profile: String,
+    /// Commit id of the dotfiles repository HEAD at apply time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub git_head: Option<String>,
+    /// Transaction metadata (description, timestamp, etc.)
+    pub metadata: HashMap<String, String>,
+    /// Operations executed, in order
+    pub operations: Vec<RecordedOperation>,
+}
+
+impl Generation {
+    pub fn timestamp(&self) -> Option<&str> {
+        self.metadata.get("timestamp").map(String::as_str)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.metadata.get("description").map(String::as_str)
+    }
+
+    /// Distinct targets touched by this generation, in first-touched order
+    pub fn targets(&self) -> Vec<&Path> {
+        let mut seen = HashSet::new();
+        self.operations
+            .iter()
+            .map(|op| op.operation.target())
+            .filter(|target| seen.insert(target.to_path_buf()))
+            .collect()
+    }
+}
+
+/// On-disk store of generations.
+pub struct GenerationStore {
+    root: PathBuf,
+    /// Generations kept when a new one is recorded (all of them if `None`)
+    keep: Option<usize>,
+}
+
+/// Prior states captured for a transaction that has not been committed yet.
+pub struct PendingGeneration {
+    staging_dir: PathBuf,
+    operations: Vec<RecordedOperation>,
+}
+
+impl GenerationStore {
+    /// Open the store in the configured state directory, with the configured retention.
+    pub fn open(config: &Config) -> Result<Self> {
+        Ok(Self {
+            keep: Some(
+                config
+                    .general
+                    .keep_generations
+                    .unwrap_or(DEFAULT_KEEP_GENERATIONS),
+            ),
+            ..Self::new(config.get_state_dir()?.join("generations"))
+        })
+    }
+
+    /// Open a store that keeps every generation.
+    pub fn new(root: PathBuf) -> Self {
+        Self { root, keep: None }
+    }
+
+    fn generation_dir(&self, number: u64) -> PathBuf {
+        self.root.join(number.to_string())
+    }
+
+    /// List all generations, oldest first.
+    pub fn list(&self) -> Result<Vec<Generation>> {
+        let mut numbers = self.numbers()?;
+        numbers.sort_unstable();
+        numbers.into_iter().map(|n| self.load(n)).collect()
+    }
+
+    fn numbers(&self) -> Result<Vec<u64>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut numbers = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if entry.path().join(RECORD_FILE).is_file()
+                && let Some(number) = entry.file_name().to_str().and_then(|n| n.parse().ok())
+            {
+                numbers.push(number);
+            }
+        }
+        Ok(numbers)
+    }
+
+    /// Number of the most recent generation (0 if none exist).
+    pub fn latest_number(&self) -> Result<u64> {
+        Ok(self.numbers()?.into_iter().max().unwrap_or(0))
+    }
+
+    pub fn load(&self, number: u64) -> Result<Generation> {
+        let record = self.generation_dir(number).join(RECORD_FILE);
+        if !record.is_file() {
+            return Err(DotfilesError::Config(format!(
+                "Generation {} does not exist. Run `flux generations list` to see available generations",
+                number
+            )));
+        }
+
+        let content = fs::read_to_string(&record)?;
+        serde_json::from_str(&content).map_err(|e| {
+            DotfilesError::Config(format!(
+                "Failed to parse generation record {}: {}",
+                record.display(),
+                e
+            ))
+        })
+    }
+
+    /// Capture the prior state of every target in the transaction.
+    ///
+    /// Must be called before the transaction is committed.
+    pub fn stage(&self, transaction: &Transaction) -> Result<PendingGeneration> {
+        let staging_dir = self.root.join(format!(".staging-{}", transaction.id));
+        if staging_dir.exists() {
+            fs::remove_dir_all(&staging_dir)?;
+        }
+        fs::create_dir_all(staging_dir.join(PRIOR_DIR))?;
+
+        // Operations on the same target share the state captured before the first one
+        let mut captured: HashMap<PathBuf, PriorState> = HashMap::new();
+        let mut operations = Vec::new();
+        for (index, operation) in transaction.operations.iter().enumerate() {
+            let target = operation.target();
+            let prior = match captured.get(target) {
+                Some(prior) => prior.clone(),
+                None => {
+                    let snapshot = Path::new(PRIOR_DIR).join(index.to_string());
+                    let prior = capture_prior_state(target, &staging_dir, &snapshot)?;
+                    captured.insert(target.to_path_buf(), prior.clone());
+                    prior
+                }
+            };
+            operations.push(RecordedOperation {
+                operation: operation.clone(),
+                prior,
+            });
+        }
+
+        // Persist captured states so an interrupted apply can still be recorded by `flux recover`
+        let content = serde_json::to_string_pretty(&operations)
+            .map_err(|e| DotfilesError::Config(format!("Failed to serialize generation: {}", e)))?;
+        fs::write(staging_dir.join(STAGED_FILE), content)?;
+
+        Ok(PendingGeneration {
+            staging_dir,
+            operations,
+        })
+    }
+
+    /// Reload the staged generation of an interrupted transaction, if any.
+    pub fn pending(&self, transaction_id: &str) -> Result<Option<PendingGeneration>> {
+        let staging_dir = self.root.join(format!(".staging-{}", transaction_id));
+        let staged = staging_dir.join(STAGED_FILE);
+        if !staged.is_file() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&staged)?;
+        let operations = serde_json::from_str(&content).map_err(|e| {
+            DotfilesError::Config(format!(
+                "Failed to parse staged generation {}: {}",
+                staged.display(),
+                e
+            ))
+        })?;
+
+        Ok(Some(PendingGeneration {
+            staging_dir,
+            operations,
+        }))
+    }
+
+    /// Generations that pruning down to `keep` would delete, oldest first. The newest
+    /// generation is always kept.
+    pub fn prunable(&self, keep: usize) -> Result<Vec<u64>> {
+        let mut numbers = self.numbers()?;
+        numbers.sort_unstable();
+        let excess = numbers.len().saturating_sub(keep.max(1));
+        numbers.truncate(excess);
+        Ok(numbers)
+    }
+
+    /// Delete all but the newest `keep` generations, returning the deleted numbers.
+    pub fn prune(&self, keep: usize) -> Result<Vec<u64>> {
+        let pruned = self.prunable(keep)?;
+        for number in &pruned {
+            self.remove(*number)?;
+        }
+        Ok(pruned)
+    }
+
+    /// Delete a generation and its snapshots.
+    pub fn remove(&self, number: u64) -> Result<()> {
+        let dir = self.generation_dir(number);
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Add the operations undoing `generations` (newest first) to `transaction`. Each
+    /// target is restored to the state it had before the oldest of them touched it.
+    pub fn add_undo_to_transaction(
+        &self,
+        generations: &[Generation],
+        transaction: &mut Transaction,
+    ) {
+        for step in undo_steps(generations) {
+            let target = step.applied.target().to_path_buf();
+            transaction.add_operation(FileOperation::RemoveSymlink {
+                target: target.clone(),
+                original: None,
+            });
+
+            let (source, resolution) = match step.prior {
+                PriorState::Missing => continue,
+                PriorState::Symlink { link_target } => link_source(&target, link_target),
+                PriorState::File { snapshot } | PriorState::Directory { snapshot } => (
+                    self.generation_dir(step.generation).join(snapshot),
+                    SymlinkResolution::Replace,
+                ),
+                PriorState::Links { links } => {
+                    for (relative, link_target) in links {
+                        let link = target.join(relative);
+                        let (source, resolution) = link_source(&link, link_target);
+                        transaction.add_operation(FileOperation::CreateSymlink {
+                            source,
+                            target: link,
+                            resolution,
+                        });
+                    }
+                    continue;
+                }
+            };
+            transaction.add_operation(FileOperation::CreateSymlink {
+                source,
+                target,
+                resolution,
+            });
+        }
+    }
+}
+
+/// Source and resolution of the operation recreating a symlink at `link`.
+///
+/// A relative link target is resolved lexically, since the directories above `link`
+/// may themselves be symlinks until the undo recreates them.
+fn link_source(link: &Path, link_target: &Path) -> (PathBuf, SymlinkResolution) {
+    if link_target.is_absolute() {
+        return (link_target.to_path_buf(), SymlinkResolution::Absolute);
+    }
+    let mut source = link.parent().unwrap_or(Path::new("")).to_path_buf();
+    for component in link_target.components() {
+        match component {
+            Component::ParentDir => {
+                source.pop();
+            }
+            Component::CurDir => {}
+            component => source.push(component),
+        }
+    }
+    (source, SymlinkResolution::Relative)
+}
+
+impl PendingGeneration {
+    /// Persist the generation after its transaction committed successfully.
+    pub fn commit(
+        self,
+        store: &GenerationStore,
+        transaction: &Transaction,
+        profile: &str,
+        git_head: Option<String>,
+    ) -> Result<Generation> {
+        let generation = Generation {
+            number: store.latest_number()? + 1,
+            transaction_id: transaction.id.clone(),
+            profile: profile.to_string(),
+            git_head,
+            metadata: transaction.metadata.clone(),
+            operations: self.operations,
+        };
+
+        let content = serde_json::to_string_pretty(&generation)
+            .map_err(|e| DotfilesError::Config(format!("Failed to serialize generation: {}", e)))?;
+        fs::remove_file(self.staging_dir.join(STAGED_FILE))?;
+        fs::write(self.staging_dir.join(RECORD_FILE), content)?;
+        fs::rename(&self.staging_dir, store.generation_dir(generation.number))?;
+        if let Some(keep) = store.keep {
+            store.prune(keep)?;
+        }
+
+        Ok(generation)
+    }
+
+    /// Drop captured snapshots when the transaction did not commit.
+    pub fn discard(self) -> Result<()> {
+        if self.staging_dir.exists() {
+            fs::remove_dir_all(&self.staging_dir)?;
+        }
+        Ok(())
+    }
+}
+
+fn capture_prior_state(
+    target: &Path,
+    generation_dir: &Path,
+    snapshot: &Path,
+) -> Result<PriorState> {
+    if target.is_symlink() {
+        return Ok(PriorState::Symlink {
+            link_target: fs::read_link(target)?,
+        });
+    }
+
+    if target.is_dir() {
+        let entries = DirFilter::default().entries(target)?;
+        if !entries.is_empty() && entries.values().all(|path| path.is_symlink()) {
+            let mut links = BTreeMap::new();
+            for (relative, path) in entries {
+                links.insert(relative, fs::read_link(path)?);
+            }
+            return Ok(PriorState::Links { links });
+        }
+        copy_dir_all(target, &generation_dir.join(snapshot))?;
+        Ok(PriorState::Directory {
+            snapshot: snapshot.to_path_buf(),
+        })
+    } else if target.exists() {
+        fs::copy(target, generation_dir.join(snapshot))?;
+        Ok(PriorState::File {
+            snapshot: snapshot.to_path_buf(),
+        })
+    } else {
+        Ok(PriorState::Missing)
+    }
+}
+
+/// Targets changed since the newest of `generations` (newest first) touched them.
+/// Undoing the generations would lose those changes.
+pub fn drifted_targets(generations: &[Generation]) -> Result<Vec<PathBuf>> {
+    let steps = undo_steps(generations);
+    let targets: HashSet<&Path> = steps.iter().map(|step| step.applied.target()).collect();
+
+    let mut drifted = Vec::new();
+    for step in &steps {
+        if !still_applied(step.applied, &targets)? {
+            drifted.push(step.applied.target().to_path_buf());
+        }
+    }
+    Ok(drifted)
+}
+
+/// How undoing generations restores one target.
+struct UndoStep<'a> {
+    /// Newest operation on the target, whose result should still be in place
+    applied: &'a FileOperation,
+    /// State before the oldest operation on the target
+    prior: &'a PriorState,
+    /// Generation holding the snapshot of `prior`
+    generation: u64,
+}
+
+/// One step per target of `generations` (newest first), in undo order.
+fn undo_steps(generations: &[Generation]) -> Vec<UndoStep<'_>> {
+    let mut steps: Vec<UndoStep> = Vec::new();
+    let mut positions: HashMap<&Path, usize> = HashMap::new();
+
+    for generation in generations {
+        for recorded in generation.operations.iter().rev() {
+            let target = recorded.operation.target();
+            match positions.get(target) {
+                Some(&position) => {
+                    steps[position].prior = &recorded.prior;
+                    steps[position].generation = generation.number;
+                }
+                None => {
+                    positions.insert(target, steps.len());
+                    steps.push(UndoStep {
+                        applied: &recorded.operation,
+                        prior: &recorded.prior,
+                        generation: generation.number,
+                    });
+                }
+            }
+        }
+    }
+    steps
+}
+
+/// Whether the target of `operation` still holds what the operation left there.
+///
+/// A removed target may have become a directory, as long as it only holds other
+/// `targets` (an unfolded `link = "contents"` directory).
+fn still_applied(operation: &FileOperation, targets: &HashSet<&Path>) -> Result<bool> {
+    match operation {
+        FileOperation::RemoveSymlink { target, .. } => {
+            if target.is_symlink() || !target.is_dir() {
+                return Ok(!target.exists() && !target.is_symlink());
+            }
+            Ok(DirFilter::default()
+                .entries(target)?
+                .values()
+                .all(|path| targets.contains(path.as_path())))
+        }
+        FileOperation::CreateSymlink {
+            source,
+            target,
+            resolution,
+        }
+        | FileOperation::BackupAndReplace {
+            source,
+            target,
+            resolution,
+            ..
+        } => {
+            if *resolution == SymlinkResolution::Replace {
+                return Ok(!copy_differs(source, target, &DirFilter::default())?);
+            }
+            if !target.is_symlink() {
+                return Ok(false);
+            }
+            // Follow links the end of the target's chain, so compare where it leads
+            let resolved = fs::canonicalize(target).ok();
+            Ok(
+                symlink_points_to_correct_target(target, &fs::read_link(target)?, source)
+                    || resolved.is_some() && resolved == fs::canonicalize(source).ok(),
+            )
+        }
+    }
+}
@@ -4,7 +4,7 @@ use crate::utils::error::{DotfilesError, Result};
 use crate::utils::error_utils;
 use colored::Colorize;
 use git2::{CredentialType, FetchOptions, RemoteCallbacks, Repository, Signature};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
@@ -280,6 +280,126 @@ pub fn detect_changes(repo: &Repository) -> Result<Vec<FileChange>> {
     Ok(changes)
 }
 
+/// Clone `url` into `path`, authenticating the same way as fetch and push. Checks out
+/// `branch`, or the remote's default branch.
+pub fn clone_repo(
+    url: &str,
+    path: &Path,
+    branch: Option<&str>,
+    timeout_seconds: u64,
+) -> Result<Repository> {
+    // Clone in a thread so a stalled connection can time out
+    let url = url.to_string();
+    let path_clone = path.to_path_buf();
+    let branch = branch.map(str::to_string);
+    execute_with_timeout(
+        move || -> Result<()> {
+            let mut fetch_options = FetchOptions::new();
+            fetch_options.remote_callbacks(setup_credential_callbacks());
+            let mut builder = git2::build::RepoBuilder::new();
+            builder.fetch_options(fetch_options);
+            if let Some(branch) = &branch {
+                builder.branch(branch);
+            }
+            builder.clone(&url, &path_clone)?;
+            Ok(())
+        },
+        timeout_seconds,
+    )?;
+    Ok(Repository::open(path)?)
+}
+
+/// Commit HEAD points to, if any.
+pub fn head_oid(repo: &Repository) -> Option<git2::Oid> {
+    repo.head().ok().and_then(|head| head.target())
+}
+
+/// Paths (relative to the repository) that differ between two commits. With no `from`
+/// commit, every file of `to` counts as changed.
+pub fn changed_paths(
+    repo: &Repository,
+    from: Option<git2::Oid>,
+    to: git2::Oid,
+) -> Result<Vec<PathBuf>> {
+    let old_tree = match from {
+        Some(oid) => Some(repo.find_commit(oid)?.tree()?),
+        None => None,
+    };
+    let new_tree = repo.find_commit(to)?.tree()?;
+    let diff = repo.diff_tree_to_tree(old_tree.as_ref(), Some(&new_tree), None)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(Path::to_path_buf)
+        .collect())
+}
+
+/// A file whose working tree version differs from the one committed at HEAD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorktreeChange {
+    /// Path relative to the repository root
+    pub path: PathBuf,
+    /// Contents at HEAD (`None` for new files)
+    pub head: Option<Vec<u8>>,
+    /// Contents in the working tree (`None` for deleted files)
+    pub worktree: Option<Vec<u8>>,
+}
+
+/// Files that differ between HEAD and the working tree, including untracked files.
+///
+/// In a repository without commits every file counts as new. Symlinks are compared by
+/// their target, as git stores them.
+pub fn worktree_changes(repo: &Repository) -> Result<Vec<WorktreeChange>> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| DotfilesError::Config("Repository has no working tree".to_string()))?
+        .to_path_buf();
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None,
+    };
+
+    let mut options = git2::DiffOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    let diff = repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut options))?;
+
+    let mut changes = Vec::new();
+    for delta in diff.deltas() {
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let head = if delta.old_file().id().is_zero() {
+            None
+        } else {
+            Some(repo.find_blob(delta.old_file().id())?.content().to_vec())
+        };
+
+        let full_path = workdir.join(path);
+        let worktree = if full_path.is_symlink() {
+            Some(
+                std::fs::read_link(&full_path)?
+                    .to_string_lossy()
+                    .into_owned()
+                    .into_bytes(),
+            )
+        } else if full_path.is_file() {
+            Some(std::fs::read(&full_path)?)
+        } else {
+            None
+        };
+
+        changes.push(WorktreeChange {
+            path: path.to_path_buf(),
+            head,
+            worktree,
+        });
+    }
+    Ok(changes)
+}
+
 /// Get the current branch name (shorthand of HEAD)
 pub fn get_current_branch(repo: &Repository) -> Result<String> {
     let head = repo.head()?;
@@ -293,6 +413,13 @@ pub fn get_current_branch(repo: &Repository) -> Result<String> {
     Ok(shorthand.to_string())
 }
 
+/// Get the commit id HEAD points to, if the path is a repository with commits
+pub fn get_head_commit_id(repo_path: &Path) -> Option<String> {
+    let repo = Repository::open(repo_path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
 /// Add a remote to the repository
 pub fn add_remote(
     repo: &Repository,
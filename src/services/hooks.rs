@@ -0,0 +1,432 @@
+//! Lifecycle hooks: shell commands run before and after apply, commit and pull.
+//!
+//! Hooks are declared per tool (`[tools.sway.hooks]`) and globally
+//! (`[environment.hooks]`). Tool hooks run only for the tools an operation changes;
+//! global hooks run once when any tool changes. Pre hooks run global first, post hooks
+//! run global last. Commands run through `[environment] shell` (default `sh`) with
+//! `[environment.variables]` exported, from the repository root.
+
+use crate::config::Config;
+use crate::services::git::{commit_changes, stage_changes};
+use crate::types::{FileChange, Hooks};
+use crate::utils::dry_run::DryRun;
+use crate::utils::error::{DotfilesError, Result};
+use colored::Colorize;
+use git2::Repository;
+use nix::sys::signal::{Signal, killpg};
+use nix::unistd::Pid;
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Seconds a hook may run before it is killed, unless configured.
+const DEFAULT_TIMEOUT: u64 = 60;
+
+/// How long output is still collected after the shell exits. Processes the hook left
+/// running in the background (`waybar &`) keep the pipes open and are not waited for.
+const OUTPUT_GRACE: Duration = Duration::from_millis(200);
+
+/// Point in an operation at which hooks run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    PreApply,
+    PostApply,
+    PreCommit,
+    PostCommit,
+    PrePull,
+    PostPull,
+}
+
+impl HookPoint {
+    /// Key of this hook in the config (e.g. `post_apply`).
+    pub fn name(self) -> &'static str {
+        match self {
+            HookPoint::PreApply => "pre_apply",
+            HookPoint::PostApply => "post_apply",
+            HookPoint::PreCommit => "pre_commit",
+            HookPoint::PostCommit => "post_commit",
+            HookPoint::PrePull => "pre_pull",
+            HookPoint::PostPull => "post_pull",
+        }
+    }
+
+    fn is_pre(self) -> bool {
+        matches!(
+            self,
+            HookPoint::PreApply | HookPoint::PreCommit | HookPoint::PrePull
+        )
+    }
+
+    fn command(self, hooks: &Hooks) -> Option<&str> {
+        match self {
+            HookPoint::PreApply => hooks.pre_apply.as_deref(),
+            HookPoint::PostApply => hooks.post_apply.as_deref(),
+            HookPoint::PreCommit => hooks.pre_commit.as_deref(),
+            HookPoint::PostCommit => hooks.post_commit.as_deref(),
+            HookPoint::PrePull => hooks.pre_pull.as_deref(),
+            HookPoint::PostPull => hooks.post_pull.as_deref(),
+        }
+    }
+}
+
+/// A hook selected to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedHook {
+    /// Tool the hook belongs to (`None` for a global hook)
+    pub tool: Option<String>,
+    pub point: HookPoint,
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl PlannedHook {
+    fn label(&self) -> String {
+        format!(
+            "{} {}",
+            self.tool.as_deref().unwrap_or("[global]"),
+            self.point.name()
+        )
+    }
+}
+
+/// How a hook ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookOutcome {
+    Success,
+    /// Non-zero exit code, or `None` if killed by a signal
+    Failed(Option<i32>),
+    TimedOut,
+    /// The shell could not be started
+    Error(String),
+}
+
+/// Result of running one hook.
+#[derive(Debug, Clone)]
+pub struct HookResult {
+    pub hook: PlannedHook,
+    pub outcome: HookOutcome,
+    /// Combined stdout and stderr
+    pub output: String,
+    pub duration: Duration,
+}
+
+impl HookResult {
+    pub fn succeeded(&self) -> bool {
+        self.outcome == HookOutcome::Success
+    }
+}
+
+/// Hooks for `point` of the given tools, in the order they run.
+pub fn plan_hooks(config: &Config, point: HookPoint, tools: &BTreeSet<String>) -> Vec<PlannedHook> {
+    if tools.is_empty() {
+        return Vec::new();
+    }
+    let global = config
+        .environment
+        .as_ref()
+        .and_then(|env| env.hooks.as_ref());
+    let global_timeout = global.and_then(|hooks| hooks.timeout);
+
+    let mut planned: Vec<PlannedHook> = tools
+        .iter()
+        .filter_map(|tool| {
+            let hooks = config.tools.get(tool)?.hooks.as_ref()?;
+            Some(PlannedHook {
+                tool: Some(tool.clone()),
+                point,
+                command: point.command(hooks)?.to_string(),
+                timeout: Duration::from_secs(
+                    hooks.timeout.or(global_timeout).unwrap_or(DEFAULT_TIMEOUT),
+                ),
+            })
+        })
+        .collect();
+
+    if let Some(command) = global.and_then(|hooks| point.command(hooks)) {
+        let hook = PlannedHook {
+            tool: None,
+            point,
+            command: command.to_string(),
+            timeout: Duration::from_secs(global_timeout.unwrap_or(DEFAULT_TIMEOUT)),
+        };
+        if point.is_pre() {
+            planned.insert(0, hook);
+        } else {
+            planned.push(hook);
+        }
+    }
+    planned
+}
+
+/// Tools that own `paths` (absolute or relative to the repository): the tool of a tracked
+/// file at or above the path, or else the tool named by the path's first directory.
+pub fn tools_for_paths(
+    config: &Config,
+    profile: Option<&str>,
+    paths: &[PathBuf],
+) -> Result<BTreeSet<String>> {
+    let repo_root = config.get_repo_path()?;
+    let tracked = config.get_tracked_files(profile)?;
+
+    let mut tools = BTreeSet::new();
+    for path in paths {
+        let path = repo_root.join(path);
+        if let Some(file) = tracked
+            .iter()
+            .find(|file| path.starts_with(&file.repo_path))
+        {
+            tools.insert(file.tool.clone());
+        } else if let Some(first) = path
+            .strip_prefix(&repo_root)
+            .ok()
+            .and_then(|relative| relative.components().next())
+        {
+            let name = first.as_os_str().to_string_lossy();
+            if config.tools.contains_key(name.as_ref()) {
+                tools.insert(name.into_owned());
+            }
+        }
+    }
+    Ok(tools)
+}
+
+/// Pre and post commit hooks for the tools owning `changes`.
+pub fn plan_commit_hooks(
+    config: &Config,
+    profile: Option<&str>,
+    changes: &[FileChange],
+) -> Result<(Vec<PlannedHook>, Vec<PlannedHook>)> {
+    let paths: Vec<PathBuf> = changes
+        .iter()
+        .map(|change| change.path().to_path_buf())
+        .collect();
+    let tools = tools_for_paths(config, profile, &paths)?;
+    Ok((
+        plan_hooks(config, HookPoint::PreCommit, &tools),
+        plan_hooks(config, HookPoint::PostCommit, &tools),
+    ))
+}
+
+/// Stage and commit `changes`, running the commit hooks of the tools they belong to.
+pub fn commit_with_hooks(
+    config: &Config,
+    profile: Option<&str>,
+    repo: &Repository,
+    changes: &[FileChange],
+    message: &str,
+    dry_run: &mut DryRun,
+) -> Result<()> {
+    let (pre_hooks, post_hooks) = plan_commit_hooks(config, profile, changes)?;
+    let mut results = run_hooks(config, profile, &pre_hooks, true)?;
+    if let Err(e) = check_pre_hooks(&results) {
+        display_hook_results(&results);
+        return Err(e);
+    }
+
+    stage_changes(repo, changes, dry_run, false)?;
+    commit_changes(repo, message, dry_run, false)?;
+
+    results.extend(run_hooks(config, profile, &post_hooks, false)?);
+    display_hook_results(&results);
+    Ok(())
+}
+
+/// Run `hooks` in order. With `stop_on_failure` (pre hooks), the first failure stops
+/// the rest.
+pub fn run_hooks(
+    config: &Config,
+    profile: Option<&str>,
+    hooks: &[PlannedHook],
+    stop_on_failure: bool,
+) -> Result<Vec<HookResult>> {
+    let repo_root = config.get_repo_path()?;
+    let mut results = Vec::new();
+    for hook in hooks {
+        println!("{} Running {}: {}", "→".cyan(), hook.label(), hook.command);
+        let result = run_hook(config, profile, &repo_root, hook);
+        let failed = !result.succeeded();
+        results.push(result);
+        if failed && stop_on_failure {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+/// Run one hook through the configured shell, killing it after its timeout.
+pub fn run_hook(
+    config: &Config,
+    profile: Option<&str>,
+    working_dir: &Path,
+    hook: &PlannedHook,
+) -> HookResult {
+    let environment = config.environment.as_ref();
+    let shell = environment
+        .and_then(|env| env.shell.as_deref())
+        .unwrap_or("sh");
+    let mut command = Command::new(shell);
+    command
+        .arg("-c")
+        .arg(&hook.command)
+        .current_dir(working_dir)
+        .env("FLUX_HOOK", hook.point.name())
+        .env("FLUX_TOOL", hook.tool.as_deref().unwrap_or(""))
+        .env(
+            "FLUX_PROFILE",
+            profile.unwrap_or(&config.general.current_profile),
+        )
+        .env("FLUX_REPO", working_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A process group of its own, so a timeout kills everything the hook started
+        .process_group(0);
+    if let Some(environment) = environment {
+        command.envs(&environment.variables);
+    }
+
+    let start = Instant::now();
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            return HookResult {
+                hook: hook.clone(),
+                outcome: HookOutcome::Error(format!("Cannot run {}: {}", shell, e)),
+                output: String::new(),
+                duration: start.elapsed(),
+            };
+        }
+    };
+
+    // Drain both pipes while waiting so a chatty hook cannot block on a full pipe
+    let output = Arc::new(Mutex::new(Vec::new()));
+    let readers: Vec<_> = [
+        child
+            .stdout
+            .take()
+            .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|mut pipe| {
+        let output = Arc::clone(&output);
+        std::thread::spawn(move || {
+            let mut buffer = [0; 4096];
+            while let Ok(read) = pipe.read(&mut buffer)
+                && read > 0
+            {
+                if let Ok(mut output) = output.lock() {
+                    output.extend_from_slice(&buffer[..read]);
+                }
+            }
+        })
+    })
+    .collect();
+
+    let outcome = loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break HookOutcome::Success,
+            Ok(Some(status)) => break HookOutcome::Failed(status.code()),
+            Ok(None) if start.elapsed() >= hook.timeout => {
+                let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+                let _ = child.wait();
+                break HookOutcome::TimedOut;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+            Err(e) => break HookOutcome::Error(e.to_string()),
+        }
+    };
+
+    // Readers still blocked after the grace period are left to finish on their own
+    let grace = Instant::now() + OUTPUT_GRACE;
+    while readers.iter().any(|reader| !reader.is_finished()) && Instant::now() < grace {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let duration = start.elapsed();
+    let output = output
+        .lock()
+        .map(|output| String::from_utf8_lossy(&output).trim_end().to_string())
+        .unwrap_or_default();
+
+    HookResult {
+        hook: hook.clone(),
+        outcome,
+        output,
+        duration,
+    }
+}
+
+/// Error for the first failed pre hook, which stops the operation.
+pub fn check_pre_hooks(results: &[HookResult]) -> Result<()> {
+    let Some(failed) = results.iter().find(|result| !result.succeeded()) else {
+        return Ok(());
+    };
+    let section = match &failed.hook.tool {
+        Some(tool) => format!("[tools.{}.hooks]", tool),
+        None => "[environment.hooks]".to_string(),
+    };
+    Err(DotfilesError::Config(format!(
+        "What: {} hook failed ({}), nothing was changed\n  \
+         💡 Solution: Fix '{}' in {}, or run it by hand to see the error",
+        failed.hook.label(),
+        outcome_text(failed),
+        failed.hook.command,
+        section
+    )))
+}
+
+/// List hooks that would run, for dry runs and previews.
+pub fn display_planned_hooks(hooks: &[PlannedHook]) {
+    if hooks.is_empty() {
+        return;
+    }
+    println!("\n  {} Hooks to run ({}):", "🪝".cyan(), hooks.len());
+    for hook in hooks {
+        println!("    • {}: {}", hook.label(), hook.command);
+    }
+}
+
+/// Summary of hook results with their output.
+pub fn display_hook_results(results: &[HookResult]) {
+    if results.is_empty() {
+        return;
+    }
+    println!("\n{}", "Hooks:".bold());
+    for result in results {
+        let icon = match result.outcome {
+            HookOutcome::Success => "✓".green(),
+            HookOutcome::TimedOut => "⚠".yellow(),
+            HookOutcome::Failed(_) | HookOutcome::Error(_) => "✗".red(),
+        };
+        println!(
+            "  {} {}: {} ({})",
+            icon,
+            result.hook.label(),
+            result.hook.command,
+            outcome_text(result)
+        );
+        for line in result.output.lines() {
+            println!("      {}", line.bright_black());
+        }
+    }
+}
+
+fn outcome_text(result: &HookResult) -> String {
+    match &result.outcome {
+        HookOutcome::Success => format!("ok, {:.1}s", result.duration.as_secs_f64()),
+        HookOutcome::Failed(Some(code)) => format!("exit status {}", code),
+        HookOutcome::Failed(None) => "killed by a signal".to_string(),
+        HookOutcome::TimedOut => {
+            format!("timed out after {}s", result.hook.timeout.as_secs())
+        }
+        HookOutcome::Error(e) => e.clone(),
+    }
+}
@@ -0,0 +1,479 @@
+//! Readers for other dotfiles setups, used by `flux import`.
+//!
+//! Each reader lists the files of a setup with the destination they are deployed to:
+//!
+//! - GNU Stow: every package directory mirrors the home directory. A directory that
+//!   Stow folded into a single symlink becomes one directory entry. `dot-` prefixes
+//!   (`stow --dotfiles`) are decoded.
+//! - chezmoi: source state names encode attributes (`dot_`, `private_`, `executable_`,
+//!   ...), which are decoded. `private_`, `executable_` and `readonly_` set the mode of
+//!   the repository copy; files below a private directory are private too, since flux
+//!   links them one by one. Templates, scripts, encrypted files and symlinks have no
+//!   flux equivalent and are skipped.
+//! - yadm: a bare repository whose work tree is the home directory. Alternate files
+//!   (`config##os.Linux`) become variants of one destination selected by `os`, `hosts`
+//!   or `profile` (for yadm classes).
+//! - bare: a bare git repository whose work tree is the home directory.
+
+use crate::utils::error::{DotfilesError, Result};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+/// Kind of dotfiles setup to import from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportSource {
+    Stow,
+    Chezmoi,
+    Yadm,
+    Bare,
+}
+
+impl std::fmt::Display for ImportSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ImportSource::Stow => "stow",
+            ImportSource::Chezmoi => "chezmoi",
+            ImportSource::Yadm => "yadm",
+            ImportSource::Bare => "bare",
+        })
+    }
+}
+
+/// A file or directory of another setup and where it is deployed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportItem {
+    /// Tool the entry is registered under
+    pub tool: String,
+    /// File or directory holding the content
+    pub source: PathBuf,
+    /// Destination relative to the home directory
+    pub dest: PathBuf,
+    /// Name distinguishing alternates of the same destination (e.g. "linux")
+    pub variant: Option<String>,
+    pub profile: Option<String>,
+    pub hosts: Option<Vec<String>>,
+    pub os: Option<String>,
+    /// Permissions for the repository copy, if the setup records them
+    pub mode: Option<u32>,
+}
+
+/// A file of the setup that cannot be imported, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedItem {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Everything found in a setup.
+#[derive(Debug, Clone, Default)]
+pub struct ImportScan {
+    pub items: Vec<ImportItem>,
+    pub skipped: Vec<SkippedItem>,
+}
+
+impl ImportScan {
+    fn skip(&mut self, path: &Path, reason: impl Into<String>) {
+        self.skipped.push(SkippedItem {
+            path: path.to_path_buf(),
+            reason: reason.into(),
+        });
+    }
+}
+
+/// List the files of the setup at `path`, with destinations under `home`.
+pub fn scan(source: ImportSource, path: &Path, home: &Path) -> Result<ImportScan> {
+    if !path.exists() {
+        return Err(DotfilesError::Path(format!(
+            "What: {} does not exist\n  \
+             💡 Solution: Pass the {} directory to import from",
+            path.display(),
+            match source {
+                ImportSource::Stow => "Stow directory (holding the packages)",
+                ImportSource::Chezmoi => "chezmoi source directory (chezmoi source-path)",
+                ImportSource::Yadm => "yadm repository (e.g. ~/.local/share/yadm/repo.git)",
+                ImportSource::Bare => "bare git repository",
+            }
+        )));
+    }
+
+    let mut scan = ImportScan::default();
+    match source {
+        ImportSource::Stow => scan_stow(path, home, &mut scan)?,
+        ImportSource::Chezmoi => {
+            // .chezmoiroot moves the source state into a subdirectory
+            let root = match fs::read_to_string(path.join(".chezmoiroot")) {
+                Ok(subdir) => path.join(subdir.trim()),
+                Err(_) => path.to_path_buf(),
+            };
+            scan_chezmoi(&root, Path::new(""), false, &mut scan)?;
+        }
+        ImportSource::Yadm | ImportSource::Bare => {
+            scan_git(path, home, source == ImportSource::Yadm, &mut scan)?
+        }
+    }
+    Ok(scan)
+}
+
+/// Files Stow never links.
+fn stow_ignored(name: &str, top_level: bool) -> bool {
+    matches!(
+        name,
+        ".git" | ".gitignore" | ".gitmodules" | ".stow-local-ignore" | ".DS_Store"
+    ) || name.ends_with('~')
+        || (top_level
+            && (name.starts_with("README") || name.starts_with("LICENSE") || name == "COPYING"))
+}
+
+fn scan_stow(stow_dir: &Path, home: &Path, scan: &mut ImportScan) -> Result<()> {
+    for package in sorted_entries(stow_dir)? {
+        let name = file_name(&package);
+        if package.is_dir() && !name.starts_with('.') {
+            walk_stow_package(&name, &package, Path::new(""), home, scan)?;
+        }
+    }
+    Ok(())
+}
+
+fn walk_stow_package(
+    package: &str,
+    dir: &Path,
+    relative: &Path,
+    home: &Path,
+    scan: &mut ImportScan,
+) -> Result<()> {
+    for path in sorted_entries(dir)? {
+        let name = file_name(&path);
+        if stow_ignored(&name, relative.as_os_str().is_empty()) {
+            continue;
+        }
+        let dest = relative.join(match name.strip_prefix("dot-") {
+            Some(rest) => format!(".{}", rest),
+            None => name,
+        });
+
+        // A folded directory is a single symlink to the package directory
+        let deployed = home.join(&dest);
+        let folded = deployed.is_symlink()
+            && fs::canonicalize(&deployed).ok() == fs::canonicalize(&path).ok();
+        if path.is_dir() && !folded {
+            walk_stow_package(package, &path, &dest, home, scan)?;
+        } else {
+            scan.items.push(ImportItem {
+                tool: package.to_string(),
+                source: path,
+                dest,
+                ..Default::default()
+            });
+        }
+    }
+    Ok(())
+}
+
+fn scan_chezmoi(dir: &Path, relative: &Path, private: bool, scan: &mut ImportScan) -> Result<()> {
+    for path in sorted_entries(dir)? {
+        let name = file_name(&path);
+        // Names starting with a dot are chezmoi's own files (.chezmoiignore, .git, ...)
+        if name.starts_with('.') {
+            continue;
+        }
+        let is_dir = path.is_dir();
+        let (decoded, mut attributes) = match decode_chezmoi_name(&name, is_dir) {
+            Ok(decoded) => decoded,
+            Err(reason) => {
+                scan.skip(&path, reason);
+                continue;
+            }
+        };
+        attributes.private |= private;
+        let dest = relative.join(decoded);
+        if is_dir {
+            scan_chezmoi(&path, &dest, attributes.private, scan)?;
+        } else {
+            let mode = fs::metadata(&path)?.permissions().mode() & 0o777;
+            scan.items.push(ImportItem {
+                tool: tool_for_dest(&dest),
+                source: path,
+                dest,
+                mode: Some(attributes.apply(mode)),
+                ..Default::default()
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Permission attributes of a chezmoi source state name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChezmoiAttributes {
+    /// `private_`: no permissions for group and others
+    pub private: bool,
+    /// `executable_`
+    pub executable: bool,
+    /// `readonly_`: no write permissions
+    pub readonly: bool,
+}
+
+impl ChezmoiAttributes {
+    /// `mode` with the attributes applied.
+    pub fn apply(self, mut mode: u32) -> u32 {
+        if self.executable {
+            mode |= 0o111;
+        }
+        if self.private {
+            mode &= !0o077;
+        }
+        if self.readonly {
+            mode &= !0o222;
+        }
+        mode
+    }
+}
+
+/// Target name and permission attributes of a chezmoi source state entry, or why it
+/// cannot be imported.
+pub fn decode_chezmoi_name(
+    name: &str,
+    is_dir: bool,
+) -> std::result::Result<(String, ChezmoiAttributes), String> {
+    const UNSUPPORTED: &[&str] = &[
+        "create_",
+        "modify_",
+        "remove_",
+        "run_",
+        "symlink_",
+        "encrypted_",
+    ];
+    const FILE_ATTRIBUTES: &[&str] = &["private_", "readonly_", "empty_", "executable_"];
+    const DIR_ATTRIBUTES: &[&str] = &["exact_", "external_", "private_", "readonly_"];
+
+    let known = if is_dir {
+        DIR_ATTRIBUTES
+    } else {
+        FILE_ATTRIBUTES
+    };
+    let mut attributes = ChezmoiAttributes::default();
+    let mut rest = name;
+    loop {
+        if let Some(prefix) = UNSUPPORTED.iter().find(|prefix| rest.starts_with(*prefix)) {
+            return Err(format!(
+                "chezmoi {} entries have no flux equivalent",
+                prefix.trim_end_matches('_')
+            ));
+        }
+        match known.iter().find(|prefix| rest.starts_with(*prefix)) {
+            Some(prefix) => {
+                match *prefix {
+                    "private_" => attributes.private = true,
+                    "executable_" => attributes.executable = true,
+                    "readonly_" => attributes.readonly = true,
+                    _ => {}
+                }
+                rest = &rest[prefix.len()..];
+            }
+            None => break,
+        }
+    }
+
+    if !is_dir && rest.ends_with(".tmpl") {
+        return Err("chezmoi template: convert it to a flux template by hand".to_string());
+    }
+    let rest = rest.strip_suffix(".literal").unwrap_or(rest);
+    let decoded = if let Some(literal) = rest.strip_prefix("literal_") {
+        literal.to_string()
+    } else if let Some(dotted) = rest.strip_prefix("dot_") {
+        format!(".{}", dotted)
+    } else {
+        rest.to_string()
+    };
+    Ok((decoded, attributes))
+}
+
+fn scan_git(git_dir: &Path, home: &Path, yadm: bool, scan: &mut ImportScan) -> Result<()> {
+    let repo = git2::Repository::open(git_dir)?;
+    let worktree = repo.workdir().unwrap_or(home).to_path_buf();
+
+    let mut paths: Vec<PathBuf> = repo
+        .index()?
+        .iter()
+        // Submodules are not files to deploy
+        .filter(|entry| entry.mode != 0o160000)
+        .map(|entry| PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned()))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let source = worktree.join(&path);
+        let Ok(dest) = source.strip_prefix(home).map(Path::to_path_buf) else {
+            scan.skip(&source, "outside the home directory");
+            continue;
+        };
+        if !source.exists() {
+            scan.skip(&source, "not checked out");
+            continue;
+        }
+
+        if !yadm {
+            scan.items.push(ImportItem {
+                tool: tool_for_dest(&dest),
+                source,
+                dest,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        // yadm 3 also reads alternates from ~/.config/yadm/alt
+        let dest = match dest.strip_prefix(".config/yadm/alt") {
+            Ok(alt) => alt.to_path_buf(),
+            Err(_) if dest.starts_with(".config/yadm") => {
+                scan.skip(&source, "yadm's own configuration");
+                continue;
+            }
+            Err(_) => dest,
+        };
+        match decode_yadm_alt(&dest) {
+            Ok(Some(alternate)) => scan.items.push(ImportItem {
+                source,
+                ..alternate
+            }),
+            Ok(None) => scan.items.push(ImportItem {
+                tool: tool_for_dest(&dest),
+                source,
+                dest,
+                ..Default::default()
+            }),
+            Err(reason) => scan.skip(&source, reason),
+        }
+    }
+
+    if yadm {
+        // The link yadm points at the selected alternate is not content of its own
+        let alternates: Vec<PathBuf> = scan
+            .items
+            .iter()
+            .filter(|item| item.variant.is_some())
+            .map(|item| item.dest.clone())
+            .collect();
+        let (links, items) = std::mem::take(&mut scan.items)
+            .into_iter()
+            .partition(|item| item.variant.is_none() && alternates.contains(&item.dest));
+        scan.items = items;
+        for link in links {
+            scan.skip(&link.source, "link to the alternate yadm selected");
+        }
+    }
+
+    // Alternates with more conditions are more specific; among entries of the same
+    // profile layer flux lets the later one win
+    scan.items.sort_by_key(|item| {
+        (
+            item.profile.is_some(),
+            item.hosts.is_some(),
+            item.os.is_some(),
+        )
+    });
+    Ok(())
+}
+
+/// Destination and selectors of a yadm alternate file (`config##os.Linux,class.work`),
+/// `None` for a regular file, or why it cannot be imported.
+pub fn decode_yadm_alt(path: &Path) -> std::result::Result<Option<ImportItem>, String> {
+    let mut item = ImportItem::default();
+    let mut dest = PathBuf::new();
+    let mut variant = Vec::new();
+    let mut alternate = false;
+
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        let Some((base, conditions)) = name.split_once("##") else {
+            dest.push(name.as_ref());
+            continue;
+        };
+        alternate = true;
+        dest.push(base);
+
+        for condition in conditions.split(',').filter(|c| !c.is_empty()) {
+            let (key, value) = condition.split_once('.').unwrap_or((condition, ""));
+            match key {
+                "default" => {}
+                // The extension only helps editors pick a syntax
+                "extension" | "e" => {}
+                "os" | "o" => {
+                    let os = match value {
+                        "Darwin" => "macos".to_string(),
+                        other => other.to_lowercase(),
+                    };
+                    variant.push(os.clone());
+                    item.os = Some(os);
+                }
+                "hostname" | "h" => {
+                    variant.push(value.to_string());
+                    item.hosts = Some(vec![value.to_string()]);
+                }
+                "class" | "c" => {
+                    variant.push(value.to_string());
+                    item.profile = Some(value.to_string());
+                }
+                "template" | "t" => {
+                    return Err("yadm template: convert it to a flux template by hand".to_string());
+                }
+                other => {
+                    return Err(format!("yadm condition '{}' has no flux equivalent", other));
+                }
+            }
+        }
+    }
+
+    if !alternate {
+        return Ok(None);
+    }
+    item.tool = tool_for_dest(&dest);
+    item.dest = dest;
+    item.variant = Some(if variant.is_empty() {
+        "default".to_string()
+    } else {
+        variant.join(".")
+    });
+    Ok(Some(item))
+}
+
+/// Tool name for a destination: the app directory under `.config` (or
+/// `.local/share`), else the first path component without its leading dot and
+/// extension.
+pub fn tool_for_dest(dest: &Path) -> String {
+    let components: Vec<String> = dest
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let name = match components.as_slice() {
+        [config, app, _, ..] if config == ".config" => app,
+        [local, share, app, _, ..] if local == ".local" && share == "share" => app,
+        [config, file] if config == ".config" => file,
+        [first, ..] => first,
+        [] => "home",
+    };
+    let name = name.trim_start_matches('.');
+    name.split('.')
+        .next()
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or("home")
+        .to_string()
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
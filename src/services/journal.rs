@@ -0,0 +1,215 @@
+//! Write-ahead journal for transactions.
+//!
+//! Before a transaction commits, its full plan is written to a journal file. Each
+//! operation is then recorded as started before it runs and completed after it
+//! succeeds, and every backup is recorded as soon as it exists. Entries are
+//! appended as JSON lines and synced to disk, so a process killed partway through a
+//! commit leaves enough behind for `flux recover` to finish or undo the transaction.
+//! The journal is removed once the transaction is cleaned up or rolled back.
+
+use crate::config::Config;
+use crate::services::transactions::{FileOperation, RemovedOriginal};
+use crate::utils::error::{DotfilesError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A single journal record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// Transaction plan, always the first entry
+    Begin {
+        transaction_id: String,
+        temp_dir: PathBuf,
+        metadata: HashMap<String, String>,
+        operations: Vec<FileOperation>,
+    },
+    /// Operation at `index` is about to run
+    Started { index: usize },
+    /// Operation at `index` created a backup at `path`
+    Backup { index: usize, path: PathBuf },
+    /// Operation at `index` is about to remove its target, which is preserved as `original`
+    Removed {
+        index: usize,
+        original: RemovedOriginal,
+    },
+    /// Operation at `index` finished successfully
+    Completed { index: usize },
+}
+
+/// Open journal of a running transaction.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+/// Transaction state reconstructed from an unfinished journal.
+#[derive(Debug, Clone)]
+pub struct JournalState {
+    pub transaction_id: String,
+    pub temp_dir: PathBuf,
+    pub metadata: HashMap<String, String>,
+    pub operations: Vec<FileOperation>,
+    /// Number of operations that completed (always a prefix of `operations`)
+    pub completed: usize,
+    /// Operation that was started but never completed
+    pub in_flight: Option<usize>,
+    /// Backups created before the interruption
+    pub backups: Vec<PathBuf>,
+}
+
+/// Location of the transaction journal in the configured state directory.
+pub fn journal_path(config: &Config) -> Result<PathBuf> {
+    Ok(journal_path_in(&config.get_state_dir()?))
+}
+
+/// Location of the transaction journal in `state_dir`.
+pub fn journal_path_in(state_dir: &Path) -> PathBuf {
+    state_dir.join("transaction.journal")
+}
+
+impl Journal {
+    /// Create a new journal. Fails if an unfinished journal already exists.
+    pub fn create(path: &Path, begin: &JournalEntry) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::AlreadyExists {
+                    DotfilesError::Config(format!(
+                        "An interrupted transaction was found at {}. Run `flux recover` before applying again",
+                        path.display()
+                    ))
+                } else {
+                    e.into()
+                }
+            })?;
+
+        let mut journal = Self {
+            path: path.to_path_buf(),
+            file,
+        };
+        journal.append(begin)?;
+
+        // Make the new directory entry durable as well
+        if let Some(parent) = path.parent()
+            && let Ok(dir) = File::open(parent)
+        {
+            let _ = dir.sync_all();
+        }
+
+        Ok(journal)
+    }
+
+    /// Reopen an existing journal to continue appending to it.
+    pub fn reopen(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Append an entry and sync it to disk before returning.
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<()> {
+        let line = serde_json::to_string(entry).map_err(|e| {
+            DotfilesError::Config(format!("Failed to serialize journal entry: {}", e))
+        })?;
+        writeln!(self.file, "{}", line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Remove the journal once the transaction no longer needs recovery.
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+
+    /// Read an unfinished journal, if one exists at `path`.
+    ///
+    /// A truncated last line (the process died while writing it) is ignored, and a
+    /// journal that never got its plan written is discarded.
+    pub fn load(path: &Path) -> Result<Option<JournalState>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let reader = BufReader::new(File::open(path)?);
+        let mut state: Option<JournalState> = None;
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Ok(entry) = serde_json::from_str::<JournalEntry>(&line) else {
+                break;
+            };
+
+            match (entry, state.as_mut()) {
+                (
+                    JournalEntry::Begin {
+                        transaction_id,
+                        temp_dir,
+                        metadata,
+                        operations,
+                    },
+                    None,
+                ) => {
+                    state = Some(JournalState {
+                        transaction_id,
+                        temp_dir,
+                        metadata,
+                        operations,
+                        completed: 0,
+                        in_flight: None,
+                        backups: Vec::new(),
+                    });
+                }
+                (JournalEntry::Started { index }, Some(state)) => {
+                    state.in_flight = Some(index);
+                }
+                (JournalEntry::Backup { path, .. }, Some(state)) => {
+                    state.backups.push(path);
+                }
+                (JournalEntry::Removed { index, original }, Some(state)) => {
+                    if let Some(FileOperation::RemoveSymlink {
+                        original: recorded, ..
+                    }) = state.operations.get_mut(index)
+                    {
+                        *recorded = Some(original);
+                    }
+                }
+                (JournalEntry::Completed { index }, Some(state)) => {
+                    state.completed = index + 1;
+                    state.in_flight = None;
+                }
+                _ => {
+                    return Err(DotfilesError::Config(format!(
+                        "Transaction journal is corrupted: {}",
+                        path.display()
+                    )));
+                }
+            }
+        }
+
+        // Without a plan no operation can have run, so there is nothing to recover
+        if state.is_none() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(state)
+    }
+}
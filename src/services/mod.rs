@@ -1,7 +1,17 @@
+pub mod browser;
+pub mod catalog;
+pub mod generations;
 pub mod git;
+pub mod hooks;
+pub mod importers;
+pub mod journal;
+pub mod secrets;
+pub mod sqlite;
+pub mod systemd;
+pub mod template;
 pub mod transactions;
 pub use git::{
-    add_remote, commit_changes, detect_changes, init_repo, list_remotes, pull_from_remote,
-    push_to_remote, remove_remote, set_remote_url, show_git_status, stage_changes,
+    add_remote, detect_changes, init_repo, list_remotes, pull_from_remote, push_to_remote,
+    remove_remote, set_remote_url, show_git_status,
 };
 pub use transactions::{FileOperation, Transaction};
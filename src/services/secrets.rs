@@ -0,0 +1,196 @@
+//! Encryption of tracked files marked `encrypted = true`.
+//!
+//! Secrets are stored in the repository as ASCII-armored age files, encrypted to the
+//! local X25519 identity plus any public keys listed in `general.recipients`. The
+//! identity file lives outside the repository (default: `~/.config/flux/identity.txt`),
+//! so encryption and decryption work offline and the key is never committed.
+
+use crate::config::Config;
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::security;
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+use age::secrecy::ExposeSecret;
+use age::x25519;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// First line of an armored age file.
+const ARMOR_HEADER: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+/// First line of a binary age file.
+const BINARY_HEADER: &str = "age-encryption.org/v1";
+
+/// Key material used to encrypt and decrypt secrets.
+pub struct SecretKeys {
+    /// Path of the identity file the keys were loaded from
+    pub identity_path: PathBuf,
+    identity: x25519::Identity,
+    recipients: Vec<x25519::Recipient>,
+}
+
+/// Get the identity file path (config `identity_file` or `~/.config/flux/identity.txt`).
+pub fn identity_path(config: &Config) -> Result<PathBuf> {
+    if let Some(identity_file) = &config.general.identity_file {
+        let expanded = shellexpand::tilde(identity_file).into_owned();
+        return Ok(PathBuf::from(expanded));
+    }
+
+    let config_dir = dirs::config_dir()
+        .ok_or_else(|| DotfilesError::Config("Could not find config directory".to_string()))?;
+    Ok(config_dir.join("flux/identity.txt"))
+}
+
+impl SecretKeys {
+    /// Load the identity configured for `config`.
+    pub fn load(config: &Config) -> Result<Self> {
+        Self::from_file(
+            &identity_path(config)?,
+            config.general.recipients.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// Load the configured identity, generating a new one if none exists yet.
+    ///
+    /// Returns the keys and whether a new identity was generated.
+    pub fn load_or_generate(config: &Config) -> Result<(Self, bool)> {
+        let path = identity_path(config)?;
+        if path.exists() {
+            return Ok((Self::load(config)?, false));
+        }
+
+        let identity = x25519::Identity::generate();
+        write_identity(&path, &identity)?;
+        let keys = Self::new(
+            path,
+            identity,
+            config.general.recipients.as_deref().unwrap_or_default(),
+        )?;
+        Ok((keys, true))
+    }
+
+    /// Load an identity file and combine it with extra recipient public keys.
+    pub fn from_file(path: &Path, recipients: &[String]) -> Result<Self> {
+        if !path.exists() {
+            return Err(DotfilesError::Config(format!(
+                "What: No identity file found for encrypted files\n  \
+                 Path: {}\n  \
+                 💡 Solution:\n    \
+                 - Copy your identity file from another machine to this path\n    \
+                 - Or set `identity_file` under [general] in the config",
+                path.display()
+            )));
+        }
+
+        let contents = fs::read_to_string(path)?;
+        let key = contents
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .ok_or_else(|| {
+                DotfilesError::Config(format!("Identity file is empty: {}", path.display()))
+            })?;
+        let identity = x25519::Identity::from_str(key).map_err(|e| {
+            DotfilesError::Config(format!("Invalid identity in {}: {}", path.display(), e))
+        })?;
+
+        Self::new(path.to_path_buf(), identity, recipients)
+    }
+
+    fn new(
+        identity_path: PathBuf,
+        identity: x25519::Identity,
+        recipients: &[String],
+    ) -> Result<Self> {
+        let mut all_recipients = vec![identity.to_public()];
+        for recipient in recipients {
+            let parsed = x25519::Recipient::from_str(recipient).map_err(|e| {
+                DotfilesError::Config(format!("Invalid recipient '{}': {}", recipient, e))
+            })?;
+            if !all_recipients
+                .iter()
+                .any(|r| r.to_string() == parsed.to_string())
+            {
+                all_recipients.push(parsed);
+            }
+        }
+
+        Ok(Self {
+            identity_path,
+            identity,
+            recipients: all_recipients,
+        })
+    }
+
+    /// Public key of the local identity.
+    pub fn public_key(&self) -> String {
+        self.identity.to_public().to_string()
+    }
+
+    /// Number of recipients files are encrypted to, including the local identity.
+    pub fn recipient_count(&self) -> usize {
+        self.recipients.len()
+    }
+
+    /// Encrypt `plaintext` to every recipient, producing an armored age file.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let encryptor = age::Encryptor::with_recipients(
+            self.recipients.iter().map(|r| r as &dyn age::Recipient),
+        )
+        .map_err(|e| DotfilesError::Config(format!("Failed to encrypt: {}", e)))?;
+
+        let mut ciphertext = Vec::with_capacity(plaintext.len());
+        let armored = ArmoredWriter::wrap_output(&mut ciphertext, Format::AsciiArmor)?;
+        let mut writer = encryptor.wrap_output(armored)?;
+        writer.write_all(plaintext)?;
+        writer.finish()?.finish()?;
+
+        Ok(ciphertext)
+    }
+
+    /// Decrypt an age file (armored or binary) with the local identity.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let decryptor = age::Decryptor::new(ArmoredReader::new(ciphertext))
+            .map_err(|e| DotfilesError::Config(format!("Failed to decrypt: {}", e)))?;
+        let mut reader = decryptor
+            .decrypt(std::iter::once(&self.identity as &dyn age::Identity))
+            .map_err(|e| {
+                DotfilesError::Config(format!(
+                    "Failed to decrypt with identity {}: {}",
+                    self.identity_path.display(),
+                    e
+                ))
+            })?;
+
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+}
+
+/// Whether `contents` looks like an age file (armored or binary).
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(ARMOR_HEADER.as_bytes()) || contents.starts_with(BINARY_HEADER.as_bytes())
+}
+
+/// Write a newly generated identity in the age-keygen format with mode 0600.
+pub fn write_identity(path: &Path, identity: &x25519::Identity) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let contents = format!(
+        "# created: {}\n# public key: {}\n{}\n",
+        chrono::Local::now().to_rfc3339(),
+        identity.to_public(),
+        identity.to_string().expose_secret()
+    );
+
+    // Create with restrictive permissions before any key material is written
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, "")?;
+    security::set_secure_permissions(&temp_path)?;
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
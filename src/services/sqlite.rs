@@ -0,0 +1,246 @@
+//! Snapshots of SQLite databases tracked with `kind = "sqlite"`.
+//!
+//! Applications keep databases such as `places.sqlite` open while they run, with recent
+//! transactions still in the `-wal` file, so a byte-for-byte copy can capture a torn
+//! database. The repository holds a snapshot taken with SQLite's online backup API
+//! instead. When the application holds an exclusive lock, the database and its WAL are
+//! copied aside and the snapshot is taken from the copy, which replays the WAL.
+//!
+//! Snapshots are written back only while the application owning the database is not
+//! running, since it would otherwise keep using (and overwrite) the database it has open.
+
+use crate::types::TrackedFile;
+use crate::utils::error::{DotfilesError, Result};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Suffixes of the files SQLite keeps next to an open database.
+pub const SIDE_FILES: &[&str] = &["-wal", "-shm", "-journal"];
+
+/// Attempts at copying a database that is being written before giving up.
+const ATTEMPTS: u32 = 5;
+
+/// How long to wait for a writer to release the database, and between copies.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Write a consistent snapshot of the database at `source` to `output`.
+///
+/// The snapshot is a standalone database in rollback-journal mode, so it needs no side
+/// files. It is written next to `output` and renamed into place.
+pub fn snapshot(source: &Path, output: &Path) -> Result<()> {
+    let partial = side_file(output, ".partial");
+    let _ = fs::remove_file(&partial);
+
+    let result = match backup_live(source, &partial) {
+        Err(e) if is_busy(&e) => {
+            let _ = fs::remove_file(&partial);
+            backup_copy(source, &partial)
+        }
+        result => result.map_err(|e| snapshot_error(source, e)),
+    }
+    .and_then(|()| finish(&partial).map_err(|e| snapshot_error(source, e)));
+
+    match result {
+        Ok(()) => fs::rename(&partial, output).map_err(Into::into),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Whether the database at `live` holds different data than the snapshot at
+/// `snapshot_path`, compared through a fresh snapshot of `live`.
+pub fn snapshot_differs(snapshot_path: &Path, live: &Path) -> Result<bool> {
+    if live.is_symlink() || !live.is_file() || !snapshot_path.is_file() {
+        return Ok(true);
+    }
+    let dir = tempfile::tempdir()?;
+    let fresh = dir.path().join("snapshot.sqlite");
+    snapshot(live, &fresh)?;
+    Ok(!same_pages(&fs::read(&fresh)?, &fs::read(snapshot_path)?))
+}
+
+/// Whether two snapshots hold the same pages. A backup sets the file change counter and
+/// the "version-valid-for" number in the header, so those differ between snapshots of
+/// the same data.
+fn same_pages(a: &[u8], b: &[u8]) -> bool {
+    const BACKUP_FIELDS: [std::ops::Range<usize>; 2] = [24..28, 92..96];
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .enumerate()
+            .all(|(offset, (x, y))| x == y || BACKUP_FIELDS.iter().any(|r| r.contains(&offset)))
+}
+
+/// Whether `path` starts with the SQLite database header.
+pub fn is_database(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut header = [0u8; 16];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| &header == b"SQLite format 3\0")
+}
+
+/// Path of a file SQLite keeps next to `database`, such as its `-wal`.
+pub fn side_file(database: &Path, suffix: &str) -> PathBuf {
+    let mut name = database.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Why the database of `file` cannot be restored now, if its owning application is
+/// running: a process with the application's name (`app`, default: the tool name), or
+/// one that has the database open.
+pub fn owner_running(file: &TrackedFile) -> Option<String> {
+    let app = file.sqlite_owner.as_deref().unwrap_or(&file.tool);
+    find_process(app, &file.dest_path).map(|pid| format!("{} is running as pid {}", app, pid))
+}
+
+/// Back up the live database through SQLite, waiting briefly for a writer to finish.
+fn backup_live(source: &Path, output: &Path) -> rusqlite::Result<()> {
+    // Read-only, so closing the connection never checkpoints the application's WAL
+    let from = Connection::open_with_flags(
+        source,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    from.busy_timeout(RETRY_DELAY)?;
+    backup_from(&from, output)
+}
+
+/// Copy the database and its WAL aside and back up the copy, which replays the WAL when
+/// it is opened. Used when the application holds the database exclusively.
+fn backup_copy(source: &Path, output: &Path) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let copy = dir.path().join("database.sqlite");
+    let wal = side_file(source, "-wal");
+
+    for _ in 0..ATTEMPTS {
+        let before = modified(&[source, &wal]);
+        let _ = fs::remove_file(side_file(&copy, "-wal"));
+        fs::copy(source, &copy)?;
+        if wal.exists() {
+            fs::copy(&wal, side_file(&copy, "-wal"))?;
+        }
+        // A copy taken while the application wrote to the files may be torn
+        if modified(&[source, &wal]) != before {
+            thread::sleep(RETRY_DELAY);
+            continue;
+        }
+
+        let from = Connection::open(&copy).map_err(|e| snapshot_error(source, e))?;
+        return backup_from(&from, output).map_err(|e| snapshot_error(source, e));
+    }
+
+    Err(DotfilesError::Config(format!(
+        "What: {} kept changing while it was copied\n  \
+         💡 Solution: Close the application using it and try again",
+        source.display()
+    )))
+}
+
+/// Copy every page of `from` into a new database at `output`.
+fn backup_from(from: &Connection, output: &Path) -> rusqlite::Result<()> {
+    let mut to = Connection::open(output)?;
+    let backup = Backup::new(from, &mut to)?;
+    match backup.step(-1)? {
+        StepResult::Done => Ok(()),
+        // Still busy after the busy timeout: the application holds the database
+        _ => Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        )),
+    }
+}
+
+/// Switch the snapshot to rollback-journal mode and check it is intact.
+fn finish(output: &Path) -> rusqlite::Result<()> {
+    let connection = Connection::open(output)?;
+    connection.pragma_update_and_check(None, "journal_mode", "DELETE", |row| {
+        row.get::<_, String>(0)
+    })?;
+    let check: String = connection.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if check != "ok" {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CORRUPT),
+            Some(check),
+        ));
+    }
+    Ok(())
+}
+
+fn is_busy(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+fn snapshot_error(source: &Path, error: rusqlite::Error) -> DotfilesError {
+    DotfilesError::Config(format!(
+        "What: Cannot snapshot SQLite database {}: {}\n  \
+         💡 Solution: Check that it is a SQLite database, or close the application using it",
+        source.display(),
+        error
+    ))
+}
+
+/// Modification times of `paths`, to notice writes while they are copied.
+fn modified(paths: &[&Path]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+/// A process running `app`, or one that has `database` open.
+#[cfg(target_os = "linux")]
+fn find_process(app: &str, database: &Path) -> Option<u32> {
+    let own_pid = std::process::id();
+    let database = fs::canonicalize(database).ok();
+    let bin_name = format!("{}-bin", app);
+
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| *pid != own_pid)
+        .find(|pid| {
+            let proc_dir = Path::new("/proc").join(pid.to_string());
+            // The command name is cut to 15 bytes, so the executable name is checked too
+            let comm = fs::read_to_string(proc_dir.join("comm")).unwrap_or_default();
+            let exe = fs::read_link(proc_dir.join("exe")).ok();
+            let exe_name = exe
+                .as_deref()
+                .and_then(Path::file_name)
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            let named = [app, bin_name.as_str()]
+                .iter()
+                .any(|name| *name == exe_name || *name == comm.trim_end());
+
+            named
+                || database.as_deref().is_some_and(|database| {
+                    fs::read_dir(proc_dir.join("fd")).is_ok_and(|fds| {
+                        fds.filter_map(|fd| fs::read_link(fd.ok()?.path()).ok())
+                            .any(|target| target == database)
+                    })
+                })
+        })
+}
+
+/// A process running `app`, as reported by `pgrep`.
+#[cfg(not(target_os = "linux"))]
+fn find_process(app: &str, _database: &Path) -> Option<u32> {
+    let output = std::process::Command::new("pgrep")
+        .args(["-x", app])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().parse().ok())
+}
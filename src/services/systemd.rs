@@ -0,0 +1,76 @@
+//! systemd user units for running flux in the background.
+//!
+//! Units are rendered to strings and written to a directory, so they can be generated
+//! and inspected without systemd; only enabling and querying them calls `systemctl`.
+
+use crate::utils::error::{DotfilesError, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A unit file to write into the systemd user unit directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitFile {
+    /// File name, e.g. `flux-sync.timer`
+    pub name: String,
+    /// Unit file contents
+    pub contents: String,
+}
+
+/// Directory systemd loads user units from (`~/.config/systemd/user`).
+pub fn user_unit_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| DotfilesError::Config("Could not find config directory".to_string()))?
+        .join("systemd/user"))
+}
+
+/// Write `units` into `dir`, creating it if needed. Returns the written paths.
+pub fn write_units(dir: &Path, units: &[UnitFile]) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    units
+        .iter()
+        .map(|unit| {
+            let path = dir.join(&unit.name);
+            fs::write(&path, &unit.contents)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Quote one `ExecStart=` argument: `%` specifiers are escaped, and arguments with
+/// whitespace, quotes or backslashes are double-quoted.
+pub fn quote_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%");
+    if escaped.is_empty()
+        || escaped
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\')
+    {
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        escaped
+    }
+}
+
+/// `ExecStart=` command line for `args`, quoted for systemd.
+pub fn exec_line<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| quote_arg(arg.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run `systemctl --user` with `args`.
+pub fn systemctl(args: &[&str]) -> Result<Output> {
+    Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|e| {
+            DotfilesError::Config(format!(
+                "What: Cannot run systemctl: {}\n  \
+                 💡 Solution: Check that systemd is installed and a user session is running",
+                e
+            ))
+        })
+}
@@ -0,0 +1,232 @@
+//! Rendering of tracked files marked `template = true` or `encrypted = true`.
+//!
+//! Rendered files are copied to their destination instead of being symlinked.
+//! Encrypted files are decrypted first (see [`crate::services::secrets`]), then
+//! templates are expanded. Templates use `{{ name }}` placeholders. Variables come from
+//! `[environment].variables` plus built-in facts about the machine:
+//! `hostname`, `profile`, `os`, `arch`, `user` and `home`. Configured variables
+//! take precedence over built-in facts. Referencing an undefined variable is an error.
+//!
+//! A hash of the output last written to each destination is kept in the state
+//! directory, so a destination edited since can be told apart from one whose template
+//! changed.
+
+use crate::config::Config;
+use crate::services::secrets::{self, SecretKeys};
+use crate::types::TrackedFile;
+use crate::utils::error::{DotfilesError, Result};
+use crate::utils::path_utils::content_hash;
+use crate::utils::{host, security};
+use std::cell::{OnceCell, RefCell};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const RENDER_LOG_FILE: &str = "rendered.json";
+
+/// Variables and keys used to render tracked files.
+pub struct TemplateContext {
+    variables: BTreeMap<String, String>,
+    identity_path: Option<PathBuf>,
+    recipients: Vec<String>,
+    /// Loaded on first use, so configs without secrets never need an identity
+    secrets: OnceCell<SecretKeys>,
+    render_log_path: Option<PathBuf>,
+    /// Hash of the output last rendered to each destination
+    render_log: RefCell<BTreeMap<PathBuf, String>>,
+}
+
+impl TemplateContext {
+    /// Build the context for the given profile (default: current profile).
+    pub fn new(config: &Config, profile: Option<&str>) -> Self {
+        let mut variables = BTreeMap::new();
+        variables.insert("hostname".to_string(), host::hostname());
+        variables.insert(
+            "profile".to_string(),
+            profile
+                .unwrap_or(&config.general.current_profile)
+                .to_string(),
+        );
+        variables.insert("os".to_string(), host::os().to_string());
+        variables.insert("arch".to_string(), host::arch().to_string());
+        variables.insert("user".to_string(), host::username());
+        if let Some(home) = dirs::home_dir() {
+            variables.insert("home".to_string(), home.to_string_lossy().to_string());
+        }
+
+        if let Some(environment) = &config.environment {
+            for (name, value) in &environment.variables {
+                variables.insert(name.clone(), value.clone());
+            }
+        }
+
+        let render_log_path = config
+            .get_state_dir()
+            .ok()
+            .map(|dir| dir.join(RENDER_LOG_FILE));
+        // An unreadable log only means earlier renders are not known
+        let render_log = render_log_path
+            .as_deref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            variables,
+            identity_path: secrets::identity_path(config).ok(),
+            recipients: config.general.recipients.clone().unwrap_or_default(),
+            secrets: OnceCell::new(),
+            render_log_path,
+            render_log: RefCell::new(render_log),
+        }
+    }
+
+    /// Keys used to decrypt encrypted files.
+    fn secrets(&self) -> Result<&SecretKeys> {
+        if let Some(keys) = self.secrets.get() {
+            return Ok(keys);
+        }
+
+        let identity_path = self.identity_path.as_deref().ok_or_else(|| {
+            DotfilesError::Config("Could not determine the identity file path".to_string())
+        })?;
+        let keys = SecretKeys::from_file(identity_path, &self.recipients)?;
+        Ok(self.secrets.get_or_init(|| keys))
+    }
+
+    /// Substitute every `{{ name }}` placeholder in `template`.
+    pub fn render(&self, template: &str) -> Result<String> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let after_open = &rest[start + 2..];
+            let end = after_open.find("}}").ok_or_else(|| {
+                DotfilesError::Config(format!(
+                    "Unterminated template placeholder: {{{{{}",
+                    after_open.lines().next().unwrap_or_default()
+                ))
+            })?;
+
+            let name = after_open[..end].trim();
+            let value = self.variables.get(name).ok_or_else(|| {
+                DotfilesError::Config(format!(
+                    "Undefined template variable '{}'. Define it under [environment.variables] \
+                     or use one of: {}",
+                    name,
+                    self.variables
+                        .keys()
+                        .cloned()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+            output.push_str(value);
+            rest = &after_open[end + 2..];
+        }
+
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Produce the contents `file` should have at its destination.
+    pub fn render_file(&self, file: &TrackedFile) -> Result<Vec<u8>> {
+        let mut contents = fs::read(&file.repo_path)?;
+
+        if file.encrypted {
+            contents = self.secrets()?.decrypt(&contents).map_err(|e| {
+                e.with_context(&format!("Failed to decrypt {}", file.repo_path.display()))
+            })?;
+        }
+
+        if file.template {
+            let template = String::from_utf8(contents).map_err(|_| {
+                DotfilesError::Config(format!(
+                    "Template is not valid UTF-8: {}",
+                    file.repo_path.display()
+                ))
+            })?;
+            contents = self
+                .render(&template)
+                .map_err(|e| {
+                    e.with_context(&format!("Failed to render {}", file.repo_path.display()))
+                })?
+                .into_bytes();
+        }
+
+        Ok(contents)
+    }
+
+    /// Whether the destination of `file` was changed since flux last rendered it.
+    ///
+    /// Destinations flux has not rendered yet are never considered edited.
+    pub fn edited_since_render(&self, file: &TrackedFile) -> Result<bool> {
+        if file.dest_path.is_symlink() || !file.dest_path.is_file() {
+            return Ok(false);
+        }
+        match self.render_log.borrow().get(&file.dest_path) {
+            Some(hash) => Ok(content_hash(&file.dest_path)? != *hash),
+            None => Ok(false),
+        }
+    }
+
+    /// Record the current destination of `file` as its last rendered output.
+    pub fn record_render(&self, file: &TrackedFile) -> Result<()> {
+        let Some(path) = &self.render_log_path else {
+            return Ok(());
+        };
+        let hash = content_hash(&file.dest_path)?;
+        let mut log = self.render_log.borrow_mut();
+        if log.get(&file.dest_path) == Some(&hash) {
+            return Ok(());
+        }
+        log.insert(file.dest_path.clone(), hash);
+
+        let content = serde_json::to_string_pretty(&*log)
+            .map_err(|e| DotfilesError::Config(format!("Failed to serialize render log: {}", e)))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        // Hashes of decrypted secrets are kept private too
+        security::set_secure_permissions(path)
+    }
+}
+
+/// Whether the destination of a rendered file differs from its rendered output.
+///
+/// A symlink at the destination always differs, since rendered files are copies.
+pub fn rendered_differs(file: &TrackedFile, context: &TemplateContext) -> Result<bool> {
+    if file.dest_path.is_symlink() || !file.dest_path.is_file() {
+        return Ok(true);
+    }
+
+    let rendered = context.render_file(file)?;
+    Ok(fs::read(&file.dest_path)? != rendered)
+}
+
+/// Render `file` into `output`.
+///
+/// Decrypted secrets are given mode 0600; templates keep the permissions of the repo file.
+pub fn render_to(file: &TrackedFile, context: &TemplateContext, output: &Path) -> Result<()> {
+    let rendered = context.render_file(file)?;
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_rendered(file, output, &rendered)
+}
+
+/// Write rendered contents to `path` with the permissions appropriate for `file`.
+pub fn write_rendered(file: &TrackedFile, path: &Path, contents: &[u8]) -> Result<()> {
+    if file.encrypted {
+        // Restrict permissions before any plaintext is written
+        fs::write(path, "")?;
+        security::set_secure_permissions(path)?;
+        fs::write(path, contents)?;
+    } else {
+        fs::write(path, contents)?;
+        fs::set_permissions(path, fs::metadata(&file.repo_path)?.permissions())?;
+    }
+    Ok(())
+}
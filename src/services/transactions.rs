@@ -1,7 +1,10 @@
 use crate::config::Config;
 use crate::file_manager::FileSystemManager;
+use crate::services::journal::{Journal, JournalEntry, JournalState};
 use crate::types::SymlinkResolution;
 use crate::utils::error::{DotfilesError, Result};
+use crate::utils::path_utils::follow_symlink_chain;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -23,7 +26,8 @@ pub enum TransactionState {
 }
 
 /// Represents a single operation within a transaction
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum FileOperation {
     /// Create a symlink from source to target
     CreateSymlink {
@@ -31,8 +35,13 @@ pub enum FileOperation {
         target: PathBuf,
         resolution: SymlinkResolution,
     },
-    /// Remove a symlink at target
-    RemoveSymlink { target: PathBuf },
+    /// Remove a symlink (or whatever is in the way) at target
+    RemoveSymlink {
+        target: PathBuf,
+        /// What was at target before removal, filled in when the operation runs
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        original: Option<RemovedOriginal>,
+    },
     /// Backup existing file and replace with symlink
     BackupAndReplace {
         source: PathBuf,
@@ -42,6 +51,27 @@ pub enum FileOperation {
     },
 }
 
+/// State of a target removed by [`FileOperation::RemoveSymlink`], kept for rollback.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RemovedOriginal {
+    /// Target was a symlink with this link target
+    Symlink { link_target: PathBuf },
+    /// Target was a file or directory, moved to this path inside the transaction temp dir
+    Staged { path: PathBuf },
+}
+
+impl FileOperation {
+    /// Destination path affected by this operation
+    pub fn target(&self) -> &Path {
+        match self {
+            FileOperation::CreateSymlink { target, .. }
+            | FileOperation::RemoveSymlink { target, .. }
+            | FileOperation::BackupAndReplace { target, .. } => target,
+        }
+    }
+}
+
 /// Result of executing an operation.
 #[derive(Debug, Clone)]
 pub struct OperationResult {
@@ -71,6 +101,8 @@ pub struct Transaction {
     pub backups: Vec<PathBuf>,
     /// Additional metadata (description, profile, timestamp, etc.)
     pub metadata: HashMap<String, String>,
+    /// On-disk write-ahead journal, if enabled
+    journal: Option<Journal>,
 }
 
 impl Default for Transaction {
@@ -83,6 +115,7 @@ impl Default for Transaction {
             results: Vec::new(),
             backups: Vec::new(),
             metadata: HashMap::new(),
+            journal: None,
         }
     }
 }
@@ -101,6 +134,60 @@ impl Transaction {
         })
     }
 
+    /// Rebuild a transaction from an unfinished journal.
+    ///
+    /// Completed operations are restored as successful results so `commit` resumes
+    /// with the first operation that did not complete.
+    pub fn from_journal(state: &JournalState, journal_path: &Path) -> Result<Self> {
+        let results = state.operations[..state.completed]
+            .iter()
+            .map(|operation| OperationResult {
+                operation: operation.clone(),
+                success: true,
+                error: None,
+            })
+            .collect();
+
+        Ok(Self {
+            id: state.transaction_id.clone(),
+            state: TransactionState::Prepared,
+            temp_dir: state.temp_dir.clone(),
+            operations: state.operations.clone(),
+            results,
+            backups: state.backups.clone(),
+            metadata: state.metadata.clone(),
+            journal: Some(Journal::reopen(journal_path)?),
+        })
+    }
+
+    /// Record the transaction plan and each step of `commit` in an on-disk journal.
+    ///
+    /// Must be called after `prepare` so the full list of operations is journaled.
+    pub fn enable_journal(&mut self, path: &Path) -> Result<()> {
+        let begin = JournalEntry::Begin {
+            transaction_id: self.id.clone(),
+            temp_dir: self.temp_dir.clone(),
+            metadata: self.metadata.clone(),
+            operations: self.operations.clone(),
+        };
+        self.journal = Some(Journal::create(path, &begin)?);
+        Ok(())
+    }
+
+    fn journal(&mut self, entry: JournalEntry) -> Result<()> {
+        match self.journal.as_mut() {
+            Some(journal) => journal.append(&entry),
+            None => Ok(()),
+        }
+    }
+
+    fn finish_journal(&mut self) -> Result<()> {
+        match self.journal.take() {
+            Some(journal) => journal.finish(),
+            None => Ok(()),
+        }
+    }
+
     /// Add an operation to the transaction
     pub fn add_operation(&mut self, operation: FileOperation) {
         self.operations.push(operation);
@@ -132,7 +219,7 @@ impl Transaction {
                         // Will be created during prepare
                     }
                 }
-                FileOperation::RemoveSymlink { target } => {
+                FileOperation::RemoveSymlink { target, .. } => {
                     // Check target exists or is a symlink
                     if !target.exists() && !target.is_symlink() {
                         // Target doesn't exist, which is fine for removal
@@ -185,32 +272,46 @@ impl Transaction {
             ));
         }
 
-        // Execute all operations
-        for op in self.operations.clone() {
+        // Execute all operations, resuming after any already completed ones
+        let start = self.results.len();
+        for (index, op) in self.operations.clone().into_iter().enumerate().skip(start) {
+            self.journal(JournalEntry::Started { index })?;
+
             let result = match &op {
                 FileOperation::CreateSymlink {
                     source,
                     target,
                     resolution,
                 } => self.execute_create_symlink(source, target, *resolution, config, fs_manager),
-                FileOperation::RemoveSymlink { target } => {
-                    self.execute_remove_symlink(target, fs_manager)
+                FileOperation::RemoveSymlink { target, original } => {
+                    self.execute_remove_symlink(index, target, original.as_ref(), fs_manager)
                 }
                 FileOperation::BackupAndReplace {
                     source,
                     target,
                     backup_path,
                     resolution,
-                } => self.execute_backup_and_replace(
-                    source,
-                    target,
-                    backup_path,
-                    *resolution,
-                    config,
-                    fs_manager,
-                ),
+                } => match self.execute_backup(index, target, backup_path, fs_manager) {
+                    Ok(()) => self.execute_backup_and_replace(
+                        source,
+                        target,
+                        backup_path,
+                        *resolution,
+                        config,
+                        fs_manager,
+                    ),
+                    Err(error) => OperationResult {
+                        operation: op.clone(),
+                        success: false,
+                        error: Some(error),
+                    },
+                },
             };
 
+            if result.success {
+                self.journal(JournalEntry::Completed { index })?;
+            }
+
             self.results.push(result.clone());
 
             // If any operation fails, rollback
@@ -239,7 +340,7 @@ impl Transaction {
         // (to skip verification for RemoveSymlink operations that are immediately followed by CreateSymlink)
         let mut targets_created_after_removal = std::collections::HashSet::new();
         for (i, result) in self.results.iter().enumerate() {
-            if let FileOperation::RemoveSymlink { target } = &result.operation {
+            if let FileOperation::RemoveSymlink { target, .. } = &result.operation {
                 // Check if the next operation creates a symlink at the same target
                 if let Some(next_result) = self.results.get(i + 1)
                     && let FileOperation::CreateSymlink {
@@ -253,6 +354,25 @@ impl Transaction {
             }
         }
 
+        // Directory symlinks removed so later operations can fill a real directory
+        // (unfolding a `link = "contents"` entry)
+        let unfolded_dirs: std::collections::HashSet<_> = self
+            .results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, result)| match &result.operation {
+                FileOperation::RemoveSymlink { target, .. }
+                    if self.results[i + 1..].iter().any(|later| {
+                        later.operation.target() != target.as_path()
+                            && later.operation.target().starts_with(target)
+                    }) =>
+                {
+                    Some(target.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
         // Verify each operation succeeded
         for result in &self.results {
             if !result.success {
@@ -264,24 +384,32 @@ impl Transaction {
 
             // Additional verification for file and package operations
             match &result.operation {
-                FileOperation::CreateSymlink { target, .. } => {
-                    if !target.exists() && !target.is_symlink() {
-                        return Err(DotfilesError::Path(format!(
-                            "Verification failed: symlink does not exist: {}",
-                            target.display()
-                        )));
-                    }
+                FileOperation::CreateSymlink { target, .. }
+                    if !target.exists() && !target.is_symlink() =>
+                {
+                    return Err(DotfilesError::Path(format!(
+                        "Verification failed: symlink does not exist: {}",
+                        target.display()
+                    )));
+                }
+                FileOperation::RemoveSymlink { target, .. }
+                    if unfolded_dirs.contains(target) && target.is_symlink() =>
+                {
+                    return Err(DotfilesError::Path(format!(
+                        "Verification failed: directory was not unfolded: {}",
+                        target.display()
+                    )));
                 }
-                FileOperation::RemoveSymlink { target } => {
-                    // Skip verification if this target is immediately recreated
+                // Skip verification if this target is immediately recreated
+                FileOperation::RemoveSymlink { target, .. }
                     if !targets_created_after_removal.contains(target)
-                        && (target.exists() || target.is_symlink())
-                    {
-                        return Err(DotfilesError::Path(format!(
-                            "Verification failed: symlink still exists: {}",
-                            target.display()
-                        )));
-                    }
+                        && !unfolded_dirs.contains(target)
+                        && (target.exists() || target.is_symlink()) =>
+                {
+                    return Err(DotfilesError::Path(format!(
+                        "Verification failed: symlink still exists: {}",
+                        target.display()
+                    )));
                 }
                 _ => {
                     // Other operations verified by their success flag
@@ -309,17 +437,25 @@ impl Transaction {
                             let _ = fs_manager.remove_file(target);
                         }
                     }
-                    FileOperation::RemoveSymlink { target: _ } => {
-                        // Can't easily restore removed symlinks, but we have backups
-                        // This would require storing the original state
+                    FileOperation::RemoveSymlink {
+                        target,
+                        original: Some(original),
+                    } => {
+                        // Put back exactly what was removed
+                        restore_removed(target, original, fs_manager);
+                    }
+                    FileOperation::RemoveSymlink { original: None, .. } => {
+                        // Nothing existed at the target, so there is nothing to restore
                     }
                     FileOperation::BackupAndReplace {
                         target,
                         backup_path,
                         ..
                     } => {
-                        // Restore from backup
+                        // Restore from backup, removing the symlink first so the copy
+                        // does not write through it into the repository
                         if backup_path.exists() {
+                            let _ = fs_manager.remove_dir_all(target);
                             if let Some(parent) = target.parent() {
                                 let _ = fs_manager.create_dir_all(parent);
                             }
@@ -335,15 +471,15 @@ impl Transaction {
         }
 
         self.state = TransactionState::RolledBack;
-        Ok(())
+        self.finish_journal()
     }
 
     /// Clean up temporary files and directories
-    pub fn cleanup(&self) -> Result<()> {
+    pub fn cleanup(&mut self) -> Result<()> {
         if self.temp_dir.exists() {
             fs::remove_dir_all(&self.temp_dir)?;
         }
-        Ok(())
+        self.finish_journal()
     }
 
     // Private helper methods for executing operations
@@ -356,16 +492,38 @@ impl Transaction {
         _config: &Config,
         fs_manager: &mut FileSystemManager,
     ) -> OperationResult {
+        let operation = FileOperation::CreateSymlink {
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            resolution,
+        };
+
+        // With Follow, the link goes at the end of the target's symlink chain
+        let placement = if resolution == SymlinkResolution::Follow {
+            match follow_symlink_chain(target, source) {
+                Ok(chain) => chain
+                    .last()
+                    .cloned()
+                    .unwrap_or_else(|| target.to_path_buf()),
+                Err(e) => {
+                    return OperationResult {
+                        operation,
+                        success: false,
+                        error: Some(format!("Failed to follow symlinks: {}", e)),
+                    };
+                }
+            }
+        } else {
+            target.to_path_buf()
+        };
+        let target = placement.as_path();
+
         // Create parent directory if needed
         if let Some(parent) = target.parent()
             && let Err(e) = fs_manager.create_dir_all(parent)
         {
             return OperationResult {
-                operation: FileOperation::CreateSymlink {
-                    source: source.to_path_buf(),
-                    target: target.to_path_buf(),
-                    resolution,
-                },
+                operation,
                 success: false,
                 error: Some(format!("Failed to create parent directory: {}", e)),
             };
@@ -373,44 +531,40 @@ impl Transaction {
 
         // Create symlink using atomic approach
         let link_target = match resolution {
-            SymlinkResolution::Auto | SymlinkResolution::Relative => {
+            SymlinkResolution::Auto | SymlinkResolution::Relative | SymlinkResolution::Follow => {
                 pathdiff::diff_paths(source, target.parent().unwrap())
                     .unwrap_or_else(|| source.to_path_buf())
             }
             SymlinkResolution::Absolute => source.to_path_buf(),
-            SymlinkResolution::Follow => pathdiff::diff_paths(source, target.parent().unwrap())
-                .unwrap_or_else(|| source.to_path_buf()),
             SymlinkResolution::Replace => {
                 // For Replace, we copy instead of symlink
+                if source.is_dir() {
+                    let result = fs_manager.copy_dir_over(source, target);
+                    return OperationResult {
+                        operation,
+                        success: result.is_ok(),
+                        error: result
+                            .err()
+                            .map(|e| format!("Failed to copy directory: {}", e)),
+                    };
+                }
                 let temp_path = target.with_extension("flux-temp-copy");
                 if let Err(e) = fs_manager.copy(source, &temp_path) {
                     return OperationResult {
-                        operation: FileOperation::CreateSymlink {
-                            source: source.to_path_buf(),
-                            target: target.to_path_buf(),
-                            resolution,
-                        },
+                        operation,
                         success: false,
                         error: Some(format!("Failed to copy file: {}", e)),
                     };
                 }
                 if let Err(e) = fs_manager.rename(&temp_path, target) {
                     return OperationResult {
-                        operation: FileOperation::CreateSymlink {
-                            source: source.to_path_buf(),
-                            target: target.to_path_buf(),
-                            resolution,
-                        },
+                        operation,
                         success: false,
                         error: Some(format!("Failed to rename temp file: {}", e)),
                     };
                 }
                 return OperationResult {
-                    operation: FileOperation::CreateSymlink {
-                        source: source.to_path_buf(),
-                        target: target.to_path_buf(),
-                        resolution,
-                    },
+                    operation,
                     success: true,
                     error: None,
                 };
@@ -428,11 +582,7 @@ impl Transaction {
 
         if let Err(e) = fs_manager.symlink(&link_target, &temp_link_path) {
             return OperationResult {
-                operation: FileOperation::CreateSymlink {
-                    source: source.to_path_buf(),
-                    target: target.to_path_buf(),
-                    resolution,
-                },
+                operation,
                 success: false,
                 error: Some(format!("Failed to create temp symlink: {}", e)),
             };
@@ -441,22 +591,14 @@ impl Transaction {
         // Atomically rename
         if let Err(e) = fs_manager.rename(&temp_link_path, target) {
             return OperationResult {
-                operation: FileOperation::CreateSymlink {
-                    source: source.to_path_buf(),
-                    target: target.to_path_buf(),
-                    resolution,
-                },
+                operation,
                 success: false,
                 error: Some(format!("Failed to rename temp symlink: {}", e)),
             };
         }
 
         OperationResult {
-            operation: FileOperation::CreateSymlink {
-                source: source.to_path_buf(),
-                target: target.to_path_buf(),
-                resolution,
-            },
+            operation,
             success: true,
             error: None,
         }
@@ -464,14 +606,77 @@ impl Transaction {
 
     fn execute_remove_symlink(
         &mut self,
+        index: usize,
         target: &Path,
+        recorded: Option<&RemovedOriginal>,
         fs_manager: &mut FileSystemManager,
     ) -> OperationResult {
         let target_path = target.to_path_buf();
-        if let Err(e) = fs_manager.remove_file(&target_path) {
+
+        // Resuming after a crash: the target was already removed and its original recorded
+        if let Some(recorded) = recorded
+            && !target.exists()
+            && !target.is_symlink()
+        {
+            return OperationResult {
+                operation: FileOperation::RemoveSymlink {
+                    target: target_path,
+                    original: Some(recorded.clone()),
+                },
+                success: true,
+                error: None,
+            };
+        }
+
+        // Record what is being removed before touching it, so rollback can restore it
+        let original = if target.is_symlink() {
+            match fs::read_link(target) {
+                Ok(link_target) => Some(RemovedOriginal::Symlink { link_target }),
+                Err(e) => {
+                    return OperationResult {
+                        operation: FileOperation::RemoveSymlink {
+                            target: target_path,
+                            original: None,
+                        },
+                        success: false,
+                        error: Some(format!("Failed to read symlink: {}", e)),
+                    };
+                }
+            }
+        } else if target.exists() {
+            Some(RemovedOriginal::Staged {
+                path: self.temp_dir.join("removed").join(index.to_string()),
+            })
+        } else {
+            None
+        };
+
+        if let Some(original) = &original
+            && let Err(e) = self.journal(JournalEntry::Removed {
+                index,
+                original: original.clone(),
+            })
+        {
+            return OperationResult {
+                operation: FileOperation::RemoveSymlink {
+                    target: target_path,
+                    original: None,
+                },
+                success: false,
+                error: Some(format!("Failed to journal removal: {}", e)),
+            };
+        }
+
+        let removed = match &original {
+            Some(RemovedOriginal::Staged { path }) => stage_removed(target, path, fs_manager),
+            _ => fs_manager.remove_file(&target_path),
+        };
+
+        if let Err(e) = removed {
             OperationResult {
                 operation: FileOperation::RemoveSymlink {
                     target: target_path,
+                    original,
                 },
                 success: false,
                 error: Some(format!("Failed to remove symlink: {}", e)),
@@ -480,6 +685,7 @@ impl Transaction {
             OperationResult {
                 operation: FileOperation::RemoveSymlink {
                     target: target_path,
+                    original,
                 },
                 success: true,
                 error: None,
@@ -487,6 +693,43 @@ impl Transaction {
         }
     }
 
+    /// Back up the target of a backup-and-replace and journal the backup before the
+    /// target is changed. Skipped when resuming an operation whose backup already exists.
+    fn execute_backup(
+        &mut self,
+        index: usize,
+        target: &Path,
+        backup_path: &Path,
+        fs_manager: &mut FileSystemManager,
+    ) -> std::result::Result<(), String> {
+        if !target.exists() || self.backups.iter().any(|b| b == backup_path) {
+            return Ok(());
+        }
+
+        if let Some(parent) = backup_path.parent() {
+            fs_manager
+                .create_dir_all(parent)
+                .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+        }
+        if target.is_dir() {
+            fs_manager
+                .copy_dir_all(target, backup_path)
+                .map_err(|e| format!("Failed to backup directory: {}", e))?;
+        } else {
+            fs_manager
+                .copy(target, backup_path)
+                .map_err(|e| format!("Failed to backup file: {}", e))?;
+        }
+        self.backups.push(backup_path.to_path_buf());
+
+        // Recorded before the target is touched, so recovery can always restore it
+        self.journal(JournalEntry::Backup {
+            index,
+            path: backup_path.to_path_buf(),
+        })
+        .map_err(|e| format!("Failed to journal backup: {}", e))
+    }
+
     fn execute_backup_and_replace(
         &mut self,
         source: &Path,
@@ -496,50 +739,23 @@ impl Transaction {
         config: &Config,
         fs_manager: &mut FileSystemManager,
     ) -> OperationResult {
-        // Create backup
-        if target.exists() {
-            if let Some(parent) = backup_path.parent()
-                && let Err(e) = fs_manager.create_dir_all(parent)
-            {
-                return OperationResult {
-                    operation: FileOperation::BackupAndReplace {
-                        source: source.to_path_buf(),
-                        target: target.to_path_buf(),
-                        backup_path: backup_path.to_path_buf(),
-                        resolution,
-                    },
-                    success: false,
-                    error: Some(format!("Failed to create backup directory: {}", e)),
-                };
-            }
-
-            if target.is_dir() {
-                if let Err(e) = fs_manager.copy_dir_all(target, backup_path) {
-                    return OperationResult {
-                        operation: FileOperation::BackupAndReplace {
-                            source: source.to_path_buf(),
-                            target: target.to_path_buf(),
-                            backup_path: backup_path.to_path_buf(),
-                            resolution,
-                        },
-                        success: false,
-                        error: Some(format!("Failed to backup directory: {}", e)),
-                    };
-                }
-            } else if let Err(e) = fs_manager.copy(target, backup_path) {
-                return OperationResult {
-                    operation: FileOperation::BackupAndReplace {
-                        source: source.to_path_buf(),
-                        target: target.to_path_buf(),
-                        backup_path: backup_path.to_path_buf(),
-                        resolution,
-                    },
-                    success: false,
-                    error: Some(format!("Failed to backup file: {}", e)),
-                };
-            }
-
-            self.backups.push(backup_path.to_path_buf());
+        // A link cannot be renamed over a directory, so a backed-up directory is removed
+        // first (rollback restores it from the backup). Directory copies are made in place.
+        if resolution != SymlinkResolution::Replace
+            && target.is_dir()
+            && !target.is_symlink()
+            && let Err(e) = fs_manager.remove_dir_all(target)
+        {
+            return OperationResult {
+                operation: FileOperation::BackupAndReplace {
+                    source: source.to_path_buf(),
+                    target: target.to_path_buf(),
+                    backup_path: backup_path.to_path_buf(),
+                    resolution,
+                },
+                success: false,
+                error: Some(format!("Failed to remove directory: {}", e)),
+            };
         }
 
         // Now create symlink (or copy for Replace)
@@ -560,3 +776,60 @@ impl Transaction {
         }
     }
 }
+
+/// Move a file or directory out of the way into the transaction temp dir.
+///
+/// Falls back to copy and delete when the temp dir is on another filesystem.
+fn stage_removed(target: &Path, staged: &Path, fs_manager: &mut FileSystemManager) -> Result<()> {
+    if let Some(parent) = staged.parent() {
+        fs_manager.create_dir_all(parent)?;
+    }
+
+    if fs_manager.rename(target, staged).is_ok() {
+        return Ok(());
+    }
+
+    if target.is_dir() {
+        fs_manager.copy_dir_all(target, staged)?;
+    } else {
+        fs_manager.copy(target, staged)?;
+    }
+    fs_manager.remove_dir_all(target)
+}
+
+/// Restore a target removed by a `RemoveSymlink` operation.
+fn restore_removed(target: &Path, original: &RemovedOriginal, fs_manager: &mut FileSystemManager) {
+    match original {
+        RemovedOriginal::Symlink { link_target } => {
+            if fs::read_link(target).ok().as_ref() == Some(link_target) {
+                return;
+            }
+            if target.exists() || target.is_symlink() {
+                let _ = fs_manager.remove_dir_all(target);
+            }
+            if let Some(parent) = target.parent() {
+                let _ = fs_manager.create_dir_all(parent);
+            }
+            let _ = fs_manager.symlink(link_target, target);
+        }
+        RemovedOriginal::Staged { path } => {
+            // If the staged copy is missing, the target was never moved away
+            if !path.exists() && !path.is_symlink() {
+                return;
+            }
+            if target.exists() || target.is_symlink() {
+                let _ = fs_manager.remove_dir_all(target);
+            }
+            if let Some(parent) = target.parent() {
+                let _ = fs_manager.create_dir_all(parent);
+            }
+            if fs_manager.rename(path, target).is_err() {
+                if path.is_dir() {
+                    let _ = fs_manager.copy_dir_all(path, target);
+                } else {
+                    let _ = fs_manager.copy(path, target);
+                }
+            }
+        }
+    }
+}
@@ -327,9 +327,8 @@ mod symlink_resolution_extended_tests {
         ];
 
         for mode in modes {
-            let _cloned = mode.clone();
+            let _cloned = mode;
             let _formatted = format!("{:?}", mode);
-            assert!(true); // All operations succeed
         }
     }
 
@@ -408,6 +407,7 @@ mod error_handling_extended_tests {
 
     /// Test Result type operations
     #[test]
+    #[allow(clippy::unnecessary_literal_unwrap)]
     fn test_result_operations() {
         let ok_result: Result<String> = Ok("success".to_string());
         let err_result: Result<String> = Err(DotfilesError::Cancelled);
@@ -468,7 +468,6 @@ mod dry_run_extended_tests {
 
         // Display should not panic
         dry_run.display_summary();
-        assert!(true);
     }
 
     /// Test git remote operations
@@ -491,7 +490,6 @@ mod dry_run_extended_tests {
         });
 
         dry_run.display_summary();
-        assert!(true);
     }
 
     /// Test git push operation
@@ -506,7 +504,6 @@ mod dry_run_extended_tests {
         });
 
         dry_run.display_summary();
-        assert!(true);
     }
 }
 
@@ -516,7 +513,7 @@ mod dry_run_extended_tests {
 
 #[cfg(test)]
 mod file_entry_extended_tests {
-    use crate::types::FileEntry;
+    use crate::types::{EntryType, FileEntry, FileKind, LinkMode};
 
     /// Test file entry creation without profile
     #[test]
@@ -525,6 +522,17 @@ mod file_entry_extended_tests {
             repo: "config".to_string(),
             dest: ".config/app".to_string(),
             profile: None,
+            hosts: None,
+            os: None,
+            template: false,
+            encrypted: false,
+            resolution: None,
+            entry_type: EntryType::File,
+            ignore: Vec::new(),
+            include: Vec::new(),
+            link: LinkMode::Dir,
+            kind: FileKind::Plain,
+            app: None,
         };
 
         assert_eq!(entry.repo, "config");
@@ -539,6 +547,17 @@ mod file_entry_extended_tests {
             repo: "work_config".to_string(),
             dest: ".config/app".to_string(),
             profile: Some("work".to_string()),
+            hosts: None,
+            os: None,
+            template: false,
+            encrypted: false,
+            resolution: None,
+            entry_type: EntryType::File,
+            ignore: Vec::new(),
+            include: Vec::new(),
+            link: LinkMode::Dir,
+            kind: FileKind::Plain,
+            app: None,
         };
 
         assert_eq!(entry.profile, Some("work".to_string()));
@@ -551,6 +570,17 @@ mod file_entry_extended_tests {
             repo: "config".to_string(),
             dest: ".config".to_string(),
             profile: Some("default".to_string()),
+            hosts: None,
+            os: None,
+            template: false,
+            encrypted: false,
+            resolution: None,
+            entry_type: EntryType::File,
+            ignore: Vec::new(),
+            include: Vec::new(),
+            link: LinkMode::Dir,
+            kind: FileKind::Plain,
+            app: None,
         };
 
         let entry2 = entry1.clone();
@@ -600,3 +630,3648 @@ mod path_handling_tests {
         assert_eq!(joined, PathBuf::from("/home/user/.config"));
     }
 }
+
+// ============================================================================
+// Generations - Record and Rollback Tests
+// ============================================================================
+
+#[cfg(test)]
+mod generation_tests {
+    use crate::commands::apply::{begin_transaction, run_transaction};
+    use crate::commands::rollback_to_generation;
+    use crate::config::Config;
+    use crate::file_manager::FileSystemManager;
+    use crate::services::generations::{GenerationStore, PriorState, drifted_targets};
+    use crate::services::transactions::{FileOperation, Transaction};
+    use crate::types::SymlinkResolution;
+    use crate::utils::dry_run::DryRun;
+    use crate::utils::path_utils::DirFilter;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    /// Test that a committed apply is recorded and can be undone exactly
+    #[test]
+    fn test_generation_record_and_undo() {
+        let dir = tempdir().unwrap();
+        let repo_file = dir.path().join("repo/app.conf");
+        let existing = dir.path().join("home/existing.conf");
+        let missing = dir.path().join("home/new.conf");
+        fs::create_dir_all(repo_file.parent().unwrap()).unwrap();
+        fs::create_dir_all(existing.parent().unwrap()).unwrap();
+        fs::write(&repo_file, "repo").unwrap();
+        fs::write(&existing, "local").unwrap();
+
+        let config = Config::default();
+        let store = GenerationStore::new(dir.path().join("state/generations"));
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        transaction.add_operation(FileOperation::BackupAndReplace {
+            source: repo_file.clone(),
+            target: existing.clone(),
+            backup_path: dir.path().join("backup/existing.conf"),
+            resolution: SymlinkResolution::Absolute,
+        });
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: repo_file.clone(),
+            target: missing.clone(),
+            resolution: SymlinkResolution::Absolute,
+        });
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        transaction.validate(&config).unwrap();
+        transaction.prepare(&config).unwrap();
+        let pending = store.stage(&transaction).unwrap();
+        transaction.commit(&config, &mut fs_manager).unwrap();
+        let generation = pending
+            .commit(&store, &transaction, "default", None)
+            .unwrap();
+
+        assert_eq!(generation.number, 1);
+        assert!(existing.is_symlink());
+        assert!(missing.is_symlink());
+
+        let loaded = store.load(1).unwrap();
+        assert_eq!(loaded.transaction_id, transaction.id);
+        assert!(matches!(loaded.operations[0].prior, PriorState::File { .. }));
+        assert_eq!(loaded.operations[1].prior, PriorState::Missing);
+
+        let generations = [loaded];
+        assert!(drifted_targets(&generations).unwrap().is_empty());
+        let mut undo = Transaction::begin(dir.path().join("undo")).unwrap();
+        store.add_undo_to_transaction(&generations, &mut undo);
+        undo.validate(&config).unwrap();
+        undo.prepare(&config).unwrap();
+        undo.commit(&config, &mut fs_manager).unwrap();
+        assert!(!existing.is_symlink());
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "local");
+        assert!(!missing.exists() && !missing.is_symlink());
+    }
+
+    /// Test that a rollback refuses to overwrite changed targets without force, backs
+    /// them up with it, and is recorded as a generation that can itself be rolled back
+    #[test]
+    fn test_rollback_changed_targets() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.general.state_dir = Some(dir.path().join("state").display().to_string());
+        config.general.backup_dir = dir.path().join("backups").display().to_string();
+        config.general.repo_path = dir.path().join("repo").display().to_string();
+        let repo_file = dir.path().join("repo/app.conf");
+        let target = dir.path().join("home/app.conf");
+        fs::create_dir_all(repo_file.parent().unwrap()).unwrap();
+        fs::write(&repo_file, "repo").unwrap();
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        let mut transaction = begin_transaction(&config).unwrap();
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: repo_file.clone(),
+            target: target.clone(),
+            resolution: SymlinkResolution::Relative,
+        });
+        run_transaction(&config, &mut transaction, "default", &mut fs_manager).unwrap();
+
+        // Replace the link with a local edit
+        fs::remove_file(&target).unwrap();
+        fs::write(&target, "edited").unwrap();
+
+        let error = rollback_to_generation(&config, 0, true, false, false).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("changed since they were applied")
+        );
+        assert_eq!(fs::read_to_string(&target).unwrap(), "edited");
+
+        rollback_to_generation(&config, 0, true, true, false).unwrap();
+        assert!(!target.exists());
+        let backups = DirFilter::default()
+            .entries(&dir.path().join("backups"))
+            .unwrap();
+        assert!(
+            backups
+                .values()
+                .any(|path| fs::read_to_string(path).unwrap() == "edited")
+        );
+
+        let store = GenerationStore::open(&config).unwrap();
+        let rollback = store.load(2).unwrap();
+        assert_eq!(rollback.description(), Some("Rollback to generation 0"));
+
+        // Undoing the rollback brings the edit back
+        rollback_to_generation(&config, 1, true, false, false).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "edited");
+        assert_eq!(store.latest_number().unwrap(), 3);
+    }
+
+    /// Test that a directory of symlinks folded into one link is recorded as its links,
+    /// so rolling back recreates the links rather than copies
+    #[test]
+    fn test_rollback_refolded_dir() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.general.state_dir = Some(dir.path().join("state").display().to_string());
+        config.general.backup_dir = dir.path().join("backups").display().to_string();
+        config.general.repo_path = dir.path().join("repo").display().to_string();
+        let repo_dir = dir.path().join("repo/sub");
+        let target = dir.path().join("home/sub");
+        fs::create_dir_all(&repo_dir).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(repo_dir.join("b"), "b").unwrap();
+        symlink("../../repo/sub/b", target.join("b")).unwrap();
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        let mut transaction = begin_transaction(&config).unwrap();
+        transaction.add_operation(FileOperation::RemoveSymlink {
+            target: target.clone(),
+            original: None,
+        });
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: repo_dir.clone(),
+            target: target.clone(),
+            resolution: SymlinkResolution::Relative,
+        });
+        let generation =
+            run_transaction(&config, &mut transaction, "default", &mut fs_manager).unwrap();
+        assert!(target.is_symlink());
+        assert!(matches!(
+            &generation.operations[0].prior,
+            PriorState::Links { links } if links.len() == 1
+        ));
+
+        rollback_to_generation(&config, 0, true, false, false).unwrap();
+        assert!(target.is_dir() && !target.is_symlink());
+        assert_eq!(
+            fs::read_link(target.join("b")).unwrap(),
+            Path::new("../../repo/sub/b")
+        );
+    }
+
+    /// Test that discarded generations are not listed and numbering is sequential
+    #[test]
+    fn test_generation_discard_and_numbering() {
+        let dir = tempdir().unwrap();
+        let store = GenerationStore::new(dir.path().join("generations"));
+        let transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+
+        store.stage(&transaction).unwrap().discard().unwrap();
+        assert!(store.list().unwrap().is_empty());
+        assert_eq!(store.latest_number().unwrap(), 0);
+
+        for expected in 1..=2 {
+            let generation = store
+                .stage(&transaction)
+                .unwrap()
+                .commit(&store, &transaction, "work", Some("abc".to_string()))
+                .unwrap();
+            assert_eq!(generation.number, expected);
+        }
+
+        let generations = store.list().unwrap();
+        assert_eq!(generations.len(), 2);
+        assert_eq!(generations[1].profile, "work");
+        assert!(store.load(3).is_err());
+    }
+
+    /// Test that old generations are pruned on record and cannot be rolled back to
+    #[test]
+    fn test_generation_pruning() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.general.state_dir = Some(dir.path().join("state").display().to_string());
+        config.general.keep_generations = Some(2);
+        let store = GenerationStore::open(&config).unwrap();
+        let transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+
+        for _ in 0..5 {
+            store
+                .stage(&transaction)
+                .unwrap()
+                .commit(&store, &transaction, "default", None)
+                .unwrap();
+        }
+        let numbers = |store: &GenerationStore| -> Vec<u64> {
+            store.list().unwrap().iter().map(|g| g.number).collect()
+        };
+        assert_eq!(numbers(&store), vec![4, 5]);
+
+        // Rolling back past a pruned generation is refused
+        let error = rollback_to_generation(&config, 2, true, false, false).unwrap_err();
+        assert!(error.to_string().contains("pruned"));
+        rollback_to_generation(&config, 3, true, false, true).unwrap();
+
+        // The newest generation is always kept
+        assert_eq!(store.prunable(0).unwrap(), vec![4]);
+        assert_eq!(store.prune(1).unwrap(), vec![4]);
+        assert_eq!(numbers(&store), vec![5]);
+    }
+}
+
+// ============================================================================
+// Transaction Journal - Crash Recovery Tests
+// ============================================================================
+
+#[cfg(test)]
+mod journal_tests {
+    use crate::commands::recover_transaction;
+    use crate::config::Config;
+    use crate::file_manager::FileSystemManager;
+    use crate::services::journal::{Journal, JournalEntry, journal_path};
+    use crate::services::transactions::{FileOperation, Transaction};
+    use crate::types::SymlinkResolution;
+    use crate::utils::dry_run::DryRun;
+    use crate::utils::prompt::RecoveryChoice;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn begin(dir: &Path, operations: Vec<FileOperation>) -> JournalEntry {
+        JournalEntry::Begin {
+            transaction_id: "tx-1".to_string(),
+            temp_dir: dir.join("tx"),
+            metadata: HashMap::new(),
+            operations,
+        }
+    }
+
+    fn link(source: &Path, target: &Path) -> FileOperation {
+        FileOperation::CreateSymlink {
+            source: source.to_path_buf(),
+            target: target.to_path_buf(),
+            resolution: SymlinkResolution::Absolute,
+        }
+    }
+
+    /// Test that progress is reconstructed and a torn last line is ignored
+    #[test]
+    fn test_journal_load_progress() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transaction.journal");
+        let ops = vec![
+            link(Path::new("/repo/a"), Path::new("/home/a")),
+            link(Path::new("/repo/b"), Path::new("/home/b")),
+        ];
+
+        let mut journal = Journal::create(&path, &begin(dir.path(), ops)).unwrap();
+        journal.append(&JournalEntry::Started { index: 0 }).unwrap();
+        journal.append(&JournalEntry::Completed { index: 0 }).unwrap();
+        journal.append(&JournalEntry::Started { index: 1 }).unwrap();
+        drop(journal);
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"entry\":\"compl").unwrap();
+
+        let state = Journal::load(&path).unwrap().unwrap();
+        assert_eq!(state.transaction_id, "tx-1");
+        assert_eq!(state.completed, 1);
+        assert_eq!(state.in_flight, Some(1));
+
+        // A second journal cannot be started while one is unfinished
+        assert!(Journal::create(&path, &begin(dir.path(), Vec::new())).is_err());
+    }
+
+    /// Test finishing and undoing an interrupted transaction
+    #[test]
+    fn test_journal_finish_and_undo() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("transaction.journal");
+        let source = dir.path().join("repo/file");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, "content").unwrap();
+        let first = dir.path().join("home/first");
+        let second = dir.path().join("home/second");
+        let ops = vec![link(&source, &first), link(&source, &second)];
+
+        // Simulate a crash after the first operation completed
+        let mut journal = Journal::create(&path, &begin(dir.path(), ops)).unwrap();
+        journal.append(&JournalEntry::Started { index: 0 }).unwrap();
+        fs::create_dir_all(first.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(&source, &first).unwrap();
+        journal.append(&JournalEntry::Completed { index: 0 }).unwrap();
+        drop(journal);
+
+        let config = Config::default();
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+
+        let state = Journal::load(&path).unwrap().unwrap();
+        let mut transaction = Transaction::from_journal(&state, &path).unwrap();
+        transaction.commit(&config, &mut fs_manager).unwrap();
+        assert_eq!(transaction.results.len(), 2);
+        assert!(second.is_symlink());
+        transaction.verify().unwrap();
+        transaction.cleanup().unwrap();
+        assert!(!path.exists());
+
+        // Undo a transaction interrupted at the same point
+        fs::remove_file(&second).unwrap();
+        let ops = vec![link(&source, &first), link(&source, &second)];
+        let mut journal = Journal::create(&path, &begin(dir.path(), ops)).unwrap();
+        journal.append(&JournalEntry::Started { index: 0 }).unwrap();
+        journal.append(&JournalEntry::Completed { index: 0 }).unwrap();
+        drop(journal);
+
+        let state = Journal::load(&path).unwrap().unwrap();
+        let mut transaction = Transaction::from_journal(&state, &path).unwrap();
+        transaction.rollback(&config, &mut fs_manager).unwrap();
+        assert!(!first.exists() && !first.is_symlink());
+        assert!(!path.exists());
+    }
+
+    /// Test that a directory backup is journaled before the directory is removed, so an
+    /// interrupted replacement can be undone
+    #[test]
+    fn test_journal_backup_before_directory_removed() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("repo/nvim");
+        let target = dir.path().join("home/nvim");
+        let backup = dir.path().join("backups/nvim");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("init.lua"), "local").unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "{}"
+state_dir = "{}"
+current_profile = "default"
+"#,
+            dir.path().join("repo").display(),
+            dir.path().join("backups").display(),
+            dir.path().join("state").display()
+        ))
+        .unwrap();
+        let path = journal_path(&config).unwrap();
+        let replace = FileOperation::BackupAndReplace {
+            source: source.clone(),
+            target: target.clone(),
+            backup_path: backup.clone(),
+            resolution: SymlinkResolution::Absolute,
+        };
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        transaction.add_operation(replace.clone());
+        transaction.validate(&config).unwrap();
+        transaction.prepare(&config).unwrap();
+        transaction.enable_journal(&path).unwrap();
+        transaction.commit(&config, &mut fs_manager).unwrap();
+        let entries: Vec<String> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                entry["entry"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(entries, vec!["begin", "started", "backup", "completed"]);
+        transaction.rollback(&config, &mut fs_manager).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join("init.lua")).unwrap(),
+            "local"
+        );
+        fs::remove_dir_all(&backup).unwrap();
+
+        // Interrupted after the directory was backed up and removed, before it was linked
+        let mut journal = Journal::create(&path, &begin(dir.path(), vec![replace])).unwrap();
+        journal.append(&JournalEntry::Started { index: 0 }).unwrap();
+        fs::create_dir_all(&backup).unwrap();
+        fs::copy(target.join("init.lua"), backup.join("init.lua")).unwrap();
+        journal
+            .append(&JournalEntry::Backup {
+                index: 0,
+                path: backup.clone(),
+            })
+            .unwrap();
+        fs::remove_dir_all(&target).unwrap();
+        drop(journal);
+
+        recover_transaction(&config, Some(RecoveryChoice::Undo)).unwrap();
+        assert!(!target.is_symlink());
+        assert_eq!(
+            fs::read_to_string(target.join("init.lua")).unwrap(),
+            "local"
+        );
+        assert!(!path.exists());
+    }
+}
+
+// ============================================================================
+// Transactions - Rollback Tests
+// ============================================================================
+
+#[cfg(test)]
+mod transaction_rollback_tests {
+    use crate::config::Config;
+    use crate::file_manager::FileSystemManager;
+    use crate::services::transactions::{FileOperation, RemovedOriginal, Transaction};
+    use crate::types::SymlinkResolution;
+    use crate::utils::dry_run::DryRun;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    /// Test that a failed commit restores removed symlinks and files exactly
+    #[test]
+    fn test_rollback_restores_removed_targets() {
+        let dir = tempdir().unwrap();
+        let home = dir.path().join("home");
+        fs::create_dir_all(&home).unwrap();
+
+        let wrong_link = home.join("link");
+        std::os::unix::fs::symlink("somewhere/else", &wrong_link).unwrap();
+        let regular = home.join("file");
+        fs::write(&regular, "original").unwrap();
+        let blocker = home.join("blocker");
+        fs::write(&blocker, "not a directory").unwrap();
+
+        let config = Config::default();
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        transaction.add_operation(FileOperation::RemoveSymlink {
+            target: wrong_link.clone(),
+            original: None,
+        });
+        transaction.add_operation(FileOperation::RemoveSymlink {
+            target: regular.clone(),
+            original: None,
+        });
+        // Fails because its parent is a regular file
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: regular.clone(),
+            target: blocker.join("child"),
+            resolution: SymlinkResolution::Absolute,
+        });
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        transaction.validate(&config).unwrap();
+        transaction.prepare(&config).unwrap();
+        assert!(transaction.commit(&config, &mut fs_manager).is_err());
+
+        assert!(matches!(
+            &transaction.results[0].operation,
+            FileOperation::RemoveSymlink {
+                original: Some(RemovedOriginal::Symlink { .. }),
+                ..
+            }
+        ));
+        assert_eq!(
+            fs::read_link(&wrong_link).unwrap(),
+            PathBuf::from("somewhere/else")
+        );
+        assert!(!regular.is_symlink());
+        assert_eq!(fs::read_to_string(&regular).unwrap(), "original");
+    }
+}
+
+// ============================================================================
+// Templates - Rendering Tests
+// ============================================================================
+
+#[cfg(test)]
+mod template_tests {
+    use crate::config::Config;
+    use crate::file_manager::is_conflict;
+    use crate::services::template::{TemplateContext, render_to, rendered_differs};
+    use crate::types::{EnvironmentSpec, SymlinkResolution, TrackedFile};
+    use std::collections::HashMap;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn config_with_variables() -> Config {
+        Config {
+            environment: Some(EnvironmentSpec {
+                variables: HashMap::from([
+                    ("email".to_string(), "me@example.com".to_string()),
+                    ("os".to_string(), "custom".to_string()),
+                ]),
+                shell: None,
+                hooks: None,
+            }),
+            ..Config::default()
+        }
+    }
+
+    /// Test variable substitution, built-in facts and overrides
+    #[test]
+    fn test_render_variables() {
+        let context = TemplateContext::new(&config_with_variables(), Some("work"));
+
+        let rendered = context
+            .render("user.email = {{ email }}\nprofile={{profile}} os={{ os }}")
+            .unwrap();
+        assert_eq!(rendered, "user.email = me@example.com\nprofile=work os=custom");
+        assert_eq!(context.render("no placeholders").unwrap(), "no placeholders");
+    }
+
+    /// Test that undefined variables and unterminated placeholders are errors
+    #[test]
+    fn test_render_errors() {
+        let context = TemplateContext::new(&Config::default(), None);
+
+        assert!(context.render("{{ missing }}").is_err());
+        assert!(context.render("value = {{ profile").is_err());
+    }
+
+    /// Test that destinations are compared against the rendered output
+    #[test]
+    fn test_rendered_differs() {
+        let dir = tempdir().unwrap();
+        let repo_path = dir.path().join("repo/gitconfig");
+        fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
+        fs::write(&repo_path, "email = {{ email }}\n").unwrap();
+
+        let file = TrackedFile {
+            tool: "git".to_string(),
+            repo_path: repo_path.clone(),
+            dest_path: dir.path().join("home/.gitconfig"),
+            profile: None,
+            template: true,
+            encrypted: false,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        let context = TemplateContext::new(&config_with_variables(), None);
+
+        assert!(rendered_differs(&file, &context).unwrap());
+        render_to(&file, &context, &file.dest_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&file.dest_path).unwrap(),
+            "email = me@example.com\n"
+        );
+        assert!(!rendered_differs(&file, &context).unwrap());
+
+        // A symlink to the raw template is never up to date
+        fs::remove_file(&file.dest_path).unwrap();
+        std::os::unix::fs::symlink(&repo_path, &file.dest_path).unwrap();
+        assert!(rendered_differs(&file, &context).unwrap());
+    }
+
+    /// Test that a destination edited since its last render is a conflict, while a
+    /// changed template is not
+    #[test]
+    fn test_rendered_conflict() {
+        let dir = tempdir().unwrap();
+        let repo_path = dir.path().join("repo/gitconfig");
+        fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
+        fs::write(&repo_path, "email = {{ email }}\n").unwrap();
+        let file = TrackedFile {
+            tool: "git".to_string(),
+            repo_path: repo_path.clone(),
+            dest_path: dir.path().join("home/.gitconfig"),
+            profile: None,
+            template: true,
+            encrypted: false,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        let mut config = config_with_variables();
+        config.general.state_dir = Some(dir.path().join("state").display().to_string());
+        let context = TemplateContext::new(&config, None);
+
+        // Destinations flux never rendered are not conflicts
+        fs::create_dir_all(file.dest_path.parent().unwrap()).unwrap();
+        fs::write(&file.dest_path, "handwritten\n").unwrap();
+        assert!(!is_conflict(&file, &context).unwrap());
+
+        render_to(&file, &context, &file.dest_path).unwrap();
+        context.record_render(&file).unwrap();
+        fs::write(&repo_path, "email = {{ email }}\nname = me\n").unwrap();
+        assert!(!is_conflict(&file, &context).unwrap());
+
+        fs::write(&file.dest_path, "email = edited\n").unwrap();
+        assert!(context.edited_since_render(&file).unwrap());
+        assert!(is_conflict(&file, &context).unwrap());
+
+        // The log survives across runs
+        let context = TemplateContext::new(&config, None);
+        assert!(is_conflict(&file, &context).unwrap());
+        render_to(&file, &context, &file.dest_path).unwrap();
+        context.record_render(&file).unwrap();
+        assert!(!is_conflict(&file, &context).unwrap());
+    }
+}
+
+// ============================================================================
+// Secrets - Encryption Tests
+// ============================================================================
+
+#[cfg(test)]
+mod secret_tests {
+    use crate::commands::apply::add_adoptions_to_transaction;
+    use crate::commands::rekey_secrets;
+    use crate::config::Config;
+    use crate::file_manager::{FileSystemManager, adopt_local_file, is_conflict};
+    use crate::services::secrets::{SecretKeys, is_encrypted, write_identity};
+    use crate::services::template::{TemplateContext, render_to, rendered_differs};
+    use crate::services::transactions::Transaction;
+    use crate::types::{ConflictPolicy, SymlinkResolution, TrackedFile};
+    use crate::utils::dry_run::DryRun;
+    use age::x25519;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    /// Test that encrypted files round-trip and are only readable with the right identity
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let dir = tempdir().unwrap();
+        let identity_path = dir.path().join("identity.txt");
+        write_identity(&identity_path, &x25519::Identity::generate()).unwrap();
+        assert_eq!(
+            fs::metadata(&identity_path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        let keys = SecretKeys::from_file(&identity_path, &[]).unwrap();
+        let ciphertext = keys.encrypt(b"machine example.com password hunter2").unwrap();
+        assert!(is_encrypted(&ciphertext));
+        assert!(!is_encrypted(b"machine example.com password hunter2"));
+        assert_eq!(
+            keys.decrypt(&ciphertext).unwrap(),
+            b"machine example.com password hunter2"
+        );
+
+        // A different identity cannot decrypt
+        let other_path = dir.path().join("other.txt");
+        write_identity(&other_path, &x25519::Identity::generate()).unwrap();
+        let other = SecretKeys::from_file(&other_path, &[]).unwrap();
+        assert!(other.decrypt(&ciphertext).is_err());
+
+        // Unless it was added as a recipient
+        let shared = SecretKeys::from_file(&identity_path, &[other.public_key()]).unwrap();
+        assert_eq!(shared.recipient_count(), 2);
+        let ciphertext = shared.encrypt(b"token").unwrap();
+        assert_eq!(other.decrypt(&ciphertext).unwrap(), b"token");
+    }
+
+    /// Test that a missing identity file is reported
+    #[test]
+    fn test_missing_identity() {
+        let dir = tempdir().unwrap();
+        assert!(SecretKeys::from_file(&dir.path().join("missing.txt"), &[]).is_err());
+    }
+
+    /// Test that encrypted files are decrypted to their destination with mode 0600
+    #[test]
+    fn test_render_encrypted_file() {
+        let dir = tempdir().unwrap();
+        let identity_path = dir.path().join("identity.txt");
+        write_identity(&identity_path, &x25519::Identity::generate()).unwrap();
+        let keys = SecretKeys::from_file(&identity_path, &[]).unwrap();
+
+        let repo_path = dir.path().join("repo/netrc");
+        fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
+        fs::write(&repo_path, keys.encrypt(b"login {{ user }}\n").unwrap()).unwrap();
+
+        let mut config = Config::default();
+        config.general.identity_file = Some(identity_path.to_string_lossy().to_string());
+        let context = TemplateContext::new(&config, None);
+
+        let mut file = TrackedFile {
+            tool: "netrc".to_string(),
+            repo_path,
+            dest_path: dir.path().join("home/.netrc"),
+            profile: None,
+            template: false,
+            encrypted: true,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        assert!(rendered_differs(&file, &context).unwrap());
+        render_to(&file, &context, &file.dest_path).unwrap();
+        assert_eq!(fs::read(&file.dest_path).unwrap(), b"login {{ user }}\n");
+        assert_eq!(
+            fs::metadata(&file.dest_path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(!rendered_differs(&file, &context).unwrap());
+
+        // Encrypted templates are decrypted before variables are substituted
+        file.template = true;
+        assert!(rendered_differs(&file, &context).unwrap());
+    }
+
+    /// Test that an edited secret destination is a conflict and that keeping it
+    /// encrypts it into the repo, directly or through an apply transaction
+    #[test]
+    fn test_adopt_edited_secret() {
+        let dir = tempdir().unwrap();
+        let identity_path = dir.path().join("identity.txt");
+        write_identity(&identity_path, &x25519::Identity::generate()).unwrap();
+        let keys = SecretKeys::from_file(&identity_path, &[]).unwrap();
+
+        let home = dir.path().join("home");
+        let repo_path = home.join(".dotfiles/netrc/netrc");
+        fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
+        fs::write(&repo_path, keys.encrypt(b"token old\n").unwrap()).unwrap();
+
+        let mut config = Config::default();
+        config.general.identity_file = Some(identity_path.to_string_lossy().to_string());
+        config.general.state_dir = Some(dir.path().join("state").display().to_string());
+        let context = TemplateContext::new(&config, None);
+        let file = TrackedFile {
+            tool: "netrc".to_string(),
+            repo_path: repo_path.clone(),
+            dest_path: home.join(".netrc"),
+            profile: None,
+            template: false,
+            encrypted: true,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        render_to(&file, &context, &file.dest_path).unwrap();
+        context.record_render(&file).unwrap();
+        fs::write(&file.dest_path, "token new\n").unwrap();
+        assert!(is_conflict(&file, &context).unwrap());
+
+        // Through an apply transaction, the staged local version is already encrypted
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        let adopted = std::slice::from_ref(&file);
+        let backups = dir.path().join("backups");
+        add_adoptions_to_transaction(
+            &config,
+            &mut transaction,
+            adopted,
+            ConflictPolicy::Adopt,
+            &home,
+            &backups,
+        )
+        .unwrap();
+        let staged = fs::read(transaction.temp_dir.join("adopted/0")).unwrap();
+        assert!(is_encrypted(&staged));
+        assert_eq!(keys.decrypt(&staged).unwrap(), b"token new\n");
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        adopt_local_file(&file, ConflictPolicy::Adopt, &config, &mut fs_manager, None).unwrap();
+        let stored = fs::read(&repo_path).unwrap();
+        assert!(is_encrypted(&stored));
+        assert_eq!(keys.decrypt(&stored).unwrap(), b"token new\n");
+        assert!(!is_conflict(&file, &context).unwrap());
+    }
+    /// Test that rotating the identity twice keeps both previous identities
+    #[test]
+    fn test_rekey_keeps_old_identities() {
+        let dir = tempdir().unwrap();
+        let identity_path = dir.path().join("identity.txt");
+        write_identity(&identity_path, &x25519::Identity::generate()).unwrap();
+        let first = fs::read_to_string(&identity_path).unwrap();
+        let keys = SecretKeys::from_file(&identity_path, &[]).unwrap();
+        let repo_path = dir.path().join("repo/netrc/netrc");
+        fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
+        fs::write(&repo_path, keys.encrypt(b"secret").unwrap()).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+    [general]
+    repo_path = "{}"
+    backup_dir = "{}"
+    identity_file = "{}"
+    current_profile = "default"
+    
+    [tools.netrc]
+    files = [{{ repo = "netrc", dest = ".netrc-flux-test", encrypted = true }}]
+    "#,
+            dir.path().join("repo").display(),
+            dir.path().join("backups").display(),
+            identity_path.display()
+        ))
+        .unwrap();
+        rekey_secrets(&config, true, false).unwrap();
+        let second = fs::read_to_string(&identity_path).unwrap();
+        rekey_secrets(&config, true, false).unwrap();
+
+        let mut retired: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.to_string_lossy().ends_with(".old"))
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect();
+        retired.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(retired, expected);
+
+        let keys = SecretKeys::from_file(&identity_path, &[]).unwrap();
+        assert_eq!(
+            keys.decrypt(&fs::read(&repo_path).unwrap()).unwrap(),
+            b"secret"
+        );
+    }
+}
+
+// ============================================================================
+// File Selection - Host and OS Matcher Tests
+// ============================================================================
+
+#[cfg(test)]
+mod selection_tests {
+    use crate::config::Config;
+    use crate::config::selection::{HostFacts, select_entry};
+    use crate::types::{EntryType, FileEntry, FileKind, LinkMode};
+
+    fn entry(profile: Option<&str>, hosts: Option<&[&str]>, os: Option<&str>) -> FileEntry {
+        FileEntry {
+            repo: "config".to_string(),
+            dest: ".config/app/config".to_string(),
+            profile: profile.map(str::to_string),
+            hosts: hosts.map(|hosts| hosts.iter().map(|h| h.to_string()).collect()),
+            os: os.map(str::to_string),
+            template: false,
+            encrypted: false,
+            resolution: None,
+            entry_type: EntryType::File,
+            ignore: Vec::new(),
+            include: Vec::new(),
+            link: LinkMode::Dir,
+            kind: FileKind::Plain,
+            app: None,
+        }
+    }
+
+    fn default_layers() -> Vec<String> {
+        vec!["default".to_string()]
+    }
+
+    fn desktop() -> HostFacts {
+        HostFacts {
+            hostname: "desktop-02".to_string(),
+            os: "linux".to_string(),
+        }
+    }
+
+    /// Test host glob patterns against the hostname
+    #[test]
+    fn test_select_by_host() {
+        let included = select_entry(
+            &entry(None, Some(&["laptop", "Desktop-*"]), None),
+            &default_layers(),
+            &desktop(),
+        )
+        .unwrap();
+        assert!(included.included);
+        assert!(included.reasons[1].contains("matches 'Desktop-*'"));
+
+        let excluded =
+            select_entry(&entry(None, Some(&["laptop"]), None), &default_layers(), &desktop()).unwrap();
+        assert!(!excluded.included);
+        assert!(excluded.reasons[1].contains("matches none of: laptop"));
+    }
+
+    /// Test os matcher and that every matcher must match
+    #[test]
+    fn test_select_by_os_and_profile() {
+        assert!(
+            select_entry(&entry(None, None, Some("linux")), &default_layers(), &desktop())
+                .unwrap()
+                .included
+        );
+        assert!(
+            !select_entry(&entry(None, None, Some("macos")), &default_layers(), &desktop())
+                .unwrap()
+                .included
+        );
+
+        // Host matches but profile does not
+        let selection = select_entry(
+            &entry(Some("work"), Some(&["desktop-*"]), Some("linux")),
+            &default_layers(),
+            &desktop(),
+        )
+        .unwrap();
+        assert!(!selection.included);
+        assert_eq!(selection.reasons.len(), 3);
+        assert!(selection.reasons[0].contains("profile 'work' is not active"));
+    }
+
+    /// Test that invalid host patterns are reported
+    #[test]
+    fn test_invalid_host_pattern() {
+        assert!(select_entry(&entry(None, Some(&["desk[top"]), None), &default_layers(), &desktop()).is_err());
+    }
+
+    /// Test that select_files keeps excluded entries with their reasons
+    #[test]
+    fn test_select_files_from_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [general]
+            repo_path = "/tmp/dotfiles"
+            backup_dir = "/tmp/backup"
+            current_profile = "default"
+
+            [tools.sway]
+            files = [
+                { repo = "config", dest = ".config/sway/config", hosts = ["desktop-*"] },
+                { repo = "config.mac", dest = ".config/sway/config", os = "macos" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let selected = config.select_files(None, &desktop()).unwrap();
+        assert_eq!(selected.len(), 2);
+        let included: Vec<_> = selected
+            .iter()
+            .filter(|s| s.selection.included)
+            .map(|s| s.file.repo_path.clone())
+            .collect();
+        assert_eq!(included, vec![std::path::PathBuf::from("/tmp/dotfiles/sway/config")]);
+    }
+}
+
+// ============================================================================
+// Profiles - Inheritance Tests
+// ============================================================================
+
+#[cfg(test)]
+mod profile_inheritance_tests {
+    use crate::config::Config;
+    use crate::config::profile::resolve_layers;
+    use crate::config::selection::HostFacts;
+    use std::path::PathBuf;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [general]
+            repo_path = "/tmp/dotfiles"
+            backup_dir = "/tmp/backup"
+            current_profile = "work"
+
+            [profiles.laptop]
+            extends = ["base"]
+
+            [profiles.work]
+            extends = ["base", "laptop"]
+
+            [tools.git]
+            files = [
+                { repo = "gitconfig", dest = ".gitconfig" },
+                { repo = "gitconfig.work", dest = ".gitconfig", profile = "work" },
+                { repo = "gitconfig.base", dest = ".gitconfig", profile = "base" },
+            ]
+
+            [tools.sway]
+            files = [
+                { repo = "config.laptop", dest = ".config/sway/config", profile = "laptop" },
+                { repo = "config.base", dest = ".config/sway/config", profile = "base" },
+                { repo = "config.home", dest = ".config/sway/config", profile = "home" },
+            ]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn facts() -> HostFacts {
+        HostFacts {
+            hostname: "laptop".to_string(),
+            os: "linux".to_string(),
+        }
+    }
+
+    /// Test that layers are resolved depth-first with each profile once
+    #[test]
+    fn test_resolve_layers_order() {
+        let config = config();
+        assert_eq!(
+            resolve_layers(&config, "work").unwrap(),
+            vec!["base", "laptop", "work"]
+        );
+        assert_eq!(resolve_layers(&config, "laptop").unwrap(), vec!["base", "laptop"]);
+        assert_eq!(resolve_layers(&config, "other").unwrap(), vec!["other"]);
+    }
+
+    /// Test that inheritance cycles are rejected
+    #[test]
+    fn test_resolve_layers_cycle() {
+        let mut config = config();
+        config
+            .profiles
+            .get_mut("laptop")
+            .unwrap()
+            .extends
+            .push("work".to_string());
+
+        let error = resolve_layers(&config, "work").unwrap_err().to_string();
+        assert!(error.contains("work → laptop → work"), "{}", error);
+    }
+
+    /// Test that the highest layer wins for each destination
+    #[test]
+    fn test_override_by_destination() {
+        let config = config();
+        let selected = config.select_files(None, &facts()).unwrap();
+
+        let effective: Vec<_> = selected
+            .iter()
+            .filter(|s| s.selection.included)
+            .map(|s| s.file.repo_path.clone())
+            .collect();
+        assert_eq!(
+            effective,
+            vec![
+                PathBuf::from("/tmp/dotfiles/git/gitconfig.work"),
+                PathBuf::from("/tmp/dotfiles/sway/config.laptop"),
+            ]
+        );
+
+        let overridden = selected.iter().filter(|s| s.selection.overridden).count();
+        assert_eq!(overridden, 3);
+
+        // Entries from unrelated profiles are excluded, not overridden
+        let home = selected
+            .iter()
+            .find(|s| s.file.profile.as_deref() == Some("home"))
+            .unwrap();
+        assert!(!home.selection.included && !home.selection.overridden);
+
+        // Without inheritance the common layer is used
+        let selected = config.select_files(Some("default"), &facts()).unwrap();
+        let effective: Vec<_> = selected
+            .iter()
+            .filter(|s| s.selection.included)
+            .map(|s| s.file.repo_path.clone())
+            .collect();
+        assert_eq!(effective, vec![PathBuf::from("/tmp/dotfiles/git/gitconfig")]);
+    }
+}
+
+// ============================================================================
+// Machine-Readable Output Tests
+// ============================================================================
+
+#[cfg(test)]
+mod output_tests {
+    use crate::commands::apply::StateDiff;
+    use crate::commands::status::{FileStatus, StatusReport};
+    use crate::commands::validate::{ValidationIssue, ValidationReport};
+    use crate::config::selection::{EntrySelection, SelectedFile};
+    use crate::types::{SymlinkResolution, TrackedFile};
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn tracked_file() -> TrackedFile {
+        TrackedFile {
+            tool: "git".to_string(),
+            repo_path: PathBuf::from("/tmp/dotfiles/git/gitconfig"),
+            dest_path: PathBuf::from("/home/user/.gitconfig"),
+            profile: Some("work".to_string()),
+            template: false,
+            encrypted: false,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        }
+    }
+
+    /// Test that status reports serialize with stable field names
+    #[test]
+    fn test_status_report_json() {
+        let report = StatusReport {
+            file: tracked_file(),
+            status: FileStatus::MissingSymlink,
+            message: "Missing: /home/user/.gitconfig".to_string(),
+            chain: Vec::new(),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            json!({
+                "file": {
+                    "tool": "git",
+                    "repo_path": "/tmp/dotfiles/git/gitconfig",
+                    "dest_path": "/home/user/.gitconfig",
+                    "profile": "work",
+                    "template": false,
+                    "encrypted": false,
+                    "resolution": "auto",
+                },
+                "status": "missing_symlink",
+                "message": "Missing: /home/user/.gitconfig",
+            })
+        );
+    }
+
+    /// Test that validation issues carry a kind, a message and their fields
+    #[test]
+    fn test_validation_report_json() {
+        let report = ValidationReport {
+            issues: vec![
+                ValidationIssue::MissingRepoFile(tracked_file()),
+                ValidationIssue::OrphanedEntry("sway".to_string(), "config.old".to_string()),
+                ValidationIssue::InvalidConfig("Bad value".to_string()),
+            ],
+            is_valid: false,
+        };
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["is_valid"], json!(false));
+        assert_eq!(value["issues"][0]["kind"], json!("missing_repo_file"));
+        assert_eq!(value["issues"][0]["file"]["tool"], json!("git"));
+        assert_eq!(
+            value["issues"][1],
+            json!({
+                "kind": "orphaned_entry",
+                "message": "Orphaned file in sway: config.old",
+                "tool": "sway",
+                "path": "config.old",
+            })
+        );
+        assert_eq!(
+            value["issues"][2],
+            json!({"kind": "invalid_config", "message": "Bad value"})
+        );
+    }
+
+    /// Test that ls-files records flatten the selection next to the file
+    #[test]
+    fn test_selected_file_json() {
+        let selected = SelectedFile {
+            file: tracked_file(),
+            selection: EntrySelection {
+                included: true,
+                reasons: vec!["profile 'work' is active".to_string()],
+                overridden: false,
+            },
+        };
+
+        let value = serde_json::to_value(&selected).unwrap();
+        assert_eq!(value["file"]["dest_path"], json!("/home/user/.gitconfig"));
+        assert_eq!(value["included"], json!(true));
+        assert_eq!(value["reasons"], json!(["profile 'work' is active"]));
+        assert_eq!(value["overridden"], json!(false));
+    }
+
+    /// Test that apply --check records cover every drift category
+    #[test]
+    fn test_drift_records_json() {
+        let diff = StateDiff {
+            files_to_sync: vec![tracked_file()],
+            dirs_to_unfold: vec![PathBuf::from("/home/user/.config/foo")],
+            stale_links: vec![PathBuf::from("/home/user/.config/bar/old.toml")],
+            ..Default::default()
+        };
+
+        let value = serde_json::to_value(diff.records()).unwrap();
+        assert_eq!(
+            value[0],
+            json!({"kind": "unfold", "path": "/home/user/.config/foo"})
+        );
+        assert_eq!(
+            value[1],
+            json!({"kind": "stale_link", "path": "/home/user/.config/bar/old.toml"})
+        );
+        assert_eq!(value[2]["kind"], json!("sync"));
+        assert_eq!(value[2]["dest_path"], json!("/home/user/.gitconfig"));
+    }
+}
+
+// ============================================================================
+// Severity Tests
+// ============================================================================
+
+#[cfg(test)]
+mod severity_tests {
+    use crate::commands::status::FileStatus;
+    use crate::commands::untracked::IssueType;
+    use crate::commands::validate::ValidationIssue;
+    use crate::types::Severity;
+
+    /// Test that the most severe problem determines the exit code
+    #[test]
+    fn test_max_severity_exit_code() {
+        assert_eq!(Severity::max_of([]).exit_code(), 0);
+        assert_eq!(
+            Severity::max_of([Severity::Ok, Severity::Warning]).exit_code(),
+            3
+        );
+        assert_eq!(
+            Severity::max_of([Severity::Error, Severity::Warning]).exit_code(),
+            4
+        );
+    }
+
+    /// Test that drift is a warning and broken state is an error
+    #[test]
+    fn test_issue_severities() {
+        assert_eq!(FileStatus::Synced.severity(), Severity::Ok);
+        assert_eq!(FileStatus::OutOfSync.severity(), Severity::Warning);
+        assert_eq!(FileStatus::BrokenSymlink.severity(), Severity::Error);
+
+        assert_eq!(IssueType::WrongTarget.severity(), Severity::Warning);
+        assert_eq!(IssueType::MissingRepo.severity(), Severity::Error);
+
+        assert_eq!(
+            ValidationIssue::MissingProfileDir("work".to_string()).severity(),
+            Severity::Warning
+        );
+        assert_eq!(
+            ValidationIssue::InvalidConfig("bad".to_string()).severity(),
+            Severity::Error
+        );
+    }
+}
+
+// ============================================================================
+// Conflict Policy Tests
+// ============================================================================
+
+#[cfg(test)]
+mod conflict_policy_tests {
+    use crate::commands::apply::add_adoptions_to_transaction;
+    use crate::config::Config;
+    use crate::file_manager::{FileSystemManager, adopt_local_file, is_conflict};
+    use crate::services::template::TemplateContext;
+    use crate::services::transactions::{FileOperation, Transaction};
+    use crate::types::{ConflictPolicy, SymlinkResolution, TrackedFile};
+    use crate::utils::dry_run::DryRun;
+    use crate::utils::prompt::select_conflict_policy;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::{TempDir, tempdir};
+
+    /// Test that the policy is only used when explicit or when flux cannot prompt
+    #[test]
+    fn test_select_conflict_policy() {
+        assert_eq!(select_conflict_policy(None, None, true), None);
+        assert_eq!(
+            select_conflict_policy(None, None, false),
+            Some(ConflictPolicy::KeepRepo)
+        );
+        assert_eq!(
+            select_conflict_policy(None, Some(ConflictPolicy::Fail), false),
+            Some(ConflictPolicy::Fail)
+        );
+        assert_eq!(
+            select_conflict_policy(Some(ConflictPolicy::Skip), Some(ConflictPolicy::Fail), true),
+            Some(ConflictPolicy::Skip)
+        );
+    }
+
+    /// Test that on_conflict is parsed from the general section
+    #[test]
+    fn test_on_conflict_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [general]
+            repo_path = "~/.dotfiles"
+            backup_dir = "~/.dotfiles-backup"
+            current_profile = "default"
+            on_conflict = "keep-local"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.general.on_conflict, Some(ConflictPolicy::KeepLocal));
+    }
+
+    /// Test that a differing local file is a conflict and adopt copies it into the repo
+    #[test]
+    fn test_adopt_local_file() {
+        let dir = tempdir().unwrap();
+        let file = TrackedFile {
+            tool: "app".to_string(),
+            repo_path: dir.path().join("repo/app.conf"),
+            dest_path: dir.path().join("home/app.conf"),
+            profile: None,
+            template: false,
+            encrypted: false,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        fs::create_dir_all(file.repo_path.parent().unwrap()).unwrap();
+        fs::create_dir_all(file.dest_path.parent().unwrap()).unwrap();
+        fs::write(&file.repo_path, "repo").unwrap();
+        fs::write(&file.dest_path, "repo").unwrap();
+        let config = Config::default();
+        let templates = TemplateContext::new(&config, None);
+        assert!(!is_conflict(&file, &templates).unwrap());
+
+        fs::write(&file.dest_path, "local").unwrap();
+        assert!(is_conflict(&file, &templates).unwrap());
+
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        adopt_local_file(&file, ConflictPolicy::Adopt, &config, &mut fs_manager, None).unwrap();
+        assert_eq!(fs::read_to_string(&file.repo_path).unwrap(), "local");
+        assert!(!is_conflict(&file, &templates).unwrap());
+    }
+
+    /// Copy the local version of a conflicting file into the repo through an apply
+    /// transaction, once with a failing commit and once successfully. Returns the
+    /// temp dir, the file and the backup dir.
+    fn adopt_in_transaction(policy: ConflictPolicy) -> (TempDir, TrackedFile, PathBuf) {
+        let dir = tempdir().unwrap();
+        let home = dir.path().join("home");
+        let file = TrackedFile {
+            tool: "app".to_string(),
+            repo_path: home.join(".dotfiles/app/app.conf"),
+            dest_path: home.join("app.conf"),
+            profile: None,
+            template: false,
+            encrypted: false,
+            resolution: SymlinkResolution::Auto,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        fs::create_dir_all(file.repo_path.parent().unwrap()).unwrap();
+        fs::write(&file.repo_path, "repo").unwrap();
+        fs::write(&file.dest_path, "local").unwrap();
+        let blocker = home.join("blocker");
+        fs::write(&blocker, "not a directory").unwrap();
+        let backups = dir.path().join("backups");
+
+        let config = Config::default();
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        let run = |fail: bool, fs_manager: &mut FileSystemManager| {
+            let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+            let adopted = std::slice::from_ref(&file);
+            add_adoptions_to_transaction(
+                &config,
+                &mut transaction,
+                adopted,
+                policy,
+                &home,
+                &backups,
+            )
+            .unwrap();
+            // The repo is not touched before the transaction commits
+            assert_eq!(fs::read_to_string(&file.repo_path).unwrap(), "repo");
+            if fail {
+                transaction.add_operation(FileOperation::CreateSymlink {
+                    source: file.repo_path.clone(),
+                    target: blocker.join("child"),
+                    resolution: SymlinkResolution::Absolute,
+                });
+            }
+            transaction.validate(&config).unwrap();
+            transaction.prepare(&config).unwrap();
+            let result = transaction.commit(&config, fs_manager);
+            transaction.cleanup().unwrap();
+            result
+        };
+
+        assert!(run(true, &mut fs_manager).is_err());
+        assert_eq!(fs::read_to_string(&file.repo_path).unwrap(), "repo");
+
+        run(false, &mut fs_manager).unwrap();
+        assert_eq!(fs::read_to_string(&file.repo_path).unwrap(), "local");
+        (dir, file, backups)
+    }
+
+    /// Test that apply's keep-local copies the local version into the repo inside its
+    /// transaction and backs up the repo version
+    #[test]
+    fn test_keep_local_in_transaction() {
+        let (_dir, _file, backups) = adopt_in_transaction(ConflictPolicy::KeepLocal);
+        assert_eq!(
+            fs::read_to_string(backups.join(".dotfiles/app/app.conf")).unwrap(),
+            "repo"
+        );
+    }
+
+    /// Test that apply's adopt copies the local version into the repo inside its
+    /// transaction without a backup, like adopt_local_file
+    #[test]
+    fn test_adopt_in_transaction() {
+        let (_dir, file, backups) = adopt_in_transaction(ConflictPolicy::Adopt);
+        assert!(!backups.exists());
+        assert!(!file.repo_path.is_symlink());
+    }
+}
+
+// ============================================================================
+// Config Include Tests
+// ============================================================================
+
+#[cfg(test)]
+mod include_tests {
+    use crate::config::Config;
+    use crate::config::include::load_composed;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const ROOT: &str = r#"
+[general]
+repo_path = "~/.dotfiles"
+backup_dir = "~/.dotfiles-backup"
+current_profile = "default"
+include = ["tools/nvim.toml", "local.toml", "missing.toml"]
+
+[tools.bash]
+files = [{ repo = "bashrc", dest = ".bashrc" }]
+
+[tools.nvim]
+files = [{ repo = "old.lua", dest = ".config/nvim/init.lua" }]
+"#;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// Test that includes are merged depth first with later files overriding earlier ones
+    #[test]
+    fn test_include_merge_order() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("config.toml");
+        write(&root, ROOT);
+        write(
+            &dir.path().join("tools/nvim.toml"),
+            "[tools.nvim]\nfiles = [{ repo = \"init.lua\", dest = \".config/nvim/init.lua\" }]\n",
+        );
+        write(
+            &dir.path().join("local.toml"),
+            "[general]\ncurrent_profile = \"work\"\ninclude = [\"tools/nvim.toml\"]\n",
+        );
+
+        let (merged, sources) = load_composed(&root).unwrap();
+        let config: Config = toml::Value::Table(merged).try_into().unwrap();
+
+        assert_eq!(
+            sources.files,
+            vec![
+                root.clone(),
+                dir.path().join("tools/nvim.toml"),
+                dir.path().join("local.toml"),
+            ]
+        );
+        assert_eq!(sources.missing, vec![dir.path().join("missing.toml")]);
+        assert_eq!(config.general.current_profile, "work");
+        assert_eq!(config.general.include.as_ref().unwrap().len(), 3);
+        assert_eq!(config.tools["nvim"].files[0].repo, "init.lua");
+        assert_eq!(
+            sources.origin(&["tools", "nvim"]),
+            Some(dir.path().join("tools/nvim.toml").as_path())
+        );
+        assert_eq!(sources.origin(&["tools", "bash"]), None);
+    }
+
+    /// Test that include cycles are reported with the chain of files
+    #[test]
+    fn test_include_cycle() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("config.toml");
+        write(&root, &ROOT.replace("\"local.toml\"", "\"a.toml\""));
+        write(
+            &dir.path().join("a.toml"),
+            "[general]\ninclude = [\"b.toml\"]\n",
+        );
+        write(
+            &dir.path().join("b.toml"),
+            "[general]\ninclude = [\"a.toml\"]\n",
+        );
+
+        let error = load_composed(&root).unwrap_err().to_string();
+        assert!(error.contains("Config include cycle"), "{}", error);
+        assert!(error.contains("a.toml → "), "{}", error);
+        assert!(error.ends_with("a.toml"), "{}", error);
+    }
+
+    /// Test that changes to included parts are written back to their own file
+    #[test]
+    fn test_save_included() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("config.toml");
+        let nvim = dir.path().join("tools/nvim.toml");
+        let local = dir.path().join("local.toml");
+        write(&root, ROOT);
+        write(
+            &nvim,
+            "# Neovim\n[tools.nvim]\nfiles = [{ repo = \"init.lua\", dest = \".config/nvim/init.lua\" }]\n",
+        );
+        write(&local, "[general]\ncurrent_profile = \"work\"\n");
+
+        let (merged, sources) = load_composed(&root).unwrap();
+        let mut config: Config = toml::Value::Table(merged).try_into().unwrap();
+        config
+            .add_file_to_tool(
+                "nvim",
+                "lua/plugins.lua",
+                Path::new(".config/nvim/lua/plugins.lua"),
+                None,
+            )
+            .unwrap();
+
+        let current = toml::Table::try_from(&config).unwrap();
+        let included_keys = sources.save_included(&current).unwrap();
+
+        let saved = fs::read_to_string(&nvim).unwrap();
+        assert!(saved.starts_with("# Neovim"), "{}", saved);
+        assert!(saved.contains("lua/plugins.lua"), "{}", saved);
+        assert_eq!(
+            fs::read_to_string(&local).unwrap(),
+            "[general]\ncurrent_profile = \"work\"\n"
+        );
+        assert!(included_keys.contains(&vec!["tools".to_string(), "nvim".to_string()]));
+        assert!(!included_keys.contains(&vec!["tools".to_string(), "bash".to_string()]));
+    }
+}
+
+// ============================================================================
+// Per-File Resolution Tests
+// ============================================================================
+
+#[cfg(test)]
+mod file_resolution_tests {
+    use crate::config::Config;
+    use crate::types::{FileEntry, SymlinkResolution};
+    use crate::utils::path_utils::{DirFilter, content_hash, copy_differs};
+    use std::fs;
+    use tempfile::tempdir;
+
+    /// Test that `resolution` (or its `mode` alias) overrides the global setting
+    #[test]
+    fn test_file_resolution_overrides_global() {
+        let mut config: Config = toml::from_str(
+            r#"
+[general]
+repo_path = "/tmp/flux-dotfiles"
+backup_dir = "/tmp/flux-backups"
+current_profile = "default"
+symlink_resolution = "absolute"
+
+[tools.firefox]
+files = [
+    { repo = "user.js", dest = ".mozilla/firefox/default/user.js", mode = "replace" },
+    { repo = "chrome", dest = ".mozilla/firefox/default/chrome" },
+]
+"#,
+        )
+        .unwrap();
+        config.tools.get_mut("firefox").unwrap().files[1].resolution =
+            Some(SymlinkResolution::Relative);
+
+        let files = config.get_tracked_files(None).unwrap();
+        assert_eq!(files[0].resolution, SymlinkResolution::Replace);
+        assert!(files[0].is_copy());
+        assert_eq!(files[1].resolution, SymlinkResolution::Relative);
+        assert!(!files[1].is_copy());
+
+        let entry: FileEntry = toml::from_str("repo = \"a\"\ndest = \"b\"").unwrap();
+        assert_eq!(entry.resolution, None);
+        let serialized = toml::to_string(&config.tools["firefox"].files[0]).unwrap();
+        assert!(
+            serialized.contains("resolution = \"replace\""),
+            "{}",
+            serialized
+        );
+    }
+
+    /// Test that copies are compared by content hash, and a symlink is never a copy
+    #[test]
+    fn test_copy_drift_detection() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo.js");
+        let dest = dir.path().join("dest.js");
+        fs::write(&repo, "user_pref(\"a\", 1);\n").unwrap();
+
+        assert!(copy_differs(&repo, &dest, &DirFilter::default()).unwrap());
+        fs::copy(&repo, &dest).unwrap();
+        assert!(!copy_differs(&repo, &dest, &DirFilter::default()).unwrap());
+        assert_eq!(content_hash(&repo).unwrap(), content_hash(&dest).unwrap());
+
+        fs::write(&dest, "user_pref(\"a\", 2);\n").unwrap();
+        assert!(copy_differs(&repo, &dest, &DirFilter::default()).unwrap());
+
+        fs::remove_file(&dest).unwrap();
+        std::os::unix::fs::symlink(&repo, &dest).unwrap();
+        assert!(copy_differs(&repo, &dest, &DirFilter::default()).unwrap());
+    }
+}
+
+// ============================================================================
+// Follow Resolution Tests
+// ============================================================================
+
+#[cfg(test)]
+mod follow_resolution_tests {
+    use crate::config::Config;
+    use crate::file_manager::FileSystemManager;
+    use crate::services::transactions::{FileOperation, Transaction};
+    use crate::types::{SymlinkResolution, TrackedFile};
+    use crate::utils::dry_run::DryRun;
+    use crate::utils::path_utils::follow_symlink_chain;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    /// Test that a chain is followed to its last hop and stops at the repo file
+    #[test]
+    fn test_follow_symlink_chain() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo/bashrc");
+        let stowed = dir.path().join("stow/bash/.bashrc");
+        let dest = dir.path().join("home/.bashrc");
+        for path in [&repo, &stowed, &dest] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+        }
+        fs::write(&repo, "repo").unwrap();
+        fs::write(&stowed, "stow").unwrap();
+        symlink("../stow/bash/.bashrc", &dest).unwrap();
+
+        let chain = follow_symlink_chain(&dest, &repo).unwrap();
+        assert_eq!(
+            chain,
+            vec![dest.clone(), dir.path().join("home/../stow/bash/.bashrc")]
+        );
+
+        fs::remove_file(&stowed).unwrap();
+        symlink(&repo, &stowed).unwrap();
+        let chain = follow_symlink_chain(&dest, &repo).unwrap();
+        assert_eq!(chain.len(), 2, "stops at the link to the repo: {:?}", chain);
+
+        // A file that is not a symlink is its own chain
+        assert_eq!(follow_symlink_chain(&repo, &repo).unwrap(), vec![repo]);
+    }
+
+    /// Test that symlink cycles are reported instead of looping
+    #[test]
+    fn test_follow_symlink_cycle() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        symlink(&b, &a).unwrap();
+        symlink(&a, &b).unwrap();
+
+        let error = follow_symlink_chain(&a, &dir.path().join("repo"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Symlink cycle"), "{}", error);
+    }
+
+    /// Test that Follow places the link at the end of the chain, keeping the chain
+    #[test]
+    fn test_follow_places_at_chain_end() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo/bashrc");
+        let stowed = dir.path().join("stow/.bashrc");
+        let dest = dir.path().join("home/.bashrc");
+        for path in [&repo, &stowed, &dest] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+        }
+        fs::write(&repo, "repo").unwrap();
+        fs::write(&stowed, "stow").unwrap();
+        symlink(&stowed, &dest).unwrap();
+
+        let file = TrackedFile {
+            tool: "bash".to_string(),
+            repo_path: repo.clone(),
+            dest_path: dest.clone(),
+            profile: None,
+            template: false,
+            encrypted: false,
+            resolution: SymlinkResolution::Follow,
+            dir: None,
+            link_root: None,
+            sqlite_owner: None,
+        };
+        let (followed, chain) = file.followed().unwrap();
+        assert_eq!(followed.dest_path, stowed);
+        assert_eq!(chain, vec![dest.clone(), stowed.clone()]);
+
+        let config = Config::default();
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: repo.clone(),
+            target: dest.clone(),
+            resolution: SymlinkResolution::Follow,
+        });
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        transaction.validate(&config).unwrap();
+        transaction.prepare(&config).unwrap();
+        transaction.commit(&config, &mut fs_manager).unwrap();
+
+        assert_eq!(fs::read_link(&dest).unwrap(), stowed);
+        assert!(stowed.is_symlink());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "repo");
+        assert_eq!(file.followed().unwrap().1.len(), 2);
+    }
+}
+
+// ============================================================================
+// Directory Entry Tests
+// ============================================================================
+
+#[cfg(test)]
+mod dir_entry_tests {
+    use crate::config::Config;
+    use crate::file_manager::{FileSystemManager, exclude_ignored_changes};
+    use crate::types::{EntryType, FileChange};
+    use crate::utils::dry_run::DryRun;
+    use crate::utils::path_utils::{DirFilter, dir_differences, paths_differ};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Test that ignore/include globs filter the recursive comparison
+    #[test]
+    fn test_dir_filter_and_differences() {
+        let filter = DirFilter::new(&[], &["*.log".to_string(), "Cache/**".to_string()]).unwrap();
+        assert!(filter.matches(Path::new("settings.json")));
+        assert!(!filter.matches(Path::new("logs/today.log")));
+        assert!(!filter.matches(Path::new("Cache/data/blob")));
+
+        let include = DirFilter::new(&["*.json".to_string()], &[]).unwrap();
+        assert!(include.matches(Path::new("nested/settings.json")));
+        assert!(!include.matches(Path::new("notes.txt")));
+        assert!(DirFilter::new(&[], &["[".to_string()]).is_err());
+
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let dest = dir.path().join("dest");
+        for root in [&repo, &dest] {
+            write(&root.join("settings.json"), "{}");
+            write(&root.join("nested/keys.json"), "[]");
+        }
+        write(&dest.join("debug.log"), "noise");
+        write(&dest.join("Cache/data/blob"), "noise");
+
+        // Ignored files don't count, but an unfiltered comparison sees them
+        assert!(dir_differences(&repo, &dest, &filter).unwrap().is_empty());
+        assert!(!paths_differ(&repo, &dest, &filter).unwrap());
+        assert!(paths_differ(&repo, &dest, &DirFilter::default()).unwrap());
+
+        // Nested content changes are found even though the top level matches
+        write(&dest.join("nested/keys.json"), "[1]");
+        assert_eq!(
+            dir_differences(&repo, &dest, &filter).unwrap(),
+            vec![PathBuf::from("nested/keys.json")]
+        );
+        assert!(paths_differ(&repo, &dest, &filter).unwrap());
+    }
+
+    /// Test that copying a directory into the repo skips ignored files
+    #[test]
+    fn test_copy_dir_filtered_skips_ignored() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        write(&src.join("config.toml"), "a = 1");
+        write(&src.join("Cache/index"), "cache");
+        write(&src.join("run.log"), "log");
+
+        let filter = DirFilter::new(&[], &["*.log".to_string(), "Cache".to_string()]).unwrap();
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        fs_manager.copy_dir_filtered(&src, &dst, &filter).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dst.join("config.toml")).unwrap(),
+            "a = 1"
+        );
+        assert!(!dst.join("Cache").exists());
+        assert!(!dst.join("run.log").exists());
+    }
+
+    /// Test `type = "dir"` parsing and that commit skips ignored paths
+    #[test]
+    fn test_dir_entry_config_and_commit_filter() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let config: Config = toml::from_str(&format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "/tmp/flux-backups"
+current_profile = "default"
+
+[tools.vscode]
+files = [
+    {{ repo = "User", dest = ".config/Code/User", type = "dir", ignore = ["*.log", "workspaceStorage/**"] }},
+    {{ repo = "keybindings.json", dest = ".config/Code/keybindings.json" }},
+]
+"#,
+            repo.display()
+        ))
+        .unwrap();
+
+        let entries = &config.tools["vscode"].files;
+        assert_eq!(entries[0].entry_type, EntryType::Dir);
+        assert_eq!(entries[1].entry_type, EntryType::File);
+        let serialized = toml::to_string(&entries[1]).unwrap();
+        assert!(!serialized.contains("type"), "{}", serialized);
+
+        let files = config.get_tracked_files(None).unwrap();
+        assert_eq!(files[0].dir.as_ref().unwrap().ignore.len(), 2);
+        assert!(files[1].dir.is_none());
+
+        let user = repo.join("vscode/User");
+        write(&user.join("settings.json"), "{}");
+        write(&user.join("workspaceStorage/abc/state"), "x");
+        let changes = vec![
+            FileChange::Modified(user.join("settings.json")),
+            FileChange::Added(user.join("renderer.log")),
+            FileChange::Added(user.join("workspaceStorage")),
+            FileChange::Modified(repo.join("vscode/keybindings.json")),
+        ];
+        let kept = exclude_ignored_changes(&config, changes).unwrap();
+        assert_eq!(
+            kept,
+            vec![
+                FileChange::Modified(user.join("settings.json")),
+                FileChange::Modified(repo.join("vscode/keybindings.json")),
+            ]
+        );
+
+        // A brand-new tool directory is expanded before filtering
+        let changes = vec![FileChange::Added(repo.join("vscode"))];
+        let kept = exclude_ignored_changes(&config, changes).unwrap();
+        assert_eq!(kept, vec![FileChange::Added(user.join("settings.json"))]);
+    }
+}
+
+// ============================================================================
+// Link Contents Tests
+// ============================================================================
+
+#[cfg(test)]
+mod link_contents_tests {
+    use crate::commands::apply::compare_states;
+    use crate::config::Config;
+    use crate::file_manager::FileSystemManager;
+    use crate::services::transactions::{FileOperation, Transaction};
+    use crate::types::{DirSpec, LinkMode, LinkRoot, SymlinkResolution, TrackedFile};
+    use crate::utils::dry_run::DryRun;
+    use crate::utils::path_utils::{
+        folded_ancestor, is_foldable_dir, is_unfolded_dir, stale_links,
+    };
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use tempfile::tempdir;
+
+    /// Test that a `link = "contents"` entry expands into one file per repo file
+    #[test]
+    fn test_expand_contents() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir_all(repo.join("foo/conf/sub")).unwrap();
+        fs::write(repo.join("foo/conf/a.toml"), "a").unwrap();
+        fs::write(repo.join("foo/conf/sub/b.toml"), "b").unwrap();
+        fs::write(repo.join("foo/conf/debug.log"), "log").unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "/tmp/flux-backups"
+current_profile = "default"
+
+[tools.foo]
+files = [
+    {{ repo = "conf", dest = ".config/foo", type = "dir", link = "contents", ignore = ["*.log"] }},
+]
+"#,
+            repo.display()
+        ))
+        .unwrap();
+        assert_eq!(config.tools["foo"].files[0].link, LinkMode::Contents);
+
+        let files = config.get_tracked_files(None).unwrap();
+        let home = dirs::home_dir().unwrap();
+        let dests: Vec<_> = files.iter().map(|file| file.dest_path.clone()).collect();
+        assert_eq!(
+            dests,
+            vec![
+                home.join(".config/foo/a.toml"),
+                home.join(".config/foo/sub")
+            ]
+        );
+        // The subdirectory does not exist yet, so it is folded into one link
+        assert_eq!(files[1].repo_path, repo.join("foo/conf/sub"));
+        assert_eq!(files[0].dir, None);
+        assert_eq!(files[1].dir, Some(DirSpec::default()));
+        assert_eq!(
+            files[0].link_root,
+            Some(LinkRoot {
+                repo: repo.join("foo/conf"),
+                dest: home.join(".config/foo"),
+            })
+        );
+    }
+
+    /// Test detection of folded directories, unfolded directories and stale links
+    #[test]
+    fn test_folded_unfolded_and_stale() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(repo.join("sub")).unwrap();
+        fs::write(repo.join("a"), "a").unwrap();
+        fs::write(repo.join("sub/b"), "b").unwrap();
+
+        // The whole directory is linked: files below it are folded
+        symlink(&repo, &dest).unwrap();
+        assert_eq!(
+            folded_ancestor(&dest.join("sub/b"), &dest, &repo),
+            Some(dest.clone())
+        );
+        assert!(!is_unfolded_dir(&dest, &repo).unwrap());
+
+        // Real directories holding a link per file can be folded back
+        fs::remove_file(&dest).unwrap();
+        fs::create_dir_all(dest.join("sub")).unwrap();
+        symlink(repo.join("a"), dest.join("a")).unwrap();
+        symlink(repo.join("sub"), dest.join("sub/linked")).unwrap();
+        assert_eq!(folded_ancestor(&dest.join("a"), &dest, &repo), None);
+        assert_eq!(
+            folded_ancestor(&dest.join("sub/linked/b"), &dest, &repo),
+            Some(dest.join("sub/linked"))
+        );
+        fs::remove_file(dest.join("sub/linked")).unwrap();
+        symlink(repo.join("sub/b"), dest.join("sub/b")).unwrap();
+        assert!(is_unfolded_dir(&dest, &repo).unwrap());
+        assert!(stale_links(&dest, &repo).unwrap().is_empty());
+
+        // Removing a repo file leaves a stale link; app files make it a real directory
+        fs::remove_file(repo.join("a")).unwrap();
+        assert_eq!(stale_links(&dest, &repo).unwrap(), vec![dest.join("a")]);
+        fs::write(dest.join("runtime.db"), "app").unwrap();
+        assert!(!is_unfolded_dir(&dest, &repo).unwrap());
+    }
+
+    /// Test that a subdirectory holding only links is folded into one link by a
+    /// transaction, and unfolded again once it holds a file the entry ignores
+    #[test]
+    fn test_contents_subdirs_refolded() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(repo.join("foo/conf/sub")).unwrap();
+        fs::write(repo.join("foo/conf/a"), "a").unwrap();
+        fs::write(repo.join("foo/conf/sub/b"), "b").unwrap();
+        fs::create_dir_all(dest.join("sub")).unwrap();
+        symlink(repo.join("foo/conf/a"), dest.join("a")).unwrap();
+        symlink(repo.join("foo/conf/sub/b"), dest.join("sub/b")).unwrap();
+
+        let config_with = |ignore: &str| -> Config {
+            toml::from_str(&format!(
+                r#"
+[general]
+repo_path = "{}"
+backup_dir = "{}"
+current_profile = "default"
+
+[tools.foo]
+files = [
+    {{ repo = "conf", dest = "{}", type = "dir", link = "contents", ignore = [{}] }},
+]
+"#,
+                repo.display(),
+                dir.path().join("backups").display(),
+                dest.display(),
+                ignore
+            ))
+            .unwrap()
+        };
+        let config = config_with("");
+        assert!(is_foldable_dir(&dest.join("sub"), &repo.join("foo/conf/sub")).unwrap());
+        assert!(!is_foldable_dir(&dest.join("a"), &repo.join("foo/conf/sub")).unwrap());
+
+        let diff = compare_states(&config, None, false).unwrap();
+        assert_eq!(diff.files_to_sync.len(), 1);
+        let folded = &diff.files_to_sync[0];
+        assert_eq!(folded.dest_path, dest.join("sub"));
+        assert_eq!(folded.repo_path, repo.join("foo/conf/sub"));
+
+        // The per-file links are staged rather than backed up, so rollback restores them
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        transaction.add_operation(FileOperation::RemoveSymlink {
+            target: folded.dest_path.clone(),
+            original: None,
+        });
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: folded.repo_path.clone(),
+            target: folded.dest_path.clone(),
+            resolution: folded.resolution,
+        });
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        transaction.validate(&config).unwrap();
+        transaction.prepare(&config).unwrap();
+        transaction.commit(&config, &mut fs_manager).unwrap();
+        transaction.verify().unwrap();
+        assert!(dest.join("sub").is_symlink());
+        assert_eq!(fs::read_to_string(dest.join("sub/b")).unwrap(), "b");
+        assert!(compare_states(&config, None, false).unwrap().is_empty());
+
+        transaction.rollback(&config, &mut fs_manager).unwrap();
+        assert!(is_unfolded_dir(&dest.join("sub"), &repo.join("foo/conf/sub")).unwrap());
+
+        // A repo file the entry ignores must not show through a folded link
+        fs::remove_dir_all(dest.join("sub")).unwrap();
+        symlink(repo.join("foo/conf/sub"), dest.join("sub")).unwrap();
+        fs::write(repo.join("foo/conf/sub/debug.log"), "log").unwrap();
+        let diff = compare_states(&config_with(r#""*.log""#), None, false).unwrap();
+        assert_eq!(diff.dirs_to_unfold, vec![dest.join("sub")]);
+        let dests: Vec<_> = diff.files_to_sync.iter().map(|f| &f.dest_path).collect();
+        assert_eq!(dests, vec![&dest.join("sub/b")]);
+    }
+
+    /// Test that a transaction unfolds a directory symlink and rollback refolds it
+    #[test]
+    fn test_unfold_transaction_and_rollback() {
+        let dir = tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(repo.join("a"), "a").unwrap();
+        symlink(&repo, &dest).unwrap();
+
+        let file = TrackedFile {
+            tool: "foo".to_string(),
+            repo_path: repo.join("a"),
+            dest_path: dest.join("a"),
+            profile: None,
+            template: false,
+            encrypted: false,
+            resolution: SymlinkResolution::Absolute,
+            dir: None,
+            link_root: Some(LinkRoot {
+                repo: repo.clone(),
+                dest: dest.clone(),
+            }),
+            sqlite_owner: None,
+        };
+        assert_eq!(file.folded_dir(), Some(dest.clone()));
+
+        let config = Config::default();
+        let mut transaction = Transaction::begin(dir.path().join("tx")).unwrap();
+        transaction.add_operation(FileOperation::RemoveSymlink {
+            target: dest.clone(),
+            original: None,
+        });
+        transaction.add_operation(FileOperation::CreateSymlink {
+            source: file.repo_path.clone(),
+            target: file.dest_path.clone(),
+            resolution: file.resolution,
+        });
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        transaction.validate(&config).unwrap();
+        transaction.prepare(&config).unwrap();
+        transaction.commit(&config, &mut fs_manager).unwrap();
+        transaction.verify().unwrap();
+
+        assert!(dest.is_dir() && !dest.is_symlink());
+        assert_eq!(fs::read_link(dest.join("a")).unwrap(), repo.join("a"));
+        assert_eq!(file.folded_dir(), None);
+
+        transaction.rollback(&config, &mut fs_manager).unwrap();
+        assert_eq!(fs::read_link(&dest).unwrap(), repo);
+        assert_eq!(fs::read_to_string(repo.join("a")).unwrap(), "a");
+    }
+}
+
+// ============================================================================
+// Diff Tests
+// ============================================================================
+
+#[cfg(test)]
+mod diff_tests {
+    use crate::commands::restore::{BackupInfo, select_backup};
+    use crate::services::git::worktree_changes;
+    use crate::utils::diff::{diff_contents, stat_bar};
+    use git2::{Repository, Signature};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    /// Test unified hunks, line counts and binary detection
+    #[test]
+    fn test_diff_contents() {
+        assert!(diff_contents("f", "a/f", "b/f", Some(b"same\n"), Some(b"same\n")).is_none());
+
+        let diff = diff_contents(
+            "f",
+            "a/f",
+            "b/f",
+            Some(b"one\ntwo\nthree\n"),
+            Some(b"one\n2\nthree\nfour\n"),
+        )
+        .unwrap();
+        assert_eq!((diff.insertions, diff.deletions), (2, 1));
+        assert!(diff.hunks.starts_with("@@ -1,3 +1,4 @@\n"));
+        assert!(diff.hunks.contains("-two\n+2\n"));
+        let rendered = diff.render(false);
+        assert!(rendered.starts_with("--- a/f\n+++ b/f\n@@"));
+
+        // A missing side is /dev/null and every line counts
+        let added = diff_contents("f", "a/f", "b/f", None, Some(b"x\ny\n")).unwrap();
+        assert_eq!(added.old_label, "/dev/null");
+        assert_eq!((added.insertions, added.deletions), (2, 0));
+
+        let binary = diff_contents("f", "a/f", "b/f", Some(b"a\0b"), Some(b"text")).unwrap();
+        assert!(binary.binary && binary.hunks.is_empty());
+        assert_eq!(binary.render(false), "Binary files a/f and b/f differ\n");
+
+        // Bars are only scaled once the largest change no longer fits
+        assert_eq!(stat_bar(3, 2, 5), (3, 2));
+        assert_eq!(stat_bar(100, 1, 200), (20, 1));
+    }
+
+    /// Test that new, modified and deleted files are reported against HEAD
+    #[test]
+    fn test_worktree_changes() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::create_dir_all(dir.path().join("nvim")).unwrap();
+        fs::write(dir.path().join("nvim/init.lua"), "old\n").unwrap();
+        fs::write(dir.path().join("gone"), "bye\n").unwrap();
+
+        // Without commits, everything is new
+        let changes = worktree_changes(&repo).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|c| c.head.is_none()));
+
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+        assert!(worktree_changes(&repo).unwrap().is_empty());
+
+        fs::write(dir.path().join("nvim/init.lua"), "new\n").unwrap();
+        fs::remove_file(dir.path().join("gone")).unwrap();
+        fs::write(dir.path().join("added"), "hi\n").unwrap();
+        let mut changes = worktree_changes(&repo).unwrap();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.path.as_path(), c.head.as_deref(), c.worktree.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Path::new("added"), None, Some(&b"hi\n"[..])),
+                (Path::new("gone"), Some(&b"bye\n"[..]), None),
+                (
+                    Path::new("nvim/init.lua"),
+                    Some(&b"old\n"[..]),
+                    Some(&b"new\n"[..])
+                ),
+            ]
+        );
+    }
+
+    /// Test selecting a backup by `latest` or by index
+    #[test]
+    fn test_select_backup() {
+        let backups: Vec<BackupInfo> = ["b2", "b1"]
+            .iter()
+            .map(|name| BackupInfo {
+                path: PathBuf::from(name),
+                timestamp: chrono::Local::now(),
+                files: Vec::new(),
+            })
+            .collect();
+
+        assert_eq!(
+            select_backup(&backups, "latest").unwrap().path,
+            backups[0].path
+        );
+        assert_eq!(select_backup(&backups, "2").unwrap().path, backups[1].path);
+        assert!(select_backup(&backups, "0").is_err());
+        assert!(select_backup(&backups, "3").is_err());
+        assert!(select_backup(&backups, "newest").is_err());
+        assert!(select_backup(&[], "latest").is_err());
+    }
+}
+
+// ============================================================================
+// Watch Tests
+// ============================================================================
+
+#[cfg(test)]
+mod watch_tests {
+    use crate::commands::untracked::IssueType;
+    use crate::commands::watch::{
+        WatchOptions, render_watch_unit, replaced_policy, replaced_symlinks, watch_commit_message,
+    };
+    use crate::config::Config;
+    use crate::types::{ConflictPolicy, FileChange, SymlinkResolution, TrackedFile};
+    use std::collections::{BTreeSet, HashSet};
+    use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    /// Test that commit messages name a single file, or the tools of several
+    #[test]
+    fn test_watch_commit_message() {
+        let root = Path::new("/repo");
+        assert_eq!(
+            watch_commit_message(&[FileChange::Modified(root.join("nvim/init.lua"))], root),
+            "flux watch: update nvim/init.lua"
+        );
+        assert_eq!(
+            watch_commit_message(&[FileChange::Deleted(root.join("zsh/zshrc"))], root),
+            "flux watch: remove zsh/zshrc"
+        );
+        assert_eq!(
+            watch_commit_message(
+                &[
+                    FileChange::Added(root.join("zsh/aliases")),
+                    FileChange::Modified(root.join("nvim/init.lua")),
+                    FileChange::Modified(root.join("zsh/zshrc")),
+                ],
+                root
+            ),
+            "flux watch: update 3 files in nvim, zsh"
+        );
+    }
+
+    /// Test that only touched symlinks replaced by a regular file are reported
+    #[test]
+    fn test_replaced_symlinks() {
+        let dir = tempdir().unwrap();
+        let tracked = |name: &str| {
+            let repo_path = dir.path().join(format!("repo/{}", name));
+            fs::create_dir_all(repo_path.parent().unwrap()).unwrap();
+            fs::write(&repo_path, "repo").unwrap();
+            TrackedFile {
+                tool: "app".to_string(),
+                repo_path,
+                dest_path: dir.path().join(format!("home/{}", name)),
+                profile: None,
+                template: false,
+                encrypted: false,
+                resolution: SymlinkResolution::Absolute,
+                dir: None,
+                link_root: None,
+                sqlite_owner: None,
+            }
+        };
+        let files = vec![tracked("replaced"), tracked("linked"), tracked("untouched")];
+        fs::create_dir_all(dir.path().join("home")).unwrap();
+        for file in &files {
+            symlink(&file.repo_path, &file.dest_path).unwrap();
+        }
+        let linked: HashSet<PathBuf> = files
+            .iter()
+            .filter(|file| file.is_linked())
+            .map(|file| file.dest_path.clone())
+            .collect();
+        assert_eq!(linked.len(), 3);
+
+        for file in [&files[0], &files[2]] {
+            fs::remove_file(&file.dest_path).unwrap();
+            fs::write(&file.dest_path, "saved by app").unwrap();
+        }
+        let touched: BTreeSet<PathBuf> = [&files[0], &files[1]]
+            .iter()
+            .map(|file| file.dest_path.clone())
+            .collect();
+
+        let replaced = replaced_symlinks(&files, &linked, &touched);
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(replaced[0].file.dest_path, files[0].dest_path);
+        assert!(matches!(replaced[0].issue, IssueType::NotSymlink));
+    }
+
+    /// Test that replaced destinations are only adopted when asked to
+    #[test]
+    fn test_replaced_policy() {
+        let mut config = Config::default();
+        let mut options = WatchOptions {
+            profile: None,
+            debounce: Duration::from_secs(2),
+            conflict_policy: None,
+        };
+        assert_eq!(replaced_policy(&config, &options), ConflictPolicy::KeepRepo);
+
+        config.general.on_conflict = Some(ConflictPolicy::Skip);
+        assert_eq!(replaced_policy(&config, &options), ConflictPolicy::Skip);
+
+        options.conflict_policy = Some(ConflictPolicy::Adopt);
+        assert_eq!(replaced_policy(&config, &options), ConflictPolicy::Adopt);
+    }
+    /// Test that the systemd unit runs watch with the given options
+    #[test]
+    fn test_render_watch_unit() {
+        let options = WatchOptions {
+            profile: Some("work".to_string()),
+            debounce: Duration::from_secs(5),
+            conflict_policy: Some(ConflictPolicy::KeepRepo),
+        };
+        let unit = render_watch_unit(Path::new("/usr/bin/flux"), &options);
+        assert!(unit.contains(
+            "ExecStart=/usr/bin/flux watch --debounce 5 --profile work --on-conflict keep-repo\n"
+        ));
+        assert!(unit.contains("WantedBy=default.target"));
+    }
+}
+
+// ============================================================================
+// Service Tests
+// ============================================================================
+
+#[cfg(test)]
+mod service_tests {
+    use crate::commands::service::{
+        SYNC_SERVICE_NAME, SYNC_TIMER_NAME, ServiceOptions, render_sync_units, service_status,
+        uninstall_service,
+    };
+    use crate::config::Config;
+    use crate::services::systemd::{exec_line, quote_arg, write_units};
+    use crate::types::ConflictPolicy;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    fn options() -> ServiceOptions {
+        ServiceOptions {
+            profile: None,
+            remote: None,
+            branch: None,
+            schedule: "daily".to_string(),
+            conflict_policy: None,
+            config_file: None,
+        }
+    }
+
+    /// Test that config defaults for remote, branch, timeout and policy reach the units
+    #[test]
+    fn test_render_sync_units() {
+        let mut config = Config::default();
+        config.general.default_remote = Some("upstream".to_string());
+        config.general.default_branch = Some("main".to_string());
+        config.general.push_timeout = Some(15);
+        config.general.on_conflict = Some(ConflictPolicy::Skip);
+
+        let units = render_sync_units(Path::new("/usr/bin/flux"), &config, &options());
+        assert_eq!(units[0].name, SYNC_SERVICE_NAME);
+        assert!(units[0].contents.contains(
+            "ExecStart=/usr/bin/flux pull --remote upstream --branch main --timeout 15\n\
+             ExecStart=/usr/bin/flux apply --yes --on-conflict skip\n"
+        ));
+        assert_eq!(units[1].name, SYNC_TIMER_NAME);
+        assert!(units[1].contents.contains("OnCalendar=daily\n"));
+
+        // Options override the config; without a configured policy keep-repo is used
+        let config = Config::default();
+        let options = ServiceOptions {
+            profile: Some("work".to_string()),
+            remote: Some("backup".to_string()),
+            config_file: Some(PathBuf::from("/home/me/my config.toml")),
+            ..options()
+        };
+        let service = &render_sync_units(Path::new("/usr/bin/flux"), &config, &options)[0];
+        assert!(service.contents.contains(
+            "ExecStart=/usr/bin/flux pull --remote backup --timeout 60\n\
+             ExecStart=/usr/bin/flux apply --yes --on-conflict keep-repo --profile work\n"
+        ));
+        assert!(
+            service
+                .contents
+                .contains("Environment=\"DOTFILES_CONFIG=/home/me/my config.toml\"\n")
+        );
+    }
+
+    /// Test that units are written into and removed from a unit directory
+    #[test]
+    fn test_write_and_uninstall_units() {
+        let dir = tempdir().unwrap();
+        let unit_dir = dir.path().join("systemd/user");
+        let units = render_sync_units(Path::new("/usr/bin/flux"), &Config::default(), &options());
+
+        let paths = write_units(&unit_dir, &units).unwrap();
+        assert_eq!(paths.len(), 2);
+        let states = service_status(&unit_dir, false);
+        assert!(states[0].path.is_some() && states[1].path.is_some());
+        assert!(states[2].path.is_none());
+        assert!(states.iter().all(|state| state.enabled.is_none()));
+
+        assert_eq!(uninstall_service(&unit_dir, false).unwrap().len(), 2);
+        assert!(!paths[0].exists() && !paths[1].exists());
+        assert!(uninstall_service(&unit_dir, false).unwrap().is_empty());
+    }
+
+    /// Test quoting of ExecStart arguments
+    #[test]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg("plain"), "plain");
+        assert_eq!(quote_arg("100%"), "100%%");
+        assert_eq!(quote_arg("with space"), "\"with space\"");
+        assert_eq!(quote_arg("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote_arg(""), "\"\"");
+        assert_eq!(
+            exec_line(&["/opt/my apps/flux", "apply"]),
+            "\"/opt/my apps/flux\" apply"
+        );
+    }
+}
+
+// ============================================================================
+// Hook Tests
+// ============================================================================
+
+#[cfg(test)]
+mod hooks_tests {
+    use crate::config::Config;
+    use crate::services::hooks::{
+        HookOutcome, HookPoint, PlannedHook, plan_hooks, run_hook, tools_for_paths,
+    };
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    fn config(repo_path: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "~/.dotfiles-backup"
+current_profile = "default"
+
+[environment.variables]
+GREETING = "hello"
+
+[environment.hooks]
+pre_apply = "echo global-pre"
+post_apply = "echo global-post"
+timeout = 30
+
+[tools.sway]
+files = [{{ repo = "config", dest = ".config/sway/config" }}]
+hooks = {{ post_apply = "swaymsg reload", timeout = 5 }}
+
+[tools.waybar]
+files = [{{ repo = "config", dest = ".config/waybar/config" }}]
+hooks = {{ pre_apply = "pkill waybar" }}
+
+[tools.zsh]
+files = [{{ repo = "zshrc", dest = ".zshrc" }}]
+"#,
+            repo_path
+        ))
+        .unwrap()
+    }
+
+    fn tools(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Test that hooks are planned only for changed tools, global pre hooks first and
+    /// global post hooks last, with tool timeouts overriding the global one
+    #[test]
+    fn test_plan_hooks() {
+        let config = config("~/.dotfiles");
+
+        let post = plan_hooks(&config, HookPoint::PostApply, &tools(&["sway", "zsh"]));
+        let commands: Vec<&str> = post.iter().map(|hook| hook.command.as_str()).collect();
+        assert_eq!(commands, vec!["swaymsg reload", "echo global-post"]);
+        assert_eq!(post[0].tool.as_deref(), Some("sway"));
+        assert_eq!(post[0].timeout, Duration::from_secs(5));
+        assert_eq!(post[1].tool, None);
+        assert_eq!(post[1].timeout, Duration::from_secs(30));
+
+        let pre = plan_hooks(&config, HookPoint::PreApply, &tools(&["waybar", "zsh"]));
+        let commands: Vec<&str> = pre.iter().map(|hook| hook.command.as_str()).collect();
+        assert_eq!(commands, vec!["echo global-pre", "pkill waybar"]);
+        assert_eq!(pre[1].timeout, Duration::from_secs(30));
+
+        // Nothing changed, nothing runs (not even global hooks)
+        assert!(plan_hooks(&config, HookPoint::PostApply, &tools(&[])).is_empty());
+        assert!(plan_hooks(&config, HookPoint::PostCommit, &tools(&["sway"])).is_empty());
+    }
+
+    /// Test running hooks: output and environment, exit codes, and timeouts
+    #[test]
+    fn test_run_hook() {
+        let dir = tempdir().unwrap();
+        let config = config(&dir.path().display().to_string());
+        let hook = |command: &str, timeout: u64| PlannedHook {
+            tool: Some("sway".to_string()),
+            point: HookPoint::PostApply,
+            command: command.to_string(),
+            timeout: Duration::from_secs(timeout),
+        };
+
+        let command = "echo $GREETING $FLUX_TOOL $FLUX_HOOK $FLUX_PROFILE; pwd";
+        let result = run_hook(&config, Some("work"), dir.path(), &hook(command, 10));
+        assert!(result.succeeded());
+        assert_eq!(
+            result.output.lines().next(),
+            Some("hello sway post_apply work")
+        );
+        assert!(result.output.contains(&dir.path().display().to_string()));
+
+        let command = "echo oops >&2; exit 3";
+        let result = run_hook(&config, None, dir.path(), &hook(command, 10));
+        assert_eq!(result.outcome, HookOutcome::Failed(Some(3)));
+        assert_eq!(result.output.trim(), "oops");
+
+        let result = run_hook(&config, None, dir.path(), &hook("sleep 5", 1));
+        assert_eq!(result.outcome, HookOutcome::TimedOut);
+        assert!(result.duration < Duration::from_secs(4));
+    }
+
+    /// Test that processes a hook leaves in the background neither hold up nor fail it,
+    /// and that a timeout also kills them
+    #[test]
+    fn test_run_hook_background() {
+        let dir = tempdir().unwrap();
+        let config = config(&dir.path().display().to_string());
+        let hook = |command: &str, timeout: u64| PlannedHook {
+            tool: Some("waybar".to_string()),
+            point: HookPoint::PostApply,
+            command: command.to_string(),
+            timeout: Duration::from_secs(timeout),
+        };
+
+        let start = Instant::now();
+        let command = "sleep 8 & echo started";
+        let result = run_hook(&config, None, dir.path(), &hook(command, 2));
+        assert!(result.succeeded());
+        assert_eq!(result.output, "started");
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(result.duration <= start.elapsed());
+
+        let start = Instant::now();
+        let command = "sleep 8 & sleep 8";
+        let result = run_hook(&config, None, dir.path(), &hook(command, 1));
+        assert_eq!(result.outcome, HookOutcome::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(result.duration >= Duration::from_secs(1));
+    }
+
+    /// Test mapping changed repository paths to the tools that own them
+    #[test]
+    fn test_tools_for_paths() {
+        let dir = tempdir().unwrap();
+        let config = config(&dir.path().display().to_string());
+
+        let paths = vec![
+            PathBuf::from("sway/config"),
+            dir.path().join("zsh/zshrc"),
+            PathBuf::from("waybar/unused.css"),
+            PathBuf::from("README.md"),
+            PathBuf::from("unknown/file"),
+        ];
+        assert_eq!(
+            tools_for_paths(&config, None, &paths).unwrap(),
+            tools(&["sway", "waybar", "zsh"])
+        );
+        assert!(tools_for_paths(&config, None, &[]).unwrap().is_empty());
+    }
+}
+
+// ============================================================================
+// Clone Tests
+// ============================================================================
+
+#[cfg(test)]
+mod clone_tests {
+    use crate::commands::clone::{CloneOptions, ConfigLocation, clone_dotfiles, select_profile};
+    use crate::config::Config;
+    use crate::utils::error::DotfilesError;
+    use crate::utils::host;
+    use git2::{Repository, Signature};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const CONFIG: &str = r#"# shared dotfiles
+[general]
+repo_path = "~/.dotfiles"
+backup_dir = "~/.dotfiles-backup"
+current_profile = "default"
+include = ["tools/local.toml"]
+
+[tools.sway]
+files = [{ repo = "config", dest = ".config/sway/config" }]
+"#;
+
+    /// Create a bare repository holding a flux config, a tracked file and a `work` profile.
+    fn bare_remote(root: &Path) -> String {
+        let work = root.join("work");
+        let repo = Repository::init(&work).unwrap();
+        for (path, contents) in [
+            ("config.toml", CONFIG),
+            ("sway/config", "bindsym $mod+Return exec foot\n"),
+            ("profiles/work/.keep", ""),
+        ] {
+            let path = work.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "init", &tree, &[])
+            .unwrap();
+
+        let bare = root.join("remote.git");
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(work.to_str().unwrap(), &bare)
+            .unwrap();
+        bare.display().to_string()
+    }
+
+    fn options_for(root: &Path, url: &str, dest: &str) -> CloneOptions {
+        CloneOptions {
+            url: url.to_string(),
+            dest: root.join(dest),
+            branch: None,
+            profile: Some("work".to_string()),
+            timeout: 30,
+            xdg_config: root.join("xdg/flux/config.toml"),
+            interactive: false,
+        }
+    }
+
+    /// Test cloning a bare repository and writing this machine's settings to a new XDG config
+    #[test]
+    fn test_clone_writes_machine_config() {
+        let dir = tempdir().unwrap();
+        let url = bare_remote(dir.path());
+        let options = options_for(dir.path(), &url, "dotfiles");
+
+        let cloned = clone_dotfiles(&options).unwrap();
+        assert_eq!(cloned.profile, "work");
+        assert_eq!(cloned.config.get_repo_path().unwrap(), options.dest);
+        assert_eq!(cloned.config.general.current_profile, "work");
+        assert!(cloned.config.tools.contains_key("sway"));
+        assert!(options.dest.join("sway/config").exists());
+        assert_eq!(
+            cloned.location,
+            ConfigLocation::Written(options.xdg_config.clone())
+        );
+
+        // The XDG config keeps the repository's comments, and includes still resolve
+        let written = fs::read_to_string(&options.xdg_config).unwrap();
+        assert!(written.starts_with("# shared dotfiles"));
+        assert!(written.contains(&format!("repo_path = \"{}\"", options.dest.display())));
+        assert!(written.contains("current_profile = \"work\""));
+        assert!(written.contains(&options.dest.join("tools/local.toml").display().to_string()));
+        let reloaded = Config::load_file(&options.xdg_config).unwrap();
+        assert_eq!(reloaded.get_repo_path().unwrap(), options.dest);
+    }
+
+    /// Test that an existing XDG config and a non-empty destination are left untouched
+    #[test]
+    fn test_clone_does_not_clobber() {
+        let dir = tempdir().unwrap();
+        let url = bare_remote(dir.path());
+        let options = options_for(dir.path(), &url, "dotfiles");
+        fs::create_dir_all(options.xdg_config.parent().unwrap()).unwrap();
+        fs::write(&options.xdg_config, "# mine\n").unwrap();
+
+        let cloned = clone_dotfiles(&options).unwrap();
+        assert_eq!(
+            cloned.location,
+            ConfigLocation::Shadowed(options.xdg_config.clone())
+        );
+        assert_eq!(fs::read_to_string(&options.xdg_config).unwrap(), "# mine\n");
+
+        // Cloning again into the same directory is refused before touching it
+        let result = clone_dotfiles(&options);
+        assert!(matches!(result, Err(DotfilesError::Config(msg)) if msg.contains("not empty")));
+        assert!(options.dest.join("sway/config").exists());
+
+        // A URL that cannot be cloned is reported
+        let missing = dir.path().join("missing.git").display().to_string();
+        let result = clone_dotfiles(&options_for(dir.path(), &missing, "other"));
+        assert!(matches!(result, Err(DotfilesError::Config(msg)) if msg.contains("Cannot clone")));
+    }
+
+    /// Test profile selection: explicit, named after the host, then the current profile
+    #[test]
+    fn test_select_profile() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.general.repo_path = dir.path().display().to_string();
+        fs::create_dir_all(dir.path().join("profiles/work")).unwrap();
+
+        assert_eq!(
+            select_profile(&config, Some("work"), false).unwrap(),
+            "work"
+        );
+        assert!(matches!(
+            select_profile(&config, Some("gaming"), false),
+            Err(DotfilesError::ProfileNotFound(_))
+        ));
+        assert_eq!(select_profile(&config, None, false).unwrap(), "default");
+
+        let hostname = host::hostname();
+        let short_hostname = hostname.split('.').next().unwrap_or_default();
+        if !short_hostname.is_empty() {
+            fs::create_dir_all(dir.path().join("profiles").join(short_hostname)).unwrap();
+            assert_eq!(
+                select_profile(&config, None, false).unwrap(),
+                short_hostname
+            );
+        }
+    }
+}
+
+// ============================================================================
+// Browser Tests
+// ============================================================================
+
+#[cfg(test)]
+mod browser_tests {
+    use crate::config::Config;
+    use crate::services::browser::{find_profile, read_profiles, resolve_dest};
+    use crate::types::{EntryType, LinkMode, SymlinkResolution};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const PROFILES_INI: &str = "[General]\nStartWithLastProfile=1\n\n\
+        [Profile1]\nName=work\nIsRelative=1\nPath=abcd1234.work\n\n\
+        [Profile0]\nName=default\nIsRelative=1\nPath=efgh5678.default\nDefault=1\n";
+
+    /// Create a Firefox installation with two profiles under `home`.
+    fn firefox_root(home: &Path) -> std::path::PathBuf {
+        let root = home.join(".mozilla/firefox");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("profiles.ini"), PROFILES_INI).unwrap();
+        root
+    }
+
+    /// Test that installs.ini decides the default profile over the Default=1 flag
+    #[test]
+    fn test_read_profiles() {
+        let dir = tempdir().unwrap();
+        let root = firefox_root(dir.path());
+
+        let profiles = read_profiles(&root).unwrap();
+        assert_eq!(profiles.len(), 2);
+        let default = find_profile(&profiles, None).unwrap();
+        assert_eq!(default.name, "default");
+        assert_eq!(default.path, root.join("efgh5678.default"));
+
+        fs::write(
+            root.join("installs.ini"),
+            "[4F96D1932A9F858E]\nDefault=abcd1234.work\nLocked=1\n",
+        )
+        .unwrap();
+        let profiles = read_profiles(&root).unwrap();
+        assert_eq!(
+            find_profile(&profiles, Some("default")).unwrap().name,
+            "work"
+        );
+        assert_eq!(
+            find_profile(&profiles, Some("default")),
+            find_profile(&profiles, Some("work"))
+        );
+        assert!(find_profile(&profiles, Some("missing")).is_none());
+    }
+
+    /// Test resolving plain and symbolic destinations
+    #[test]
+    fn test_resolve_dest() {
+        let dir = tempdir().unwrap();
+        let home = dir.path();
+        assert_eq!(
+            resolve_dest(home, ".config/foot/foot.ini").unwrap(),
+            home.join(".config/foot/foot.ini")
+        );
+
+        // Without profiles.ini the browser is not installed on this machine
+        let missing = resolve_dest(home, "{firefox.default}/prefs.js").unwrap_err();
+        assert!(missing.contains("not installed"));
+
+        let root = firefox_root(home);
+        assert_eq!(
+            resolve_dest(home, "{firefox.default}/prefs.js").unwrap(),
+            root.join("efgh5678.default/prefs.js")
+        );
+        assert_eq!(
+            resolve_dest(home, "{firefox.work}/chrome/userChrome.css").unwrap(),
+            root.join("abcd1234.work/chrome/userChrome.css")
+        );
+        assert!(
+            resolve_dest(home, "{firefox.gaming}/prefs.js")
+                .unwrap_err()
+                .contains("no profile 'gaming'")
+        );
+        assert!(
+            resolve_dest(home, "{netscape.default}/prefs.js")
+                .unwrap_err()
+                .contains("unknown browser")
+        );
+    }
+
+    /// Test that saving a config keeps per-entry resolution, type and globs
+    #[test]
+    fn test_tool_table_keeps_entry_options() {
+        let toml = r#"
+[general]
+repo_path = "~/.dotfiles"
+backup_dir = "~/.dotfiles-backup"
+current_profile = "default"
+
+[tools.firefox]
+files = [
+    { repo = "firefox/prefs.js", dest = "{firefox.default}/prefs.js", resolution = "replace" },
+    { repo = "firefox/extensions", dest = "{firefox.default}/extensions", type = "dir", ignore = ["*.tmp"], link = "contents" },
+]
+"#;
+        let config: Config = toml::from_str(toml).unwrap();
+        let saved = config.to_document().unwrap().to_string();
+        let reloaded: Config = toml::from_str(&saved).unwrap();
+
+        let files = &reloaded.tools["firefox"].files;
+        assert_eq!(files[0].dest, "{firefox.default}/prefs.js");
+        assert_eq!(files[0].resolution, Some(SymlinkResolution::Replace));
+        assert_eq!(files[1].entry_type, EntryType::Dir);
+        assert_eq!(files[1].ignore, vec!["*.tmp".to_string()]);
+        assert_eq!(files[1].link, LinkMode::Contents);
+    }
+}
+
+// ============================================================================
+// SQLite Snapshot Tests
+// ============================================================================
+
+#[cfg(test)]
+mod sqlite_tests {
+    use crate::config::Config;
+    use crate::services::sqlite::{
+        is_database, owner_running, side_file, snapshot, snapshot_differs,
+    };
+    use crate::types::SymlinkResolution;
+    use rusqlite::Connection;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    /// Open a WAL database with a table of bookmarks, keeping commits out of the main file.
+    fn open_places(path: &Path) -> Connection {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .unwrap();
+        connection
+            .pragma_update(None, "wal_autocheckpoint", 0)
+            .unwrap();
+        connection
+            .execute("CREATE TABLE bookmarks (url TEXT)", [])
+            .unwrap();
+        connection
+    }
+
+    fn count(path: &Path) -> i64 {
+        Connection::open(path)
+            .unwrap()
+            .query_row("SELECT count(*) FROM bookmarks", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// Test that a snapshot includes commits still in the WAL of an open database
+    #[test]
+    fn test_snapshot_open_database() {
+        let dir = tempdir().unwrap();
+        let live = dir.path().join("places.sqlite");
+        let repo = dir.path().join("repo.sqlite");
+        let connection = open_places(&live);
+        connection
+            .execute("INSERT INTO bookmarks VALUES ('https://example.org')", [])
+            .unwrap();
+        assert!(side_file(&live, "-wal").exists());
+
+        snapshot(&live, &repo).unwrap();
+        assert!(is_database(&repo));
+        assert_eq!(count(&repo), 1);
+        assert!(!side_file(&repo, "-wal").exists());
+        let journal_mode: String = Connection::open(&repo)
+            .unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(journal_mode, "delete");
+
+        // Snapshots of unchanged data are identical, so they only differ after a write
+        assert!(!snapshot_differs(&repo, &live).unwrap());
+        connection
+            .execute("INSERT INTO bookmarks VALUES ('https://example.com')", [])
+            .unwrap();
+        assert!(snapshot_differs(&repo, &live).unwrap());
+
+        // A restored copy of the snapshot matches it
+        let restored = dir.path().join("restored.sqlite");
+        fs::copy(&repo, &restored).unwrap();
+        assert!(!snapshot_differs(&repo, &restored).unwrap());
+
+        let text = dir.path().join("prefs.js");
+        fs::write(&text, "user_pref(\"a\", 1);\n").unwrap();
+        assert!(!is_database(&text));
+        assert!(snapshot(&text, &dir.path().join("out.sqlite")).is_err());
+    }
+
+    /// Test that a database held with an exclusive lock is snapshotted from a copy
+    #[test]
+    fn test_snapshot_exclusive_lock() {
+        let dir = tempdir().unwrap();
+        let live = dir.path().join("places.sqlite");
+        let connection = Connection::open(&live).unwrap();
+        connection
+            .pragma_update(None, "locking_mode", "EXCLUSIVE")
+            .unwrap();
+        connection
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .unwrap();
+        connection
+            .pragma_update(None, "wal_autocheckpoint", 0)
+            .unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE bookmarks (url TEXT);
+                 INSERT INTO bookmarks VALUES ('https://example.org');
+                 INSERT INTO bookmarks VALUES ('https://example.com');",
+            )
+            .unwrap();
+
+        let repo = dir.path().join("repo.sqlite");
+        snapshot(&live, &repo).unwrap();
+        drop(connection);
+        assert_eq!(count(&repo), 2);
+    }
+
+    /// Test that sqlite entries are copied and owned by their tool unless `app` is set
+    #[test]
+    fn test_sqlite_entries() {
+        let dir = tempdir().unwrap();
+        let toml = format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "~/.dotfiles-backup"
+current_profile = "default"
+
+[tools.firefox]
+files = [
+    {{ repo = "places.sqlite", dest = ".flux-test/places.sqlite", kind = "sqlite" }},
+    {{ repo = "cookies.sqlite", dest = ".flux-test/cookies.sqlite", kind = "sqlite", app = "flux-test-app" }},
+    {{ repo = "prefs.js", dest = ".flux-test/prefs.js" }},
+]
+"#,
+            dir.path().display()
+        );
+        let config: Config = toml::from_str(&toml).unwrap();
+        let saved = config.to_document().unwrap().to_string();
+        assert!(saved.contains("kind = \"sqlite\", app = \"flux-test-app\""));
+
+        let files = config.get_tracked_files(None).unwrap();
+        let file = |name: &str| {
+            files
+                .iter()
+                .find(|file| file.repo_path.ends_with(name))
+                .unwrap()
+        };
+        assert_eq!(
+            file("places.sqlite").sqlite_owner.as_deref(),
+            Some("firefox")
+        );
+        assert_eq!(file("places.sqlite").resolution, SymlinkResolution::Replace);
+        assert_eq!(
+            file("cookies.sqlite").sqlite_owner.as_deref(),
+            Some("flux-test-app")
+        );
+        assert!(file("places.sqlite").is_sqlite() && !file("prefs.js").is_sqlite());
+
+        // The application is found by its executable name
+        let cookies = file("cookies.sqlite");
+        assert!(owner_running(cookies).is_none());
+        let app = dir.path().join("flux-test-app");
+        std::os::unix::fs::symlink("/bin/sleep", &app).unwrap();
+        let mut child = Command::new(&app).arg("30").spawn().unwrap();
+        // The process only carries the app's name once it has executed
+        let mut running = None;
+        for _ in 0..100 {
+            running = owner_running(cookies);
+            if running.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(running.unwrap().starts_with("flux-test-app is running"));
+    }
+}
+// ============================================================================
+// Discover Tests
+// ============================================================================
+
+#[cfg(test)]
+mod discover_tests {
+    use crate::commands::discover::find_untracked_apps;
+    use crate::config::Config;
+    use crate::services::catalog::Catalog;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    /// Test that the built-in catalog parses and covers the apps of the example config
+    #[test]
+    fn test_builtin_catalog() {
+        let catalog = Catalog::builtin().unwrap();
+        for name in [
+            "sway",
+            "waybar",
+            "cursor",
+            "vscode",
+            "mako",
+            "rofi",
+            "alacritty",
+            "starship",
+        ] {
+            let app = &catalog.apps[name];
+            assert!(!app.paths.is_empty(), "{} has no paths", name);
+            assert!(!app.description.is_empty(), "{} has no description", name);
+        }
+        assert_eq!(
+            catalog.apps["fish"].ignore,
+            vec!["fish_variables".to_string()]
+        );
+    }
+
+    /// Test that a user catalog adds apps and replaces built-in ones of the same name
+    #[test]
+    fn test_user_catalog_extends_builtin() {
+        let mut catalog = Catalog::builtin().unwrap();
+        let user = Catalog::parse(
+            r#"
+[apps.myapp]
+description = "My app"
+paths = ["~/.myapprc"]
+
+[apps.sway]
+paths = ["~/.sway/config"]
+"#,
+            Path::new("catalog.toml"),
+        )
+        .unwrap();
+        catalog.extend(user);
+
+        assert_eq!(catalog.apps["myapp"].paths, vec!["~/.myapprc".to_string()]);
+        assert_eq!(
+            catalog.apps["sway"].paths,
+            vec!["~/.sway/config".to_string()]
+        );
+        assert!(catalog.apps["sway"].description.is_empty());
+        assert!(catalog.apps.contains_key("waybar"));
+
+        let invalid = Catalog::parse("[apps.broken]\ndescription = 1\n", Path::new("bad.toml"));
+        assert!(invalid.unwrap_err().to_string().contains("bad.toml"));
+    }
+
+    /// Test that discovery lists only existing paths no entry covers
+    #[test]
+    fn test_find_untracked_apps() {
+        let dir = tempdir().unwrap();
+        let home = dir.path();
+        for file in [
+            ".editorrc",
+            ".config/editor/init.lua",
+            ".shellrc",
+            ".toolrc",
+            ".other/.editorrc",
+        ] {
+            let path = home.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        let catalog = Catalog::parse(
+            r#"
+[apps.editor]
+paths = ["~/.editorrc", "$HOME/.config/editor", "~/.missingrc", "~/.other/.editorrc"]
+ignore = ["*.log"]
+
+[apps.shell]
+paths = ["~/.shellrc"]
+
+[apps.tool]
+paths = ["~/.toolrc"]
+
+[apps.absent]
+paths = ["~/.absentrc"]
+"#,
+            Path::new("catalog.toml"),
+        )
+        .unwrap();
+        let config: Config = toml::from_str(
+            r#"
+[general]
+repo_path = "~/.dotfiles"
+backup_dir = "~/.dotfiles-backup"
+current_profile = "default"
+
+[tools.sh]
+files = [{ repo = "shellrc", dest = ".shellrc", profile = "work" }]
+"#,
+        )
+        .unwrap();
+
+        let found = find_untracked_apps(&config, &catalog, home);
+        let names: Vec<&str> = found.iter().map(|app| app.name.as_str()).collect();
+        // Tracked under another tool name and in another profile still counts as tracked
+        assert_eq!(names, vec!["editor", "tool"]);
+        // ~/.other/.editorrc would be stored as the same repository file as ~/.editorrc
+        assert_eq!(
+            found[0].paths,
+            vec![home.join(".editorrc"), home.join(".config/editor")]
+        );
+        assert_eq!(found[0].ignore, vec!["*.log".to_string()]);
+
+        // A tracked file inside the directory makes the directory tracked too
+        let mut config = config;
+        config
+            .add_file_to_tool(
+                "editor",
+                "editor/init.lua",
+                Path::new(".config/editor/init.lua"),
+                None,
+            )
+            .unwrap();
+        let found = find_untracked_apps(&config, &catalog, home);
+        assert_eq!(found[0].paths, vec![home.join(".editorrc")]);
+    }
+}
+// ============================================================================
+// Import Tests
+// ============================================================================
+
+#[cfg(test)]
+mod import_tests {
+    use crate::commands::import::{PlannedEntry, Relink, copy_into_repo, plan_import};
+    use crate::config::Config;
+    use crate::file_manager::FileSystemManager;
+    use crate::services::importers::{
+        ChezmoiAttributes, ImportItem, ImportScan, ImportSource, decode_chezmoi_name,
+        decode_yadm_alt, scan, tool_for_dest,
+    };
+    use crate::utils::dry_run::DryRun;
+    use std::fs;
+    use std::os::unix::fs::{PermissionsExt, symlink};
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    /// Test decoding chezmoi source state names
+    #[test]
+    fn test_decode_chezmoi_name() {
+        assert_eq!(
+            decode_chezmoi_name("private_dot_config", true).unwrap().0,
+            ".config"
+        );
+        assert_eq!(decode_chezmoi_name("exact_fish", true).unwrap().0, "fish");
+        assert_eq!(
+            decode_chezmoi_name("private_executable_dot_profile", false).unwrap(),
+            (
+                ".profile".to_string(),
+                ChezmoiAttributes {
+                    private: true,
+                    executable: true,
+                    readonly: false
+                }
+            )
+        );
+        assert_eq!(
+            decode_chezmoi_name("literal_dot_x", false).unwrap().0,
+            "dot_x"
+        );
+        assert_eq!(
+            decode_chezmoi_name("notes.txt.literal", false).unwrap().0,
+            "notes.txt"
+        );
+        assert!(
+            decode_chezmoi_name("dot_gitconfig.tmpl", false)
+                .unwrap_err()
+                .contains("template")
+        );
+        assert!(decode_chezmoi_name("run_once_install.sh", false).is_err());
+        assert!(decode_chezmoi_name("private_encrypted_dot_netrc", false).is_err());
+        assert!(decode_chezmoi_name("symlink_dot_vimrc", false).is_err());
+    }
+
+    /// Test decoding yadm alternates and naming tools after destinations
+    #[test]
+    fn test_decode_yadm_alt() {
+        assert_eq!(decode_yadm_alt(Path::new(".bashrc")).unwrap(), None);
+
+        let item = decode_yadm_alt(Path::new(".config/foo/conf##os.Darwin,hostname.box"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.dest, PathBuf::from(".config/foo/conf"));
+        assert_eq!(item.tool, "foo");
+        assert_eq!(item.os.as_deref(), Some("macos"));
+        assert_eq!(item.hosts, Some(vec!["box".to_string()]));
+        assert_eq!(item.variant.as_deref(), Some("macos.box"));
+
+        let item = decode_yadm_alt(Path::new(".gitconfig##c.work"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.profile.as_deref(), Some("work"));
+        let item = decode_yadm_alt(Path::new(".gitconfig##default"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.variant.as_deref(), Some("default"));
+        assert!(decode_yadm_alt(Path::new(".tmux.conf##template")).is_err());
+        assert!(decode_yadm_alt(Path::new(".tmux.conf##user.me")).is_err());
+
+        assert_eq!(
+            tool_for_dest(Path::new(".config/starship.toml")),
+            "starship"
+        );
+        assert_eq!(
+            tool_for_dest(Path::new(".local/share/fonts/a.ttf")),
+            "fonts"
+        );
+        assert_eq!(tool_for_dest(Path::new(".bashrc")), "bashrc");
+    }
+
+    /// Test that Stow packages map to their destinations, folded directories included
+    #[test]
+    fn test_scan_stow() {
+        let dir = tempdir().unwrap();
+        let home = dir.path().join("home");
+        let stow = home.join("dotfiles");
+        for file in [
+            "sway/.config/sway/config",
+            "nvim/.config/nvim/init.lua",
+            "nvim/README.md",
+            "bash/dot-bashrc",
+        ] {
+            let path = stow.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "x").unwrap();
+        }
+        fs::create_dir_all(home.join(".config")).unwrap();
+        symlink(stow.join("nvim/.config/nvim"), home.join(".config/nvim")).unwrap();
+
+        let found = scan(ImportSource::Stow, &stow, &home).unwrap();
+        let mapped: Vec<(&str, &Path)> = found
+            .items
+            .iter()
+            .map(|item| (item.tool.as_str(), item.dest.as_path()))
+            .collect();
+        assert_eq!(
+            mapped,
+            vec![
+                ("bash", Path::new(".bashrc")),
+                ("nvim", Path::new(".config/nvim")),
+                ("sway", Path::new(".config/sway/config")),
+            ]
+        );
+        assert_eq!(found.items[1].source, stow.join("nvim/.config/nvim"));
+    }
+
+    /// Test that chezmoi's private, executable and readonly attributes become the modes
+    /// of the repository copies
+    #[test]
+    fn test_scan_chezmoi_modes() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("chezmoi");
+        for file in [
+            "private_dot_ssh/config",
+            "private_dot_ssh/executable_askpass",
+            "executable_dot_script",
+            "readonly_dot_pinned",
+            "dot_bashrc",
+        ] {
+            let path = source.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "x").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+
+        let found = scan(ImportSource::Chezmoi, &source, dir.path()).unwrap();
+        let modes: Vec<(&Path, Option<u32>)> = found
+            .items
+            .iter()
+            .map(|item| (item.dest.as_path(), item.mode))
+            .collect();
+        assert_eq!(
+            modes,
+            vec![
+                (Path::new(".bashrc"), Some(0o644)),
+                (Path::new(".script"), Some(0o755)),
+                (Path::new(".ssh/config"), Some(0o600)),
+                (Path::new(".ssh/askpass"), Some(0o700)),
+                (Path::new(".pinned"), Some(0o444)),
+            ]
+        );
+
+        let repo = dir.path().join("repo");
+        let entries: Vec<PlannedEntry> = found
+            .items
+            .into_iter()
+            .map(|item| PlannedEntry {
+                repo: item.dest.display().to_string(),
+                item,
+            })
+            .collect();
+        let mut dry_run = DryRun::default();
+        let mut fs_manager = FileSystemManager::new(&mut dry_run, false);
+        copy_into_repo(&entries, &repo, &mut fs_manager).unwrap();
+        for (path, mode) in [
+            (".bashrc", 0o644),
+            (".pinned", 0o444),
+            (".script", 0o755),
+            (".ssh/config", 0o600),
+            (".ssh/askpass", 0o700),
+        ] {
+            let metadata = fs::metadata(repo.join(path)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, mode, "{}", path);
+        }
+    }
+    /// Test repository names, skipped destinations and what happens to each destination
+    #[test]
+    fn test_plan_import() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let dest = dir.path().join("dest");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(dest.join("b")).unwrap();
+        for name in ["a", "b", "c", "d", "e"] {
+            fs::write(source.join(name), name).unwrap();
+        }
+        // a: link into the old setup, b: identical copy, c: changed copy, d: missing
+        symlink(source.join("a"), dest.join("a")).unwrap();
+        fs::write(dest.join("b/config"), "b").unwrap();
+        fs::write(dest.join("c"), "changed").unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "{}"
+current_profile = "default"
+
+[tools.app]
+files = [{{ repo = "app/config", dest = "{}" }}]
+"#,
+            dir.path().join("repo").display(),
+            dir.path().join("backups").display(),
+            dest.join("e").display()
+        ))
+        .unwrap();
+
+        let item = |name: &str, dest: PathBuf| ImportItem {
+            tool: "app".to_string(),
+            source: source.join(name),
+            dest,
+            ..Default::default()
+        };
+        let found = ImportScan {
+            items: vec![
+                item("a", dest.join("a")),
+                item("b", dest.join("b/config")),
+                item("c", dest.join("c")),
+                item("d", dest.join("d")),
+                item("e", dest.join("e")),
+            ],
+            skipped: Vec::new(),
+        };
+        let plan = plan_import(&config, found, None).unwrap();
+
+        // e is tracked already, and b's file name is taken by the existing entry
+        assert_eq!(plan.entries.len(), 4);
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(plan.entries[0].repo, "app/a");
+        assert_eq!(
+            plan.entries[1].repo,
+            format!("app/{}", dest.join("b/config").display())
+        );
+        let relinks: Vec<Relink> = plan.links.iter().map(|(_, relink)| *relink).collect();
+        assert_eq!(
+            relinks,
+            vec![
+                Relink::Repoint,
+                Relink::ReplaceCopy,
+                Relink::Keep,
+                Relink::Create
+            ]
+        );
+        assert_eq!(plan.config.tools["app"].files.len(), 5);
+    }
+}
+// ============================================================================
+// Export Tests
+// ============================================================================
+
+#[cfg(test)]
+mod export_tests {
+    use crate::commands::export::{
+        Export, Manifest, Placement, collect_export, render_installer, write_tarball,
+    };
+    use crate::config::Config;
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    /// Repository with one file per placement, a template, a secret and a directory.
+    /// Destinations are under `.config/flux-export-test` and are never written.
+    fn export_fixture(repo: &Path) -> Export {
+        for (path, content) in [
+            ("app/auto", "auto"),
+            ("app/absolute", "absolute"),
+            ("app/replace", "replace"),
+            ("app/template", "profile={{ profile }}"),
+            ("app/secret.age", "secret"),
+            ("app/dir/keep", "keep"),
+            ("app/dir/cache/drop", "drop"),
+        ] {
+            fs::create_dir_all(repo.join(path).parent().unwrap()).unwrap();
+            fs::write(repo.join(path), content).unwrap();
+        }
+        let config: Config = toml::from_str(&format!(
+            r#"
+[general]
+repo_path = "{}"
+backup_dir = "{}"
+current_profile = "default"
+
+[tools.app]
+files = [
+    {{ repo = "auto", dest = ".config/flux-export-test/auto" }},
+    {{ repo = "absolute", dest = ".config/flux-export-test/absolute", resolution = "absolute" }},
+    {{ repo = "replace", dest = ".config/flux-export-test/replace", resolution = "replace" }},
+    {{ repo = "template", dest = ".config/flux-export-test/template", template = true }},
+    {{ repo = "secret.age", dest = ".config/flux-export-test/secret", encrypted = true }},
+    {{ repo = "dir", dest = ".config/flux-export-test/dir", type = "dir", ignore = ["cache/**"] }},
+    {{ repo = "missing", dest = ".config/flux-export-test/missing" }},
+]
+"#,
+            repo.display(),
+            repo.with_file_name("backups").display()
+        ))
+        .unwrap();
+        collect_export(&config, None).unwrap()
+    }
+
+    /// Test that each entry gets the placement its resolution asks for
+    #[test]
+    fn test_collect_export() {
+        let dir = tempdir().unwrap();
+        let export = export_fixture(&dir.path().join("repo"));
+
+        let placements: Vec<(&str, Placement, Option<&str>)> = export
+            .manifest
+            .files
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.placement, entry.link.as_deref()))
+            .collect();
+        assert_eq!(
+            placements,
+            vec![
+                (
+                    "app/auto",
+                    Placement::Relative,
+                    Some("../../.dotfiles/app/auto")
+                ),
+                ("app/absolute", Placement::Absolute, None),
+                ("app/replace", Placement::Copy, None),
+                ("app/template", Placement::Copy, None),
+                (
+                    "app/dir",
+                    Placement::Relative,
+                    Some("../../.dotfiles/app/dir")
+                ),
+            ]
+        );
+        assert_eq!(
+            export.manifest.files[0].dest,
+            ".config/flux-export-test/auto"
+        );
+        assert!(export.manifest.files[4].dir);
+
+        // The secret stays out, and so does the entry missing from the repository
+        assert_eq!(export.skipped.len(), 2);
+        assert!(export.skipped[0].1.contains("encrypted"));
+    }
+
+    /// Test that the tarball holds the manifest, rendered templates and filtered directories
+    #[test]
+    fn test_export_tarball() {
+        let dir = tempdir().unwrap();
+        let export = export_fixture(&dir.path().join("repo"));
+        let bytes = write_tarball(&export, Vec::new()).unwrap();
+
+        let mut archive = tar::Archive::new(GzDecoder::new(bytes.as_slice()));
+        let mut entries = Vec::new();
+        let mut manifest = String::new();
+        let mut template = String::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().into_owned();
+            if path == Path::new("flux-export/manifest.toml") {
+                entry.read_to_string(&mut manifest).unwrap();
+            } else if path == Path::new("flux-export/files/app/template") {
+                entry.read_to_string(&mut template).unwrap();
+            }
+            entries.push(path);
+        }
+
+        assert!(entries.contains(&PathBuf::from("flux-export/files/app/dir/keep")));
+        assert!(!entries.iter().any(|path| path.ends_with("drop")));
+        assert!(!entries.iter().any(|path| path.ends_with("secret.age")));
+        assert_eq!(template, "profile=default");
+        let manifest: Manifest = toml::from_str(&manifest).unwrap();
+        assert_eq!(manifest, export.manifest);
+    }
+
+    /// Test that the installer links and copies files, backs up conflicts and is idempotent
+    #[test]
+    fn test_export_installer() {
+        let dir = tempdir().unwrap();
+        let export = export_fixture(&dir.path().join("repo"));
+        let home = dir.path().join("home");
+        let target = home.join(".config/flux-export-test");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("auto"), "old").unwrap();
+        let installer = dir.path().join("install.sh");
+        fs::write(&installer, render_installer(&export).unwrap()).unwrap();
+
+        let install = |args: &[&str]| {
+            let output = Command::new("sh")
+                .arg(&installer)
+                .args(args)
+                .env("HOME", &home)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        install(&["--dry-run"]);
+        assert_eq!(fs::read_to_string(target.join("auto")).unwrap(), "old");
+        assert!(!home.join(".dotfiles").exists());
+
+        install(&[]);
+        assert_eq!(
+            fs::read_link(target.join("auto")).unwrap(),
+            Path::new("../../.dotfiles/app/auto")
+        );
+        assert_eq!(fs::read_to_string(target.join("auto")).unwrap(), "auto");
+        assert_eq!(
+            fs::read_link(target.join("absolute")).unwrap(),
+            home.join(".dotfiles/app/absolute")
+        );
+        assert!(!target.join("replace").is_symlink());
+        assert_eq!(
+            fs::read_to_string(target.join("template")).unwrap(),
+            "profile=default"
+        );
+        assert_eq!(fs::read_to_string(target.join("dir/keep")).unwrap(), "keep");
+        assert!(!target.join("dir/cache").exists());
+
+        let backups: Vec<_> = fs::read_dir(home.join(".dotfiles-backup"))
+            .unwrap()
+            .collect();
+        assert_eq!(backups.len(), 1);
+        let backup = backups[0].as_ref().unwrap().path();
+        assert_eq!(
+            fs::read_to_string(backup.join(".config/flux-export-test/auto")).unwrap(),
+            "old"
+        );
+
+        let output = install(&[]);
+        assert!(!output.contains("backing up"), "{}", output);
+        assert!(!output.contains("linking"), "{}", output);
+    }
+}
@@ -1,6 +1,13 @@
+use crate::services::sqlite::snapshot_differs;
+use crate::utils::error::Result;
+use crate::utils::path_utils::{
+    DirFilter, copy_differs, folded_ancestor, follow_symlink_chain, is_foldable_dir,
+    symlink_points_to_correct_target,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 /// A tracked file entry in the configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +19,117 @@ pub struct FileEntry {
     /// Optional profile name for this file
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile: Option<String>,
+    /// Hostname glob patterns this file applies to (e.g. "laptop", "desktop-*")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hosts: Option<Vec<String>>,
+    /// Operating system this file applies to (e.g. "linux", "macos")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// Render the repo file as a template instead of symlinking it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub template: bool,
+    /// Store the repo file encrypted and decrypt it to the destination
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// How to place this file, overriding `general.symlink_resolution` ("replace" copies it)
+    #[serde(default, alias = "mode", skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<SymlinkResolution>,
+    /// Whether this entry is a single file or a whole directory
+    #[serde(rename = "type", default, skip_serializing_if = "EntryType::is_file")]
+    pub entry_type: EntryType,
+    /// Globs of paths inside a directory entry to leave out (e.g. "*.log", "Cache/**")
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// Globs of the only paths inside a directory entry to track
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Whether a directory entry is linked as a whole or file by file
+    #[serde(default, skip_serializing_if = "LinkMode::is_dir")]
+    pub link: LinkMode,
+    /// How the file's contents are captured ("sqlite" stores a snapshot of a database)
+    #[serde(default, skip_serializing_if = "FileKind::is_plain")]
+    pub kind: FileKind,
+    /// Process name of the application owning a `kind = "sqlite"` database
+    /// (default: the tool name)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+}
+
+/// Kind of filesystem entry a file entry tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    /// A single file
+    #[default]
+    File,
+    /// A directory, compared file by file using its `include` and `ignore` globs
+    Dir,
+}
+
+impl EntryType {
+    pub fn is_file(&self) -> bool {
+        *self == EntryType::File
+    }
+}
+
+/// How the contents of a file entry are captured and restored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    /// Copied into the repository as it is
+    #[default]
+    Plain,
+    /// A SQLite database, stored as a consistent snapshot and written back only while
+    /// the application owning it is not running
+    Sqlite,
+}
+
+impl FileKind {
+    pub fn is_plain(&self) -> bool {
+        *self == FileKind::Plain
+    }
+}
+
+/// How a directory entry is placed at its destination.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Link the directory itself
+    #[default]
+    Dir,
+    /// Create real directories and link each file inside them (like GNU Stow),
+    /// so files an app writes next to its config stay out of the repository.
+    /// Like Stow, a subdirectory holding nothing else is folded into a single link.
+    Contents,
+}
+
+impl LinkMode {
+    pub fn is_dir(&self) -> bool {
+        *self == LinkMode::Dir
+    }
+}
+
+/// Globs selecting the contents of a directory entry, relative to the directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DirSpec {
+    /// Only paths matching one of these are tracked (all paths if empty)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Paths matching one of these are not tracked
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    /// Whether the directory is linked as a whole or file by file
+    #[serde(skip_serializing_if = "LinkMode::is_dir")]
+    pub link: LinkMode,
+}
+
+/// The `link = "contents"` directory a [`TrackedFile`] was expanded from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LinkRoot {
+    /// The directory in the repository
+    pub repo: PathBuf,
+    /// The real directory at the destination that holds the links
+    pub dest: PathBuf,
 }
 
 /// Represents a change detected in a file.
@@ -25,6 +143,49 @@ pub enum FileChange {
     Deleted(PathBuf),
 }
 
+impl FileChange {
+    /// Path of the changed file.
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            FileChange::Added(path) | FileChange::Modified(path) | FileChange::Deleted(path) => {
+                path
+            }
+        }
+    }
+}
+
+/// How serious a reported problem is, ordered from least to most severe.
+///
+/// `status`, `maintain check`, `maintain validate` and `apply --check` exit with the
+/// code of the most severe problem they find.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Nothing to report
+    Ok,
+    /// Drift that `flux apply` can fix, or a harmless inconsistency
+    Warning,
+    /// Something is broken and needs manual attention
+    Error,
+}
+
+impl Severity {
+    /// Process exit code for this severity.
+    ///
+    /// 1 is used for flux's own failures and 2 for usage errors, so problems start at 3.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Severity::Ok => 0,
+            Severity::Warning => 3,
+            Severity::Error => 4,
+        }
+    }
+
+    /// The most severe of `severities`, or `Ok` if there are none.
+    pub fn max_of(severities: impl IntoIterator<Item = Severity>) -> Severity {
+        severities.into_iter().max().unwrap_or(Severity::Ok)
+    }
+}
+
 // ==================== Environment Types ====================
 
 /// Environment configuration for declarative operations.
@@ -37,6 +198,36 @@ pub struct EnvironmentSpec {
     /// Shell to use for command execution
     #[serde(skip_serializing_if = "Option::is_none")]
     pub shell: Option<String>,
+
+    /// Hooks run once per operation that changes any tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
+}
+
+/// Shell commands run before and after flux changes a tool's files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Run before `flux apply` changes the tool's destinations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_apply: Option<String>,
+    /// Run after `flux apply` changed the tool's destinations (e.g. `swaymsg reload`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_apply: Option<String>,
+    /// Run before `flux commit` commits changes to the tool's repo files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_commit: Option<String>,
+    /// Run after `flux commit` committed changes to the tool's repo files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_commit: Option<String>,
+    /// Run before every `flux pull`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_pull: Option<String>,
+    /// Run after `flux pull` changed the tool's repo files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_pull: Option<String>,
+    /// Seconds before a hook is killed (default: the global hooks timeout, or 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
 }
 
 /// Strategy for resolving symlink targets.
@@ -49,7 +240,7 @@ pub enum SymlinkResolution {
     Relative,
     /// Always use absolute paths
     Absolute,
-    /// Follow existing symlinks (treated as Auto)
+    /// Follow an existing symlink chain and place the file at its end
     Follow,
     /// Copy files instead of creating symlinks
     Replace,
@@ -58,7 +249,7 @@ pub enum SymlinkResolution {
 impl std::str::FromStr for SymlinkResolution {
     type Err = String;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "auto" => Ok(SymlinkResolution::Auto),
             "relative" => Ok(SymlinkResolution::Relative),
@@ -70,8 +261,38 @@ impl std::str::FromStr for SymlinkResolution {
     }
 }
 
+/// How to resolve a destination that conflicts with the repository version without
+/// prompting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Back up the local file and replace it with the repository version
+    KeepRepo,
+    /// Back up the repository version, then replace it with the local file
+    KeepLocal,
+    /// Leave the local file untouched
+    Skip,
+    /// Abort before changing anything
+    Fail,
+    /// Move the local file into the repository (the old version stays in git history)
+    Adopt,
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ConflictPolicy::KeepRepo => "keep-repo",
+            ConflictPolicy::KeepLocal => "keep-local",
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Fail => "fail",
+            ConflictPolicy::Adopt => "adopt",
+        };
+        f.write_str(name)
+    }
+}
+
 /// A file being tracked by the dotfiles manager.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TrackedFile {
     /// Tool name this file belongs to
     pub tool: String,
@@ -81,4 +302,176 @@ pub struct TrackedFile {
     pub dest_path: PathBuf,
     /// Optional profile name for this file
     pub profile: Option<String>,
+    /// Whether the repo file is rendered as a template
+    pub template: bool,
+    /// Whether the repo file is encrypted
+    pub encrypted: bool,
+    /// How the file is placed at its destination
+    pub resolution: SymlinkResolution,
+    /// Globs of a `type = "dir"` entry (`None` for single files)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<DirSpec>,
+    /// Directory this file was expanded from when it is linked with `link = "contents"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_root: Option<LinkRoot>,
+    /// Application owning the database of a `kind = "sqlite"` entry (`None` for other
+    /// files)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sqlite_owner: Option<String>,
+}
+
+impl TrackedFile {
+    /// Whether the destination is a rendered copy rather than a symlink.
+    pub fn is_rendered(&self) -> bool {
+        self.template || self.encrypted
+    }
+
+    /// Whether the destination is a plain copy of the repo file rather than a symlink.
+    pub fn is_copy(&self) -> bool {
+        !self.is_rendered() && self.resolution == SymlinkResolution::Replace
+    }
+
+    /// Whether the repo file is a snapshot of a SQLite database.
+    pub fn is_sqlite(&self) -> bool {
+        self.sqlite_owner.is_some()
+    }
+
+    /// Whether the destination of a copied entry differs from the repo file. A database
+    /// is compared through a fresh snapshot, since its file alone may be out of date.
+    pub fn copy_differs(&self) -> Result<bool> {
+        if self.is_sqlite() {
+            return snapshot_differs(&self.repo_path, &self.dest_path);
+        }
+        copy_differs(&self.repo_path, &self.dest_path, &self.dir_filter()?)
+    }
+
+    /// Whether the destination is a symlink to the repo file, as a linked entry should be.
+    pub fn is_linked(&self) -> bool {
+        !self.is_rendered()
+            && !self.is_copy()
+            && fs::read_link(&self.dest_path).is_ok_and(|link_target| {
+                symlink_points_to_correct_target(&self.dest_path, &link_target, &self.repo_path)
+            })
+    }
+
+    /// Filter selecting the files of a directory entry that are compared and copied.
+    pub fn dir_filter(&self) -> Result<DirFilter> {
+        match &self.dir {
+            Some(dir) => DirFilter::new(&dir.include, &dir.ignore),
+            None => Ok(DirFilter::default()),
+        }
+    }
+
+    /// Split a `link = "contents"` directory entry into one file per tracked file in
+    /// the repository directory, and one directory per subdirectory that can be
+    /// folded into a single link. Other entries are returned unchanged.
+    pub fn expand_contents(self) -> Result<Vec<TrackedFile>> {
+        if !self
+            .dir
+            .as_ref()
+            .is_some_and(|dir| dir.link == LinkMode::Contents)
+        {
+            return Ok(vec![self]);
+        }
+
+        let root = LinkRoot {
+            repo: self.repo_path.clone(),
+            dest: self.dest_path.clone(),
+        };
+        let selected = self.dir_filter()?.entries(&self.repo_path)?;
+        let all = DirFilter::default().entries(&self.repo_path)?;
+        // Copies are placed file by file, so only linked entries fold
+        let folds = !self.is_rendered() && !self.is_copy();
+
+        let mut foldable = HashMap::new();
+        let mut files: Vec<TrackedFile> = Vec::new();
+        for (relative, repo_path) in selected.iter() {
+            let fold = if folds {
+                fold_point(&root, relative, &selected, &all, &mut foldable)?
+            } else {
+                None
+            };
+            let file = match fold {
+                Some(dir) => TrackedFile {
+                    repo_path: root.repo.join(dir),
+                    dest_path: root.dest.join(dir),
+                    dir: Some(DirSpec::default()),
+                    link_root: Some(root.clone()),
+                    ..self.clone()
+                },
+                None => TrackedFile {
+                    repo_path: repo_path.clone(),
+                    dest_path: root.dest.join(relative),
+                    dir: None,
+                    link_root: Some(root.clone()),
+                    ..self.clone()
+                },
+            };
+            // Files are sorted, so the files of a folded directory follow each other
+            if files
+                .last()
+                .is_none_or(|last| last.dest_path != file.dest_path)
+            {
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    /// A directory above the destination that is still a symlink into the repository
+    /// directory of a `link = "contents"` entry, and has to be unfolded into a real one.
+    pub fn folded_dir(&self) -> Option<PathBuf> {
+        let root = self.link_root.as_ref()?;
+        folded_ancestor(&self.dest_path, &root.dest, &root.repo)
+    }
+
+    /// With `Follow` resolution, the file placed at the end of the destination's
+    /// symlink chain, along with the chain (empty when there is nothing to follow).
+    pub fn followed(&self) -> Result<(TrackedFile, Vec<PathBuf>)> {
+        if self.resolution != SymlinkResolution::Follow || self.is_rendered() {
+            return Ok((self.clone(), Vec::new()));
+        }
+
+        let chain = follow_symlink_chain(&self.dest_path, &self.repo_path)?;
+        if chain.len() < 2 {
+            return Ok((self.clone(), Vec::new()));
+        }
+        let followed = TrackedFile {
+            dest_path: chain[chain.len() - 1].clone(),
+            ..self.clone()
+        };
+        Ok((followed, chain))
+    }
+}
+
+/// The outermost subdirectory above `relative` that a `link = "contents"` entry links
+/// as a whole, like Stow folding a tree no other package uses: every repo file below
+/// it is tracked, and at the destination it is missing, already folded, or holds
+/// nothing but links to the repo. The entry's own directory always stays real.
+fn fold_point<'a>(
+    root: &LinkRoot,
+    relative: &'a Path,
+    selected: &BTreeMap<PathBuf, PathBuf>,
+    all: &BTreeMap<PathBuf, PathBuf>,
+    foldable: &mut HashMap<PathBuf, bool>,
+) -> Result<Option<&'a Path>> {
+    let mut dirs: Vec<&Path> = relative.ancestors().skip(1).collect();
+    dirs.pop(); // The entry's own directory
+    for dir in dirs.into_iter().rev() {
+        if !foldable.contains_key(dir) {
+            let dest = root.dest.join(dir);
+            let tracked = all
+                .keys()
+                .filter(|path| path.starts_with(dir))
+                .all(|path| selected.contains_key(path));
+            // Under a directory that is about to be unfolded, the destination is missing
+            let fits = folded_ancestor(&dest, &root.dest, &root.repo).is_some()
+                || is_foldable_dir(&dest, &root.repo.join(dir))?;
+            foldable.insert(dir.to_path_buf(), tracked && fits);
+        }
+        if foldable[dir] {
+            return Ok(Some(dir));
+        }
+    }
+    Ok(None)
 }
@@ -0,0 +1,199 @@
+//! In-process unified diffs for `flux diff` and the conflict prompt.
+//!
+//! Contents are compared line by line; anything that is not UTF-8 text (or contains a
+//! NUL byte) is reported as a binary difference without hunks.
+
+use colored::Colorize;
+use similar::{ChangeTag, TextDiff};
+
+/// Lines of context around each change, as in `diff -u`.
+const CONTEXT_LINES: usize = 3;
+
+/// Widest `+`/`-` bar printed by `--stat`.
+const STAT_BAR_WIDTH: usize = 40;
+
+/// Difference between two versions of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    /// Name shown for the old side (`---`)
+    pub old_label: String,
+    /// Name shown for the new side (`+++`)
+    pub new_label: String,
+    /// Name shown in `--stat` output
+    pub path: String,
+    /// Unified diff hunks, starting at the first `@@` line (empty for binary files)
+    pub hunks: String,
+    /// Number of added lines
+    pub insertions: usize,
+    /// Number of removed lines
+    pub deletions: usize,
+    /// Whether either side is binary
+    pub binary: bool,
+}
+
+/// Compare two versions of a file. `None` stands for a side that does not exist.
+///
+/// Returns `None` when both sides are identical.
+pub fn diff_contents(
+    path: &str,
+    old_label: &str,
+    new_label: &str,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+) -> Option<FileDiff> {
+    if old == new {
+        return None;
+    }
+    let old_label = if old.is_some() {
+        old_label
+    } else {
+        "/dev/null"
+    };
+    let new_label = if new.is_some() {
+        new_label
+    } else {
+        "/dev/null"
+    };
+    let mut diff = FileDiff {
+        old_label: old_label.to_string(),
+        new_label: new_label.to_string(),
+        path: path.to_string(),
+        hunks: String::new(),
+        insertions: 0,
+        deletions: 0,
+        binary: false,
+    };
+
+    let (Some(old_text), Some(new_text)) = (as_text(old), as_text(new)) else {
+        diff.binary = true;
+        return Some(diff);
+    };
+
+    let text_diff = TextDiff::from_lines(old_text, new_text);
+    for change in text_diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => diff.insertions += 1,
+            ChangeTag::Delete => diff.deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    diff.hunks = text_diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .missing_newline_hint(true)
+        .to_string();
+    Some(diff)
+}
+
+/// Text of one side; a missing side is empty.
+fn as_text(contents: Option<&[u8]>) -> Option<&str> {
+    match contents {
+        None => Some(""),
+        Some(bytes) if bytes.contains(&0) => None,
+        Some(bytes) => std::str::from_utf8(bytes).ok(),
+    }
+}
+
+impl FileDiff {
+    /// The diff in unified format, colored when `color` is set.
+    pub fn render(&self, color: bool) -> String {
+        let mut output = String::new();
+        let mut push = |line: &str, styled: String| {
+            output.push_str(if color { &styled } else { line });
+            output.push('\n');
+        };
+
+        if self.binary {
+            let line = format!(
+                "Binary files {} and {} differ",
+                self.old_label, self.new_label
+            );
+            push(&line, line.bold().to_string());
+            return output;
+        }
+
+        let old = format!("--- {}", self.old_label);
+        push(&old, old.bold().to_string());
+        let new = format!("+++ {}", self.new_label);
+        push(&new, new.bold().to_string());
+        for line in self.hunks.lines() {
+            let styled = if line.starts_with("@@") {
+                line.cyan().to_string()
+            } else if line.starts_with('+') {
+                line.green().to_string()
+            } else if line.starts_with('-') {
+                line.red().to_string()
+            } else {
+                line.to_string()
+            };
+            push(line, styled);
+        }
+        output
+    }
+}
+
+/// Print each diff in unified format.
+pub fn print_diffs(diffs: &[FileDiff]) {
+    for diff in diffs {
+        print!(
+            "{}",
+            diff.render(colored::control::SHOULD_COLORIZE.should_colorize())
+        );
+    }
+}
+
+/// Print a `git diff --stat` style summary: one line per file with a `+`/`-` bar,
+/// followed by the totals.
+pub fn print_stat(diffs: &[FileDiff]) {
+    let name_width = diffs.iter().map(|d| d.path.len()).max().unwrap_or(0);
+    let count_width = diffs
+        .iter()
+        .map(|d| (d.insertions + d.deletions).to_string().len())
+        .max()
+        .unwrap_or(1);
+    let largest = diffs
+        .iter()
+        .map(|d| d.insertions + d.deletions)
+        .max()
+        .unwrap_or(0);
+
+    for diff in diffs {
+        if diff.binary {
+            println!(" {:<name_width$} | Bin", diff.path);
+            continue;
+        }
+        let (plus, minus) = stat_bar(diff.insertions, diff.deletions, largest);
+        println!(
+            " {:<name_width$} | {:>count_width$} {}{}",
+            diff.path,
+            diff.insertions + diff.deletions,
+            "+".repeat(plus).green(),
+            "-".repeat(minus).red()
+        );
+    }
+
+    let insertions: usize = diffs.iter().map(|d| d.insertions).sum();
+    let deletions: usize = diffs.iter().map(|d| d.deletions).sum();
+    println!(
+        " {} file(s) changed, {} insertion(s)(+), {} deletion(s)(-)",
+        diffs.len(),
+        insertions,
+        deletions
+    );
+}
+
+/// Lengths of the `+` and `-` runs for one file, scaled so the largest change fits
+/// in [`STAT_BAR_WIDTH`] while every non-zero count keeps at least one mark.
+pub fn stat_bar(insertions: usize, deletions: usize, largest: usize) -> (usize, usize) {
+    if largest <= STAT_BAR_WIDTH {
+        return (insertions, deletions);
+    }
+    let scale = |count: usize| {
+        if count == 0 {
+            0
+        } else {
+            (count * STAT_BAR_WIDTH / largest).max(1)
+        }
+    };
+    (scale(insertions), scale(deletions))
+}
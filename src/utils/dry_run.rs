@@ -12,6 +12,8 @@ pub enum Operation {
     CreateDirectory { path: PathBuf },
     /// Copy a file
     CopyFile { from: PathBuf, to: PathBuf },
+    /// Write generated content to a file
+    WriteFile { path: PathBuf },
     /// Remove a file
     RemoveFile { path: PathBuf },
     /// Git commit operation
@@ -94,6 +96,9 @@ impl DryRun {
                         to.display()
                     );
                 }
+                Operation::WriteFile { path } => {
+                    println!("   {} {}", "Write file:".cyan(), path.display());
+                }
                 Operation::RemoveFile { path } => {
                     println!("   {} {}", "Remove file:".red(), path.display());
                 }
@@ -53,7 +53,6 @@ pub enum DotfilesError {
 
 impl DotfilesError {
     /// Provide additional context for the error
-    #[cfg(test)]
     pub fn with_context(self, context: &str) -> Self {
         match self {
             DotfilesError::Config(msg) => {
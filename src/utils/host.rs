@@ -0,0 +1,26 @@
+//! Facts about the machine flux is running on.
+
+/// Short hostname of this machine (empty if it cannot be determined)
+pub fn hostname() -> String {
+    nix::unistd::gethostname()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_default()
+}
+
+/// Operating system name, as reported by Rust (e.g. "linux", "macos")
+pub fn os() -> &'static str {
+    std::env::consts::OS
+}
+
+/// CPU architecture, as reported by Rust (e.g. "x86_64", "aarch64")
+pub fn arch() -> &'static str {
+    std::env::consts::ARCH
+}
+
+/// Name of the current user
+pub fn username() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_default()
+}
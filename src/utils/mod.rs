@@ -1,7 +1,10 @@
+pub mod diff;
 pub mod dry_run;
 pub mod error;
 pub mod error_utils;
+pub mod host;
 pub mod logging;
+pub mod output;
 pub mod path_utils;
 pub mod prompt;
 pub mod security;
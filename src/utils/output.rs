@@ -0,0 +1,39 @@
+//! Machine-readable output for `--format json` and `--format ndjson`.
+//!
+//! `json` prints one pretty-printed document per command; `ndjson` prints one compact
+//! record per line so consumers can stream it. Field names come from the `Serialize`
+//! derives of the reported types and are part of flux's stable interface.
+
+use crate::utils::error::Result;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// Output format selected with the global `--format` flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Colored text for humans
+    #[default]
+    Text,
+    /// A single JSON document
+    Json,
+    /// One JSON record per line
+    Ndjson,
+}
+
+/// Print `document` as a single pretty-printed JSON document.
+pub fn print_json<T: Serialize + ?Sized>(document: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, document).map_err(io::Error::from)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// Print each record as one compact JSON line.
+pub fn print_ndjson<T: Serialize>(records: &[T]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for record in records {
+        serde_json::to_writer(&mut stdout, record).map_err(io::Error::from)?;
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
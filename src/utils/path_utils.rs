@@ -1,23 +1,29 @@
-use crate::utils::error::Result;
+use crate::utils::error::{DotfilesError, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 /// Normalize a path by canonicalizing it, falling back to the path itself if canonicalization fails.
 pub fn normalize_path(path: &Path) -> PathBuf {
     path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
 }
 
-/// Check if two files have different content.
-pub fn files_differ(path1: &Path, path2: &Path) -> Result<bool> {
-    use std::fs;
-
+/// Check if two files, or the files `filter` selects in two directories, have
+/// different content.
+pub fn paths_differ(path1: &Path, path2: &Path, filter: &DirFilter) -> Result<bool> {
     if !path1.exists() || !path2.exists() {
         return Ok(true); // One or both don't exist, so they are "different"
     }
 
-    // If either is a directory, we can't compare contents directly
     if path1.is_dir() || path2.is_dir() {
-        // For directories, we consider them different if one is dir and other isn't
-        return Ok(path1.is_dir() != path2.is_dir());
+        if path1.is_dir() != path2.is_dir() {
+            return Ok(true);
+        }
+        return Ok(!dir_differences(path1, path2, filter)?.is_empty());
     }
 
     let content1 = fs::read(path1)?;
@@ -26,6 +32,139 @@ pub fn files_differ(path1: &Path, path2: &Path) -> Result<bool> {
     Ok(content1 != content2)
 }
 
+/// Include and ignore globs of a directory entry, matched against paths relative to
+/// the directory. `*` also matches `/`, so `*.log` ignores logs at any depth.
+#[derive(Debug, Default)]
+pub struct DirFilter {
+    include: Option<GlobSet>,
+    ignore: Option<GlobSet>,
+}
+
+impl DirFilter {
+    pub fn new(include: &[String], ignore: &[String]) -> Result<Self> {
+        Ok(Self {
+            include: build_globset(include)?,
+            ignore: build_globset(ignore)?,
+        })
+    }
+
+    /// Whether a path relative to the directory is tracked.
+    pub fn matches(&self, relative: &Path) -> bool {
+        !self.is_ignored(relative)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    }
+
+    fn is_ignored(&self, relative: &Path) -> bool {
+        self.ignore
+            .as_ref()
+            .is_some_and(|ignore| ignore.is_match(relative))
+    }
+
+    /// Tracked files and symlinks under `root`, keyed by their path relative to it.
+    ///
+    /// Ignored directories are not descended into.
+    pub fn entries(&self, root: &Path) -> Result<BTreeMap<PathBuf, PathBuf>> {
+        let mut entries = BTreeMap::new();
+        if !root.is_dir() {
+            return Ok(entries);
+        }
+
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let walker = WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|entry| {
+                !(entry.file_type().is_dir() && self.is_ignored(&relative(entry.path())))
+            });
+        for entry in walker {
+            let entry = entry.map_err(io::Error::from)?;
+            if entry.file_type().is_dir() {
+                continue;
+            }
+            let path = relative(entry.path());
+            if self.matches(&path) {
+                entries.insert(path, entry.into_path());
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder
+            .add(Glob::new(pattern).map_err(|e| {
+                DotfilesError::Config(format!("Invalid glob '{}': {}", pattern, e))
+            })?);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| DotfilesError::Config(format!("Invalid globs: {}", e)))
+}
+
+/// Paths (relative to the directories) that differ between two directories, including
+/// files present in only one of them.
+pub fn dir_differences(dir1: &Path, dir2: &Path, filter: &DirFilter) -> Result<Vec<PathBuf>> {
+    let left = filter.entries(dir1)?;
+    let right = filter.entries(dir2)?;
+
+    let mut differences = Vec::new();
+    for (relative, path) in &left {
+        match right.get(relative) {
+            Some(other) if !entries_differ(path, other)? => {}
+            _ => differences.push(relative.clone()),
+        }
+    }
+    differences.extend(
+        right
+            .keys()
+            .filter(|relative| !left.contains_key(*relative))
+            .cloned(),
+    );
+    differences.sort();
+    Ok(differences)
+}
+
+fn entries_differ(path1: &Path, path2: &Path) -> Result<bool> {
+    if path1.is_symlink() || path2.is_symlink() {
+        return Ok(fs::read_link(path1).ok() != fs::read_link(path2).ok());
+    }
+    // Sizes are cheap to compare and settle most differences
+    if fs::metadata(path1)?.len() != fs::metadata(path2)?.len() {
+        return Ok(true);
+    }
+    Ok(fs::read(path1)? != fs::read(path2)?)
+}
+
+/// SHA-256 of a file's content as a hex string.
+pub fn content_hash(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check if a copied destination has drifted from its source.
+///
+/// A symlink is never a valid copy, so it always counts as drift. Directories are
+/// compared file by file with `filter`.
+pub fn copy_differs(source: &Path, dest: &Path, filter: &DirFilter) -> Result<bool> {
+    if dest.is_symlink() || !source.exists() || !dest.exists() || source.is_dir() != dest.is_dir() {
+        return Ok(true);
+    }
+    if source.is_dir() {
+        return Ok(!dir_differences(source, dest, filter)?.is_empty());
+    }
+    Ok(content_hash(source)? != content_hash(dest)?)
+}
+
 /// Resolve a symlink target to an absolute path.
 ///
 /// If the target is already absolute, returns it as-is.
@@ -53,3 +192,108 @@ pub fn symlink_points_to_correct_target(
     normalized_target == normalized_expected
 }
 
+/// Follow the chain of symlinks starting at `path` to the place a file should go.
+///
+/// Returns every hop, starting with `path`. The chain stops at the first path that is
+/// not a symlink (or does not exist), or at a symlink that already points to
+/// `stop_at`. Intermediate links are compared by location, without following the
+/// final component, so a chain ending at `stop_at` is reported in full.
+pub fn follow_symlink_chain(path: &Path, stop_at: &Path) -> Result<Vec<PathBuf>> {
+    let stop_at = normalize_location(stop_at);
+    let mut chain = vec![path.to_path_buf()];
+    let mut current = path.to_path_buf();
+
+    while current.is_symlink() {
+        let next = resolve_symlink_target(&current, &fs::read_link(&current)?);
+        if normalize_location(&next) == stop_at {
+            break;
+        }
+        if chain
+            .iter()
+            .any(|hop| normalize_location(hop) == normalize_location(&next))
+        {
+            chain.push(next);
+            let hops: Vec<_> = chain.iter().map(|hop| hop.display().to_string()).collect();
+            return Err(DotfilesError::Path(format!(
+                "Symlink cycle: {}",
+                hops.join(" → ")
+            )));
+        }
+        chain.push(next.clone());
+        current = next;
+    }
+
+    Ok(chain)
+}
+
+/// Canonicalize a path's parent directory but not the path itself, so a symlink keeps
+/// its own location.
+fn normalize_location(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => normalize_path(parent).join(name),
+        _ => normalize_path(path),
+    }
+}
+
+/// The outermost directory between `dest_root` and `dest` (inclusive of `dest_root`)
+/// that is a symlink into `repo_root`, i.e. a directory that is still folded into the
+/// repository instead of holding per-file links.
+pub fn folded_ancestor(dest: &Path, dest_root: &Path, repo_root: &Path) -> Option<PathBuf> {
+    let repo_root = fs::canonicalize(repo_root).ok()?;
+    dest.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(dest_root))
+        .filter(|dir| dir.is_symlink())
+        .filter(|dir| fs::canonicalize(dir).is_ok_and(|target| target.starts_with(&repo_root)))
+        .last()
+        .map(Path::to_path_buf)
+}
+
+/// Whether `dest` is a real directory holding nothing but symlinks to the matching
+/// files under `repo`, as left behind by `link = "contents"`. Such a directory can
+/// be folded back into a single link without losing anything.
+pub fn is_unfolded_dir(dest: &Path, repo: &Path) -> Result<bool> {
+    if dest.is_symlink() || !dest.is_dir() {
+        return Ok(false);
+    }
+    let entries = DirFilter::default().entries(dest)?;
+    Ok(!entries.is_empty()
+        && entries.iter().all(|(relative, path)| {
+            fs::read_link(path).is_ok_and(|link_target| {
+                symlink_points_to_correct_target(path, &link_target, &repo.join(relative))
+            })
+        }))
+}
+
+/// Whether a `link = "contents"` subdirectory at `dest` can be folded into a single
+/// link to `repo`: it is missing, already such a link, or holds nothing but links to
+/// the matching repo files.
+pub fn is_foldable_dir(dest: &Path, repo: &Path) -> Result<bool> {
+    if dest.is_symlink() {
+        return Ok(fs::canonicalize(dest)
+            .is_ok_and(|target| fs::canonicalize(repo).is_ok_and(|repo| repo == target)));
+    }
+    Ok(!dest.exists() || is_unfolded_dir(dest, repo)?)
+}
+
+/// Symlinks under `dest_root` that point into `repo_root` at files that no longer
+/// exist, left behind when files are removed from a `link = "contents"` directory.
+pub fn stale_links(dest_root: &Path, repo_root: &Path) -> Result<Vec<PathBuf>> {
+    if dest_root.is_symlink() || !dest_root.is_dir() {
+        return Ok(Vec::new());
+    }
+    let repo_root = normalize_path(repo_root);
+
+    let mut stale = Vec::new();
+    for entry in WalkDir::new(dest_root).min_depth(1) {
+        let entry = entry.map_err(io::Error::from)?;
+        if !entry.path_is_symlink() {
+            continue;
+        }
+        let target = resolve_symlink_target(entry.path(), &fs::read_link(entry.path())?);
+        if !target.exists() && normalize_location(&target).starts_with(&repo_root) {
+            stale.push(entry.into_path());
+        }
+    }
+    Ok(stale)
+}
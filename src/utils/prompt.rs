@@ -1,7 +1,8 @@
-use crate::types::FileChange;
+use crate::types::{ConflictPolicy, FileChange};
 use crate::utils::error::{DotfilesError, Result};
 use colored::Colorize;
 use dialoguer::{Input, Select, theme::ColorfulTheme};
+use std::io::IsTerminal;
 use std::path::Path;
 
 pub enum ConflictResolution {
@@ -11,6 +12,30 @@ pub enum ConflictResolution {
     Cancel,
 }
 
+/// Whether flux can prompt: stdin is a terminal and no CI environment was detected.
+pub fn is_interactive(is_ci_environment: bool) -> bool {
+    !is_ci_environment && std::io::stdin().is_terminal()
+}
+
+/// Choose the policy for resolving conflicts, or `None` to prompt for each one.
+///
+/// An explicit `--on-conflict` always applies. Otherwise the configured policy
+/// (default: keep-repo) is used when flux cannot prompt.
+pub fn select_conflict_policy(
+    explicit: Option<ConflictPolicy>,
+    configured: Option<ConflictPolicy>,
+    interactive: bool,
+) -> Option<ConflictPolicy> {
+    if explicit.is_some() {
+        return explicit;
+    }
+    if interactive {
+        None
+    } else {
+        Some(configured.unwrap_or(ConflictPolicy::KeepRepo))
+    }
+}
+
 pub fn prompt_conflict(file_path: &Path) -> Result<ConflictResolution> {
     let options = vec!["Backup and replace", "Skip", "View diff", "Cancel"];
 
@@ -33,6 +58,34 @@ pub fn prompt_conflict(file_path: &Path) -> Result<ConflictResolution> {
     }
 }
 
+pub enum RecoveryChoice {
+    Finish,
+    Undo,
+    Cancel,
+}
+
+pub fn prompt_recovery() -> Result<RecoveryChoice> {
+    let options = vec![
+        "Finish the remaining operations",
+        "Undo the completed operations",
+        "Cancel",
+    ];
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("An interrupted transaction was found. How would you like to proceed?")
+        .items(&options)
+        .default(0)
+        .interact()
+        .map_err(|e| DotfilesError::Io(std::io::Error::other(e)))?;
+
+    match selection {
+        0 => Ok(RecoveryChoice::Finish),
+        1 => Ok(RecoveryChoice::Undo),
+        2 => Ok(RecoveryChoice::Cancel),
+        _ => Err(DotfilesError::Cancelled),
+    }
+}
+
 pub fn prompt_yes_no(question: &str) -> Result<bool> {
     let options = vec!["Yes", "No"];
 